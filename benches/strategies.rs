@@ -0,0 +1,136 @@
+// compares the ways this crate can execute the program: the hand transpiled functions and the ones
+// build.rs generates, the generic interpreter (matching on each step, or threaded through
+// pre-decoded handlers), native code from the jit, and the sieve shortcut for the prime buffer.
+// the jit is timed from cold, compile time and all, and again with everything already compiled.
+// each one runs with the memory trace recording on and off, since that is the main cost the
+// interpreter adds on top
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use disasm::ex::{self, State};
+use disasm::{generated, jit, threaded};
+use disasm::vm::{Vm, ENTRY};
+
+// quiet state with the winning input in place. `record` turns on the memory trace
+fn setup(record: bool) -> State {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let input = ex::winning_input(&mut scratch);
+
+    let mut s = State::with_input(&input);
+    s.quiet = true;
+    if record {
+        s.trace = Some(Vec::new());
+    }
+    s
+}
+
+fn generate_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_buffer");
+    for record in [false, true] {
+        let label = if record { "trace on" } else { "trace off" };
+        let s = setup(record);
+
+        group.bench_function(format!("transpiled/{}", label), |b| {
+            b.iter_batched_ref(|| s.clone(), ex::generate_buffer, BatchSize::SmallInput)
+        });
+        group.bench_function(format!("sieve/{}", label), |b| {
+            b.iter_batched_ref(|| s.clone(), ex::generate_buffer_fast, BatchSize::SmallInput)
+        });
+        group.bench_function(format!("interpreter/{}", label), |b| {
+            // same setup the program does at 0xc8, then the 0x151 loop
+            b.iter_batched_ref(
+                || {
+                    let mut vm = Vm::new(decrypted(&s));
+                    vm.s.regs[4] = 0x1388;
+                    vm.s.regs[0] = 0x3390;
+                    vm
+                },
+                |vm| vm.call(0x151).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(format!("threaded/{}", label), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut vm = Vm::new(decrypted(&s));
+                    vm.s.regs[4] = 0x1388;
+                    vm.s.regs[0] = 0x3390;
+                    vm
+                },
+                |vm| threaded::call(vm, 0x151).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn full_program(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full program");
+    for record in [false, true] {
+        let label = if record { "trace on" } else { "trace off" };
+        let s = setup(record);
+
+        group.bench_function(format!("transpiled/{}", label), |b| {
+            b.iter_batched_ref(|| s.clone(), ex::stage2, BatchSize::SmallInput)
+        });
+        group.bench_function(format!("generated/{}", label), |b| {
+            b.iter_batched_ref(|| s.clone(), generated::stage2_c8, BatchSize::SmallInput)
+        });
+        group.bench_function(format!("interpreter/{}", label), |b| {
+            // this one includes the stage1 decryption
+            b.iter_batched_ref(
+                || {
+                    let mut vm = Vm::new(s.clone());
+                    vm.pc = ENTRY;
+                    vm
+                },
+                |vm| vm.run().unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(format!("jit/{}", label), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut vm = Vm::new(s.clone());
+                    vm.pc = ENTRY;
+                    vm
+                },
+                |vm| jit::run(vm).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(format!("jit compiled/{}", label), |b| {
+            let fresh = || {
+                let mut vm = Vm::new(s.clone());
+                vm.pc = ENTRY;
+                vm
+            };
+            // one run to get the hot functions compiled, every run after that just calls them
+            let mut jit = jit::Jit::new();
+            jit::run_with(&mut fresh(), &mut jit).unwrap();
+            b.iter_batched_ref(fresh, |vm| jit::run_with(vm, &mut jit).unwrap(), BatchSize::SmallInput)
+        });
+        group.bench_function(format!("threaded/{}", label), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut vm = Vm::new(s.clone());
+                    vm.pc = ENTRY;
+                    vm
+                },
+                |vm| threaded::run(vm).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// the interpreter needs stage2 un-xored before it can call into it
+fn decrypted(s: &State) -> State {
+    let mut s = Vm::boot(s.clone()).unwrap().s;
+    s.trace = s.trace.map(|_| Vec::new());
+    s
+}
+
+criterion_group!(benches, generate_buffer, full_program);
+criterion_main!(benches);
@@ -0,0 +1,143 @@
+// generates $OUT_DIR/ex_generated.rs: the stage2 functions translated straight from the mem image,
+// the same way ex.rs started out before it was cleaned up by hand. it's rebuilt whenever the image
+// or the parser changes, so the generated functions always match what's actually in mem
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+// inst.rs is written for the no_std engine
+extern crate alloc;
+
+#[allow(dead_code)]
+#[path = "src/inst.rs"]
+mod inst;
+
+use inst::{DestMode, Instruction, Operation, SrcMode, Width};
+
+// stage2 main
+const MAIN: usize = 0xc8;
+
+fn main() {
+    println!("cargo:rerun-if-changed=mem");
+    println!("cargo:rerun-if-changed=src/inst.rs");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mem = inst::decrypted_image();
+    let mut out = String::new();
+    writeln!(out, "// generated by build.rs from mem, don't edit").unwrap();
+    for (addr, body) in functions(&mem) {
+        writeln!(out, "\npub fn stage2_{:x}(s: &mut State) {{", addr).unwrap();
+        for (pc, inst) in body {
+            writeln!(out, "    // {:#05x}: {}", pc, inst).unwrap();
+            if inst.op != Operation::Ret {
+                writeln!(out, "    {}", translate(&inst, pc)).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("ex_generated.rs");
+    std::fs::write(path, out).unwrap();
+}
+
+// main and everything it calls, each up to its ret
+fn functions(mem: &[u8]) -> BTreeMap<usize, Vec<(usize, Instruction)>> {
+    let mut functions = BTreeMap::new();
+    let mut todo = vec![MAIN];
+    let mut seen = HashSet::new();
+    while let Some(addr) = todo.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        let mut body = Vec::new();
+        let mut pc = addr;
+        loop {
+            let (inst, rest) = Instruction::parse(&mem[pc..]);
+            body.push((pc, inst));
+            if inst.op == Operation::Jmp {
+                todo.push(inst.dest as usize);
+            }
+            if inst.op == Operation::Ret {
+                break;
+            }
+            pc = mem.len() - rest.len();
+        }
+        functions.insert(addr, body);
+    }
+    functions
+}
+
+// one instruction as rust against ex::State, in the order Vm::step does things
+fn translate(inst: &Instruction, pc: usize) -> String {
+    let reg = |n: u32| format!("s.regs[{}]", n);
+    let width = match inst.width {
+        Width::W8 => "Width::W8",
+        Width::W16 => "Width::W16",
+        Width::W32 => "Width::W32",
+        Width::W64 => return format!("panic!(\"64 bit access at {:#x}\");", pc),
+    };
+
+    if inst.op == Operation::Jmp {
+        let call = format!("stage2_{:x}(s);", inst.dest);
+        let cond = match inst.dest_mode {
+            DestMode::Minus => "< 0",
+            DestMode::Plus => "> 0",
+            DestMode::ZeroPad => "== 0",
+            DestMode::NoPlusMinus => return call,
+        };
+        return format!("if {} {} {{ {} }}", reg(inst.src), cond, call);
+    }
+
+    let src = match inst.src_mode {
+        SrcMode::HH => format!("s.read_width({}, {})", imm(inst.src), width),
+        SrcMode::H => format!("s.read_width({}, {})", reg(inst.src), width),
+        SrcMode::L => reg(inst.src),
+        SrcMode::LL => imm(inst.src),
+        SrcMode::None => return format!("panic!(\"bad operand at {:#x}\");", pc),
+    };
+    let dest = match inst.dest_mode {
+        DestMode::NoPlusMinus => {
+            return format!("{} = {};", reg(inst.dest), apply(inst.op, &reg(inst.dest), &src));
+        }
+        DestMode::Minus => imm(inst.dest),
+        DestMode::Plus => reg(inst.dest),
+        DestMode::ZeroPad => return format!("panic!(\"bad operand at {:#x}\");", pc),
+    };
+    match inst.op {
+        Operation::Mov => format!("s.store_width({}, {}, {});", dest, src, width),
+        op => format!(
+            "{{ let src = {}; let dest = s.read_width({}, {}); s.store_width({}, {}, {}); }}",
+            src,
+            dest,
+            width,
+            dest,
+            apply(op, "dest", "src"),
+            width
+        ),
+    }
+}
+
+// wrapping int math, idiv and sar, like vm::apply
+fn apply(op: Operation, dest: &str, src: &str) -> String {
+    match op {
+        Operation::Mov => src.to_string(),
+        Operation::Add => format!("{}.wrapping_add({})", dest, src),
+        Operation::Sub => format!("{}.wrapping_sub({})", dest, src),
+        Operation::Mul => format!("{}.wrapping_mul({})", dest, src),
+        Operation::Div => format!("{}.wrapping_div({})", dest, src),
+        Operation::Mod => format!("{}.wrapping_rem({})", dest, src),
+        Operation::ShLeft => format!("{}.wrapping_shl({} as u32)", dest, src),
+        Operation::ShRight => format!("{}.wrapping_shr({} as u32)", dest, src),
+        Operation::Xor => format!("{} ^ {}", dest, src),
+        Operation::And => format!("{} & {}", dest, src),
+        Operation::Or => format!("{} | {}", dest, src),
+        Operation::Jmp | Operation::Ret => unreachable!(),
+    }
+}
+
+// immediates are i32 bit patterns, negative ones need parens to take a method call
+fn imm(n: u32) -> String {
+    match n as i32 {
+        v if v < 0 => format!("({}i32)", v),
+        _ => format!("{:#x}", n),
+    }
+}
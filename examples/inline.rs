@@ -0,0 +1,17 @@
+// a tiny program written inline: sums 1..=10 into r0 by calling a loop body while r1 is positive
+use disasm::format_vm;
+use disasm::vm::Vm;
+
+fn main() {
+    // 0x00: r1 = 10, then call the loop at 0x10
+    // 0x10: r0 += r1, r1 -= 1, call 0x10 again while r1 > 0
+    let program = format_vm!("%1.10llM%16C\0\0\0\0%0.1lS%1.1llO%+16.1C");
+    for &(at, inst, _) in program.insts {
+        println!("{:#04x}:  {}", at, inst);
+    }
+
+    let mut vm: Vm = Vm::inline(&program);
+    vm.s.quiet = true;
+    vm.run().unwrap();
+    println!("r0 = {} after {} steps", vm.s.regs[0], vm.steps);
+}
@@ -0,0 +1,47 @@
+// format_vm!("%1.2llM%3.1lS") decodes a printf vm program while the crate using it compiles. it
+// expands to a disasm::inst::Inline: the program's bytes with a nul on the end (the final ret),
+// and every instruction with its offset and length. a specifier the parser doesn't take is a
+// compile error pointing at the literal
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, LitStr};
+
+// the crate's own parser, so the two can't disagree. it's written for the no_std engine
+extern crate alloc;
+
+#[allow(dead_code)]
+#[path = "../../src/inst.rs"]
+mod inst;
+
+#[proc_macro]
+pub fn format_vm(input: TokenStream) -> TokenStream {
+    let lit = parse_macro_input!(input as LitStr);
+    let mut text = lit.value().into_bytes();
+    text.push(0);
+
+    let mut insts = Vec::new();
+    let mut at = 0;
+    while at < text.len() {
+        let (inst, len) = match inst::Instruction::decode(&text[at..]) {
+            Ok((inst, rest)) => (inst, text.len() - at - rest.len()),
+            Err(e) => {
+                let message = format!("no instruction decodes at offset {:#x}: {}", at, e);
+                return syn::Error::new(lit.span(), message).to_compile_error().into();
+            }
+        };
+        insts.push(format!(
+            "({}, ::disasm::inst::Instruction {{ dest: {}, src: {}, dest_mode: \
+             ::disasm::inst::DestMode::{:?}, src_mode: ::disasm::inst::SrcMode::{:?}, \
+             op: ::disasm::inst::Operation::{:?}, width: ::disasm::inst::Width::{:?} }}, {})",
+            at, inst.dest, inst.src, inst.dest_mode, inst.src_mode, inst.op, inst.width, len
+        ));
+        at += len;
+    }
+
+    format!(
+        "::disasm::inst::Inline {{ text: &{:?}, insts: &[{}] }}",
+        text,
+        insts.join(", ")
+    )
+    .parse()
+    .unwrap()
+}
@@ -0,0 +1,44 @@
+/* the printf vm interpreter from the disasm crate, built as a cdylib (libdisasm.so). see
+ * src/ffi.rs for what each function does */
+#ifndef WEATHER_H
+#define WEATHER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+struct wvm;
+
+/* return codes. the negative ones are faults, the vm is left where it stopped */
+#define WVM_OK 0
+#define WVM_HALTED 1
+#define WVM_OUT_OF_STEPS 2
+#define WVM_OUT_OF_BOUNDS -1
+#define WVM_DIVIDE_BY_ZERO -2
+#define WVM_STACK_OVERFLOW -3
+#define WVM_BAD_OPERAND -4
+#define WVM_BAD_INSTRUCTION -5
+#define WVM_CODE_WRITE -6
+#define WVM_ASSERTION_FAILED -7
+#define WVM_PROTECTION_FAULT -8
+#define WVM_BAD_ARGUMENT -100
+
+struct wvm *wvm_new(void);
+void wvm_free(struct wvm *vm);
+int wvm_load_image(struct wvm *vm, const uint8_t *image, size_t len);
+int wvm_set_input(struct wvm *vm, const uint8_t *input, size_t len);
+
+int wvm_boot(struct wvm *vm);
+int wvm_step(struct wvm *vm);
+int wvm_run(struct wvm *vm);
+int wvm_run_until(struct wvm *vm, size_t stop, uint64_t max_steps);
+int wvm_call(struct wvm *vm, size_t addr);
+
+size_t wvm_pc(const struct wvm *vm);
+void wvm_set_pc(struct wvm *vm, size_t pc);
+uint64_t wvm_steps(const struct wvm *vm);
+int32_t wvm_reg(const struct wvm *vm, uint32_t n);
+int wvm_set_reg(struct wvm *vm, uint32_t n, int32_t value);
+int wvm_peek(const struct wvm *vm, size_t addr, uint8_t *out, size_t len);
+int wvm_poke(struct wvm *vm, size_t addr, const uint8_t *bytes, size_t len);
+
+#endif
@@ -0,0 +1,178 @@
+// triage for an image before disassembling it: entropy and the share of printable bytes per
+// window, and a guess at what each window is. the printf code is all printable and full of '%'.
+// xoring it with one byte usually keeps it printable, so a stage that's still encrypted is told
+// apart from strings by trying keys on it, see keys.rs. tables of little endian numbers in a
+// narrow range, like the primes, repeat their high bytes
+use crate::inst::try_parse;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Zero,
+    Code,
+    String,
+    Encrypted,
+    Table,
+    Data,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Kind::Zero => "zero",
+            Kind::Code => "code",
+            Kind::String => "string",
+            Kind::Encrypted => "encrypted?",
+            Kind::Table => "table",
+            Kind::Data => "data",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    pub start: usize,
+    pub len: usize,
+    // shannon entropy in bits per byte, at most log2(len)
+    pub entropy: f64,
+    pub printable: f64,
+    pub kind: Kind,
+}
+
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// share of a window that has to decode for it to be code, like keys::CONFIDENT
+const DECODES: f64 = 0.9;
+
+// how much of `bytes` decodes when xored with `key`, like keys::score. the window cuts
+// instructions off at both ends, so counting starts at the first one that decodes and the last
+// one can run on into `after`
+fn decoded(bytes: &[u8], after: &[u8], key: u8) -> f64 {
+    let plain: Vec<u8> = bytes.iter().chain(after).map(|b| b ^ key).collect();
+    let mut first = None;
+    let mut covered = 0;
+    let mut curr = 0;
+    while curr < bytes.len() {
+        let decoded = match plain[curr] {
+            b'%' | 0 => try_parse(&plain[curr..]),
+            _ => None,
+        };
+        match decoded {
+            Some((_, len)) => {
+                first.get_or_insert(curr);
+                covered += len.min(bytes.len() - curr);
+                curr += len;
+            }
+            None => curr += 1,
+        }
+    }
+    match first {
+        Some(first) if first < bytes.len() / 2 => covered as f64 / (bytes.len() - first) as f64,
+        _ => 0.0,
+    }
+}
+
+fn classify(bytes: &[u8], after: &[u8], entropy: f64, printable: f64) -> Kind {
+    let max = (bytes.len() as f64).log2().min(8.0);
+    let decodes = |key: u8| decoded(bytes, after, key) >= DECODES;
+
+    if bytes.iter().all(|&b| b == 0) {
+        Kind::Zero
+    } else if printable >= 0.9 && bytes.contains(&b'%') && decodes(0) {
+        Kind::Code
+    } else if (1..=255).any(decodes) {
+        Kind::Encrypted
+    } else if table(bytes) {
+        Kind::Table
+    } else if printable >= 0.9 {
+        Kind::String
+    } else if entropy >= max * 0.75 && printable < 0.6 {
+        Kind::Encrypted
+    } else {
+        Kind::Data
+    }
+}
+
+// u16s or u32s whose top byte barely changes while the bottom one does
+fn table(bytes: &[u8]) -> bool {
+    [2, 4].iter().any(|&size| {
+        let lane = |n: usize| -> Vec<u8> { bytes.iter().skip(n).step_by(size).copied().collect() };
+        let (low, high) = (entropy(&lane(0)), entropy(&lane(size - 1)));
+        bytes.len() >= size * 4 && high <= 1.5 && low >= high + 1.0
+    })
+}
+
+// every `window` bytes of `mem`, the last one possibly short
+pub fn scan(mem: &[u8], window: usize) -> Vec<Window> {
+    mem.chunks(window)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let end = i * window + bytes.len();
+            let after = &mem[end..mem.len().min(end + 32)];
+            let entropy = entropy(bytes);
+            let printable = bytes.iter().filter(|&&b| (0x20..0x7f).contains(&b)).count() as f64 / bytes.len() as f64;
+            Window {
+                start: i * window,
+                len: bytes.len(),
+                entropy,
+                printable,
+                kind: classify(bytes, after, entropy, printable),
+            }
+        })
+        .collect()
+}
+
+// one line per window with bars for entropy and printable, then the windows merged into regions
+pub fn report(mem: &[u8], window: usize) -> String {
+    const BAR: usize = 24;
+    let windows = scan(mem, window);
+    let max = (window as f64).log2().min(8.0);
+    let bar = |fraction: f64| {
+        let n = ((fraction * BAR as f64).round() as usize).min(BAR);
+        format!("{}{}", "#".repeat(n), ".".repeat(BAR - n))
+    };
+
+    let mut out = String::new();
+    writeln!(out, "addr     {:24}  {:24}  kind", "entropy", "printable").unwrap();
+    for w in &windows {
+        writeln!(
+            out,
+            "{:#06x}   {} {:.2}  {} {:.2}  {}",
+            w.start,
+            bar(w.entropy / max),
+            w.entropy.abs(),
+            bar(w.printable),
+            w.printable,
+            w.kind
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\nregions:").unwrap();
+    let mut i = 0;
+    while i < windows.len() {
+        let kind = windows[i].kind;
+        let run = windows[i..].iter().take_while(|w| w.kind == kind).count();
+        let last = &windows[i + run - 1];
+        if kind != Kind::Zero {
+            writeln!(out, "  {:#06x}..{:#06x}  {}", windows[i].start, last.start + last.len, kind).unwrap();
+        }
+        i += run;
+    }
+    out
+}
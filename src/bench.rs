@@ -0,0 +1,78 @@
+// `disasm bench [runs]`: the whole program on the winning input, timed phase by phase, for
+// anyone wondering where the time goes without setting up criterion (benches/ is for comparing
+// the ways of running it while working on them).
+//
+// the phases are the calls stage2_main makes one after the other, and each runs from the first
+// time the interpreter reaches its function until the next one's, so the few instructions in
+// between count towards the one before
+use crate::ex::{self, State};
+use crate::stats::STAGES;
+use crate::vm::{Vm, VmError, ENTRY};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Phase {
+    steps: u64,
+    time: Duration,
+}
+
+// one run, with what each phase took
+fn run_once(input: &[u8]) -> Result<Vec<Phase>, VmError> {
+    let mut s: State = State::with_input(input);
+    s.quiet = true;
+    let mut vm = Vm::new(s);
+    vm.pc = ENTRY;
+
+    let mut phases = vec![Phase::default(); STAGES.len()];
+    let mut current = 0;
+    let mut start = (Instant::now(), 0);
+    let mut finish = |current: usize, start: &mut (Instant, u64), steps: u64| {
+        phases[current] = Phase {
+            steps: steps - start.1,
+            time: start.0.elapsed(),
+        };
+        *start = (Instant::now(), steps);
+    };
+    while !vm.halted {
+        if let Some(next) = STAGES[current + 1..].iter().position(|&(_, addr)| addr == vm.pc) {
+            finish(current, &mut start, vm.steps);
+            current += 1 + next;
+        }
+        vm.step()?;
+    }
+    finish(current, &mut start, vm.steps);
+    Ok(phases)
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort();
+    times[times.len() / 2]
+}
+
+pub fn run(runs: usize) -> Result<(), String> {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let input = ex::winning_input(&mut scratch);
+
+    let mut all = Vec::new();
+    for _ in 0..runs.max(1) {
+        all.push(run_once(&input).map_err(|e| e.to_string())?);
+    }
+
+    println!("{} runs of the winning input, interpreted", all.len());
+    println!("{:20} {:>10} {:>12} {:>12}", "phase", "steps", "min", "median");
+    let row = |name: &str, pick: &dyn Fn(&[Phase]) -> Phase| {
+        let times: Vec<Duration> = all.iter().map(|run| pick(run).time).collect();
+        let steps = pick(&all[0]).steps;
+        let min = *times.iter().min().unwrap();
+        println!("{:20} {:>10} {:>12.3?} {:>12.3?}", name, steps, min, median(times));
+    };
+    for (i, &(name, _)) in STAGES.iter().enumerate() {
+        row(name, &|run| run[i]);
+    }
+    row("total", &|run| Phase {
+        steps: run.iter().map(|p| p.steps).sum(),
+        time: run.iter().map(|p| p.time).sum(),
+    });
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// `run --max-steps n` and `--deadline secs`: how far a run gets to go. a program still going when
+// the budget runs out is most likely going round a loop it'll never leave, so rather than just
+// stopping it leaves behind what's needed to see which:
+//
+//     budget.dump    the machine, `post-mortem budget.dump` opens it in the repl
+//     budget.trace   the last 1000 events, one a line
+//
+// and prints the head of the loop and the calls it's in
+use crate::crash;
+use crate::project::Project;
+use crate::trace::Event;
+use crate::vm::{Vm, VmError};
+use crate::word::Word;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+pub const DUMP: &str = "budget.dump";
+pub const TRACE: &str = "budget.trace";
+
+// events written to budget.trace
+const EVENTS: usize = 1000;
+
+// the clock is only looked at this often, it costs more than a step
+const CHECK_EVERY: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub steps: Option<u64>,
+    pub deadline: Option<Duration>,
+}
+
+// how a budgeted run ended short of the program finishing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expired {
+    pub why: String,
+    // the function each frame is in, outermost first, for as far as the run saw them entered
+    pub functions: Vec<usize>,
+}
+
+impl Budget {
+    pub fn is_set(&self) -> bool {
+        self.steps.is_some() || self.deadline.is_some()
+    }
+
+    // interpret until the program finishes or the budget is gone, which is Ok(Some(..))
+    pub fn run<R: Word>(&self, vm: &mut Vm<R>) -> Result<Option<Expired>, VmError> {
+        let start = (Instant::now(), vm.steps);
+        // the function each frame is in, the first is wherever the run started
+        let mut functions = vec![vm.pc];
+        while !vm.halted {
+            let ran = vm.steps - start.1;
+            let why = match (self.steps, self.deadline) {
+                (Some(max), _) if ran >= max => Some(format!("out of steps after {}", ran)),
+                (_, Some(deadline)) if ran.is_multiple_of(CHECK_EVERY) && start.0.elapsed() >= deadline => {
+                    Some(format!("past the {:.1?} deadline after {} steps", deadline, ran))
+                }
+                _ => None,
+            };
+            if let Some(why) = why {
+                return Ok(Some(Expired { why, functions }));
+            }
+
+            let depth = vm.stack.len();
+            vm.step()?;
+            if vm.stack.len() > depth {
+                functions.push(vm.pc);
+            } else if vm.stack.len() < depth && functions.len() > 1 {
+                functions.pop();
+            }
+        }
+        Ok(None)
+    }
+}
+
+// the most taken backward jump's target in `events`: the top of whatever loop the machine is
+// going round. the programs mostly loop by calling back into the function they're in, so a call
+// backwards counts, but a return doesn't
+pub fn loop_head(events: &[Event]) -> Option<usize> {
+    let mut heads: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    let mut back = |from: usize, to: usize, i: usize| {
+        if to <= from {
+            let head = heads.entry(to).or_default();
+            *head = (head.0 + 1, i);
+        }
+    };
+    let mut last = None;
+    for (i, e) in events.iter().enumerate() {
+        match *e {
+            Event::Step { pc } => {
+                if let Some(from) = last {
+                    back(from, pc, i);
+                }
+                last = Some(pc);
+            }
+            Event::Call { from, to } => {
+                back(from, to, i);
+                last = None;
+            }
+            Event::Return { .. } => last = None,
+            _ => {}
+        }
+    }
+    // the most recent of the equally common ones
+    heads.into_iter().max_by_key(|&(_, count)| count).map(|(pc, _)| pc)
+}
+
+// write budget.dump and budget.trace, and say where the machine was
+pub fn report<R: Word>(vm: &Vm<R>, expired: &Expired, project: &Project) -> String {
+    let mut out = String::new();
+    writeln!(out, "budget: {}", expired.why).unwrap();
+
+    let events = vm.s.trace.as_deref().unwrap_or_default();
+    let recent = &events[events.len().saturating_sub(EVENTS)..];
+    let trace: String = recent.iter().map(|e| crash::event(e) + "\n").collect();
+    for (path, text) in [(DUMP, crash::dump(vm, &expired.why)), (TRACE, trace)] {
+        match std::fs::write(path, text) {
+            Ok(()) => writeln!(out, "  wrote {}", path).unwrap(),
+            Err(e) => writeln!(out, "  couldn't write {}: {}", path, e).unwrap(),
+        }
+    }
+
+    match loop_head(recent) {
+        Some(head) => writeln!(out, "  loop head {:#05x} in {}", head, project.function(containing(expired, head))).unwrap(),
+        None => writeln!(out, "  no loop in the last {} events", recent.len()).unwrap(),
+    }
+    writeln!(out, "  call stack, innermost first:").unwrap();
+    let frames = vm.backtrace();
+    let mut lines: Vec<String> = Vec::new();
+    for (i, &at) in frames.iter().enumerate() {
+        let what = if i == 0 { "at" } else { "returns to" };
+        // frames from before the run started, the functions those are in weren't seen
+        let line = match expired.functions.iter().rev().nth(i) {
+            Some(&function) => format!("{} {:#05x} in {}", what, at, project.function(function)),
+            None => format!("{} {:#05x}", what, at),
+        };
+        lines.push(line);
+    }
+    // recursion, one line for each run of the same frame
+    let mut i = 0;
+    while i < lines.len() {
+        let same = lines[i..].iter().take_while(|l| **l == lines[i]).count();
+        match same {
+            1 => writeln!(out, "    #{} {}", i, lines[i]).unwrap(),
+            _ => writeln!(out, "    #{}..#{} {}, {} times", i, i + same - 1, lines[i], same).unwrap(),
+        }
+        i += same;
+    }
+    out.pop();
+    out
+}
+
+// the innermost function the loop head could be in, the nearest entry below it
+fn containing(expired: &Expired, head: usize) -> usize {
+    let below = expired.functions.iter().filter(|&&f| f <= head).max();
+    below.copied().unwrap_or(expired.functions[expired.functions.len() - 1])
+}
@@ -0,0 +1,130 @@
+// ansi color for listings, traces and diff reports. `--color auto` (the default) colors only when
+// stdout is a terminal and NO_COLOR isn't set, `always` and `never` do what they say. `--theme`
+// picks one of THEMES. nothing is colored until init is called, so library users and the
+// snapshot files get plain text
+use crate::inst::Palette;
+use crate::word::Word;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+const RESET: &str = "\x1b[0m";
+
+pub struct Theme {
+    pub name: &'static str,
+    // the parts of an instruction, see Named
+    pub palette: Palette,
+    pub addr: &'static str,
+    // the "// ..." column in listings
+    pub note: &'static str,
+    // registers a traced instruction changed
+    pub changed: &'static str,
+    pub ok: &'static str,
+    pub bad: &'static str,
+}
+
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "dark",
+        palette: Palette {
+            op: "\x1b[1;33m",
+            reg: "\x1b[36m",
+            imm: "\x1b[35m",
+            call: "\x1b[1;32m",
+        },
+        addr: "\x1b[90m",
+        note: "\x1b[32m",
+        changed: "\x1b[1;31m",
+        ok: "\x1b[32m",
+        bad: "\x1b[1;31m",
+    },
+    Theme {
+        name: "light",
+        palette: Palette {
+            op: "\x1b[1;34m",
+            reg: "\x1b[34m",
+            imm: "\x1b[31m",
+            call: "\x1b[1;35m",
+        },
+        addr: "\x1b[37m",
+        note: "\x1b[2;32m",
+        changed: "\x1b[1;4;31m",
+        ok: "\x1b[32m",
+        bad: "\x1b[1;31m",
+    },
+];
+
+static THEME: OnceLock<Option<&'static Theme>> = OnceLock::new();
+
+// pick the theme for the rest of the run from `--color` and `--theme`
+pub fn init(when: &str, theme: &str) -> Result<(), String> {
+    let on = match when {
+        "auto" => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        "always" => true,
+        "never" => false,
+        _ => return Err(format!("bad --color {}, expected auto, always or never", when)),
+    };
+    let theme = THEMES.iter().find(|t| t.name == theme).ok_or_else(|| {
+        let names: Vec<&str> = THEMES.iter().map(|t| t.name).collect();
+        format!("no theme {}, there's {}", theme, names.join(", "))
+    })?;
+    let _ = THEME.set(if on { Some(theme) } else { None });
+    Ok(())
+}
+
+pub fn theme() -> Option<&'static Theme> {
+    THEME.get().copied().flatten()
+}
+
+// `text` in the color `pick` chooses from the theme, or as it is with color off
+pub fn paint(pick: fn(&Theme) -> &'static str, text: &str) -> String {
+    match theme() {
+        Some(theme) => format!("{}{}{}", pick(theme), text, RESET),
+        None => text.to_string(),
+    }
+}
+
+// `text` without its color escapes or OSC 8 links
+pub fn strip(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // colors run up to the m
+            Some('[') => while chars.next().is_some_and(|c| c != 'm') {},
+            // links up to the ESC \ terminator
+            Some(']') => while chars.next().is_some_and(|c| c != '\\') {},
+            _ => {}
+        }
+    }
+    out
+}
+
+// how many columns `text` takes up on the terminal
+pub fn width(text: &str) -> usize {
+    strip(text).chars().count()
+}
+
+// `text` padded with spaces out to `to` columns, like {:to$} would without escapes in it
+pub fn pad(text: &str, to: usize) -> String {
+    format!("{}{}", text, " ".repeat(to.saturating_sub(width(text))))
+}
+
+// registers like State::print_regs, with the ones that differ from `before` highlighted
+pub fn regs<R: Word>(before: &[R], after: &[R]) -> String {
+    let regs: Vec<String> = after
+        .iter()
+        .enumerate()
+        .map(|(n, r)| {
+            let text = format!("{:04x}", r);
+            match before.get(n) == Some(r) {
+                true => text,
+                false => paint(|t| t.changed, &text),
+            }
+        })
+        .collect();
+    regs.join(" ")
+}
@@ -0,0 +1,231 @@
+// `disasm completions bash|zsh|fish`: tab completion for the commands and their flags.
+//
+//     source <(disasm completions bash)
+//     disasm completions fish > ~/.config/fish/completions/disasm.fish
+//
+// main.rs parses the command line by hand, so this table is kept by hand next to it. a command or
+// flag added there wants adding here too. flags ending in '=' take a value
+use crate::color::THEMES;
+use crate::programs::PROGRAMS;
+use std::fmt::Write;
+
+// project(args), for commands that show names
+const PROJECT: &[&str] = &["--program=", "--regs=", "--verbose"];
+// the machine setup `run` and `call` share
+const MACHINE: &[&str] = &["--set=", "--wx=", "--protect", "--assert="];
+// any command
+const GLOBAL: &[&str] = &["--color=", "--theme=", "--config=", "--narrow-widths"];
+
+// the command, and its flags beyond the groups above it asks for
+const COMMANDS: &[(&str, &[&[&str]])] = &[
+    (
+        "run",
+        &[
+            &[
+                "--entry=", "--word=", "--inline=", "--data=", "--data-at=", "--image=", "--quiet",
+                "--expect-digest=", "--generated", "--verify-primes", "--porcelain", "--big-endian",
+                "--reg-count=", "--stub=", "--log-calls", "--trace", "--no-pager", "--jit", "--threaded",
+                "--dashboard", "--max-steps=", "--deadline=", "--record=", "--input-file=", "--dump-region=", "--hex=", "--trace-fn=", "--compress", "--timing", "--top=",
+            ],
+            MACHINE,
+            PROJECT,
+        ],
+    ),
+    (
+        "disasm",
+        &[
+            &[
+                "--range=", "--xor-key=", "--html", "--links", "--out=", "--no-comments", "--no-raw",
+                "--raw-width=", "--comment-at=", "--xrefs", "--no-pager", "--inline=", "--data=", "--data-at=",
+            ],
+            PROJECT,
+        ],
+    ),
+    ("diff", &[]),
+    ("golden", &[]),
+    ("snapshot", &[&["--update"]]),
+    ("fuzz", &[&["--persistent", "--mutate"]]),
+    ("call", &[MACHINE, PROJECT]),
+    ("bench", &[]),
+    ("hot", &[PROJECT]),
+    ("repl", &[PROJECT]),
+    ("script", &[]),
+    ("post-mortem", &[PROJECT]),
+    ("heatmap", &[&["--rows="]]),
+    ("lift", &[&["--entry="], PROJECT]),
+    ("timeline", &[PROJECT]),
+    ("flamegraph", &[PROJECT]),
+    ("programs", &[]),
+    ("analyze", &[&["--after=", "--image=", "--window=", "--program="]]),
+    ("keys", &[&["--image=", "--out=", "--top=", "--program="]]),
+    ("unpack", &[&["--image=", "--input-file=", "--out=", "--program="]]),
+    ("validate", &[&["--program="]]),
+    ("diff-mem-files", &[&["--program=", "--no-pager"]]),
+    ("trace-diff", &[&["--only=", "--no-pager"], PROJECT]),
+    ("cover", &[&["--input-file=", "--max-runs=", "--max-paths=", "--max-steps="]]),
+    ("explore", &[&["--entry=", "--inline=", "--image=", "--max-paths=", "--max-steps="], MACHINE, PROJECT]),
+    (
+        "solve",
+        &[
+            &[
+                "--variant=", "--image=", "--remote=", "--transcript=", "--report=", "--porcelain", "--timeout=",
+                "--retries=", "--backoff=",
+            ],
+            PROJECT,
+        ],
+    ),
+    ("proof", &[&["--variant=", "--image="]]),
+    ("serve", &[&["--timeout=", "--connections=", "--metrics=", "--metrics-every="]]),
+    ("native", &[&["--binary=", "--random=", "--metrics-every="]]),
+    ("verify-check", &[]),
+    ("check", &[&["--next", "--variant=", "--image=", "--metrics-every="]]),
+    ("ranges", &[&["--no-pager"], PROJECT]),
+    ("def-use", &[&["--function=", "--at=", "--no-pager"], PROJECT]),
+    ("dataflow", &[PROJECT]),
+    ("gdb", &[&["--binary="], PROJECT]),
+    ("label", &[PROJECT]),
+    ("comment", &[PROJECT]),
+    ("region", &[PROJECT]),
+    ("project", &[PROJECT]),
+    ("completions", &[]),
+    ("config", &[]),
+];
+
+const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+// flags with a fixed set of values
+fn values() -> Vec<(&'static str, Vec<&'static str>)> {
+    vec![
+        ("--color", vec!["auto", "always", "never"]),
+        ("--theme", THEMES.iter().map(|t| t.name).collect()),
+        ("--program", PROGRAMS.iter().map(|p| p.name).collect()),
+        ("--word", vec!["32", "64"]),
+        ("--wx", vec!["off", "warn", "fault"]),
+        ("--xor-key", vec!["auto"]),
+    ]
+}
+
+// every flag `command` takes, the global ones included. a flag is in the list once
+fn flags(command: &str) -> Vec<&'static str> {
+    let groups = COMMANDS.iter().find(|(name, _)| *name == command).map_or(&[][..], |(_, groups)| groups);
+    let mut flags: Vec<&str> = Vec::new();
+    for &flag in groups.iter().flat_map(|group| group.iter()).chain(GLOBAL) {
+        if !flags.contains(&flag) {
+            flags.push(flag);
+        }
+    }
+    flags
+}
+
+fn names(flags: &[&str]) -> String {
+    let names: Vec<&str> = flags.iter().map(|f| f.trim_end_matches('=')).collect();
+    names.join(" ")
+}
+
+fn commands() -> String {
+    let names: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).collect();
+    names.join(" ")
+}
+
+fn bash() -> String {
+    let mut out = String::new();
+    writeln!(out, "# bash completion for disasm, from `disasm completions bash`").unwrap();
+    writeln!(out, "_disasm() {{").unwrap();
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(out, "    case \"$prev\" in").unwrap();
+    for (flag, values) in values() {
+        writeln!(out, "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;", flag, values.join(" ")).unwrap();
+    }
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "    if [ \"$COMP_CWORD\" -eq 1 ] && [[ \"$cur\" != -* ]]; then").unwrap();
+    writeln!(out, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return", commands()).unwrap();
+    writeln!(out, "    fi").unwrap();
+    writeln!(out, "    if [ \"${{COMP_WORDS[1]}}\" = completions ] && [ \"$COMP_CWORD\" -eq 2 ]; then").unwrap();
+    writeln!(out, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return", SHELLS.join(" ")).unwrap();
+    writeln!(out, "    fi").unwrap();
+    // anything that isn't a flag is likely a path, which -o default takes care of
+    writeln!(out, "    [[ \"$cur\" == -* ]] || return").unwrap();
+    writeln!(out, "    local flags").unwrap();
+    writeln!(out, "    case \"${{COMP_WORDS[1]}}\" in").unwrap();
+    for (name, _) in COMMANDS {
+        writeln!(out, "        {}) flags=\"{}\" ;;", name, names(&flags(name))).unwrap();
+    }
+    // no command is `run`
+    writeln!(out, "        *) flags=\"{}\" ;;", names(&flags("run"))).unwrap();
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "    COMPREPLY=($(compgen -W \"$flags\" -- \"$cur\"))").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "complete -o default -F _disasm disasm").unwrap();
+    out
+}
+
+fn zsh() -> String {
+    let mut out = String::new();
+    writeln!(out, "#compdef disasm").unwrap();
+    writeln!(out, "# zsh completion for disasm, from `disasm completions zsh`").unwrap();
+    writeln!(out, "_disasm() {{").unwrap();
+    writeln!(out, "    case $words[CURRENT-1] in").unwrap();
+    for (flag, values) in values() {
+        writeln!(out, "        {}) compadd -- {}; return ;;", flag, values.join(" ")).unwrap();
+    }
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "    if (( CURRENT == 2 )) && [[ $words[CURRENT] != -* ]]; then").unwrap();
+    writeln!(out, "        compadd -- {}; return", commands()).unwrap();
+    writeln!(out, "    fi").unwrap();
+    writeln!(out, "    if [[ $words[2] == completions ]] && (( CURRENT == 3 )); then").unwrap();
+    writeln!(out, "        compadd -- {}; return", SHELLS.join(" ")).unwrap();
+    writeln!(out, "    fi").unwrap();
+    writeln!(out, "    if [[ $words[CURRENT] != -* ]]; then").unwrap();
+    writeln!(out, "        _files; return").unwrap();
+    writeln!(out, "    fi").unwrap();
+    writeln!(out, "    case $words[2] in").unwrap();
+    for (name, _) in COMMANDS {
+        writeln!(out, "        {}) compadd -- {} ;;", name, names(&flags(name))).unwrap();
+    }
+    writeln!(out, "        *) compadd -- {} ;;", names(&flags("run"))).unwrap();
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "compdef _disasm disasm").unwrap();
+    out
+}
+
+fn fish() -> String {
+    let mut out = String::new();
+    writeln!(out, "# fish completion for disasm, from `disasm completions fish`").unwrap();
+    writeln!(out, "complete -c disasm -n __fish_use_subcommand -f -a \"{}\"", commands()).unwrap();
+    writeln!(out, "complete -c disasm -n \"__fish_seen_subcommand_from completions\" -f -a \"{}\"", SHELLS.join(" ")).unwrap();
+    let values = values();
+    let flag = |out: &mut String, condition: Option<&str>, flag: &str| {
+        let name = flag.trim_start_matches("--").trim_end_matches('=');
+        let arg = match values.iter().find(|(f, _)| f.trim_start_matches("--") == name) {
+            Some((_, values)) => format!(" -x -a \"{}\"", values.join(" ")),
+            None if flag.ends_with('=') => " -r".to_string(),
+            None => String::new(),
+        };
+        let condition = condition.map_or(String::new(), |c| format!(" -n \"{}\"", c));
+        writeln!(out, "complete -c disasm{} -l {}{}", condition, name, arg).unwrap();
+    };
+    for f in GLOBAL {
+        flag(&mut out, None, f);
+    }
+    for (name, _) in COMMANDS {
+        // no command is `run`
+        let condition = match *name {
+            "run" => "__fish_use_subcommand; or __fish_seen_subcommand_from run".to_string(),
+            name => format!("__fish_seen_subcommand_from {}", name),
+        };
+        for f in flags(name).iter().filter(|f| !GLOBAL.contains(f)) {
+            flag(&mut out, Some(&condition), f);
+        }
+    }
+    out
+}
+
+pub fn script(shell: &str) -> Result<String, String> {
+    match shell {
+        "bash" => Ok(bash()),
+        "zsh" => Ok(zsh()),
+        "fish" => Ok(fish()),
+        _ => Err(format!("no completions for {}, there's {}", shell, SHELLS.join(", "))),
+    }
+}
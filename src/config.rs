@@ -0,0 +1,253 @@
+// defaults for the command line flags, so the same long command line doesn't need typing out for
+// every run. they come in layers, each over the one before: weather.toml (or the file --config or
+// WEATHER_CONFIG names), then the environment, then the command line itself
+//
+//     [memory]                  WEATHER_MEM=dump.bin      --image dump.bin
+//     image = "dump.bin"
+//     [trace]                   WEATHER_TRACE=1           --trace
+//     enabled = true
+//     log = false               WEATHER_LOG=0             --quiet, no read/store log
+//
+// and the rest of SETTINGS. main's flag() and switch() look here when the command line has nothing,
+// and `disasm config dump` shows what a command line ends up with
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // `--flag value`
+    Value,
+    // `--flag` when the setting is on
+    Switch,
+    // `--flag` when the setting is off, for the flags that turn something off
+    Inverted,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Setting {
+    // section.key in the file
+    pub key: &'static str,
+    pub env: &'static str,
+    pub flag: &'static str,
+    pub kind: Kind,
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting { key: "memory.image", env: "WEATHER_MEM", flag: "--image", kind: Kind::Value },
+    Setting { key: "memory.program", env: "WEATHER_PROGRAM", flag: "--program", kind: Kind::Value },
+    Setting { key: "memory.word", env: "WEATHER_WORD", flag: "--word", kind: Kind::Value },
+    Setting { key: "memory.big-endian", env: "WEATHER_BIG_ENDIAN", flag: "--big-endian", kind: Kind::Switch },
+    Setting { key: "memory.reg-count", env: "WEATHER_REG_COUNT", flag: "--reg-count", kind: Kind::Value },
+    Setting { key: "memory.wx", env: "WEATHER_WX", flag: "--wx", kind: Kind::Value },
+    Setting { key: "memory.protect", env: "WEATHER_PROTECT", flag: "--protect", kind: Kind::Switch },
+    Setting { key: "annotations.regs", env: "WEATHER_REGS", flag: "--regs", kind: Kind::Value },
+    Setting { key: "annotations.verbose", env: "WEATHER_VERBOSE", flag: "--verbose", kind: Kind::Switch },
+    Setting { key: "trace.enabled", env: "WEATHER_TRACE", flag: "--trace", kind: Kind::Switch },
+    Setting { key: "trace.log", env: "WEATHER_LOG", flag: "--quiet", kind: Kind::Inverted },
+    Setting { key: "trace.log-calls", env: "WEATHER_LOG_CALLS", flag: "--log-calls", kind: Kind::Switch },
+    Setting { key: "trace.max-steps", env: "WEATHER_MAX_STEPS", flag: "--max-steps", kind: Kind::Value },
+    Setting { key: "trace.deadline", env: "WEATHER_DEADLINE", flag: "--deadline", kind: Kind::Value },
+    Setting { key: "solve.variant", env: "WEATHER_VARIANT", flag: "--variant", kind: Kind::Value },
+    Setting { key: "solve.binary", env: "WEATHER_BINARY", flag: "--binary", kind: Kind::Value },
+    Setting { key: "solve.remote", env: "WEATHER_REMOTE", flag: "--remote", kind: Kind::Value },
+    Setting { key: "solve.timeout", env: "WEATHER_TIMEOUT", flag: "--timeout", kind: Kind::Value },
+    Setting { key: "solve.retries", env: "WEATHER_RETRIES", flag: "--retries", kind: Kind::Value },
+    Setting { key: "solve.backoff", env: "WEATHER_BACKOFF", flag: "--backoff", kind: Kind::Value },
+    Setting { key: "display.color", env: "WEATHER_COLOR", flag: "--color", kind: Kind::Value },
+    Setting { key: "display.theme", env: "WEATHER_THEME", flag: "--theme", kind: Kind::Value },
+    Setting { key: "display.pager", env: "WEATHER_PAGER", flag: "--no-pager", kind: Kind::Inverted },
+];
+
+// picked up from the working directory when nothing names another file
+pub const FILE: &str = "weather.toml";
+
+// where a setting's value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File(String),
+    Env(&'static str),
+    Cli,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Val {
+    Text(String),
+    // the setting, so for an inverted one true means the flag isn't given
+    Bool(bool),
+}
+
+// the value of each setting anything gave one, by its flag
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    values: BTreeMap<&'static str, (Val, Source)>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+fn truth(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn by_key(key: &str) -> Result<&'static Setting, String> {
+    SETTINGS.iter().find(|s| s.key == key).ok_or_else(|| {
+        let keys: Vec<&str> = SETTINGS.iter().map(|s| s.key).collect();
+        format!("no setting {}, there's {}", key, keys.join(", "))
+    })
+}
+
+impl Config {
+    // the settings in a toml file, `path` is for the errors and the dump
+    pub fn parse(text: &str, path: &str) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|e| format!("{}: {}", path, e))?;
+        let mut config = Config::default();
+        for (section, keys) in &table {
+            let keys = match keys {
+                Value::Table(keys) => keys,
+                _ => return Err(format!("{}: {} should be a [section]", path, section)),
+            };
+            for (key, value) in keys {
+                let key = format!("{}.{}", section, key);
+                let setting = by_key(&key).map_err(|e| format!("{}: {}", path, e))?;
+                let value = match (setting.kind, value) {
+                    (Kind::Value, Value::String(s)) => Val::Text(s.clone()),
+                    (Kind::Value, Value::Integer(n)) => Val::Text(n.to_string()),
+                    (Kind::Switch | Kind::Inverted, Value::Boolean(b)) => Val::Bool(*b),
+                    (Kind::Value, _) => return Err(format!("{}: {} wants a string or a number", path, key)),
+                    _ => return Err(format!("{}: {} wants true or false", path, key)),
+                };
+                config.values.insert(setting.flag, (value, Source::File(path.to_string())));
+            }
+        }
+        Ok(config)
+    }
+
+    // the file `path` if given, otherwise weather.toml if there is one
+    pub fn file(path: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(FILE).exists() => FILE,
+            None => return Ok(Config::default()),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Config::parse(&text, path)
+    }
+
+    // whatever `var` gives for each setting's variable over what's already here, std::env::var
+    // for the real thing. empty counts as unset
+    pub fn env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        for setting in SETTINGS {
+            let value = match var(setting.env) {
+                Some(value) if !value.is_empty() => value,
+                _ => continue,
+            };
+            let value = match setting.kind {
+                Kind::Value => Val::Text(value),
+                _ => Val::Bool(
+                    truth(&value).ok_or_else(|| format!("{}={} isn't on or off, try 1 or 0", setting.env, value))?,
+                ),
+            };
+            self.values.insert(setting.flag, (value, Source::Env(setting.env)));
+        }
+        Ok(())
+    }
+
+    // the flags on a command line over what's already here
+    pub fn cli(&mut self, args: &[String]) {
+        for setting in SETTINGS {
+            let value = match setting.kind {
+                Kind::Value => {
+                    let at = args.iter().rposition(|a| a == setting.flag);
+                    match at.and_then(|i| args.get(i + 1)) {
+                        Some(value) => Val::Text(value.clone()),
+                        None => continue,
+                    }
+                }
+                Kind::Switch if args.iter().any(|a| a == setting.flag) => Val::Bool(true),
+                Kind::Inverted if args.iter().any(|a| a == setting.flag) => Val::Bool(false),
+                _ => continue,
+            };
+            self.values.insert(setting.flag, (value, Source::Cli));
+        }
+    }
+
+    pub fn value(&self, flag: &str) -> Option<&str> {
+        match self.values.get(flag) {
+            Some((Val::Text(value), _)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn switch(&self, flag: &str) -> bool {
+        let kind = SETTINGS.iter().find(|s| s.flag == flag).map(|s| s.kind);
+        match (kind, self.values.get(flag)) {
+            (Some(Kind::Switch), Some((Val::Bool(on), _))) => *on,
+            (Some(Kind::Inverted), Some((Val::Bool(on), _))) => !*on,
+            _ => false,
+        }
+    }
+
+    // every setting as the toml that would give it, with where each came from
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let mut section = "";
+        for setting in SETTINGS {
+            let (name, key) = setting.key.split_once('.').unwrap();
+            if name != section {
+                if !section.is_empty() {
+                    writeln!(out).unwrap();
+                }
+                writeln!(out, "[{}]", name).unwrap();
+                section = name;
+            }
+            let (value, from) = match self.values.get(setting.flag) {
+                Some(set) => set,
+                None => {
+                    writeln!(out, "# {} unset, {} or {}", key, setting.env, setting.flag).unwrap();
+                    continue;
+                }
+            };
+            let value = match value {
+                Val::Text(text) => Value::String(text.clone()).to_string(),
+                Val::Bool(on) => on.to_string(),
+            };
+            let from = match from {
+                Source::File(path) => path.as_str(),
+                Source::Env(var) => var,
+                Source::Cli => setting.flag,
+            };
+            writeln!(out, "{:40} # from {}", format!("{} = {}", key, value), from).unwrap();
+        }
+        out
+    }
+}
+
+// the file and the environment for the rest of the run. `path` is --config, if given
+pub fn init(path: Option<&str>) -> Result<(), String> {
+    let env = std::env::var("WEATHER_CONFIG").ok().filter(|p| !p.is_empty());
+    let mut config = Config::file(path.or(env.as_deref()))?;
+    config.env(|name| std::env::var(name).ok())?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+// what init read, without the command line
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+// the default for a `--name value` flag
+pub fn value(flag: &str) -> Option<&'static str> {
+    get().value(flag)
+}
+
+// whether a `--name` flag is on by default
+pub fn switch(flag: &str) -> bool {
+    get().switch(flag)
+}
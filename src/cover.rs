@@ -0,0 +1,189 @@
+// `disasm cover`: inputs that take each side of every conditional call, and the sides nothing
+// found an input for. there's no symbolic engine in here to solve for a side, so it's concolic
+// the cheap way: the program runs on real inputs, and each conditional call it passes says how far
+// its register was from going the other way. a side nobody has taken yet is searched for a byte at
+// a time, keeping whatever value gets that distance down, until it's taken or the runs are used
+// up. the sides to go after come from the explorer, which forks its way to conditional calls the
+// seed input never reaches:
+//
+//     cover: 12 conditional calls, 24 of 24 sides taken in 258 runs
+//       0x0b2 taken      TheNewFlagHillsByTheCtfWoods
+//       0x0b2 not taken  \x00heNewFlagHillsByTheCtfWoods
+//
+// and a side still not taken says how close it came. good at checks that look at a byte at a
+// time, hopeless at a hash. the explorer's forced paths can reach calls no input can
+use crate::explore::{conditional, Explorer};
+use crate::inst::DestMode;
+use crate::vm::Vm;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// a conditional call and which way it went
+pub type Side = (usize, bool);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // whole runs of the program, over all of the searching
+    pub runs: usize,
+    pub steps: u64,
+    // for the explorer, finding the conditional calls
+    pub paths: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            runs: 512,
+            steps: 1_000_000,
+            paths: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    // every conditional call the explorer or a run got to
+    pub sites: BTreeSet<usize>,
+    // the first input found to take each side
+    pub taken: BTreeMap<Side, Vec<u8>>,
+    // for the sides not taken, how close any run came and on what
+    pub closest: BTreeMap<Side, (u64, Vec<u8>)>,
+    pub runs: usize,
+}
+
+// how far `cond` is from sending a call with this test `taken`'s way, 0 when it does
+fn distance(mode: DestMode, cond: i32, taken: bool) -> u64 {
+    let cond = cond as i64;
+    let d = match (mode, taken) {
+        (DestMode::Minus, true) => cond + 1,
+        (DestMode::Minus, false) => -cond,
+        (DestMode::Plus, true) => 1 - cond,
+        (DestMode::Plus, false) => cond,
+        (_, true) => cond.abs(),
+        (_, false) => (cond == 0) as i64,
+    };
+    d.max(0) as u64
+}
+
+struct Search<'a, F> {
+    make: &'a F,
+    limits: Limits,
+    coverage: Coverage,
+}
+
+impl<F: Fn(&[u8]) -> Vm> Search<'_, F> {
+    // run the program on `input`, keeping what it covered. the distance each side was at closest
+    fn run(&mut self, input: &[u8]) -> BTreeMap<Side, u64> {
+        self.coverage.runs += 1;
+        let mut vm = (self.make)(input);
+        let mut closest: BTreeMap<Side, u64> = BTreeMap::new();
+        let limit = vm.steps + self.limits.steps;
+        while !vm.halted && vm.steps < limit {
+            if let Some((mode, cond)) = conditional(&vm) {
+                self.coverage.sites.insert(vm.pc);
+                for taken in [false, true] {
+                    let d = distance(mode, cond, taken);
+                    let best = closest.entry((vm.pc, taken)).or_insert(d);
+                    *best = (*best).min(d);
+                }
+            }
+            if vm.step().is_err() {
+                break;
+            }
+        }
+        for (&side, &d) in &closest {
+            if self.coverage.taken.contains_key(&side) {
+                continue;
+            }
+            if d == 0 {
+                self.coverage.taken.insert(side, input.to_vec());
+                self.coverage.closest.remove(&side);
+            } else if self.coverage.closest.get(&side).is_none_or(|(best, _)| d < *best) {
+                self.coverage.closest.insert(side, (d, input.to_vec()));
+            }
+        }
+        closest
+    }
+
+    // a byte at a time towards taking `side`, from the input that's come closest so far
+    fn chase(&mut self, side: Side, seed: &[u8]) {
+        let (mut best, mut input) = match self.coverage.closest.get(&side) {
+            Some((d, input)) => (*d, input.clone()),
+            None => (u64::MAX, seed.to_vec()),
+        };
+        for at in 0..input.len() {
+            let mut guess = input.clone();
+            for value in 0..=255u8 {
+                if self.coverage.runs >= self.limits.runs || self.coverage.taken.contains_key(&side) {
+                    return;
+                }
+                if value == input[at] {
+                    continue;
+                }
+                guess[at] = value;
+                let d = self.run(&guess).get(&side).copied().unwrap_or(u64::MAX);
+                if d < best {
+                    best = d;
+                    input = guess.clone();
+                }
+            }
+        }
+    }
+}
+
+// inputs for each side of every conditional call the program gets to, starting from `seed`.
+// `make` sets up a machine on an input, ready to run
+pub fn cover(make: impl Fn(&[u8]) -> Vm, seed: &[u8], limits: Limits) -> Coverage {
+    let mut search = Search {
+        make: &make,
+        limits,
+        coverage: Coverage::default(),
+    };
+    let mut explorer = Explorer::new(make(seed));
+    explorer.max_paths = limits.paths;
+    explorer.max_steps = limits.steps;
+    for path in &mut explorer {
+        search.coverage.sites.extend(path.branches.iter().map(|b| b.pc));
+    }
+
+    search.run(seed);
+    let sites: Vec<usize> = search.coverage.sites.iter().copied().collect();
+    for pc in sites {
+        for side in [(pc, false), (pc, true)] {
+            if search.coverage.runs >= limits.runs {
+                return search.coverage;
+            }
+            if !search.coverage.taken.contains_key(&side) {
+                search.chase(side, seed);
+            }
+        }
+    }
+    search.coverage
+}
+
+pub fn report(coverage: &Coverage) -> String {
+    let mut out = String::new();
+    let sides = coverage.sites.len() * 2;
+    writeln!(
+        out,
+        "cover: {} conditional calls, {} of {} sides taken in {} runs",
+        coverage.sites.len(),
+        coverage.taken.len(),
+        sides,
+        coverage.runs
+    )
+    .unwrap();
+    for &pc in &coverage.sites {
+        for taken in [true, false] {
+            let way = if taken { "taken" } else { "not taken" };
+            let how = match (coverage.taken.get(&(pc, taken)), coverage.closest.get(&(pc, taken))) {
+                (Some(input), _) => input.escape_ascii().to_string(),
+                (None, Some((d, input))) => format!("uncovered, {} away at best with {}", d, input.escape_ascii()),
+                (None, None) => "uncovered, no run got there".to_string(),
+            };
+            writeln!(out, "  {:#05x} {:9}  {}", pc, way, how).unwrap();
+        }
+    }
+    out.pop();
+    out
+}
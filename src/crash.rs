@@ -0,0 +1,222 @@
+// crash dumps. a run that panics (the parser on garbage, an index the engine didn't expect) leaves
+// the machine behind in a text file, which `post-mortem <dump>` opens in the repl:
+//
+//     panic index out of bounds: the len is 1792 but the index is 1800
+//     pc 0x1f4
+//     steps 1234
+//     word 32
+//     regs 3520 0 75 59 13d4
+//     stack 0xee 0xc8
+//     endian little
+//     len 0x2640
+//     mem 0x1000 546865...
+//     event step 0x1f4
+//
+// memory is 32 bytes a line, all zero lines are left out
+use crate::decode::{Decoded, CODE};
+use crate::ex::{State, MEM_CAP};
+use crate::inst::parse_num;
+use crate::memory::Endian;
+use crate::trace::Event;
+use crate::vm::{Vm, WxMode};
+use crate::word::Word;
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+pub const PATH: &str = "crash.dump";
+
+// trace events kept in a dump
+const EVENTS: usize = 64;
+
+const LINE: usize = 32;
+
+// message of the last panic, the hook can't see the machine so the dump is written after unwinding
+static MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+// keep the panic message around, then panic as usual
+pub fn install() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            None => match info.payload().downcast_ref::<String>() {
+                Some(s) => s.clone(),
+                None => "unknown".to_string(),
+            },
+        };
+        let message = match info.location() {
+            Some(at) => format!("{} at {}", message, at),
+            None => message,
+        };
+        *MESSAGE.lock().unwrap() = Some(message);
+        default(info);
+    }));
+}
+
+// run `f` on the machine, writing a dump before any panic carries on up. tracing is turned on for
+// the run if it isn't already, so the dump has the last few events
+pub fn guard<R: Word, T>(vm: &mut Vm<R>, f: impl FnOnce(&mut Vm<R>) -> T) -> T {
+    let tracing = vm.s.trace.is_some();
+    if !tracing {
+        vm.s.trace = Some(Vec::new());
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(vm)));
+    if let Err(payload) = result {
+        let message = MESSAGE.lock().unwrap().take().unwrap_or_default();
+        match std::fs::write(PATH, dump(vm, &message)) {
+            Ok(()) => eprintln!("crash dump written to {}, open it with post-mortem {}", PATH, PATH),
+            Err(e) => eprintln!("couldn't write crash dump {}: {}", PATH, e),
+        }
+        panic::resume_unwind(payload);
+    }
+    if !tracing {
+        vm.s.trace = None;
+    }
+    result.unwrap()
+}
+
+pub fn dump<R: Word>(vm: &Vm<R>, message: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "panic {}", message).unwrap();
+    writeln!(out, "pc {:#x}", vm.pc).unwrap();
+    writeln!(out, "steps {}", vm.steps).unwrap();
+    writeln!(out, "word {}", R::BYTES * 8).unwrap();
+    let regs: Vec<String> = vm.s.regs.iter().map(|r| format!("{:x}", r)).collect();
+    writeln!(out, "regs {}", regs.join(" ")).unwrap();
+    let stack: Vec<String> = vm.backtrace()[1..].iter().map(|a| format!("{:#x}", a)).collect();
+    writeln!(out, "stack {}", stack.join(" ")).unwrap();
+    let endian = match vm.s.endian {
+        Endian::Little => "little",
+        Endian::Big => "big",
+    };
+    writeln!(out, "endian {}", endian).unwrap();
+    writeln!(out, "len {:#x}", vm.s.mem.len()).unwrap();
+
+    for (i, line) in vm.s.mem.chunks(LINE).enumerate() {
+        if line.iter().any(|&b| b != 0) {
+            let hex: String = line.iter().map(|b| format!("{:02x}", b)).collect();
+            writeln!(out, "mem {:#x} {}", i * LINE, hex).unwrap();
+        }
+    }
+
+    let events = vm.s.trace.as_deref().unwrap_or_default();
+    for e in &events[events.len().saturating_sub(EVENTS)..] {
+        writeln!(out, "event {}", event(e)).unwrap();
+    }
+    out
+}
+
+// an event as the dump has it, after the "event "
+pub fn event(e: &Event) -> String {
+    match *e {
+        Event::Read { index, value } => format!("read {:#x} {:#x}", index, value),
+        Event::Store { index, value } => format!("store {:#x} {:#x}", index, value),
+        Event::Step { pc } => format!("step {:#x}", pc),
+        Event::Call { from, to } => format!("call {:#x} {:#x}", from, to),
+        Event::Return { to: Some(to) } => format!("return {:#x}", to),
+        Event::Return { to: None } => "return none".to_string(),
+    }
+}
+
+// a dump back into a machine, along with its panic message. only 32 bit dumps, since that's what
+// the repl runs
+pub fn load(path: &str) -> Result<(Vm, String), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let s = State {
+        mem_cap: MEM_CAP,
+        trace: Some(Vec::new()),
+        ..State::default()
+    };
+    let mut vm = Vm::new(s);
+    let mut message = String::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        parse_line(&mut vm, &mut message, kind, rest)
+            .map_err(|e| format!("{} line {}: {}", path, i + 1, e))?;
+    }
+
+    // decode cache and W^X tracking like the repl's own machine
+    if vm.s.mem.len() >= CODE.end {
+        vm.code = Some(Decoded::sweep(&vm.s.mem, CODE));
+    }
+    vm.wx.mode = WxMode::Warn;
+    Ok((vm, message))
+}
+
+fn parse_line(vm: &mut Vm, message: &mut String, kind: &str, rest: &str) -> Result<(), String> {
+    let num = |word: &str| parse_num(word).ok_or(format!("bad number {}", word));
+    let words: Vec<&str> = rest.split_whitespace().collect();
+
+    match kind {
+        "panic" => *message = rest.to_string(),
+        "pc" => vm.pc = num(rest)? as usize,
+        "steps" => vm.steps = rest.parse().map_err(|_| format!("bad step count {}", rest))?,
+        "word" if rest == "32" => {}
+        "word" => return Err(format!("can't load a {} bit machine", rest)),
+        "regs" => {
+            vm.s.regs = words
+                .iter()
+                .map(|r| u32::from_str_radix(r, 16).map(|r| r as i32))
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("bad registers {}", rest))?;
+        }
+        // innermost first in the file, the vm keeps the innermost on top
+        "stack" => {
+            vm.stack = words
+                .iter()
+                .rev()
+                .map(|a| num(a).map(|a| a as usize))
+                .collect::<Result<_, _>>()?;
+        }
+        "endian" => {
+            vm.s.endian = match rest {
+                "little" => Endian::Little,
+                "big" => Endian::Big,
+                _ => return Err(format!("bad endian {}", rest)),
+            }
+        }
+        "len" => vm.s.mem.resize(num(rest)? as usize, 0),
+        "mem" => {
+            let at = num(words.first().ok_or("missing address")?)? as usize;
+            let hex = words.get(1).ok_or("missing bytes")?;
+            let bytes: Vec<u8> = (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect::<Option<_>>()
+                .ok_or(format!("bad bytes {}", hex))?;
+            vm.s.write_bytes(at, &bytes);
+        }
+        "event" => {
+            let event = parse_event(&words).ok_or(format!("bad event {}", rest))?;
+            vm.s.trace.get_or_insert_with(Vec::new).push(event);
+        }
+        _ => return Err(format!("unknown entry {}", kind)),
+    }
+    Ok(())
+}
+
+pub fn parse_event(words: &[&str]) -> Option<Event> {
+    let num = |i: usize| words.get(i).and_then(|w| parse_num(w));
+    Some(match *words.first()? {
+        "read" => Event::Read {
+            index: num(1)? as i32,
+            value: num(2)? as i32,
+        },
+        "store" => Event::Store {
+            index: num(1)? as i32,
+            value: num(2)? as i32,
+        },
+        "step" => Event::Step { pc: num(1)? as usize },
+        "call" => Event::Call {
+            from: num(1)? as usize,
+            to: num(2)? as usize,
+        },
+        "return" if words.get(1) == Some(&"none") => Event::Return { to: None },
+        "return" => Event::Return {
+            to: Some(num(1)? as usize),
+        },
+        _ => return None,
+    })
+}
@@ -0,0 +1,262 @@
+// `run --dashboard`: one screen that redraws in place instead of thousands of read/store lines.
+// registers, steps, the function being run and what memory was touched since the last frame:
+//
+//     0x0000  ..r.....ww......  (one character per 32 bytes)
+//
+// x is executed code, r read, w written, * both
+//
+// `--hex 0x1000` adds a hexdump of 256 bytes from there under the map, laid out like hexyl, and
+// stops before the run for changing memory. the prompt reads a line at a time like the pager:
+//
+//     0x1000 41 42 43     write bytes, edited ones stay highlighted until the machine runs again
+//     0x1000 "ABC"        write text
+//     g first_pass        move the hexdump, names are labels and regions
+//     s [n]               run n steps and stop again
+//     c, enter            run to the end
+//     q                   stop here
+use crate::color;
+use crate::inst::parse_num;
+use crate::project::Project;
+use crate::trace::Event;
+use crate::vm::{Vm, VmError};
+use crate::word::Word;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_millis(100);
+// memory bytes per character of the map, and characters per line
+const CELL: usize = 32;
+const COLUMNS: usize = 64;
+// the map covers the challenge's memory, everything it touches is under this
+const MAP_END: usize = 0x2000;
+// bytes in the hexdump, 16 a row
+const HEX_ROWS: usize = 16;
+
+// the hexdump pane and what's been typed into it
+#[derive(Debug, Clone, Default)]
+pub struct Hex {
+    pub at: usize,
+    edited: BTreeSet<usize>,
+    // the last command's complaint, or what it did
+    status: String,
+}
+
+impl Hex {
+    pub fn new(at: usize) -> Self {
+        Hex {
+            at,
+            ..Default::default()
+        }
+    }
+}
+
+// what the prompt wants done next
+enum Next {
+    Steps(u64),
+    Finish,
+    Quit,
+}
+
+pub fn run<R: Word>(vm: &mut Vm<R>, project: &Project, mut hex: Option<Hex>) -> Result<(), VmError> {
+    // events are drained every frame, so they never pile up like a full trace would
+    let quiet = vm.s.quiet;
+    let trace = vm.s.trace.replace(Vec::new());
+    vm.s.quiet = true;
+    print!("\x1b[2J");
+
+    let mut funcs: Vec<usize> = Vec::new();
+    let mut last = Instant::now();
+    let mut result = Ok(());
+    // with the hexdump up, the run waits for the prompt first
+    let mut budget = hex.as_ref().map(|_| 0);
+    while !vm.halted {
+        if budget == Some(0) {
+            draw(vm, project, &mut funcs, hex.as_ref());
+            match prompt(vm, project, hex.as_mut().unwrap()) {
+                None => continue,
+                Some(Next::Steps(n)) => budget = Some(n),
+                Some(Next::Finish) => budget = None,
+                Some(Next::Quit) => break,
+            }
+            // running again is what makes an edit old
+            let hex = hex.as_mut().unwrap();
+            hex.edited.clear();
+            hex.status.clear();
+            continue;
+        }
+        if let Err(e) = vm.step() {
+            result = Err(e);
+            break;
+        }
+        budget = budget.map(|n| n - 1);
+        if vm.steps.is_multiple_of(1024) && last.elapsed() >= FRAME {
+            draw(vm, project, &mut funcs, hex.as_ref());
+            last = Instant::now();
+        }
+    }
+    draw(vm, project, &mut funcs, hex.as_ref());
+
+    vm.s.quiet = quiet;
+    vm.s.trace = trace;
+    result
+}
+
+// one command from the prompt, None for the ones that don't run the machine. an end of input
+// quits
+fn prompt<R: Word>(vm: &mut Vm<R>, project: &Project, hex: &mut Hex) -> Option<Next> {
+    print!("> \x1b[K");
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+        return Some(Next::Quit);
+    }
+    let addr = |word: &str| parse_num(word).map(|n| n as usize).or_else(|| project.lookup(word));
+    let words: Vec<&str> = line.split_whitespace().collect();
+    hex.status = match words.as_slice() {
+        [] | ["c"] => return Some(Next::Finish),
+        ["q"] => return Some(Next::Quit),
+        ["s"] => return Some(Next::Steps(1)),
+        ["s", n] => match parse_num(n) {
+            Some(n) => return Some(Next::Steps(n as u64)),
+            None => format!("bad count {}", n),
+        },
+        ["g", at] => match addr(at) {
+            Some(at) => {
+                hex.at = at;
+                String::new()
+            }
+            None => format!("bad address {}", at),
+        },
+        [at, ..] => match (addr(at), bytes(line.trim()[at.len()..].trim())) {
+            (Some(at), Ok(new)) => edit(vm, hex, at, &new),
+            (None, _) => format!("bad address {}", at),
+            (_, Err(e)) => e,
+        },
+    };
+    None
+}
+
+// `41 42 43` or `"ABC"`
+fn bytes(text: &str) -> Result<Vec<u8>, String> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').ok_or("unterminated string")?;
+        return Ok(quoted.as_bytes().to_vec());
+    }
+    let new: Option<Vec<u8>> = text.split_whitespace().map(|b| u8::from_str_radix(b.trim_start_matches("0x"), 16).ok()).collect();
+    match new {
+        Some(new) if !new.is_empty() => Ok(new),
+        _ => Err(format!("expected hex bytes or a \"string\", got {}", text)),
+    }
+}
+
+// `new` into the live machine at `at`
+fn edit<R: Word>(vm: &mut Vm<R>, hex: &mut Hex, at: usize, new: &[u8]) -> String {
+    let mem = match vm.s.mem.get_mut(at..at + new.len()) {
+        Some(mem) => mem,
+        None => return format!("{:#x} is past the end of memory", at),
+    };
+    mem.copy_from_slice(new);
+    vm.invalidate(at, new.len());
+    hex.edited.extend(at..at + new.len());
+    format!("wrote {} bytes at {:#x}", new.len(), at)
+}
+
+// hexyl's colors, out of the theme: nul, printable, whitespace, other ascii, the rest
+fn byte_color(b: u8) -> fn(&color::Theme) -> &'static str {
+    match b {
+        0 => |t| t.addr,
+        b if b.is_ascii_graphic() => |t| t.palette.reg,
+        b if b.is_ascii_whitespace() => |t| t.note,
+        b if b.is_ascii() => |t| t.palette.imm,
+        _ => |t| t.palette.op,
+    }
+}
+
+// one hexyl row: offset, two groups of 8 bytes, then the same as text. edited bytes are in the
+// theme's changed color, or upper case hex without color
+fn hex_row(mem: &[u8], start: usize, edited: &BTreeSet<usize>) -> String {
+    let mut hex = String::new();
+    let mut text = String::new();
+    for i in 0..16 {
+        if i == 8 {
+            hex.push_str("┊ ");
+            text.push('┊');
+        }
+        let at = start + i;
+        let b = match mem.get(at) {
+            Some(&b) => b,
+            None => {
+                hex.push_str("   ");
+                text.push(' ');
+                continue;
+            }
+        };
+        let pick = if edited.contains(&at) { |t: &color::Theme| t.changed } else { byte_color(b) };
+        let digits = match edited.contains(&at) && color::theme().is_none() {
+            true => format!("{:02X}", b),
+            false => format!("{:02x}", b),
+        };
+        let shown = match b {
+            b if b.is_ascii_graphic() || b == b' ' => b as char,
+            0 => '⋄',
+            b if b.is_ascii_whitespace() => '_',
+            b if b.is_ascii() => '•',
+            _ => '×',
+        };
+        hex.push_str(&color::paint(pick, &digits));
+        hex.push(' ');
+        text.push_str(&color::paint(pick, &shown.to_string()));
+    }
+    format!("│{}│ {}│{}│", color::paint(|t| t.addr, &format!("{:08x}", start)), hex, text)
+}
+
+fn draw<R: Word>(vm: &mut Vm<R>, project: &Project, funcs: &mut Vec<usize>, hex: Option<&Hex>) {
+    let events = vm.s.trace.as_mut().map(std::mem::take).unwrap_or_default();
+    let mut cells = [b'.'; MAP_END / CELL];
+    let mut mark = |index: usize, c: u8| {
+        if let Some(cell) = cells.get_mut(index / CELL) {
+            *cell = match (*cell, c) {
+                (b'.', c) | (b'x', c) => c,
+                (old, c) if old == c => c,
+                _ => b'*',
+            };
+        }
+    };
+    for e in &events {
+        match *e {
+            Event::Step { pc } => mark(pc, b'x'),
+            Event::Read { index, .. } => mark(index as u32 as usize, b'r'),
+            Event::Store { index, .. } => mark(index as u32 as usize, b'w'),
+            Event::Call { to, .. } => funcs.push(to),
+            Event::Return { .. } => {
+                funcs.pop();
+            }
+        }
+    }
+
+    let func = match funcs.last() {
+        Some(&addr) => project.function(addr),
+        None => "stage1".to_string(),
+    };
+    let mut out = String::from("\x1b[H");
+    writeln!(out, "steps     {}", vm.steps).unwrap();
+    writeln!(out, "pc        {:#05x} in {} (depth {})\x1b[K", vm.pc, func, vm.stack.len()).unwrap();
+    writeln!(out, "registers {}\x1b[K", vm.s.print_regs()).unwrap();
+    writeln!(out).unwrap();
+    for (i, line) in cells.chunks(COLUMNS).enumerate() {
+        writeln!(out, "{:#06x}  {}", i * COLUMNS * CELL, String::from_utf8_lossy(line)).unwrap();
+    }
+    if let Some(hex) = hex {
+        let region = project.region_or_known(hex.at).map_or(String::new(), |name| format!(" [{}]", name));
+        writeln!(out, "\nmemory at {:#x}{}\x1b[K", hex.at, region).unwrap();
+        writeln!(out, "┌────────┬─────────────────────────┬─────────────────────────┬────────┬────────┐").unwrap();
+        for row in 0..HEX_ROWS {
+            writeln!(out, "{}", hex_row(&vm.s.mem, hex.at + row * 16, &hex.edited)).unwrap();
+        }
+        writeln!(out, "└────────┴─────────────────────────┴─────────────────────────┴────────┴────────┘").unwrap();
+        writeln!(out, "{}\x1b[K", hex.status).unwrap();
+    }
+    print!("{}", out);
+}
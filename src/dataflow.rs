@@ -0,0 +1,131 @@
+// which regions of memory feed which, as a graphviz graph. every register carries the set of
+// regions its value came from: a load sets it to the region read, a mov copies it, arithmetic
+// adds the source's to the destination's. a store then draws an edge from each of those to the
+// region written. over the winning run that comes out as the user input going into the first
+// pass and the flag, with the prime table mixed into the first pass
+//
+//     disasm dataflow | dot -Tsvg > dataflow.svg
+use crate::ex::REGS;
+use crate::golden;
+use crate::hot::describe;
+use crate::inst::{decrypted_image, DestMode, Instruction, Operation, SrcMode};
+use crate::project::Project;
+use crate::trace::Event;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Default)]
+pub struct Flow {
+    // region -> (reads, writes), in order of first access
+    pub regions: Vec<(String, u64, u64)>,
+    // (from, to) -> stores
+    pub edges: BTreeMap<(String, String), u64>,
+}
+
+impl Flow {
+    fn region(&mut self, name: &str) -> &mut (String, u64, u64) {
+        let i = match self.regions.iter().position(|r| r.0 == name) {
+            Some(i) => i,
+            None => {
+                self.regions.push((name.to_string(), 0, 0));
+                self.regions.len() - 1
+            }
+        };
+        &mut self.regions[i]
+    }
+}
+
+pub fn flow(events: &[Event], project: &Project) -> Flow {
+    let mem = decrypted_image();
+    let mut flow = Flow::default();
+    let mut taint: [BTreeSet<String>; REGS] = Default::default();
+
+    // each instruction's step comes before its memory events
+    let mut i = 0;
+    while i < events.len() {
+        let pc = match events[i] {
+            Event::Step { pc } => pc,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let end = events[i + 1..].iter().position(|e| matches!(e, Event::Step { .. })).map_or(events.len(), |n| i + 1 + n);
+        let accesses = &events[i + 1..end];
+        i = end;
+
+        let (inst, _) = Instruction::parse(&mem[pc..]);
+        if inst.op == Operation::Jmp || inst.op == Operation::Ret {
+            continue;
+        }
+        let mut read = None;
+        let mut stored = None;
+        for e in accesses {
+            match *e {
+                Event::Read { index, .. } => {
+                    let name = describe(index, project);
+                    flow.region(&name).1 += 1;
+                    read.get_or_insert(name);
+                }
+                Event::Store { index, .. } => {
+                    let name = describe(index, project);
+                    flow.region(&name).2 += 1;
+                    stored = Some(name);
+                }
+                _ => {}
+            }
+        }
+
+        let src: BTreeSet<String> = match inst.src_mode {
+            SrcMode::H | SrcMode::HH => read.into_iter().collect(),
+            SrcMode::L => taint[inst.src as usize].clone(),
+            _ => BTreeSet::new(),
+        };
+        match (inst.dest_mode, stored) {
+            (DestMode::NoPlusMinus, _) => {
+                let dest = &mut taint[inst.dest as usize];
+                if inst.op == Operation::Mov {
+                    dest.clear();
+                }
+                dest.extend(src);
+            }
+            (_, Some(to)) => {
+                for from in src.into_iter().filter(|from| *from != to) {
+                    *flow.edges.entry((from, to.clone())).or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    flow
+}
+
+pub fn dot(flow: &Flow) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph dataflow {{").unwrap();
+    writeln!(out, "    rankdir=LR;").unwrap();
+    writeln!(out, "    node [shape=box];").unwrap();
+    for (name, reads, writes) in &flow.regions {
+        writeln!(out, "    {:?} [label=\"{}\\n{} reads, {} writes\"];", name, name, reads, writes).unwrap();
+    }
+    for ((from, to), stores) in &flow.edges {
+        writeln!(out, "    {:?} -> {:?} [label=\"{} stores\"];", from, to, stores).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+// the winning solve, since that runs every part of the program
+pub fn run(path: Option<&str>, project: &Project) {
+    let graph = dot(&flow(&golden::known_good_trace(), project));
+    match path {
+        Some(path) => {
+            std::fs::write(path, graph).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => print!("{}", graph),
+    }
+}
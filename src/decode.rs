@@ -0,0 +1,208 @@
+// decode cache for a code region. writes into the region (patches, or the program rewriting itself
+// like the xor stub does to stage2) only re-decode from the instruction they land in until the
+// decode lines back up with the old instruction boundaries
+use crate::inst::{try_parse, DestMode, Instruction, Operation, SrcMode, Width};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+// the image: stage1, then stage2 up to the end of the xored part
+pub const CODE: Range<usize> = 0..0x6fc;
+
+// longest function decode takes, anything bigger is probably not code
+const MAX_LEN: usize = 0x1000;
+
+// a function's instructions in order, as (address, instruction, address of the next one)
+pub type Body = Vec<(usize, Instruction, usize)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    // None for bytes that don't decode, like stage2 before it's decrypted. those take up one byte
+    pub inst: Option<Instruction>,
+    pub len: usize,
+}
+
+#[derive(Clone)]
+pub struct Decoded {
+    range: Range<usize>,
+    // keyed by address. the slots always tile the whole range
+    slots: BTreeMap<usize, Slot>,
+}
+
+impl Decoded {
+    // linear sweep over `range`
+    pub fn sweep(mem: &[u8], range: Range<usize>) -> Self {
+        let mut decoded = Self {
+            range: range.clone(),
+            slots: BTreeMap::new(),
+        };
+        let mut curr = range.start;
+        while curr < range.end {
+            let slot = decode(mem, curr);
+            decoded.slots.insert(curr, slot);
+            curr += slot.len;
+        }
+        decoded
+    }
+
+    // `len` bytes at `addr` changed. returns the range that was decoded again, empty if the write
+    // missed the region
+    pub fn update(&mut self, mem: &[u8], addr: usize, len: usize) -> Range<usize> {
+        let end = addr.saturating_add(len).min(self.range.end);
+        if addr >= end || end <= self.range.start {
+            return addr..addr;
+        }
+
+        // a decode that failed looked at bytes until one that can't be part of a specifier, so a
+        // broken '%' a little before the write may come out different now
+        let mut back = addr.max(self.range.start);
+        while back > self.range.start && b"-+ #0123456789.hljztL".contains(&mem[back - 1]) {
+            back -= 1;
+        }
+        if back > self.range.start && mem[back - 1] == b'%' {
+            back -= 1;
+        }
+
+        // start from the instruction that lands in, the bytes before it can't change meaning
+        let first = match self.slots.range(..=back.min(addr)).next_back() {
+            Some((&start, _)) => start,
+            None => self.range.start,
+        };
+
+        let mut curr = first;
+        loop {
+            let slot = decode(mem, curr);
+            let next = curr + slot.len;
+
+            // anything that started inside the new instruction is gone
+            let stale: Vec<usize> = self.slots.range(curr..next).map(|(&a, _)| a).collect();
+            for a in stale {
+                self.slots.remove(&a);
+            }
+            self.slots.insert(curr, slot);
+            curr = next;
+
+            // past the write and back on an old boundary, everything after decodes the same
+            if curr >= self.range.end || (curr >= end && self.slots.contains_key(&curr)) {
+                break;
+            }
+        }
+        first..curr
+    }
+
+    pub fn at(&self, addr: usize) -> Option<&Slot> {
+        self.slots.get(&addr)
+    }
+
+    // slots from `addr` on, starting with the one containing it
+    pub fn from(&self, addr: usize) -> impl Iterator<Item = (usize, &Slot)> {
+        let start = match self.slots.range(..=addr).next_back() {
+            Some((&start, _)) => start,
+            None => self.range.start,
+        };
+        self.slots.range(start..).map(|(&a, slot)| (a, slot))
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.range.contains(&addr)
+    }
+}
+
+fn decode(mem: &[u8], addr: usize) -> Slot {
+    match try_parse(&mem[addr..]) {
+        Some((inst, len)) => Slot {
+            inst: Some(inst),
+            len,
+        },
+        None => Slot { inst: None, len: 1 },
+    }
+}
+
+// instructions by offset, filled in as they're first executed so hot loops don't parse the same
+// specifiers over and over. unlike Decoded it covers all of memory and needs no sweep. only bytes
+// that have run can be in it, so a store into code that has already been executed (what W^X
+// warns about) is the only kind that invalidates anything. `T` is whatever the instruction was
+// decoded into, the threaded interpreter keeps its handlers in here
+#[derive(Debug, Clone)]
+pub struct Cache<T = Instruction> {
+    // (decoded, length) at each offset that has run
+    slots: Vec<Option<(T, usize)>>,
+    // longest cached instruction, how far back a write can reach into one
+    longest: usize,
+}
+
+impl<T: Copy> Cache<T> {
+    pub fn get(&self, addr: usize) -> Option<(T, usize)> {
+        self.slots.get(addr).copied().flatten()
+    }
+
+    pub fn insert(&mut self, addr: usize, inst: T, len: usize) {
+        if addr >= self.slots.len() {
+            self.slots.resize(addr + 1, None);
+        }
+        self.slots[addr] = Some((inst, len));
+        self.longest = self.longest.max(len);
+    }
+
+    // `len` bytes at `addr` changed, forget every instruction that overlaps them
+    pub fn invalidate(&mut self, addr: usize, len: usize) {
+        let start = addr.saturating_sub(self.longest.saturating_sub(1));
+        let end = addr.saturating_add(len).min(self.slots.len());
+        for at in start..end {
+            if matches!(self.slots[at], Some((_, n)) if at + n > addr) {
+                self.slots[at] = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.longest = 0;
+    }
+}
+
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            longest: 0,
+        }
+    }
+}
+
+// the instructions of the function at `addr` up to its ret, if it's all things the jit and the
+// analyses can work with
+pub fn function(addr: usize, mem: &[u8], regs: usize) -> Option<Body> {
+    let reg_ok = |n: u32| (n as usize) < regs;
+    let mut body = Vec::new();
+    let mut pc = addr;
+    loop {
+        let (inst, len) = try_parse(mem.get(pc..)?)?;
+        let next = pc + len;
+        let ok = match inst.op {
+            Operation::Ret => true,
+            Operation::Jmp => reg_ok(inst.src) && (inst.dest as usize) < mem.len(),
+            _ => {
+                let src = match inst.src_mode {
+                    SrcMode::H | SrcMode::L => reg_ok(inst.src),
+                    SrcMode::HH | SrcMode::LL => true,
+                    SrcMode::None => false,
+                };
+                let dest = match inst.dest_mode {
+                    DestMode::NoPlusMinus | DestMode::Plus => reg_ok(inst.dest),
+                    DestMode::Minus => true,
+                    DestMode::ZeroPad => false,
+                };
+                src && dest && inst.width != Width::W64
+            }
+        };
+        if !ok || body.len() >= MAX_LEN {
+            return None;
+        }
+        body.push((pc, inst, next));
+        if inst.op == Operation::Ret {
+            return Some(body);
+        }
+        pc = next;
+    }
+}
@@ -0,0 +1,274 @@
+// def-use chains for one register or memory cell: every instruction that gives it a value, every
+// one that reads it, and which of the first reach each of the second. it answers things like
+// where r4 gets its value before the store into the first pass (`def-use r4 --at 0x280`).
+//
+// over the winning run it's what actually happened, counted. statically it's one function from
+// its entry, where a call defines whatever the callee (or anything it calls) writes and uses
+// whatever the callee reads before writing, and stores through a register count when ranges.rs
+// says they can reach the cell
+use crate::ex::REGS;
+use crate::golden;
+use crate::inst::{decrypted_image, DestMode, Instruction, Operation, SrcMode, Width};
+use crate::decode::Body;
+use crate::project::Project;
+use crate::ranges::{self, writes};
+use crate::trace::Event;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Reg(u32),
+    // a byte of memory, any access covering it counts
+    Mem(usize),
+}
+
+impl Cell {
+    // "r4", or an address or project name for memory
+    pub fn parse(text: &str, project: &Project) -> Result<Cell, String> {
+        if let Some(n) = text.strip_prefix('r').and_then(|n| n.parse::<u32>().ok()) {
+            return match (n as usize) < REGS {
+                true => Ok(Cell::Reg(n)),
+                false => Err(format!("there are only {} registers", REGS)),
+            };
+        }
+        match crate::inst::parse_num(text).or_else(|| project.lookup(text).map(|a| a as u32)) {
+            Some(addr) => Ok(Cell::Mem(addr as usize)),
+            None => Err(format!("{} isn't a register, address or name", text)),
+        }
+    }
+
+    fn covered(&self, addr: usize, bytes: usize) -> bool {
+        matches!(*self, Cell::Mem(cell) if (addr..addr + bytes).contains(&cell))
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Reg(n) => write!(f, "r{}", n),
+            Cell::Mem(addr) => write!(f, "[{:#x}]", addr),
+        }
+    }
+}
+
+// registers `inst` reads and writes. a call only reads its condition, the callee is counted
+// separately
+pub fn regs(inst: &Instruction) -> (Vec<u32>, Vec<u32>) {
+    let (mut reads, mut writes) = (Vec::new(), Vec::new());
+    match inst.op {
+        Operation::Ret => {}
+        Operation::Jmp if inst.dest_mode != DestMode::NoPlusMinus => reads.push(inst.src),
+        Operation::Jmp => {}
+        op => {
+            if let SrcMode::L | SrcMode::H = inst.src_mode {
+                reads.push(inst.src);
+            }
+            match inst.dest_mode {
+                DestMode::Plus => reads.push(inst.dest),
+                DestMode::NoPlusMinus => {
+                    if op != Operation::Mov {
+                        reads.push(inst.dest);
+                    }
+                    writes.push(inst.dest);
+                }
+                _ => {}
+            }
+        }
+    }
+    (reads, writes)
+}
+
+// where a use got its value: an instruction, or from before the run or function started
+type Def = Option<usize>;
+
+#[derive(Debug, Default)]
+pub struct Chains {
+    // how many times each def site ran
+    pub defs: BTreeMap<usize, u64>,
+    // use site -> def that reached it -> times
+    pub uses: BTreeMap<usize, BTreeMap<Def, u64>>,
+    // sites that only might touch the cell, from the ranges
+    pub may: Vec<usize>,
+}
+
+impl Chains {
+    fn define(&mut self, pc: usize, last: &mut Def) {
+        *self.defs.entry(pc).or_default() += 1;
+        *last = Some(pc);
+    }
+
+    fn reach(&mut self, pc: usize, last: Def) {
+        *self.uses.entry(pc).or_default().entry(last).or_default() += 1;
+    }
+}
+
+// the chains for `cell` over the known good run
+pub fn dynamic(cell: Cell) -> Chains {
+    let mem = decrypted_image();
+    let mut chains = Chains::default();
+    let mut last = None;
+    let mut pc = 0;
+    for e in golden::known_good_trace() {
+        match e {
+            Event::Step { pc: at } => {
+                pc = at;
+                if let Cell::Reg(n) = cell {
+                    let (inst, _) = Instruction::parse(&mem[pc..]);
+                    let (reads, writes) = regs(&inst);
+                    if reads.contains(&n) {
+                        chains.reach(pc, last);
+                    }
+                    if writes.contains(&n) {
+                        chains.define(pc, &mut last);
+                    }
+                }
+            }
+            Event::Read { index, .. } if cell.covered(index as u32 as usize, 4) => chains.reach(pc, last),
+            Event::Store { index, .. } if cell.covered(index as u32 as usize, 4) => chains.define(pc, &mut last),
+            _ => {}
+        }
+    }
+    chains
+}
+
+// registers each function reads before writing them, its own or through what it calls
+fn reads(bodies: &BTreeMap<usize, Body>, writes: &BTreeMap<usize, [bool; REGS]>) -> BTreeMap<usize, [bool; REGS]> {
+    let mut reads: BTreeMap<usize, [bool; REGS]> = BTreeMap::new();
+    loop {
+        let mut changed = false;
+        for (&addr, body) in bodies {
+            let mut read = [false; REGS];
+            let mut written = [false; REGS];
+            for (_, inst, _) in body {
+                let (mut r, mut w) = regs(inst);
+                if inst.op == Operation::Jmp {
+                    let target = inst.dest as usize;
+                    let callee = reads.get(&target).copied().unwrap_or_default();
+                    r.extend((0..REGS as u32).filter(|&n| callee[n as usize]));
+                    // a conditional call might not happen, so only a sure one writes
+                    if inst.dest_mode == DestMode::NoPlusMinus {
+                        let callee = writes.get(&target).copied().unwrap_or([true; REGS]);
+                        w.extend((0..REGS as u32).filter(|&n| callee[n as usize]));
+                    }
+                }
+                for n in r {
+                    read[n as usize] |= !written[n as usize];
+                }
+                for n in w {
+                    written[n as usize] = true;
+                }
+            }
+            let old = reads.entry(addr).or_default();
+            let new: [bool; REGS] = std::array::from_fn(|n| old[n] || read[n]);
+            changed |= new != *old;
+            *old = new;
+        }
+        if !changed {
+            return reads;
+        }
+    }
+}
+
+// the chains for `cell` in the function at `entry`, from its entry to its ret. the ranges come
+// from `main` down, where they're tighter, unless the function can't be reached from there
+pub fn function(mem: &[u8], main: usize, entry: usize, cell: Cell) -> Result<Chains, String> {
+    let mut analysis = ranges::analyze(mem, main);
+    if !analysis.bodies.contains_key(&entry) {
+        analysis = ranges::analyze(mem, entry);
+    }
+    let body = analysis.bodies.get(&entry).ok_or(format!("nothing decodes at {:#x}", entry))?;
+    let writes = writes(&analysis.bodies);
+    let reads = reads(&analysis.bodies, &writes);
+    let targets = ranges::targets(&analysis);
+
+    let mut chains = Chains::default();
+    let mut last = None;
+    for (pc, inst, _) in body {
+        let pc = *pc;
+        match cell {
+            Cell::Reg(n) => {
+                let (mut uses, mut defs) = regs(inst);
+                if inst.op == Operation::Jmp {
+                    let target = inst.dest as usize;
+                    if reads.get(&target).is_some_and(|r| r[n as usize]) {
+                        uses.push(n);
+                    }
+                    if writes.get(&target).is_none_or(|w| w[n as usize]) {
+                        defs.push(n);
+                    }
+                }
+                if uses.contains(&n) {
+                    chains.reach(pc, last);
+                }
+                if defs.contains(&n) {
+                    chains.define(pc, &mut last);
+                }
+            }
+            Cell::Mem(_) if inst.op == Operation::Jmp || inst.op == Operation::Ret => {}
+            Cell::Mem(addr) => {
+                let bytes = match inst.width {
+                    Width::W32 | Width::W64 => 4,
+                    width => width.bytes(),
+                };
+                if inst.src_mode == SrcMode::HH && cell.covered(inst.src as usize, bytes) {
+                    chains.reach(pc, last);
+                }
+                if inst.dest_mode == DestMode::Minus && cell.covered(inst.dest as usize, bytes) {
+                    if inst.op != Operation::Mov {
+                        chains.reach(pc, last);
+                    }
+                    chains.define(pc, &mut last);
+                }
+                // a register might point anywhere in its range, or anywhere at all without one
+                let may = |reg: u32| targets.get(&(pc, reg)).is_none_or(|range| range.contains(&addr));
+                if (inst.src_mode == SrcMode::H && may(inst.src)) || (inst.dest_mode == DestMode::Plus && may(inst.dest)) {
+                    chains.may.push(pc);
+                }
+            }
+        }
+    }
+    Ok(chains)
+}
+
+// defs, then uses with what reached them
+pub fn report(cell: Cell, chains: &Chains, mem: &[u8], project: &Project) -> String {
+    let mut out = String::new();
+    let line = |pc: usize| {
+        let (inst, _) = Instruction::parse(&mem[pc..]);
+        format!("{:#05x}:  {}", pc, project.named(&inst, pc))
+    };
+    let def = |def: Def| match def {
+        Some(pc) => format!("{:#05x}", pc),
+        None => "entry".to_string(),
+    };
+
+    writeln!(out, "defs of {}:", cell).unwrap();
+    for (&pc, count) in &chains.defs {
+        writeln!(out, "  {:50} {:>8}", line(pc), count).unwrap();
+    }
+    writeln!(out, "uses of {}, and the defs that reach them:", cell).unwrap();
+    for (&pc, reached) in &chains.uses {
+        let total: u64 = reached.values().sum();
+        writeln!(out, "  {:50} {:>8}", line(pc), total).unwrap();
+        let from: Vec<String> = reached.iter().map(|(&d, n)| format!("{} x{}", def(d), n)).collect();
+        writeln!(out, "      <- {}", from.join(", ")).unwrap();
+    }
+    if !chains.may.is_empty() {
+        writeln!(out, "might also touch it, through a register:").unwrap();
+        for &pc in &chains.may {
+            writeln!(out, "  {}", line(pc)).unwrap();
+        }
+    }
+    out
+}
+
+// only the uses at `pc`
+pub fn at(chains: Chains, pc: usize) -> Chains {
+    let reached: HashMap<Def, u64> = chains.uses.get(&pc).into_iter().flatten().map(|(&d, &n)| (d, n)).collect();
+    Chains {
+        defs: chains.defs.into_iter().filter(|(d, _)| reached.contains_key(&Some(*d))).collect(),
+        uses: chains.uses.into_iter().filter(|(u, _)| *u == pc).collect(),
+        may: Vec::new(),
+    }
+}
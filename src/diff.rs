@@ -0,0 +1,98 @@
+// differential harness: run the hand transpiled functions from ex.rs and the generic interpreter
+// side by side, and compare the whole machine after every stage. any difference is a
+// transcription mistake in ex.rs (or a bug in the interpreter)
+use crate::color;
+use crate::ex::{self, log_index, State};
+use crate::stats::Clock;
+use crate::vm::Vm;
+
+pub fn run() {
+    // get the winning input from a scratch state, so both sides start from pristine buffers
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let input = ex::winning_input(&mut scratch);
+
+    if !agree(&input) {
+        std::process::exit(1);
+    }
+    println!("{}", color::paint(|t| t.ok, "transpiled and interpreted stages agree"));
+}
+
+// ex.rs's own stage2_main on `input`, with the interpreter brought up to the same place after each
+// of its stages. false from the first stage they differ after on. stage2 only decrypts with the
+// right first byte, the rest can be anything
+pub fn agree(input: &[u8]) -> bool {
+    let mut s = State::with_input(input);
+    s.quiet = true;
+
+    // let stage1 decrypt stage2 exactly like the program does, stopping before the jump into it
+    let mut vm = Vm::boot(s).unwrap();
+    assert!(vm.s.regs[0] == 0, "stage2 did not decrypt to a '%'");
+
+    // the transpiled side starts with the exact same machine
+    let mut s = vm.s.clone();
+
+    let mut same = true;
+    ex::stage2_main(&mut s, &mut Clock::default(), &mut Vec::new(), &mut |s, (name, stop)| {
+        if !same {
+            return;
+        }
+        vm.run_until(stop).unwrap();
+        same = compare(s, &vm.s, ["transpiled", "interpreter"]);
+        match same {
+            true => println!("{}", color::paint(|t| t.ok, &format!("ok: {}", name))),
+            false => println!("{}", color::paint(|t| t.bad, &format!("MISMATCH after {}", name))),
+        }
+    });
+    same
+}
+
+// print every difference between the two machines, returns true if they are identical
+pub fn compare(a: &State, b: &State, names: [&str; 2]) -> bool {
+    let mut same = true;
+    let width = names[0].len().max(names[1].len());
+
+    for n in 0..a.regs.len().max(b.regs.len()) {
+        let (x, y) = (a.regs.get(n), b.regs.get(n));
+        if x != y {
+            let show = |r: Option<&i32>| r.map_or("-".to_string(), |r| format!("{:x}", r));
+            let reg = color::paint(|t| t.bad, &format!("r{}", n));
+            println!("  {}: {} {} {} {}", reg, names[0], show(x), names[1], show(y));
+            same = false;
+        }
+    }
+
+    // memory grows on access, so one side can be longer. past the end reads as zero
+    let len = a.mem.len().max(b.mem.len());
+    let byte = |s: &State, i: usize| s.mem.get(i).copied().unwrap_or(0);
+    let bytes = |s: &State, start: usize, end: usize| -> Vec<u8> {
+        (start..end).map(|i| byte(s, i)).collect()
+    };
+
+    let mut i = 0;
+    while i < len {
+        if byte(a, i) == byte(b, i) {
+            i += 1;
+            continue;
+        }
+
+        // group differing bytes into ranges so the output stays readable
+        let start = i;
+        while i < len && byte(a, i) != byte(b, i) {
+            i += 1;
+        }
+        println!(
+            "  mem {} {}\n    {:w$} {:x?}\n    {:w$} {:x?}",
+            color::paint(|t| t.bad, &format!("{:#x}..{:#x}", start, i)),
+            log_index(start as i32),
+            names[0],
+            bytes(a, start, i),
+            names[1],
+            bytes(b, start, i),
+            w = width
+        );
+        same = false;
+    }
+
+    same
+}
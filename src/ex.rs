@@ -1,155 +1,190 @@
-// all state that the vm keeps
-#[derive(Default, Debug, Clone)]
-struct State {
-    // registers
-    r0: i32,
-    r1: i32,
-    r2: i32,
-    r3: i32,
-    r4: i32,
-    // memory
-    mem: Vec<u8>,
+use crate::primes;
+use crate::stats::{Clock, Stats};
+use crate::trace::Event;
+use crate::transform::{self, Transform};
+use crate::variant;
+use crate::word::Word;
+use indicatif::{ProgressBar, ProgressStyle};
+
+// the state moved out to state.rs so the engine can build without std, it's still ex::State to
+// everything else
+pub use crate::state::{log_index, State, CANARY, EXTENT, MEM_CAP, REGIONS, REGS};
+
+// the look of every progress bar in the tool
+pub fn progress_style() -> ProgressStyle {
+    ProgressStyle::with_template("{msg:>16} [{bar:40}] {pos}/{len} {eta}")
+        .unwrap()
+        .progress_chars("=> ")
 }
 
-impl State {
-    // memory accesses were always 4 bytes at a time, alignment didn't matter
-    fn store(&mut self, dest: i32, src: i32) {
-        // log the mem write
-        println!("storing --> {:x} to index {:x} {}", src, dest, log_index(dest));
-
-        // get index as usize
-        let i = dest as u32 as usize;
-        // copy over the little endian bytes
-        self.mem[i..i + 4].copy_from_slice(&src.to_le_bytes());
+// `s.quiet` drops the read/store log for progress bars
+pub fn run(mut s: State, input: Option<&[u8]>) -> Finished {
+    s.progress = s.quiet;
+    // the summary's memory footprint is counted from the trace
+    if s.trace.is_none() {
+        s.trace = Some(Vec::new());
     }
+    let finished = execute(s, input);
+
+    let label = if input.is_some() { "Input" } else { "Winning input" };
+    println!("{}: {}", label, finished.input.escape_ascii());
+    println!("Flag: {}", String::from_utf8_lossy(&finished.flag));
+    println!("Digest: {:016x}", finished.digest);
+    println!("{}", finished.stats);
+    finished
+}
 
-    // read 4 bytes from memory
-    fn read(&mut self, src: i32) -> i32 {
-        // log the mem read
-        println!("reading <-- index {:x} {}", src, log_index(src));
-
-        // index as usize
-        let i = src as u32 as usize;
-        // copy memory bytes into temp buf
-        let mut buf = [0; 4];
-        buf.copy_from_slice(&self.mem[i..i + 4]);
-        // return value as little endian
-        i32::from_le_bytes(buf)
+// what `run` comes to
+pub struct Finished {
+    pub input: Vec<u8>,
+    // the whole flag buffer
+    pub flag: Vec<u8>,
+    pub digest: u64,
+    pub stats: Stats,
+    // what the program did once it had the input, when `s` came with a trace
+    pub trace: Vec<Event>,
+    // memory as the run left it
+    pub mem: Vec<u8>,
+}
+
+// `run` without printing anything itself, `s` only says how loud the machine is and whether to
+// record. `input` goes in place of the winning one, NUL and all. the summary only has the memory
+// footprint when there's a trace
+pub fn execute(mut s: State, input: Option<&[u8]>) -> Finished {
+    // the following block was added after I understood the program.
+    // it reverses the flag arithmetic and final check
+    let winning_bytes = winning_input(&mut s);
+    let input = input.map_or(winning_bytes, <[u8]>::to_vec);
+
+    // put the right stuff into user input
+    s.write_bytes(0x1000, &input);
+    // working out the input isn't part of the run
+    if let Some(trace) = &mut s.trace {
+        trace.clear();
     }
 
-    // debugging
-    #[allow(dead_code)]
-    fn print_regs(&self) -> String {
-        format!(
-            "{:04x} {:04x} {:04x} {:04x} {:04x}",
-            self.r0, self.r1, self.r2, self.r3, self.r4
-        )
+    // run the original virtual machine code
+    let mut stats = Stats::default();
+    let mut clock = Clock::default();
+    stage2_main(&mut s, &mut clock, &mut stats.warnings, &mut |_, _| {});
+    stats.stages = clock.stages();
+
+    // extract the flag out of the machine memory
+    let flag = s.mem[0x1800..0x1820].to_vec();
+    s.check_canaries();
+    for at in s.clobbered_canaries() {
+        stats.warnings.push(format!("canary at {:#x} clobbered", at));
+    }
+    // there are no steps in it, so that's just the memory
+    if let Some(events) = &s.trace {
+        stats.count(events, &s.mem);
     }
-}
 
-// when memory is logged, I wanted to annotate certain known ranges
-fn log_index(index: i32) -> &'static str {
-    match index {
-        0x1000..=0x1100 => "[user input]",    // user input "city name"
-        0x1190..=0x1290 => "[first pass]",    // input lands here after XOR and add operations
-        0x1300..=0x1400 => "[RNG numbers]",   // this range was actually prime numbers but whatever
-        0x1800..=0x1900 => "[flag output]",   // points to `flag` global addr in binary, see ghidra
-        _ => "",
+    Finished {
+        input: input.split(|&b| b == 0).next().unwrap_or_default().to_vec(),
+        flag,
+        digest: s.digest(),
+        stats,
+        trace: s.trace.take().unwrap_or_default(),
+        mem: s.mem.to_vec(),
     }
 }
 
-pub fn run() {
-    // default inits everything to 0 which is fine, I manually checked for any register reads that
-    // could have been uninitialized
-    let mut s = State::default();
-
-    // copy program bytes
-    s.mem = include_bytes!("../mem").to_vec();
-    // extend out to include any reads/writes
-    s.mem.extend(&[0; 8000]);
+// quiet machine with the winning input typed in. stage2 only decrypts with the right first byte,
+// so this is the usual starting point for poking at it
+pub fn winning_state<R: Word>() -> State<R> {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let mut s = State::with_input(&winning_input(&mut scratch));
+    s.quiet = true;
+    s
+}
 
-    // the following block was added after I understood the program.
-    // it reverses the flag arithmetic and final check
-    {
-        // make the goodboy buffer
-        buffer_create(&mut s);
-        let goodboy = s.mem[0x1194..0x1194+0x1c].to_vec();
+// reverses the flag arithmetic and final check. this runs parts of the program to get at the
+// goodboy and rng buffers, so it leaves them filled in on `s`
+pub fn winning_input(s: &mut State) -> Vec<u8> {
+    // make the goodboy buffer
+    buffer_create(s);
+    let goodboy = s.mem[0x1194..0x1194 + variant::LEN].to_vec();
+    if !s.quiet {
         println!("goodboy {:x?}", goodboy);
+    }
 
-        // make the rng numbers buffer
-        generate_buffer(&mut s);
-        let numbers = s.mem[0x1388..0x1388+38*2].to_vec();
+    // make the rng numbers buffer
+    generate_buffer(s);
+    let numbers = s.mem[primes::START..primes::START + primes::COUNT * 2].to_vec();
+    if !s.quiet {
         println!("numbers {:x?}", numbers);
-
-        // get some collatz numbers
-        let mut collatz_nums = Vec::new();
-        for c in 0..0x1c {
-            s.r0 = c + 1;
-            collatz(&mut s);
-            collatz_nums.push(s.r0 as u8);
-        }
-        println!("collatz {:x?}", collatz_nums);
-
-        // generate the winning input
-        let mut winning_bytes = Vec::new();
-        for ii in 0..0x1c {
-            let a = goodboy[ii].wrapping_sub(collatz_nums[ii]) ^ numbers[ii*2];
-            winning_bytes.push(a);
-        }
-
-        // put the right stuff into user input
-        s.mem[0x1000..0x1000+winning_bytes.len()].copy_from_slice(&winning_bytes);
-        let input = String::from_utf8(s.mem[0x1000..0x101c].to_vec()).unwrap();
-        println!("Winning input: {}", input);
     }
 
-    // run the original virtual machine code
-    stage2_main(&mut s);
+    // generate the winning input, by running what process_input_byte does backwards
+    let transform = Transform {
+        steps: transform::weather(),
+        primes: numbers.iter().step_by(2).copied().collect(),
+    };
+    if !s.quiet {
+        println!("transform {}", transform);
+    }
+    transform.inverse(&goodboy).unwrap()
+}
 
-    // extract the flag out of the machine memory
-    let s = String::from_utf8(s.mem[0x1800..0x1820].to_vec()).unwrap();
-    println!("Flag: {}", s);
+// stage2_main with nobody looking
+pub fn stage2(s: &mut State) {
+    stage2_main(s, &mut Clock::default(), &mut Vec::new(), &mut |_, _| {});
 }
 
-// mostly original stage2, with added prints
-fn stage2_main(s: &mut State) {
+// the stages of stage2_main by the function that does each, with the address the program's own
+// stage2_main picks up at once it's done
+pub const STAGE2: [(&str, usize); 4] = [
+    ("generate_buffer", 0xe2),
+    ("read_input_byte", 0xee),
+    ("buffer_check", 0xf4),
+    ("stage2_28d", 0xfc),
+];
+
+// mostly original stage2, timed for the summary. `done` gets the machine after each stage, the
+// flag output stage included even when the check fails and it's skipped
+pub fn stage2_main(s: &mut State, clock: &mut Clock, warnings: &mut Vec<String>, done: &mut dyn FnMut(&State, (&'static str, usize))) {
+    clock.enter(0x151);
     generate_buffer(s);
-    println!("done generating buffer");
+    done(s, STAGE2[0]);
 
-    s.r0 = 0x0;
+    s.regs[0] = 0x0;
+    clock.enter(0x1f4);
     read_input_byte(s);
-    println!("done reading input into first pass");
+    done(s, STAGE2[1]);
 
+    clock.enter(0x4ee);
     buffer_check(s);
-    println!("done with 4ee");
+    done(s, STAGE2[2]);
 
     // r0 is 0 if buffer check is correct
-    if s.r0 == 0 {
+    clock.enter(0x28d);
+    if s.regs[0] == 0 {
         // print flag
         stage2_28d(s);
-        println!("done with 28d");
     } else {
-        // I also added this else arm, for debugging. curiously, this was always the branch taken
-        // even when I got the input right
-        println!("cheating");
-        stage2_28d(s);
-        println!("done cheating with 28d");
+        // I also added this else arm, for debugging. it used to be the branch taken even with the
+        // right input, until verify-check found process_input_byte reading 0x1338 for 0x1388.
+        // the flag is left alone here, same as the program, now that it's only for wrong inputs
+        warnings.push(format!("buffer_check failed with r0 = {:#x}", s.regs[0]));
     }
+    done(s, STAGE2[3]);
 }
 
 fn stage2_105(s: &mut State) {
     loop {
-        s.r3 = s.r0;
-        s.r3 %= s.r2;
-        if s.r3 == 0 {
-            s.r1 = 0x0;
+        s.regs[3] = s.regs[0];
+        s.regs[3] %= s.regs[2];
+        if s.regs[3] == 0 {
+            s.regs[1] = 0x0;
         }
-        s.r2 = s.r2.wrapping_add(0x1);
-        s.r3 = s.r2;
-        s.r3 = s.r3.wrapping_mul(s.r3);
-        s.r3 = s.r3.wrapping_sub(s.r0);
-        s.r3 = s.r3.wrapping_sub(0x1);
-        if s.r3 >= 0 {
+        s.regs[2] = s.regs[2].wrapping_add(0x1);
+        s.regs[3] = s.regs[2];
+        s.regs[3] = s.regs[3].wrapping_mul(s.regs[3]);
+        s.regs[3] = s.regs[3].wrapping_sub(s.regs[0]);
+        s.regs[3] = s.regs[3].wrapping_sub(0x1);
+        if s.regs[3] >= 0 {
             break;
         }
     }
@@ -157,45 +192,94 @@ fn stage2_105(s: &mut State) {
 
 // generates the same buffer every time, that's all I needed to know to solve
 // I guess they are prime numbers
-fn generate_buffer(s: &mut State) {
+pub fn generate_buffer(s: &mut State) {
     // buf to write to
-    s.r4 = 0x1388;
+    s.regs[4] = 0x1388;
+
+    let bar = match s.progress {
+        true => ProgressBar::new(0x3520 - 0x3390).with_message("generate_buffer"),
+        false => ProgressBar::hidden(),
+    };
+    bar.set_style(progress_style());
 
     // counter
-    s.r0 = 0x3390;
-    while s.r0 < 0x3520 {
-        s.r1 = 0x1;
-        s.r2 = 0x2;
+    s.regs[0] = 0x3390;
+    while s.regs[0] < 0x3520 {
+        bar.set_position((s.regs[0] - 0x3390) as u64);
+        s.regs[1] = 0x1;
+        s.regs[2] = 0x2;
         stage2_105(s);
-        if s.r1 > 0 {
-            s.store(s.r4, s.r0);
-            s.r4 = s.r4.wrapping_add(0x2);
+        if s.regs[1] > 0 {
+            s.store(s.regs[4], s.regs[0]);
+            s.regs[4] = s.regs[4].wrapping_add(0x2);
         }
-        s.r0 += 1;
+        s.regs[0] += 1;
     }
+    bar.finish_and_clear();
 }
 
+// same result as generate_buffer, registers and all, but with a sieve instead of trial division
+pub fn generate_buffer_fast(s: &mut State) {
+    let mut composite = [false; 0x3520];
+    for n in 2..0x3520 {
+        if !composite[n] {
+            for m in (n * n..0x3520).step_by(n) {
+                composite[m] = true;
+            }
+        }
+    }
+
+    s.regs[4] = 0x1388;
+    for n in (0x3390..0x3520).filter(|&n| !composite[n]) {
+        s.store(s.regs[4], n as i32);
+        s.regs[4] = s.regs[4].wrapping_add(0x2);
+    }
+
+    // stage2_105 on the last counter value leaves r2 as the first divisor it didn't need to
+    // try, and r3 as how far past the square root that was
+    s.regs[0] = 0x351f;
+    s.regs[2] = (2..).find(|d| d * d > s.regs[0]).unwrap().max(3);
+    s.regs[3] = s.regs[2] * s.regs[2] - s.regs[0] - 1;
+    s.regs[1] = !composite[0x351f] as i32;
+    s.regs[0] = 0x3520;
+}
+
+// same as collatz, without the recursion. r1 is 0 at the bottom of it either way
+pub fn collatz_fast(s: &mut State) {
+    s.regs[0] = transform::collatz(s.regs[0] as u32) as i32;
+    s.regs[1] = 0;
+}
+
+// a name, the function's address, and what to run instead
+pub type Stub = (&'static str, usize, fn(&mut State));
+
+// native versions of stage2 functions, that `run --stub <name>` swaps in for the real ones
+pub const STUBS: &[Stub] = &[
+    ("primes", 0x151, generate_buffer_fast),
+    ("collatz", 0x1d6, collatz_fast),
+];
+
 // r0 is input index + 1
 fn collatz_helper(s: &mut State) {
-    s.r1 = s.r0;
-    s.r1 %= 0x2;
-    if s.r1 == 0 {
+    s.regs[1] = s.regs[0];
+    s.regs[1] %= 0x2;
+    if s.regs[1] == 0 {
         // even index
-        s.r0 /= 0x2;
+        s.regs[0] /= 0x2;
     }
-    if s.r1 > 0 {
+    if s.regs[1] > 0 {
         // odd index
-        s.r0 = s.r0 * 3 + 1;
+        s.regs[0] = s.regs[0].wrapping_mul(3).wrapping_add(1);
     }
     collatz(s);
-    s.r0 += 1;
+    s.regs[0] = s.regs[0].wrapping_add(1);
 }
 
 // r0 is input index + 1
-fn collatz(s: &mut State) {
-    s.r1 = s.r0 - 1;
-    if s.r1 == 0 {
-        s.r0 = 0x0;
+pub fn collatz(s: &mut State) {
+    s.regs[1] = s.regs[0].wrapping_sub(1);
+    if s.regs[1] == 0 {
+        s.regs[0] = 0x0;
     } else {
         collatz_helper(s);
     }
@@ -203,13 +287,13 @@ fn collatz(s: &mut State) {
 
 // r0 is index, starts at 0
 // r4 is byte value read
-fn read_input_byte(s: &mut State) {
-    s.r2 = 0x1000 + s.r0;
-    s.r4 = s.read(s.r2);
-    s.r4 &= 0xff;
+pub fn read_input_byte(s: &mut State) {
+    s.regs[2] = 0x1000 + s.regs[0];
+    s.regs[4] = s.read(s.regs[2]);
+    s.regs[4] &= 0xff;
 
     // input nul byte check
-    if s.r4 > 0 {
+    if s.regs[4] > 0 {
         process_input_byte(s);
     }
 }
@@ -218,162 +302,162 @@ fn read_input_byte(s: &mut State) {
 // r4 is input byte
 fn process_input_byte(s: &mut State) {
     // index r2 into the static buffer and read a byte
-    s.r2 = s.read(s.r0 * 2 + 0x1338) & 0xff;
+    s.regs[2] = s.read(s.regs[0].wrapping_mul(2).wrapping_add(0x1388)) & 0xff;
 
     // xor with input byte
-    s.r4 ^= s.r2;
+    s.regs[4] ^= s.regs[2];
 
     // increment index, save in r2
-    s.r0 += 1;
-    s.r2 = s.r0;
+    s.regs[0] = s.regs[0].wrapping_add(1);
+    s.regs[2] = s.regs[0];
 
     // calculate collatz conjecture and mix into r4
     collatz(s);
-    s.r4 = s.r4.wrapping_add(s.r0);
-    s.r4 &= 0xff;
+    s.regs[4] = s.regs[4].wrapping_add(s.regs[0]);
+    s.regs[4] &= 0xff;
 
     // restore index to r0
-    s.r0 = s.r2;
-    s.r2 = s.r2.wrapping_sub(0x1);
-    s.r2 = s.r2.wrapping_add(0x1194);
+    s.regs[0] = s.regs[2];
+    s.regs[2] = s.regs[2].wrapping_sub(0x1);
+    s.regs[2] = s.regs[2].wrapping_add(0x1194);
 
     // store to 1194 buf (first pass done?)
-    s.store(s.r2, s.r4);
+    s.store(s.regs[2], s.regs[4]);
     read_input_byte(s);
 }
 
 // final flag output stage. I think it xors the "first pass" buffer then writes to the flag array.
-fn stage2_28d(s: &mut State) {
-    s.r0 = 0x75bcd15;
-    s.r1 = s.read(0x1000);
-    s.r0 ^= s.r1;
-    s.r2 = 0x3278f102;
-    s.r2 ^= s.r0;
-
-    s.r1 = 0x1800;
-    s.store(0x1800, s.r2);
-
-    s.r1 = 0x1004;
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-
-    s.r2 = 0x560aa747;
-    s.r2 ^= s.r0;
-    s.store(0x1804, s.r2);
-
-    s.r1 = 0x8;
-    s.r1 = s.r1.wrapping_add(0x1000);
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x3e6fd176);
-    s.r2 ^= s.r0;
-    s.r1 = 0x8;
-    s.r1 = s.r1.wrapping_add(0x1800);
-    s.store(s.r1, s.r2);
-    s.r1 = 0xc;
-    s.r1 = s.r1.wrapping_add(0x1000);
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x156d86fa);
-    s.r2 = s.r2.wrapping_add(0x66c93320);
-    s.r2 ^= s.r0;
-    s.r1 = 0xc;
-    s.r1 = s.r1.wrapping_add(0x1800);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x10;
-    s.r1 = s.r1.wrapping_add(0x1000);
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0xe5dbc23);
-    s.r2 ^= s.r0;
-    s.r1 = 0x10;
-    s.r1 = s.r1.wrapping_add(0x1800);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x14;
-    s.r1 = s.r1.wrapping_add(0x1000);
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0xd3f894c);
-    s.r2 ^= s.r0;
-    s.r1 = 0x14;
-    s.r1 = s.r1.wrapping_add(0x1800);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x18;
-    s.r1 = s.r1.wrapping_add(0x1000);
-    s.r1 = s.read(s.r1);
-    s.r0 ^= s.r1;
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x324fe212);
-    s.r2 ^= s.r0;
-    s.r1 = 0x18;
-    s.r1 = s.r1.wrapping_add(0x1800);
-    s.store(s.r1, s.r2);
+pub fn stage2_28d(s: &mut State) {
+    s.regs[0] = 0x75bcd15;
+    s.regs[1] = s.read(0x1000);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x3278f102;
+    s.regs[2] ^= s.regs[0];
+
+    s.regs[1] = 0x1800;
+    s.store(0x1800, s.regs[2]);
+
+    s.regs[1] = 0x1004;
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+
+    s.regs[2] = 0x560aa747;
+    s.regs[2] ^= s.regs[0];
+    s.store(0x1804, s.regs[2]);
+
+    s.regs[1] = 0x8;
+    s.regs[1] = s.regs[1].wrapping_add(0x1000);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x3e6fd176);
+    s.regs[2] ^= s.regs[0];
+    s.regs[1] = 0x8;
+    s.regs[1] = s.regs[1].wrapping_add(0x1800);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0xc;
+    s.regs[1] = s.regs[1].wrapping_add(0x1000);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x156d86fa);
+    s.regs[2] = s.regs[2].wrapping_add(0x66c93320);
+    s.regs[2] ^= s.regs[0];
+    s.regs[1] = 0xc;
+    s.regs[1] = s.regs[1].wrapping_add(0x1800);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x10;
+    s.regs[1] = s.regs[1].wrapping_add(0x1000);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0xe5dbc23);
+    s.regs[2] ^= s.regs[0];
+    s.regs[1] = 0x10;
+    s.regs[1] = s.regs[1].wrapping_add(0x1800);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x14;
+    s.regs[1] = s.regs[1].wrapping_add(0x1000);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0xd3f894c);
+    s.regs[2] ^= s.regs[0];
+    s.regs[1] = 0x14;
+    s.regs[1] = s.regs[1].wrapping_add(0x1800);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x18;
+    s.regs[1] = s.regs[1].wrapping_add(0x1000);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[0] ^= s.regs[1];
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x324fe212);
+    s.regs[2] ^= s.regs[0];
+    s.regs[1] = 0x18;
+    s.regs[1] = s.regs[1].wrapping_add(0x1800);
+    s.store(s.regs[1], s.regs[2]);
 }
 
 // takes no input
 // only uses r0-r2
-fn buffer_check(s: &mut State) {
-    s.r0 = 0x0;
+pub fn buffer_check(s: &mut State) {
+    s.regs[0] = 0x0;
 
     // read 4 bytes of first pass buffer
-    s.r1 = 0x1194;
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x51eddb21;
-    s.r2 = s.r2.wrapping_add(0x648c4a88);
-    s.r2 = s.r2.wrapping_add(0x4355a74c);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0x4;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x32333645);
-    s.r2 = s.r2.wrapping_add(0x58728e64);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0x8;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x6f57a0a3);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0xc;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x22d9bbcc);
-    s.r2 = s.r2.wrapping_add(0x569fcabc);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0x10;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0xd531548);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0x14;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x74c2318e);
-    s.r2 = s.r2.wrapping_add(0x7233f6a3);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
-    s.r1 = 0x18;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r1 = s.read(s.r1);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x6d12a1c5);
-    s.r2 = s.r2.wrapping_add(0x6c3422b6);
-    s.r2 = s.r2.wrapping_add(0xf213d9a);
-    s.r1 ^= s.r2;
-    s.r0 |= s.r1;
+    s.regs[1] = 0x1194;
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x51eddb21;
+    s.regs[2] = s.regs[2].wrapping_add(0x648c4a88);
+    s.regs[2] = s.regs[2].wrapping_add(0x4355a74c);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0x4;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x32333645);
+    s.regs[2] = s.regs[2].wrapping_add(0x58728e64);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0x8;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x6f57a0a3);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0xc;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x22d9bbcc);
+    s.regs[2] = s.regs[2].wrapping_add(0x569fcabc);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0x10;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0xd531548);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0x14;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x74c2318e);
+    s.regs[2] = s.regs[2].wrapping_add(0x7233f6a3);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
+    s.regs[1] = 0x18;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[1] = s.read(s.regs[1]);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x6d12a1c5);
+    s.regs[2] = s.regs[2].wrapping_add(0x6c3422b6);
+    s.regs[2] = s.regs[2].wrapping_add(0xf213d9a);
+    s.regs[1] ^= s.regs[2];
+    s.regs[0] |= s.regs[1];
 
     // r0 should be 0 if the whole buffer was correct
 }
@@ -383,44 +467,44 @@ fn buffer_check(s: &mut State) {
 // simply extract the correct values :)
 // This function is not called by the original program
 fn buffer_create(s: &mut State) {
-    s.r1 = 0x1194;
-    s.r2 = 0x51eddb21;
-    s.r2 = s.r2.wrapping_add(0x648c4a88);
-    s.r2 = s.r2.wrapping_add(0x4355a74c);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x4;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x32333645);
-    s.r2 = s.r2.wrapping_add(0x58728e64);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x8;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x6f57a0a3);
-    s.store(s.r1, s.r2);
-    s.r1 = 0xc;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x22d9bbcc);
-    s.r2 = s.r2.wrapping_add(0x569fcabc);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x10;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0xd531548);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x14;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x74c2318e);
-    s.r2 = s.r2.wrapping_add(0x7233f6a3);
-    s.store(s.r1, s.r2);
-    s.r1 = 0x18;
-    s.r1 = s.r1.wrapping_add(0x1194);
-    s.r2 = 0x0;
-    s.r2 = s.r2.wrapping_add(0x6d12a1c5);
-    s.r2 = s.r2.wrapping_add(0x6c3422b6);
-    s.r2 = s.r2.wrapping_add(0xf213d9a);
-    s.store(s.r1, s.r2);
+    s.regs[1] = 0x1194;
+    s.regs[2] = 0x51eddb21;
+    s.regs[2] = s.regs[2].wrapping_add(0x648c4a88);
+    s.regs[2] = s.regs[2].wrapping_add(0x4355a74c);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x4;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x32333645);
+    s.regs[2] = s.regs[2].wrapping_add(0x58728e64);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x8;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x6f57a0a3);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0xc;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x22d9bbcc);
+    s.regs[2] = s.regs[2].wrapping_add(0x569fcabc);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x10;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0xd531548);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x14;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x74c2318e);
+    s.regs[2] = s.regs[2].wrapping_add(0x7233f6a3);
+    s.store(s.regs[1], s.regs[2]);
+    s.regs[1] = 0x18;
+    s.regs[1] = s.regs[1].wrapping_add(0x1194);
+    s.regs[2] = 0x0;
+    s.regs[2] = s.regs[2].wrapping_add(0x6d12a1c5);
+    s.regs[2] = s.regs[2].wrapping_add(0x6c3422b6);
+    s.regs[2] = s.regs[2].wrapping_add(0xf213d9a);
+    s.store(s.regs[1], s.regs[2]);
 }
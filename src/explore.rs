@@ -0,0 +1,142 @@
+// every way through a program, by forking the machine at each conditional call. the fork goes
+// the way the register didn't say and the original carries on as it would have, and each goes
+// into a queue to be run to its own end:
+//
+//     let mut explorer = Explorer::new(vm);
+//     for path in &mut explorer {
+//         println!("{} after {} branches", path.end, path.branches.len());
+//     }
+//
+// nothing here knows what inputs would take a forced way, so a path with forced branches in it
+// may be one no input can take. the limits are what keep a loop on a conditional call from
+// forking forever, which is all this vm family has for loops
+use crate::inst::{try_parse, DestMode, Operation};
+use crate::vm::{Vm, VmError};
+use crate::word::Word;
+use std::collections::VecDeque;
+use std::fmt;
+
+// a conditional call a path went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+    pub pc: usize,
+    pub taken: bool,
+    // the explorer sent it this way, not the register
+    pub forced: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    Fault(VmError),
+    // the path ran past max_steps
+    OutOfSteps,
+}
+
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            End::Halted => write!(f, "halted"),
+            End::Fault(e) => write!(f, "{}", e),
+            End::OutOfSteps => write!(f, "out of steps"),
+        }
+    }
+}
+
+// a machine at the end of one path, and the branches it took to get there
+pub struct Path<R = i32> {
+    pub vm: Vm<R>,
+    pub branches: Vec<Branch>,
+    pub end: End,
+}
+
+pub struct Explorer<R = i32> {
+    queue: VecDeque<(Vm<R>, Vec<Branch>)>,
+    // paths started, the first one included. once it's reached max_paths there's no more forking
+    pub started: usize,
+    pub max_paths: usize,
+    // steps each path gets, counting from where the explorer was given the machine
+    pub max_steps: u64,
+}
+
+// the test and the register of a conditional call at pc, None for anything else
+pub(crate) fn conditional<R: Word>(vm: &Vm<R>) -> Option<(DestMode, R)> {
+    let (inst, _) = vm.s.mem.get(vm.pc..).and_then(try_parse)?;
+    if inst.op != Operation::Jmp || inst.dest_mode == DestMode::NoPlusMinus {
+        return None;
+    }
+    Some((inst.dest_mode, *vm.s.regs.get(inst.src as usize)?))
+}
+
+// the direction a conditional call at pc goes on its own, None for anything else
+pub(crate) fn condition<R: Word>(vm: &Vm<R>) -> Option<bool> {
+    let (mode, cond) = conditional(vm)?;
+    match mode {
+        DestMode::Minus => Some(cond < R::default()),
+        DestMode::Plus => Some(cond > R::default()),
+        _ => Some(cond == R::default()),
+    }
+}
+
+impl<R: Word> Explorer<R> {
+    pub fn new(vm: Vm<R>) -> Self {
+        Explorer {
+            queue: VecDeque::from([(vm, Vec::new())]),
+            started: 1,
+            max_paths: 1024,
+            max_steps: 1_000_000,
+        }
+    }
+
+    // paths forked off but not run yet
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    // one path to its end, forking at every conditional call while there's room for more
+    fn explore(&mut self, mut vm: Vm<R>, mut branches: Vec<Branch>) -> Path<R> {
+        let limit = vm.steps + self.max_steps;
+        let end = loop {
+            if vm.halted {
+                break End::Halted;
+            }
+            if vm.steps >= limit {
+                break End::OutOfSteps;
+            }
+            // a fork's first step is the branch it was forked at, which is already down
+            let decided = vm.forced.is_some();
+            if let Some(taken) = condition(&vm).filter(|_| !decided) {
+                if self.started < self.max_paths {
+                    let mut fork = vm.fork();
+                    fork.forced = Some(!taken);
+                    let mut forked = branches.clone();
+                    forked.push(Branch {
+                        pc: vm.pc,
+                        taken: !taken,
+                        forced: true,
+                    });
+                    self.queue.push_back((fork, forked));
+                    self.started += 1;
+                }
+                branches.push(Branch {
+                    pc: vm.pc,
+                    taken,
+                    forced: false,
+                });
+            }
+            if let Err(e) = vm.step() {
+                break End::Fault(e);
+            }
+        };
+        Path { vm, branches, end }
+    }
+}
+
+impl<R: Word> Iterator for Explorer<R> {
+    type Item = Path<R>;
+
+    fn next(&mut self) -> Option<Path<R>> {
+        let (vm, branches) = self.queue.pop_front()?;
+        Some(self.explore(vm, branches))
+    }
+}
@@ -0,0 +1,393 @@
+// little expression language for looking at a machine without touching it, for the repl's print,
+// watch and break commands:
+//
+//     mem32(0x1194+4) ^ 0x8aa5c4a9
+//     r0 & 0xff
+//     mem8(user_input + r0) == 0
+//
+// everything is evaluated as i64. registers are rN, memory is read with mem8/mem16/mem32/mem64
+// (zero extended, in the machine's byte order), and any other name is a project label or region
+// (spaces in region names become underscores)
+use crate::inst::{parse_num, Width};
+#[cfg(feature = "std")]
+use crate::project::Project;
+use crate::state::State;
+use crate::word::Word;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    Reg(usize),
+    // resolved when evaluated, so labels added later still work
+    Name(String),
+    Mem(Width, Box<Expr>),
+    Unary(char, Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+// longest first so "<=" isn't read as "<"
+const OPS: &[(&str, Op, u8)] = &[
+    ("<<", Op::Shl, 9),
+    (">>", Op::Shr, 9),
+    ("<=", Op::Le, 8),
+    (">=", Op::Ge, 8),
+    ("==", Op::Eq, 7),
+    ("!=", Op::Ne, 7),
+    ("&&", Op::LogicalAnd, 3),
+    ("||", Op::LogicalOr, 2),
+    ("*", Op::Mul, 11),
+    ("/", Op::Div, 11),
+    ("%", Op::Rem, 11),
+    ("+", Op::Add, 10),
+    ("-", Op::Sub, 10),
+    ("<", Op::Lt, 8),
+    (">", Op::Gt, 8),
+    ("&", Op::And, 6),
+    ("^", Op::Xor, 5),
+    ("|", Op::Or, 4),
+];
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (text, _, _) = OPS.iter().find(|(_, op, _)| op == self).unwrap();
+        write!(f, "{}", text)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Num(n) if *n < 0 => write!(f, "-{:#x}", n.unsigned_abs()),
+            Expr::Num(n) => write!(f, "{:#x}", n),
+            Expr::Reg(n) => write!(f, "r{}", n),
+            Expr::Name(name) => write!(f, "{}", name),
+            Expr::Mem(width, addr) => write!(f, "mem{}({})", width.bytes() * 8, addr),
+            Expr::Unary(op, e) => {
+                write!(f, "{}", op)?;
+                operand(f, e)
+            }
+            Expr::Binary(op, a, b) => {
+                operand(f, a)?;
+                write!(f, " {} ", op)?;
+                operand(f, b)
+            }
+        }
+    }
+}
+
+// nested operators get parentheses instead of working out which ones are needed
+fn operand(f: &mut fmt::Formatter<'_>, e: &Expr) -> fmt::Result {
+    match e {
+        Expr::Binary(..) => write!(f, "({})", e),
+        _ => write!(f, "{}", e),
+    }
+}
+
+impl core::str::FromStr for Expr {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let e = parser.binary(0)?;
+        parser.skip_space();
+        if parser.pos != text.len() {
+            return Err(format!("unexpected {} in {}", &text[parser.pos..], text));
+        }
+        Ok(e)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_space(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_space();
+        if self.text[self.pos..].starts_with(s.as_bytes()) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // precedence climbing, everything is left associative
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            self.skip_space();
+            let rest = &self.text[self.pos..];
+            let found = OPS.iter().find(|(text, _, _)| rest.starts_with(text.as_bytes()));
+            let (text, op, prec) = match found {
+                Some(&(text, op, prec)) if prec >= min => (text, op, prec),
+                _ => return Ok(lhs),
+            };
+            self.pos += text.len();
+            let rhs = self.binary(prec + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for op in ['-', '~', '!'] {
+            // "!=" can't start an operand anyway
+            if self.eat(&op.to_string()) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let e = self.binary(0)?;
+            if !self.eat(")") {
+                return Err("missing )".to_string());
+            }
+            return Ok(e);
+        }
+
+        self.skip_space();
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+        let word = core::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        if word.is_empty() {
+            return Err(match self.text.get(self.pos) {
+                Some(&c) => format!("unexpected {}", c as char),
+                None => "unexpected end of expression".to_string(),
+            });
+        }
+
+        if word.as_bytes()[0].is_ascii_digit() {
+            let n = match word.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => word.parse(),
+            };
+            return n.map(Expr::Num).map_err(|_| format!("bad number {}", word));
+        }
+
+        let width = match word {
+            "mem8" => Some(Width::W8),
+            "mem16" => Some(Width::W16),
+            "mem32" => Some(Width::W32),
+            "mem64" => Some(Width::W64),
+            _ => None,
+        };
+        if let Some(width) = width {
+            if !self.eat("(") {
+                return Err(format!("{} needs an address, like {}(0x1000)", word, word));
+            }
+            let addr = self.binary(0)?;
+            if !self.eat(")") {
+                return Err("missing )".to_string());
+            }
+            return Ok(Expr::Mem(width, Box::new(addr)));
+        }
+
+        match word.strip_prefix('r').map(str::parse) {
+            Some(Ok(n)) => Ok(Expr::Reg(n)),
+            _ => Ok(Expr::Name(word.to_string())),
+        }
+    }
+}
+
+impl Expr {
+    #[cfg(feature = "std")]
+    pub fn eval<R: Word>(&self, s: &State<R>, project: &Project) -> Result<i64, String> {
+        self.eval_with(s, &|name| project.lookup(name))
+    }
+
+    // `names` looks up anything that isn't a register or a memory read
+    pub fn eval_with<R: Word>(
+        &self,
+        s: &State<R>,
+        names: &dyn Fn(&str) -> Option<usize>,
+    ) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Reg(n) => match s.regs.get(*n) {
+                Some(r) => r.wide(),
+                None => return Err(format!("no register r{}", n)),
+            },
+            Expr::Name(name) => match names(name) {
+                Some(addr) => addr as i64,
+                None => return Err(format!("unknown name {}", name)),
+            },
+            Expr::Mem(width, addr) => {
+                let addr = addr.eval_with(s, names)?;
+                match s.peek(addr as usize, *width) {
+                    Some(value) => value as i64,
+                    None => return Err(format!("{:#x} is out of bounds", addr)),
+                }
+            }
+            Expr::Unary(op, e) => {
+                let v = e.eval_with(s, names)?;
+                match op {
+                    '-' => v.wrapping_neg(),
+                    '~' => !v,
+                    _ => (v == 0) as i64,
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let a = a.eval_with(s, names)?;
+                // short circuit, so a guard like `r1 != 0 && mem8(r1)` works
+                match op {
+                    Op::LogicalAnd if a == 0 => return Ok(0),
+                    Op::LogicalOr if a != 0 => return Ok(1),
+                    _ => {}
+                }
+                let b = b.eval_with(s, names)?;
+                match op {
+                    Op::Div | Op::Rem if b == 0 => return Err("divide by zero".to_string()),
+                    Op::Mul => a.wrapping_mul(b),
+                    Op::Div => a.wrapping_div(b),
+                    Op::Rem => a.wrapping_rem(b),
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                    Op::Shl => a.wrapping_shl(b as u32),
+                    Op::Shr => a.wrapping_shr(b as u32),
+                    Op::Lt => (a < b) as i64,
+                    Op::Le => (a <= b) as i64,
+                    Op::Gt => (a > b) as i64,
+                    Op::Ge => (a >= b) as i64,
+                    Op::Eq => (a == b) as i64,
+                    Op::Ne => (a != b) as i64,
+                    Op::And => a & b,
+                    Op::Xor => a ^ b,
+                    Op::Or => a | b,
+                    Op::LogicalAnd | Op::LogicalOr => (b != 0) as i64,
+                }
+            }
+        })
+    }
+
+    // the same expression with every name swapped for its address now, for when there won't be a
+    // project around to look them up
+    #[cfg(feature = "std")]
+    pub fn resolve(&self, project: &Project) -> Result<Expr, String> {
+        self.resolve_with(&|name| project.lookup(name))
+    }
+
+    pub fn resolve_with(&self, names: &dyn Fn(&str) -> Option<usize>) -> Result<Expr, String> {
+        let sub = |e: &Expr| e.resolve_with(names).map(Box::new);
+        Ok(match self {
+            Expr::Name(name) => match names(name) {
+                Some(addr) => Expr::Num(addr as i64),
+                None => return Err(format!("unknown name {}", name)),
+            },
+            Expr::Mem(width, addr) => Expr::Mem(*width, sub(addr)?),
+            Expr::Unary(op, e) => Expr::Unary(*op, sub(e)?),
+            Expr::Binary(op, a, b) => Expr::Binary(*op, sub(a)?, sub(b)?),
+            e => e.clone(),
+        })
+    }
+}
+
+// an invariant the vm checks before every instruction, or only before the one at `at`:
+//
+//     mem32(0x1388) == 0x3391
+//     at 0x1f4 r0 <= 0x1c
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub at: Option<usize>,
+    pub expr: Expr,
+}
+
+impl Assertion {
+    // names are resolved here, the vm doesn't know about projects
+    #[cfg(feature = "std")]
+    pub fn parse(text: &str, project: &Project) -> Result<Self, String> {
+        Self::parse_with(text, &|name| project.lookup(name))
+    }
+
+    pub fn parse_with(text: &str, names: &dyn Fn(&str) -> Option<usize>) -> Result<Self, String> {
+        let text = text.trim();
+        let (at, rest) = match text.strip_prefix("at ") {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                let (addr, rest) = rest.split_once(' ').ok_or("missing expression after at")?;
+                let addr = parse_num(addr)
+                    .map(|n| n as usize)
+                    .or_else(|| names(addr))
+                    .ok_or(format!("bad address {}", addr))?;
+                (Some(addr), rest)
+            }
+            None => (None, text),
+        };
+        let expr: Expr = rest.parse()?;
+        Ok(Assertion {
+            at,
+            expr: expr.resolve_with(names)?,
+        })
+    }
+
+    // None while it holds, otherwise what went wrong
+    pub fn check<R: Word>(&self, s: &State<R>) -> Option<String> {
+        let eval = |e: &Expr| e.eval_with(s, &|_| None);
+        match eval(&self.expr) {
+            Ok(0) => {}
+            Ok(_) => return None,
+            Err(e) => return Some(format!("{}: {}", self.expr, e)),
+        }
+        // the two sides of a comparison are usually what's interesting
+        match &self.expr {
+            Expr::Binary(_, a, b) => match (eval(a), eval(b)) {
+                (Ok(a), Ok(b)) => Some(format!("{}  (left {:#x}, right {:#x})", self.expr, a, b)),
+                _ => Some(self.expr.to_string()),
+            },
+            _ => Some(self.expr.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.at {
+            Some(at) => write!(f, "at {:#x} {}", at, self.expr),
+            None => write!(f, "{}", self.expr),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// a c interface to the interpreter, for embedding it in c or c++ analysis tools and fuzzers. the
+// crate also builds as a cdylib (target/<profile>/libdisasm.so), include/weather.h declares it all:
+//
+//     struct wvm *vm = wvm_new();
+//     wvm_set_input(vm, input, 28);
+//     wvm_boot(vm);
+//     int err = wvm_run(vm);
+//     wvm_peek(vm, 0x1800, flag, 28);
+//     wvm_free(vm);
+//
+// every function taking a `struct wvm *` wants one from wvm_new that hasn't been freed yet, and
+// buffers have to hold the length passed with them. that's the whole safety story, so the lint
+// asking for it on each function is off
+#![allow(clippy::missing_safety_doc)]
+use crate::state::State;
+use crate::vm::{Vm, VmError, ENTRY};
+use alloc::boxed::Box;
+use core::ffi::c_int;
+use core::slice;
+
+// return codes, matching WVM_* in weather.h
+const OK: c_int = 0;
+const HALTED: c_int = 1;
+const OUT_OF_STEPS: c_int = 2;
+const BAD_ARGUMENT: c_int = -100;
+
+fn code(e: VmError) -> c_int {
+    match e {
+        VmError::OutOfBounds(_) => -1,
+        VmError::DivideByZero(_) => -2,
+        VmError::StackOverflow(_) => -3,
+        VmError::BadOperand(_) => -4,
+        VmError::BadInstruction(_) => -5,
+        VmError::CodeWrite(_) => -6,
+        VmError::AssertionFailed { .. } => -7,
+        VmError::ProtectionFault { .. } => -8,
+    }
+}
+
+fn status(result: Result<(), VmError>, vm: &Vm) -> c_int {
+    match result {
+        Err(e) => code(e),
+        Ok(()) if vm.halted => HALTED,
+        Ok(()) => OK,
+    }
+}
+
+// a machine with the weather image, pc on stage1's entry
+#[no_mangle]
+pub extern "C" fn wvm_new() -> *mut Vm {
+    let mut s = State::new();
+    s.quiet = true;
+    let mut vm = Vm::new(s);
+    vm.pc = ENTRY;
+    Box::into_raw(Box::new(vm))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_free(vm: *mut Vm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+// start over with another program image, registers cleared and pc at 0
+#[no_mangle]
+pub unsafe extern "C" fn wvm_load_image(vm: *mut Vm, image: *const u8, len: usize) -> c_int {
+    let mut s = State::from_image(slice::from_raw_parts(image, len));
+    s.quiet = true;
+    *vm = Vm::new(s);
+    OK
+}
+
+// the input string, where the program reads it from
+#[no_mangle]
+pub unsafe extern "C" fn wvm_set_input(vm: *mut Vm, input: *const u8, len: usize) -> c_int {
+    wvm_poke(vm, 0x1000, input, len)
+}
+
+// run stage1 from its entry, stopping on stage2's first instruction
+#[no_mangle]
+pub unsafe extern "C" fn wvm_boot(vm: *mut Vm) -> c_int {
+    let vm = &mut *vm;
+    let result = vm.run_stage1();
+    status(result, vm)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_step(vm: *mut Vm) -> c_int {
+    let vm = &mut *vm;
+    if vm.halted {
+        return HALTED;
+    }
+    let result = vm.step();
+    status(result, vm)
+}
+
+// until the outermost function returns
+#[no_mangle]
+pub unsafe extern "C" fn wvm_run(vm: *mut Vm) -> c_int {
+    let vm = &mut *vm;
+    let result = vm.run();
+    status(result, vm)
+}
+
+// until pc is `stop` at any depth, the program ends, or `max_steps` have gone by (0 for no limit)
+#[no_mangle]
+pub unsafe extern "C" fn wvm_run_until(vm: *mut Vm, stop: usize, max_steps: u64) -> c_int {
+    let vm = &mut *vm;
+    let start = vm.steps;
+    while vm.pc != stop || vm.steps == start {
+        if vm.halted {
+            return HALTED;
+        }
+        if max_steps != 0 && vm.steps - start >= max_steps {
+            return OUT_OF_STEPS;
+        }
+        if let Err(e) = vm.step() {
+            return code(e);
+        }
+    }
+    OK
+}
+
+// a vm function to completion, like a %C that is always taken
+#[no_mangle]
+pub unsafe extern "C" fn wvm_call(vm: *mut Vm, addr: usize) -> c_int {
+    let vm = &mut *vm;
+    let result = vm.call(addr);
+    status(result, vm)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_pc(vm: *const Vm) -> usize {
+    (*vm).pc
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_set_pc(vm: *mut Vm, pc: usize) {
+    (*vm).pc = pc;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_steps(vm: *const Vm) -> u64 {
+    (*vm).steps
+}
+
+// 0 for a register that doesn't exist
+#[no_mangle]
+pub unsafe extern "C" fn wvm_reg(vm: *const Vm, n: u32) -> i32 {
+    let vm = &*vm;
+    vm.s.regs.get(n as usize).copied().unwrap_or(0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wvm_set_reg(vm: *mut Vm, n: u32, value: i32) -> c_int {
+    let vm = &mut *vm;
+    match vm.s.regs.get_mut(n as usize) {
+        Some(r) => {
+            *r = value;
+            OK
+        }
+        None => BAD_ARGUMENT,
+    }
+}
+
+// `len` bytes of memory at `addr` into `out`
+#[no_mangle]
+pub unsafe extern "C" fn wvm_peek(vm: *const Vm, addr: usize, out: *mut u8, len: usize) -> c_int {
+    let vm = &*vm;
+    let mem = &vm.s.mem;
+    match addr.checked_add(len).filter(|&end| end <= mem.len()) {
+        Some(end) => {
+            slice::from_raw_parts_mut(out, len).copy_from_slice(&mem[addr..end]);
+            OK
+        }
+        None => code(VmError::OutOfBounds(addr)),
+    }
+}
+
+// `len` bytes from `bytes` into memory at `addr`, growing it if that's within the cap
+#[no_mangle]
+pub unsafe extern "C" fn wvm_poke(vm: *mut Vm, addr: usize, bytes: *const u8, len: usize) -> c_int {
+    let vm = &mut *vm;
+    match addr.checked_add(len).filter(|&end| vm.s.grow(end)) {
+        Some(_) => {
+            vm.s.write_bytes(addr, slice::from_raw_parts(bytes, len));
+            vm.invalidate(addr, len);
+            OK
+        }
+        None => code(VmError::OutOfBounds(addr)),
+    }
+}
@@ -0,0 +1,57 @@
+// folded stacks for flamegraphs, one line per distinct vm call stack with the number of steps
+// spent in it:
+//
+//     entry;stage2_c8;stage2_151;stage2_105 118920
+//
+// feed it to inferno-flamegraph or flamegraph.pl. every step is counted, there's no sampling
+use crate::golden;
+use crate::project::Project;
+use crate::trace::Event;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+pub fn folded(events: &[Event], project: &Project) -> String {
+    let mut stack: Vec<usize> = Vec::new();
+    let mut counts: HashMap<Vec<usize>, u64> = HashMap::new();
+    for e in events {
+        match *e {
+            Event::Step { .. } => *counts.entry(stack.clone()).or_default() += 1,
+            Event::Call { to, .. } => stack.push(to),
+            Event::Return { .. } => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut lines: Vec<String> = counts
+        .into_iter()
+        .map(|(stack, n)| {
+            let mut frames = vec!["entry".to_string()];
+            frames.extend(stack.iter().map(|&addr| project.function(addr)));
+            format!("{} {}", frames.join(";"), n)
+        })
+        .collect();
+    lines.sort();
+
+    let mut out = String::new();
+    for line in lines {
+        writeln!(out, "{}", line).unwrap();
+    }
+    out
+}
+
+// the winning solve to stdout, or to `path`
+pub fn run(path: Option<&str>, project: &Project) {
+    let out = folded(&golden::known_good_trace(), project);
+    match path {
+        Some(path) => {
+            std::fs::write(path, out).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => print!("{}", out),
+    }
+}
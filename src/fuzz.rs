@@ -0,0 +1,277 @@
+// stress harness: throw random user input at both the transpiled functions and the interpreter.
+// faults are fine, they are reported by the vm. host panics (out of bounds slicing, overflow,
+// blowing the stack) are bugs
+//
+// fuzz_one is the same machine for a fuzzer that keeps the process around, libFuzzer's
+// LLVMFuzzerTestOneInput or afl's persistent mode:
+//
+//     fuzz_target!(|input: &[u8]| {
+//         let _ = disasm::fuzz::fuzz_one(input);
+//     });
+//
+// it skips stage1 and the prime sieve, which never look at anything past the first byte
+use crate::decode::{Decoded, CODE};
+use crate::ex::{self, State, REGIONS};
+use crate::programs::WEATHER;
+use crate::rng::Rng;
+use indicatif::ProgressBar;
+use crate::vm::{Vm, VmError, ENTRY};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+// the first byte is the xor key, and only this one decrypts stage2
+const KEY: u8 = b'T';
+
+// the full program takes a few hundred thousand steps, so this is plenty
+const MAX_STEPS: usize = 10_000_000;
+
+const READ_INPUT: usize = 0x1f4;
+const CHECK: usize = 0x4ee;
+
+thread_local! {
+    // the winning run as read_input_byte is entered, taken on the first fuzz_one
+    static SNAPSHOT: RefCell<Option<Vm>> = const { RefCell::new(None) };
+}
+
+pub fn run(iterations: usize) {
+    let mut rng = Rng::new(0x1c);
+    let mut panics = 0;
+    let mut faults = 0;
+    let mut clobbers = 0;
+    let bar = ProgressBar::new(iterations as u64).with_message("fuzz");
+    bar.set_style(ex::progress_style());
+
+    for ii in 0..iterations {
+        bar.inc(1);
+        let input = arbitrary_input(&mut rng);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let transpiled = transpiled(&input);
+            let interpreted = interpreted(&input);
+            (transpiled, interpreted)
+        }));
+
+        match result {
+            Ok(((transpiled, t_canaries), (interpreted, i_canaries))) => {
+                if transpiled.is_some() || interpreted.is_some() {
+                    faults += 1;
+                }
+                if !t_canaries.is_empty() || !i_canaries.is_empty() {
+                    bar.println(format!(
+                        "canaries clobbered on iteration {}: transpiled {:x?} interpreted {:x?}",
+                        ii, t_canaries, i_canaries
+                    ));
+                    clobbers += 1;
+                }
+            }
+            Err(_) => {
+                bar.println(format!("host panic on iteration {} with input {:x?}", ii, input));
+                panics += 1;
+            }
+        }
+    }
+
+    bar.finish_and_clear();
+    println!(
+        "{} inputs, {} vm faults, {} clobbered canaries, {} host panics",
+        iterations, faults, clobbers, panics
+    );
+    if panics > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn snapshot() -> Vm {
+    let mut vm = Vm::new(ex::winning_state());
+    vm.pc = ENTRY;
+    while vm.pc != READ_INPUT {
+        vm.step().expect("the winning run faulted before read_input_byte");
+    }
+    vm
+}
+
+// `input` through the check from the snapshot, whether it passes. stage2 was decrypted with the
+// winning first byte, so that one byte doesn't get to break it the way it would from the start.
+// anything longer than the input buffer is cut off
+pub fn fuzz_one(input: &[u8]) -> Result<bool, VmError> {
+    let mut vm = SNAPSHOT.with(|taken| taken.borrow_mut().get_or_insert_with(snapshot).fork());
+    let region = REGIONS[0].0.clone();
+    let n = input.len().min(region.len() - 1);
+    vm.s.mem[region.clone()].fill(0);
+    vm.s.mem[region.start..region.start + n].copy_from_slice(&input[..n]);
+
+    let mut depth = None;
+    for _ in 0..MAX_STEPS {
+        if vm.pc == CHECK && depth.is_none() {
+            depth = Some(vm.stack.len());
+        }
+        vm.step()?;
+        // back out of buffer_check, what it returned is the answer
+        if depth.is_some_and(|depth| vm.stack.len() < depth) || vm.halted {
+            return Ok(depth.is_some() && vm.s.regs[0] == 0);
+        }
+    }
+    Ok(false)
+}
+
+// steps an arbitrary machine gets, there's nothing stopping one going round forever
+#[cfg(feature = "arbitrary")]
+const MACHINE_STEPS: usize = 100_000;
+
+// a machine made out of `data` by structured.rs, run from 0. for a fuzz target like fuzz_one's,
+// but after the interpreter rather than the check
+#[cfg(feature = "arbitrary")]
+pub fn fuzz_machine(data: &[u8]) -> Result<(), VmError> {
+    let s: State = arbitrary::Unstructured::new(data).arbitrary().unwrap_or_default();
+    let mut vm = Vm::new(s);
+    for _ in 0..MACHINE_STEPS {
+        if vm.halted {
+            break;
+        }
+        vm.step()?;
+    }
+    Ok(())
+}
+
+// `fuzz --persistent n`: n random inputs through fuzz_one, for how fast a fuzzer could go
+pub fn persistent(iterations: usize) {
+    let mut rng = Rng::new(0x1c);
+    // the snapshot isn't part of the rate
+    let _ = fuzz_one(b"");
+    let start = Instant::now();
+    let mut passed = 0;
+    let mut faults = 0;
+    for _ in 0..iterations {
+        match fuzz_one(&arbitrary_input(&mut rng)) {
+            Ok(true) => passed += 1,
+            Ok(false) => {}
+            Err(_) => faults += 1,
+        }
+    }
+    let took = start.elapsed();
+    println!(
+        "{} inputs in {:.2?}, {:.0} execs/sec, {} passed the check, {} vm faults",
+        iterations,
+        took,
+        iterations as f64 / took.as_secs_f64(),
+        passed,
+        faults
+    );
+}
+
+// how a mutant's run ended, for grouping them
+fn class(e: &VmError) -> &'static str {
+    match e {
+        VmError::OutOfBounds(_) => "out of bounds",
+        VmError::DivideByZero(_) => "divide by zero",
+        VmError::StackOverflow(_) => "stack overflow",
+        VmError::BadOperand(_) => "bad operand",
+        VmError::BadInstruction(_) => "bad instruction",
+        VmError::CodeWrite(_) => "code write",
+        VmError::AssertionFailed { .. } => "assertion",
+        VmError::ProtectionFault { .. } => "protection fault",
+    }
+}
+
+// the winning input through the image with one byte changed, from the entry point and with the
+// decode cache on so every write into code has it decoding again
+fn mutant(at: usize, value: u8) -> Result<&'static str, VmError> {
+    let mut s: State = ex::winning_state();
+    s.mem[at] = value;
+    let mut vm = Vm::new(s);
+    vm.code = Some(Decoded::sweep(&vm.s.mem, CODE));
+    vm.pc = ENTRY;
+    for _ in 0..MAX_STEPS {
+        if vm.halted {
+            let flag = &vm.s.mem[WEATHER.flag.clone()];
+            return Ok(match flag.starts_with(b"CTF{") {
+                true => "still prints the flag",
+                false => "no flag",
+            });
+        }
+        vm.step()?;
+    }
+    Ok("still running")
+}
+
+// `fuzz --mutate n`: n mutants of the image, each one random byte changed, and how their runs end.
+// a vm fault is the fault model doing its job, a host panic is a bug
+pub fn mutate(iterations: usize) {
+    let mut rng = Rng::new(0x1c);
+    // class -> (count, the first mutant that ended that way)
+    let mut classes: BTreeMap<&str, (usize, String)> = BTreeMap::new();
+    let bar = ProgressBar::new(iterations as u64).with_message("mutants");
+    bar.set_style(ex::progress_style());
+    for _ in 0..iterations {
+        bar.inc(1);
+        let at = rng.below(WEATHER.image.len() as u64) as usize;
+        let value = rng.next_u64() as u8;
+        let was = WEATHER.image[at];
+        let (class, detail) = match panic::catch_unwind(|| mutant(at, value)) {
+            Ok(Ok(class)) => (class, String::new()),
+            Ok(Err(e)) => (class(&e), format!(", {}", e)),
+            Err(_) => ("host panic", String::new()),
+        };
+        let example = format!("{:#05x} {:#04x} -> {:#04x}{}", at, was, value, detail);
+        classes.entry(class).or_insert((0, example)).0 += 1;
+    }
+    bar.finish_and_clear();
+
+    println!("{} mutants of the {} byte image", iterations, WEATHER.image.len());
+    for (class, (count, example)) in &classes {
+        println!("  {:24} {:6}  first {}", class, count, example);
+    }
+    if classes.contains_key("host panic") {
+        std::process::exit(1);
+    }
+}
+
+fn arbitrary_input(rng: &mut Rng) -> Vec<u8> {
+    // mostly short strings like a city name, sometimes enough to run off the end of memory
+    let len = match rng.below(8) {
+        0 => rng.below(0x1640 + 1),
+        _ => rng.below(0x40),
+    } as usize;
+    let mut input: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+
+    // most of the time use the right key so stage2 actually runs
+    if !input.is_empty() && rng.below(4) != 0 {
+        input[0] = KEY;
+    }
+    input
+}
+
+fn with_input(input: &[u8]) -> State {
+    let mut s = State::with_input(input);
+    s.quiet = true;
+    s
+}
+
+// each runner gives back the fault if there was one, and any clobbered canaries
+
+// the transpiled code assumes stage2 is already decrypted, so the key doesn't matter here
+fn transpiled(input: &[u8]) -> (Option<String>, Vec<usize>) {
+    let mut s = with_input(input);
+    ex::stage2(&mut s);
+    (s.fault.map(|e| e.to_string()), s.clobbered_canaries())
+}
+
+// the whole program from the entry point, decryption included
+fn interpreted(input: &[u8]) -> (Option<String>, Vec<usize>) {
+    let mut vm = Vm::new(with_input(input));
+    vm.pc = ENTRY;
+    let mut fault = Some(format!("still running after {} steps", MAX_STEPS));
+    for _ in 0..MAX_STEPS {
+        if vm.halted {
+            fault = None;
+            break;
+        }
+        if let Err(e) = vm.step() {
+            fault = Some(e.to_string());
+            break;
+        }
+    }
+    (fault, vm.s.clobbered_canaries())
+}
@@ -0,0 +1,285 @@
+// a gdb script for the original binary, so something seen in the emulator can be looked at in the
+// real thing. everything comes out of the stripped elf itself: the printf handlers from the
+// register_printf_function calls in the constructor, host printf and fprintf calls through the
+// plt, and the vm's memory from where the image sits in .data
+//
+//     disasm gdb weather.gdb
+//     gdb -x weather.gdb ./weather
+//
+// it stops whenever the vm calls one of the functions worked out in ex.rs
+use crate::inst::{Instruction, Operation};
+use crate::programs::WEATHER;
+use crate::project::Project;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt::Write;
+
+// gdb turns off randomization, and then a pie always loads here on x86_64
+const BASE: u64 = 0x5555_5555_4000;
+
+// what each vm function turned out to be
+pub const FUNCTIONS: &[(usize, &str)] = &[
+    (0x7, "stage1's xor loop, decrypts stage2"),
+    (0x34, "stage1, the first thing %F's format string calls"),
+    (0xc8, "stage2_main"),
+    (0x105, "stage2_105, trial division"),
+    (0x151, "generate_buffer"),
+    (0x1ac, "collatz_helper"),
+    (0x1d6, "collatz"),
+    (0x1f4, "read_input_byte"),
+    (0x21c, "process_input_byte"),
+    (0x28d, "stage2_28d, writes the flag"),
+    (0x4ee, "buffer_check"),
+];
+
+struct Section {
+    name: String,
+    kind: u32,
+    addr: u64,
+    offset: usize,
+    size: usize,
+}
+
+// the bits of a 64 bit little endian elf this needs
+struct Elf<'a> {
+    bytes: &'a [u8],
+    sections: Vec<Section>,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if bytes.get(..5) != Some(b"\x7fELF\x02") {
+            return Err("not a 64 bit elf".to_string());
+        }
+        let mut elf = Elf { bytes, sections: Vec::new() };
+        let shoff = elf.u64(0x28)? as usize;
+        let count = elf.u16(0x3c)? as usize;
+        let names = elf.u16(0x3e)? as usize;
+        let header = |i: usize| shoff + i * 0x40;
+        let strtab = elf.u64(header(names) + 0x18)? as usize;
+        for i in 0..count {
+            let h = header(i);
+            elf.sections.push(Section {
+                name: elf.string(strtab + elf.u32(h)? as usize)?,
+                kind: elf.u32(h + 4)?,
+                addr: elf.u64(h + 0x10)?,
+                offset: elf.u64(h + 0x18)? as usize,
+                size: elf.u64(h + 0x20)? as usize,
+            });
+        }
+        Ok(elf)
+    }
+
+    fn bytes(&self, at: usize, len: usize) -> Result<&'a [u8], String> {
+        self.bytes.get(at..at + len).ok_or_else(|| format!("{:#x} is past the end of the file", at))
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(at, 2)?.try_into().unwrap()))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(at, 4)?.try_into().unwrap()))
+    }
+
+    fn u64(&self, at: usize) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(at, 8)?.try_into().unwrap()))
+    }
+
+    fn string(&self, at: usize) -> Result<String, String> {
+        let rest = self.bytes.get(at..).ok_or("string past the end of the file")?;
+        let end = rest.iter().position(|&b| b == 0).ok_or("unterminated string")?;
+        Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+
+    fn section(&self, name: &str) -> Result<&Section, String> {
+        self.sections.iter().find(|s| s.name == name).ok_or(format!("no {} section", name))
+    }
+
+    // file offset of a virtual address, None for .bss and anything unmapped
+    fn offset(&self, addr: u64) -> Option<usize> {
+        const NOBITS: u32 = 8;
+        self.sections
+            .iter()
+            .find(|s| s.kind != NOBITS && s.addr != 0 && (s.addr..s.addr + s.size as u64).contains(&addr))
+            .map(|s| s.offset + (addr - s.addr) as usize)
+    }
+
+    fn addr(&self, offset: usize) -> Option<u64> {
+        self.sections
+            .iter()
+            .find(|s| s.addr != 0 && (s.offset..s.offset + s.size).contains(&offset))
+            .map(|s| s.addr + (offset - s.offset) as u64)
+    }
+
+    // plt stub address -> the libc function it jumps to
+    fn plt(&self) -> Result<BTreeMap<u64, String>, String> {
+        let rela = self.section(".rela.plt")?;
+        let dynsym = self.section(".dynsym")?;
+        let dynstr = self.section(".dynstr")?;
+        let mut got = BTreeMap::new();
+        for at in (rela.offset..rela.offset + rela.size).step_by(24) {
+            let sym = (self.u64(at + 8)? >> 32) as usize;
+            let name = self.string(dynstr.offset + self.u32(dynsym.offset + sym * 24)? as usize)?;
+            got.insert(self.u64(at)?, name);
+        }
+
+        // every stub is a `jmp [rip+disp]` through its got slot
+        let plt = self.section(".plt")?;
+        let mut stubs = BTreeMap::new();
+        for at in (plt.offset..plt.offset + plt.size).step_by(16) {
+            if self.bytes(at, 2)? == [0xff, 0x25] {
+                let addr = plt.addr + (at - plt.offset) as u64;
+                let slot = (addr + 6).wrapping_add(self.u32(at + 2)? as i32 as u64);
+                if let Some(name) = got.get(&slot) {
+                    stubs.insert(addr, name.split('@').next().unwrap().to_string());
+                }
+            }
+        }
+        Ok(stubs)
+    }
+
+    // (address of the call, where it goes) for every direct call in .text
+    fn calls(&self) -> Result<Vec<(u64, u64)>, String> {
+        let text = self.section(".text")?;
+        let mut calls = Vec::new();
+        for at in text.offset..text.offset + text.size - 5 {
+            if self.bytes[at] == 0xe8 {
+                let addr = text.addr + (at - text.offset) as u64;
+                calls.push((addr, (addr + 5).wrapping_add(self.u32(at + 1)? as i32 as u64)));
+            }
+        }
+        Ok(calls)
+    }
+
+    // the string a `lea reg, [rip+disp]` ending right before `before` points at, for the format
+    // argument of a printf
+    fn lea_string(&self, before: u64) -> Option<String> {
+        (before - 20..before).find_map(|at| {
+            let offset = self.offset(at)?;
+            let bytes = self.bytes(offset, 7).ok()?;
+            // 48 8d with a rip relative modrm, into rdi or rsi
+            if bytes[0] != 0x48 || bytes[1] != 0x8d || !matches!(bytes[2], 0x3d | 0x35) {
+                return None;
+            }
+            let target = (at + 7).wrapping_add(i32::from_le_bytes(bytes[3..7].try_into().unwrap()) as u64);
+            self.string(self.offset(target)?).ok()
+        })
+    }
+}
+
+// a handler registered with register_printf_function
+struct Handler {
+    spec: u8,
+    addr: u64,
+}
+
+impl Handler {
+    fn about(&self) -> String {
+        match Instruction::checked(&[b'%', self.spec]) {
+            Some((inst, _)) if inst.op == Operation::Jmp => "the vm's call, width is the target".to_string(),
+            Some((inst, _)) => format!("the vm's {:?}", inst.op).to_lowercase(),
+            None => match self.spec {
+                b'P' => "precipitation".to_string(),
+                b'W' => "wind".to_string(),
+                b'T' => "temperature".to_string(),
+                b'F' => "the flag, runs the vm".to_string(),
+                _ => "not a vm instruction".to_string(),
+            },
+        }
+    }
+}
+
+pub fn script(binary: &[u8], project: &Project) -> Result<String, String> {
+    let elf = Elf::parse(binary)?;
+    let plt = elf.plt()?;
+    let calls = elf.calls()?;
+    let to = |name: &str| -> Vec<u64> {
+        let stub = plt.iter().find(|(_, n)| *n == name).map(|(&a, _)| a);
+        calls.iter().filter(|&&(_, target)| Some(target) == stub).map(|&(at, _)| at).collect()
+    };
+
+    // `lea rdx, [rip+arginfo]`, `lea rsi, [rip+handler]` then `mov edi, spec` right before each
+    // call. the arginfo functions come right after the handlers
+    let mut handlers = Vec::new();
+    let mut end = u64::MAX;
+    for at in to("register_printf_function") {
+        let lea = elf.offset(at - 19).ok_or("register call outside the image")?;
+        let bytes = elf.bytes(lea, 19)?;
+        if bytes[..3] != [0x48, 0x8d, 0x15] || bytes[7..10] != [0x48, 0x8d, 0x35] || bytes[14] != 0xbf {
+            return Err(format!("can't make out the handler registered at {:#x}", at));
+        }
+        let disp = |at: usize| i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as u64;
+        end = end.min((at - 12).wrapping_add(disp(3)));
+        handlers.push(Handler {
+            spec: bytes[15],
+            addr: (at - 5).wrapping_add(disp(10)),
+        });
+    }
+    handlers.sort_by_key(|h| h.addr);
+
+    let mem = binary
+        .windows(0x40)
+        .position(|w| w == &WEATHER.image[..0x40])
+        .and_then(|offset| elf.addr(offset))
+        .ok_or("the vm image isn't in the binary")?;
+
+    let mut out = String::new();
+    writeln!(out, "# generated by `disasm gdb` from the weather binary").unwrap();
+    writeln!(out, "set disable-randomization on").unwrap();
+    writeln!(out, "set pagination off").unwrap();
+    writeln!(out, "starti").unwrap();
+    writeln!(out, "set $base = {:#x}", BASE).unwrap();
+    writeln!(out, "# vm memory, vm address 0 is the start of the format string").unwrap();
+    writeln!(out, "set $vm = $base + {:#x}", mem).unwrap();
+
+    writeln!(out, "\n# printf handlers, (stream, struct printf_info *info, args). info->width is ((int *)$rsi)[1]").unwrap();
+    for h in &handlers {
+        writeln!(out, "# %{}: {}", h.spec as char, h.about()).unwrap();
+        writeln!(out, "set $handler_{} = $base + {:#x}", h.spec as char, h.addr).unwrap();
+    }
+
+    writeln!(out, "\n# vm calls into the recovered functions. %C stops here whether or not the call is taken").unwrap();
+    for &(addr, about) in FUNCTIONS {
+        let name = project.labels.get(&addr).map_or(about, String::as_str);
+        writeln!(out, "# {:#x}: {}", addr, name).unwrap();
+        writeln!(out, "break *$handler_C if ((int *)$rsi)[1] == {:#x}", addr).unwrap();
+    }
+
+    // a call site belongs to the last handler starting before it, anything past them is main
+    let handler = |at: u64| handlers.iter().rev().find(|h| h.addr <= at).filter(|_| at < end);
+    writeln!(out, "\n# printf calls. the ones inside handlers run for every instruction, so they start disabled").unwrap();
+    for name in ["printf", "fprintf"] {
+        for at in to(name) {
+            let format = elf.lea_string(at).map_or("format built at runtime".to_string(), |f| format!("{:?}", f));
+            match handler(at) {
+                Some(h) => writeln!(out, "# {} in the %{} handler, {}", name, h.spec as char, format).unwrap(),
+                None => writeln!(out, "# {} in main, {}", name, format).unwrap(),
+            }
+            writeln!(out, "break *($base + {:#x})", at).unwrap();
+            if handler(at).is_some() {
+                writeln!(out, "disable $bpnum").unwrap();
+            }
+        }
+    }
+    writeln!(out, "\ncontinue").unwrap();
+    Ok(out)
+}
+
+pub fn run(binary: &str, path: Option<&str>, project: &Project) {
+    let script = std::fs::read(binary).map_err(|e| format!("{}: {}", binary, e)).and_then(|bytes| script(&bytes, project));
+    let script = script.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match path {
+        Some(path) => {
+            std::fs::write(path, script).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => print!("{}", script),
+    }
+}
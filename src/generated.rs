@@ -0,0 +1,11 @@
+// the stage2 functions as build.rs translates them from mem, no loops or names like ex.rs has, just
+// one rust function per vm function. calls recurse natively, so deep recursion in the program is
+// deep recursion here
+
+// generated code says things the long way
+#![allow(clippy::all)]
+
+use crate::ex::State;
+use crate::inst::Width;
+
+include!(concat!(env!("OUT_DIR"), "/ex_generated.rs"));
@@ -0,0 +1,41 @@
+// golden trace: the interpreter's full event stream for the known good solve, boiled down to a
+// count and a hash. if a refactor of the interpreter or State changes anything the program can
+// observe, this stops matching
+use crate::ex::{self, State};
+use crate::trace::{self, Event};
+use crate::vm::{Vm, ENTRY};
+
+// recorded from the winning input, which prints CTF{curs3d_r3curs1ve_pr1ntf}
+const GOLDEN_EVENTS: usize = 574541;
+const GOLDEN_DIGEST: u64 = 0x571e2e8b17045ffd;
+
+// every event from running the whole program on the winning input
+pub fn known_good_trace() -> Vec<Event> {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let input = ex::winning_input(&mut scratch);
+
+    let mut s: State = State::with_input(&input);
+    s.quiet = true;
+    s.trace = Some(Vec::new());
+
+    let mut vm = Vm::new(s);
+    vm.pc = ENTRY;
+    vm.run().unwrap();
+    vm.s.trace.unwrap()
+}
+
+pub fn run() {
+    let events = known_good_trace();
+    let digest = trace::digest(&events);
+    println!("{} events, digest {:016x}", events.len(), digest);
+
+    if events.len() != GOLDEN_EVENTS || digest != GOLDEN_DIGEST {
+        println!(
+            "MISMATCH: golden trace is {} events, digest {:016x}",
+            GOLDEN_EVENTS, GOLDEN_DIGEST
+        );
+        std::process::exit(1);
+    }
+    println!("ok: matches the golden trace");
+}
@@ -0,0 +1,114 @@
+// picture of a run: address across, time down, one pixel per 4 bytes of memory and per slice of
+// steps. executed code is green, reads blue, writes red, so the phases stand out as blocks: stage1
+// decrypting stage2, the prime sieve, the input loop and the flag being printed
+use crate::trace::Event;
+
+// memory bytes per pixel column
+const BYTES_PER_PIXEL: usize = 4;
+
+const READ: u8 = 1;
+const WRITE: u8 = 2;
+const EXECUTE: u8 = 4;
+
+// `rows` lines of pixels, each covering an equal share of the steps in `events`
+pub fn render(events: &[Event], rows: usize) -> Vec<u8> {
+    let touched = |e: &Event| match *e {
+        Event::Read { index, .. } | Event::Store { index, .. } => Some(index as u32 as usize),
+        Event::Step { pc } => Some(pc),
+        _ => None,
+    };
+    let end = events.iter().filter_map(touched).max().map_or(0, |a| a + 1);
+    let width = end.div_ceil(BYTES_PER_PIXEL).max(1);
+    let steps = events.iter().filter(|e| matches!(e, Event::Step { .. })).count();
+    let rows = rows.clamp(1, steps.max(1));
+
+    let mut cells = vec![0u8; width * rows];
+    let mut step = 0;
+    for e in events {
+        let row = step * rows / steps.max(1);
+        let (index, kind) = match *e {
+            Event::Read { index, .. } => (index as u32 as usize, READ),
+            Event::Store { index, .. } => (index as u32 as usize, WRITE),
+            Event::Step { pc } => {
+                step += 1;
+                (pc, EXECUTE)
+            }
+            _ => continue,
+        };
+        cells[row.min(rows - 1) * width + index / BYTES_PER_PIXEL] |= kind;
+    }
+
+    let pixels: Vec<[u8; 3]> = cells
+        .iter()
+        .map(|&c| {
+            let on = |bit, level| if c & bit != 0 { level } else { 0 };
+            [on(WRITE, 0xff), on(EXECUTE, 0xc0), on(READ, 0xff)]
+        })
+        .collect();
+    png(width, rows, &pixels)
+}
+
+// just enough png for an rgb image: the pixel data goes in stored (uncompressed) deflate blocks,
+// so there's no compressor to pull in
+fn png(width: usize, height: usize, pixels: &[[u8; 3]]) -> Vec<u8> {
+    // every row starts with filter type 0, none
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width) {
+        raw.push(0);
+        for p in row {
+            raw.extend_from_slice(p);
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xffff).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i + 1 == blocks.len()) as u8);
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, default compression, filter and no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib);
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
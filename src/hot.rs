@@ -0,0 +1,96 @@
+// which memory gets used the most. most of the work happens in registers, so single addresses
+// only get a handful of accesses each, but the totals per region give away what the buffers are
+// for: the prime buffer is filled and read back, the first pass is written once and checked
+use crate::golden;
+use crate::layout::MemoryLayout;
+use crate::project::Project;
+use crate::trace::Event;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// reads and writes per address
+#[derive(Debug, Default)]
+pub struct Heat {
+    pub reads: HashMap<i32, u64>,
+    pub writes: HashMap<i32, u64>,
+}
+
+impl Heat {
+    pub fn new(events: &[Event]) -> Self {
+        let mut heat = Heat::default();
+        for e in events {
+            match *e {
+                Event::Read { index, .. } => *heat.reads.entry(index).or_default() += 1,
+                Event::Store { index, .. } => *heat.writes.entry(index).or_default() += 1,
+                _ => {}
+            }
+        }
+        heat
+    }
+
+    // the `count` busiest addresses for reads and for writes, with whatever is known about them
+    pub fn report(&self, count: usize, project: &Project) -> String {
+        let mut out = String::new();
+
+        // reads, writes and distinct addresses per region, in address order
+        let mut regions: Vec<(usize, String, u64, u64, usize)> = Vec::new();
+        let mut addrs: Vec<i32> = self.reads.keys().chain(self.writes.keys()).copied().collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        for addr in addrs {
+            let name = describe(addr, project);
+            let reads = self.reads.get(&addr).copied().unwrap_or(0);
+            let writes = self.writes.get(&addr).copied().unwrap_or(0);
+            match regions.iter_mut().find(|r| r.1 == name) {
+                Some(r) => {
+                    r.2 += reads;
+                    r.3 += writes;
+                    r.4 += 1;
+                }
+                None => regions.push((addr as u32 as usize, name, reads, writes, 1)),
+            }
+        }
+        writeln!(out, "by region:").unwrap();
+        writeln!(out, "  {:>8}  {:>8}  {:>9}  first    region", "reads", "writes", "addresses").unwrap();
+        for (first, name, reads, writes, n) in &regions {
+            writeln!(out, "  {:>8}  {:>8}  {:>9}  {:#06x}   {}", reads, writes, n, first, name).unwrap();
+        }
+
+        for (title, counts) in [("reads", &self.reads), ("writes", &self.writes)].iter() {
+            writeln!(out, "hottest {}:", title).unwrap();
+            for (addr, n) in hottest(counts, count) {
+                writeln!(out, "  {:>8}  {:#06x}  {}", n, addr, describe(addr, project)).unwrap();
+            }
+        }
+        out
+    }
+}
+
+// busiest first, ties by address so the report doesn't change between runs
+fn hottest(counts: &HashMap<i32, u64>, count: usize) -> Vec<(i32, u64)> {
+    let mut all: Vec<(i32, u64)> = counts.iter().map(|(&a, &n)| (a, n)).collect();
+    all.sort_by_key(|&(a, n)| (std::cmp::Reverse(n), a));
+    all.truncate(count);
+    all
+}
+
+// the user's label or region if there is one, otherwise the layout's region
+pub fn describe(addr: i32, project: &Project) -> String {
+    let addr = addr as u32 as usize;
+    if let Some(label) = project.labels.get(&addr) {
+        return label.clone();
+    }
+    if let Some(region) = project.region(addr) {
+        return region.to_string();
+    }
+    match MemoryLayout::default().region(addr) {
+        Some(region) => region.name.to_string(),
+        None => "scratch".to_string(),
+    }
+}
+
+// the winning solve, since that runs every part of the program
+pub fn run(count: usize, project: &Project) {
+    let events = golden::known_good_trace();
+    print!("{}", Heat::new(&events).report(count, project));
+}
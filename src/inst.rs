@@ -0,0 +1,604 @@
+// decoding the format string specifiers into vm instructions, and back again
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    // width
+    pub dest: u32,
+    // precision
+    pub src: u32,
+    // operand1 mode
+    pub dest_mode: DestMode,
+    // operand2 mode
+    pub src_mode: SrcMode,
+    // arithmetic to do
+    pub op: Operation,
+    // size of the memory operands
+    pub width: Width,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestMode {
+    NoPlusMinus,
+    Plus,
+    Minus,
+    ZeroPad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrcMode {
+    HH,
+    H,
+    LL,
+    L,
+    None,
+}
+
+// how many bytes a memory access covers. the binary always moves 4. narrower ones are this tool's
+// own extension, spelled with leading zeros on the precision when Widths::Zeros is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    W8,
+    W16,
+    W32,
+    // only ever a full register in 64 bit mode, there's no spelling for it
+    W64,
+}
+
+// why some bytes aren't an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    // ran out of bytes partway through a specifier
+    Truncated,
+    // neither a '%' nor the nul of a ret
+    NotASpecifier,
+    // more significant digits than an int has
+    TooManyDigits,
+    // a width or precision past INT_MAX
+    Overflow,
+    // a conversion letter the vm doesn't have
+    Conversion(u8),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated => write!(f, "specifier runs off the end"),
+            ParseError::NotASpecifier => write!(f, "not a specifier"),
+            ParseError::TooManyDigits => write!(f, "more than {} digits in a number", MAX_DIGITS),
+            ParseError::Overflow => write!(f, "number past {}", MAX_OPERAND),
+            ParseError::Conversion(c) => write!(f, "no conversion {:?}", *c as char),
+        }
+    }
+}
+
+// what leading zeros on a precision mean. printf ignores them, so the binary reads ".02hh" as a
+// plain access to [2], and so does Printf. Zeros makes ".02hh" a 16 bit access and ".002hh" an 8
+// bit one, for programs written for this tool and not the challenge binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Widths {
+    Printf,
+    Zeros,
+}
+
+// what decode uses, Printf unless something opted in. a global so the vm, the decode caches, the
+// compiled cores and the listings all read the same program
+static ZEROS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_widths(widths: Widths) {
+    ZEROS.store(widths == Widths::Zeros, Ordering::Relaxed);
+}
+
+pub fn widths() -> Widths {
+    match ZEROS.load(Ordering::Relaxed) {
+        true => Widths::Zeros,
+        false => Widths::Printf,
+    }
+}
+
+impl Width {
+    pub fn bytes(self) -> usize {
+        match self {
+            Width::W8 => 1,
+            Width::W16 => 2,
+            Width::W32 => 4,
+            Width::W64 => 8,
+        }
+    }
+
+    // extra zeros in front of the precision
+    fn zeros(self) -> usize {
+        match self {
+            Width::W8 => 2,
+            Width::W16 => 1,
+            Width::W32 | Width::W64 => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Jmp,
+    Mov,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    ShLeft,
+    ShRight,
+    Xor,
+    And,
+    Or,
+    Ret,
+}
+
+// this prints the instruction. started out as syntax like "mov r1, [r0]" but then changed to
+// output pseudo rust code that only required small fixups in ex.rs to actually execute
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Named {
+            inst: self,
+            names: &[],
+            label: None,
+            regions: &[],
+            verbose: false,
+            palette: None,
+            link: None,
+            pointers: &[],
+        }
+        .fmt(f)
+    }
+}
+
+// an Instruction displayed with some of its registers going by a name, like `index` for `s.r0`
+pub struct Named<'a> {
+    pub inst: &'a Instruction,
+    // indexed by register number
+    pub names: &'a [Option<String>],
+    // what to call the target of a %C, instead of stage2_<addr>
+    pub label: Option<&'a str>,
+    // start, end (exclusive), name, for naming immediates that point into them
+    pub regions: &'a [(usize, usize, String)],
+    // immediates get a /* comment */ with another form of them where it helps
+    pub verbose: bool,
+    // ansi colors for the parts, from color.rs
+    pub palette: Option<&'a Palette>,
+    // a page with an anchor for every address, to make %C targets OSC 8 hyperlinks into
+    pub link: Option<&'a str>,
+    // which registers hold an address after this instruction, from pointers.rs. once that's
+    // known, loads and stores read like c, `*(u32*)(s.r4)`, and constants going into an address
+    // register like `(u8*)0x1388`. empty keeps the old syntax
+    pub pointers: &'a [bool],
+}
+
+// escape sequences for each part of a Named
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub op: &'static str,
+    pub reg: &'static str,
+    pub imm: &'static str,
+    pub call: &'static str,
+}
+
+impl Named<'_> {
+    fn paint(&self, pick: fn(&Palette) -> &'static str, text: String) -> String {
+        match self.palette {
+            Some(palette) => format!("{}{}\x1b[0m", pick(palette), text),
+            None => text,
+        }
+    }
+
+    fn name(&self, n: u32) -> String {
+        match self.names.get(n as usize) {
+            Some(Some(name)) => name.clone(),
+            _ => format!("s.r{}", n),
+        }
+    }
+
+    fn reg(&self, n: u32) -> String {
+        self.paint(|p| p.reg, self.name(n))
+    }
+
+    // an immediate, plus the region it points into, the character(s) it spells or its decimal
+    // value when verbose. `addr` is for [imm] operands, which only get the region
+    fn imm(&self, n: u32, addr: bool) -> String {
+        let hex = self.paint(|p| p.imm, format!("{:#x}", n));
+        if !self.verbose {
+            return hex;
+        }
+        let region = self.regions.iter().find(|&&(start, end, _)| (start..end).contains(&(n as usize)));
+        let bytes = n.to_le_bytes();
+        let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        let note = match region {
+            Some((_, _, name)) => name.clone(),
+            None if addr => return hex,
+            // a character, or a whole word of them like the "none" stage1 puts in the flag. two or
+            // three printable bytes are mostly a coincidence
+            None if (len == 1 || len == 4) && bytes[..len].iter().all(|b| (0x20..0x7f).contains(b)) => {
+                let text: String = bytes[..len].iter().map(|&b| b as char).collect();
+                match len {
+                    1 => format!("{:?}", text.chars().next().unwrap()),
+                    _ => format!("{:?}", text),
+                }
+            }
+            None if (n as i32) < 0 => (n as i32).to_string(),
+            None if n >= 10 => n.to_string(),
+            None => return hex,
+        };
+        format!("{} /* {} */", hex, note)
+    }
+
+    fn target(&self) -> String {
+        let target = match self.label {
+            Some(label) => label.to_string(),
+            None => format!("stage2_{:x}", self.inst.dest),
+        };
+        let target = self.paint(|p| p.call, target);
+        match self.link {
+            Some(page) => format!("\x1b]8;;{}#{:#x}\x1b\\{}\x1b]8;;\x1b\\", page, self.inst.dest, target),
+            None => target,
+        }
+    }
+}
+
+impl fmt::Display for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inst = self.inst;
+        let op = match inst.op {
+            Operation::Jmp => {
+                let op = match inst.dest_mode {
+                    DestMode::Minus => "< 0",
+                    DestMode::Plus => "> 0",
+                    DestMode::ZeroPad => "== 0",
+                    DestMode::NoPlusMinus => return write!(f, "{}(&mut s);", self.target()),
+                };
+
+                return write!(f, "if {} {} {{ {}(&mut s); }}", self.reg(inst.src), op, self.target());
+            }
+            /*   // old syntax
+            Operation::Mov => "mov",
+            Operation::Add => "add",
+            Operation::Sub => "sub",
+            Operation::Mul => "mul",
+            Operation::Div => "div",
+            Operation::Mod => "mod",
+            Operation::ShLeft => "shl",
+            Operation::ShRight => "shr",
+            Operation::Xor => "xor",
+            Operation::And => "and",
+            Operation::Or => " or",
+            */
+            // new syntax
+            Operation::Mov => "=",
+            Operation::Add => "+=",
+            Operation::Sub => "-=",
+            Operation::Mul => "*=",
+            Operation::Div => "/=",
+            Operation::Mod => "%=",
+            Operation::ShLeft => "<<=",
+            Operation::ShRight => ">>=",
+            Operation::Xor => "^=",
+            Operation::And => "&=",
+            Operation::Or => "|=",
+            Operation::Ret => return write!(f, "{}", self.paint(|p| p.op, "ret".to_string())),
+        };
+        let op = self.paint(|p| p.op, op.to_string());
+
+        // narrow accesses get their size inside the brackets
+        let width = match inst.width {
+            Width::W8 => "; u8",
+            Width::W16 => "; u16",
+            Width::W32 | Width::W64 => "",
+        };
+
+        let typed = !self.pointers.is_empty();
+        let deref = |n: u32| {
+            let ty = match inst.width {
+                Width::W8 => "u8",
+                Width::W16 => "u16",
+                Width::W32 | Width::W64 => "u32",
+            };
+            format!("*({}*)({})", ty, self.reg(n))
+        };
+
+        // write the destination part
+        match inst.dest_mode {
+            DestMode::Minus => write!(f, "[{}{}]", self.imm(inst.dest, true), width)?,
+            DestMode::Plus if typed => write!(f, "{}", deref(inst.dest))?,
+            DestMode::Plus => {
+                let reg = self.name(inst.dest).trim_start_matches("s.").to_string();
+                write!(f, "[{}{}]", self.paint(|p| p.reg, reg), width)?
+            }
+            DestMode::NoPlusMinus => write!(f, "{}", self.reg(inst.dest))?,
+            // only a call has a use for it, the vm faults on anything else
+            DestMode::ZeroPad => write!(f, "<bad operand>")?,
+        }
+
+        // write the opcode
+        write!(f, " {} ", op)?;
+
+        // write the source part
+        match inst.src_mode {
+            SrcMode::HH => write!(f, "[{}{}];", self.imm(inst.src, true), width),
+            SrcMode::H if typed => write!(f, "{};", deref(inst.src)),
+            SrcMode::H => write!(f, "s.mem[{} as u32 as usize{}];", self.reg(inst.src), width),
+            SrcMode::LL
+                if inst.op == Operation::Mov
+                    && inst.dest_mode == DestMode::NoPlusMinus
+                    && self.pointers.get(inst.dest as usize) == Some(&true) =>
+            {
+                write!(f, "(u8*){};", self.imm(inst.src, true))
+            }
+            SrcMode::L => write!(f, "{};", self.reg(inst.src)),
+            SrcMode::LL => write!(f, "{};", self.imm(inst.src, false)),
+            // no length modifier, which the vm faults on too
+            SrcMode::None => write!(f, "<bad operand>;"),
+        }
+    }
+}
+
+impl Instruction {
+
+    // this parses a string like "%+4.7hhX" and then returns an Instruction as well as where to
+    // keep parsing from next. panics on anything that isn't an instruction
+    pub fn parse(mem: &[u8]) -> (Self, &[u8]) {
+        Self::decode(mem).unwrap_or_else(|e| panic!("not an instruction: {}", e))
+    }
+
+    // parse, but None for anything it doesn't understand, including running off the end
+    pub fn checked(mem: &[u8]) -> Option<(Self, &[u8])> {
+        Self::decode(mem).ok()
+    }
+
+    // parse, with why it isn't an instruction when it isn't
+    pub fn decode(mem: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        Self::decode_with(mem, widths())
+    }
+
+    // decode, reading leading zeros on the precision as `widths` says instead of the global
+    pub fn decode_with(mem: &[u8], widths: Widths) -> Result<(Self, &[u8]), ParseError> {
+        if *mem.first().ok_or(ParseError::Truncated)? == 0 {
+            return Ok((Self {
+                dest: 0,
+                src: 0,
+                dest_mode: DestMode::Minus,
+                src_mode: SrcMode::LL,
+                op: Operation::Ret,
+                width: Width::W32,
+            }, &mem[1..]));
+        }
+
+        let mem = mem.strip_prefix(b"%").ok_or(ParseError::NotASpecifier)?;
+
+        // parse mode from flags. printf takes any of "-+ #0" in any order and any number of times,
+        // and they come down to one mode: '-' beats '+' beats '0', the same way printf's own '-'
+        // beats '0'. ' ' and '#' don't change anything here. a '0' right before the '.' is a width
+        // of 0 and not the flag, that's how register 0 is written
+        let (mut left, mut sign, mut zero) = (false, false, false);
+        let mut mem = mem;
+        loop {
+            match mem {
+                [b'0', b'.', ..] => break,
+                [b'-', ..] => left = true,
+                [b'+', ..] => sign = true,
+                [b'0', ..] => zero = true,
+                [b' ' | b'#', ..] => {}
+                _ => break,
+            }
+            mem = &mem[1..];
+        }
+        let op1_mode = if left {
+            DestMode::Minus
+        } else if sign {
+            DestMode::Plus
+        } else if zero {
+            DestMode::ZeroPad
+        } else {
+            DestMode::NoPlusMinus
+        };
+
+        // parse width (operand1)
+        let (operand1, mem) = parse_int(mem)?;
+
+        let (operand2, op2_mode, width, mem) = if mem.first() == Some(&b'.') {
+            let mem = &mem[1..];
+
+            // with Zeros, leading zeros on the precision pick a narrower access, the last digit is
+            // never one
+            let digits = mem.iter().take_while(|b| b.is_ascii_digit()).count();
+            let zeros = mem.iter().take_while(|&&b| b == b'0').count();
+            let width = match (widths, zeros.min(digits.saturating_sub(1))) {
+                (Widths::Printf, _) | (_, 0) => Width::W32,
+                (_, 1) => Width::W16,
+                _ => Width::W8,
+            };
+            let (operand2, mem) = parse_int(mem)?;
+
+            // the handler only sees the flags glibc sets for a modifier, and on x86-64 j, z and t
+            // set the same one as l, L the same as ll. L is also the shift left conversion, so
+            // it's only a modifier when a conversion follows it, glibc fails the call otherwise
+            let (op2_mode, mem) = match mem {
+                [b'h', b'h', .. ] => (SrcMode::HH, &mem[2..]),
+                [b'h', .. ] => (SrcMode::H, &mem[1..]),
+                [b'l', b'l', .. ] => (SrcMode::LL, &mem[2..]),
+                [b'l' | b'j' | b'z' | b't', .. ] => (SrcMode::L, &mem[1..]),
+                [b'L', c, ..] if conversion(*c).is_some() => (SrcMode::LL, &mem[1..]),
+                _ => (SrcMode::None, mem),
+            };
+            (operand2, op2_mode, width, mem)
+        } else {
+            (0, SrcMode::None, Width::W32, mem)
+        };
+        
+        let c = *mem.first().ok_or(ParseError::Truncated)?;
+        let operation = conversion(c).ok_or(ParseError::Conversion(c))?;
+
+        Ok((Self {
+            dest: operand1,
+            src: operand2,
+            dest_mode: op1_mode,
+            src_mode: op2_mode,
+            op: operation,
+            width,
+        }, &mem[1..]))
+    }
+}
+
+// a program written inline with format_vm!, decoded when the code using it was compiled
+pub struct Inline {
+    // the format string with its nul
+    pub text: &'static [u8],
+    // (offset, instruction, length) for every instruction in order
+    pub insts: &'static [(usize, Instruction, usize)],
+}
+
+// decode bytes that might not be code at all. gives the instruction and its length
+pub fn try_parse(mem: &[u8]) -> Option<(Instruction, usize)> {
+    let (inst, rest) = Instruction::checked(mem)?;
+    Some((inst, mem.len() - rest.len()))
+}
+
+impl Instruction {
+    // the assembler: turns an Instruction back into the format specifier the binary would parse.
+    // where printf allows several spellings, this picks the one the challenge image uses. a
+    // narrow width comes out as Widths::Zeros spells it, and only decodes back that way
+    pub fn encode(&self) -> Vec<u8> {
+        if let Operation::Ret = self.op {
+            return vec![0];
+        }
+
+        let mut out = vec![b'%'];
+
+        // flags. a plain register destination of 0 is written "0." so it can't be mistaken for
+        // the zero pad flag, or left out entirely when there is no precision
+        let precision = !matches!(self.src_mode, SrcMode::None)
+            || self.src != 0
+            || self.width != Width::W32
+            || matches!(
+                (self.op, self.dest_mode),
+                (Operation::Jmp, DestMode::Minus | DestMode::Plus | DestMode::ZeroPad)
+            );
+        match self.dest_mode {
+            DestMode::Minus => out.push(b'-'),
+            DestMode::Plus => out.push(b'+'),
+            DestMode::ZeroPad => out.push(b'0'),
+            DestMode::NoPlusMinus => {}
+        }
+
+        // width
+        if self.dest != 0 || precision || !matches!(self.dest_mode, DestMode::NoPlusMinus) {
+            out.extend(self.dest.to_string().bytes());
+        }
+
+        // precision and length modifier
+        if precision {
+            out.push(b'.');
+            out.extend(core::iter::repeat_n(b'0', self.width.zeros()));
+            out.extend(self.src.to_string().bytes());
+            out.extend(match self.src_mode {
+                SrcMode::HH => &b"hh"[..],
+                SrcMode::H => b"h",
+                SrcMode::LL => b"ll",
+                SrcMode::L => b"l",
+                SrcMode::None => b"",
+            });
+        }
+
+        out.push(match self.op {
+            Operation::Jmp => b'C',
+            Operation::Mov => b'M',
+            Operation::Add => b'S',
+            Operation::Sub => b'O',
+            Operation::Mul => b'X',
+            Operation::Div => b'V',
+            Operation::Mod => b'N',
+            Operation::ShLeft => b'L',
+            Operation::ShRight => b'R',
+            Operation::Xor => b'E',
+            Operation::And => b'I',
+            Operation::Or => b'U',
+            Operation::Ret => unreachable!(),
+        });
+        out
+    }
+}
+
+// numbers typed in by a person: hex with 0x, otherwise decimal
+pub fn parse_num(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// the operation a conversion letter stands for
+fn conversion(c: u8) -> Option<Operation> {
+    Some(match c {
+        b'C' => Operation::Jmp,
+        b'M' => Operation::Mov,
+        b'S' => Operation::Add,
+        b'O' => Operation::Sub,
+        b'X' => Operation::Mul,
+        b'V' => Operation::Div,
+        b'N' => Operation::Mod,
+        b'L' => Operation::ShLeft,
+        b'R' => Operation::ShRight,
+        b'E' => Operation::Xor,
+        b'I' => Operation::And,
+        b'U' => Operation::Or,
+        _ => return None,
+    })
+}
+
+// printf reads a width or precision into an int and fails the whole call with EOVERFLOW on
+// anything past INT_MAX, which has 10 digits. leading zeros don't count, they're the same number
+pub const MAX_OPERAND: u32 = i32::MAX as u32;
+const MAX_DIGITS: usize = 10;
+
+// a width or precision and what comes after it. a specifier always ends in a letter, so running
+// off the end of `mem` is an error too
+pub fn parse_int(mem: &[u8]) -> Result<(u32, &[u8]), ParseError> {
+    let zeros = mem.iter().take_while(|&&b| b == b'0').count();
+    let mut val: u32 = 0;
+    let mut digits = 0;
+    for &curr in &mem[zeros..] {
+        if !curr.is_ascii_digit() {
+            break;
+        }
+        digits += 1;
+        if digits > MAX_DIGITS {
+            return Err(ParseError::TooManyDigits);
+        }
+        // 10 digits can still be past a u32, so this is checked before it's compared
+        val = val
+            .checked_mul(10)
+            .and_then(|v| v.checked_add((curr - b'0') as u32))
+            .filter(|&v| v <= MAX_OPERAND)
+            .ok_or(ParseError::Overflow)?;
+    }
+    match mem.get(zeros + digits..) {
+        Some(rest) if !rest.is_empty() => Ok((val, rest)),
+        _ => Err(ParseError::Truncated),
+    }
+}
+
+// the program image with the second stage un-xored
+pub fn decrypted_image() -> Vec<u8> {
+    unxor_stage2(include_bytes!("../mem"))
+}
+
+// stage2 is xored with one key byte, whatever turns its first byte into a '%'
+pub fn unxor_stage2(image: &[u8]) -> Vec<u8> {
+    let mut mem = image.to_vec();
+    let key = b'%' ^ mem[0xc8];
+    for b in &mut mem[0xc8..0x6fc] {
+        *b ^= key;
+    }
+    mem
+}
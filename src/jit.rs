@@ -0,0 +1,932 @@
+// native code for hot vm functions, through cranelift. the interpreter runs as usual and counts how
+// often each function gets called, once one gets hot it and everything it calls are compiled, and
+// calls into it run natively from then on.
+//
+// a vm function is straight line code with conditional calls, so each one becomes a single native
+// function taking a Ctx and the call depth. a call to itself right before a ret, which is how the
+// program loops, jumps back to the top instead, counting the frames it stands for. registers are
+// held natively and only go back to State::regs around calls and on the way out, so callees and
+// the interpreter see them. memory is loaded and stored inline after a bounds check against
+// State::mem, anything past its end or into compiled code goes out to State::read_width and
+// store_width so growing, faults and code writes work like they do in the interpreter. with the log
+// or the trace on, or big endian memory, every access goes through them. steps are counted, but
+// there are no Step/Call/Return events for native code.
+//
+// native code unwinds instead of carrying on when something goes wrong, each frame adding its
+// return address so the interpreter gets the vm stack back: on a fault, and on a store that changes
+// compiled code, where the interpreter picks up right after the store. compiled code is checked
+// against memory every time the interpreter calls into it, and thrown away if it's changed
+use crate::decode::{self, Body};
+use crate::ex::State;
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width};
+use crate::memory::Endian;
+use crate::vm::{Vm, VmError, WxMode, MAX_DEPTH};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, Block, InstBuilder, MemFlagsData, Signature, UserFuncName, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use std::collections::{HashMap, HashSet};
+use std::mem::{offset_of, ManuallyDrop};
+use std::ops::Range;
+
+// calls into a function before it's compiled
+const HOT: u32 = 16;
+
+// why native code is unwinding, in Ctx::status
+const RUNNING: u64 = 0;
+const FAULT: u64 = 1;
+const CODE_WRITE: u64 = 2;
+
+// what compiled code works on. the first few fields are read and written by the generated code
+#[repr(C)]
+struct Ctx {
+    regs: *mut i32,
+    // State::mem, the helpers point it at the new one when memory grows
+    mem: *mut u8,
+    len: u64,
+    steps: u64,
+    status: u64,
+    // bytes of compiled code, stores changing them bail out to the interpreter
+    code_start: u64,
+    code_end: u64,
+    // everything stored to, for the interpreter's decode cache. nothing yet is start past end
+    written_start: u64,
+    written_end: u64,
+    // Jit::image, what the code bytes were compiled from
+    image: *const u8,
+    state: *mut State,
+    // where the interpreter picks up after unwinding
+    pc: usize,
+    error: Option<VmError>,
+    // return addresses from the innermost frame out
+    unwound: Vec<usize>,
+}
+
+// compiled function: takes the Ctx and how deep the vm stack is, returns its status
+type Native = unsafe extern "C" fn(*mut Ctx, u64) -> u64;
+
+// the bytes a function was compiled from, and what it calls
+struct Source {
+    range: Range<usize>,
+    bytes: Vec<u8>,
+    callees: Vec<usize>,
+}
+
+pub struct Jit {
+    // freed by hand on drop, JITModule leaks its code otherwise
+    module: ManuallyDrop<JITModule>,
+    funcs: HashMap<usize, Native>,
+    ids: HashMap<usize, FuncId>,
+    calls: HashMap<usize, u32>,
+    // functions that can't be compiled, like ones that fault on a bad register
+    cold: HashSet<usize>,
+    code: Range<usize>,
+    // `code` as it was compiled, a store putting back the same bytes doesn't change anything
+    image: Vec<u8>,
+    sources: HashMap<usize, Source>,
+    // memory goes through the helpers for everything, for the log and the trace
+    watched: bool,
+    // imported helpers
+    read: FuncId,
+    store: FuncId,
+    fault: FuncId,
+    unwind: FuncId,
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false").unwrap();
+        flags.set("is_pic", "false").unwrap();
+        flags.set("opt_level", "speed").unwrap();
+        let isa = cranelift_native::builder()
+            .expect("host machine isn't supported by cranelift")
+            .finish(settings::Flags::new(flags))
+            .unwrap();
+
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("jit_read", jit_read as *const u8);
+        builder.symbol("jit_store", jit_store as *const u8);
+        builder.symbol("jit_fault", jit_fault as *const u8);
+        builder.symbol("jit_unwind", jit_unwind as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let ptr = module.target_config().pointer_type();
+        let sig = |module: &JITModule, params: &[types::Type], ret: Option<types::Type>| {
+            let mut sig = module.make_signature();
+            sig.params.push(AbiParam::new(ptr));
+            sig.params.extend(params.iter().map(|&t| AbiParam::new(t)));
+            sig.returns.extend(ret.map(AbiParam::new));
+            sig
+        };
+        let (i32t, i64t) = (types::I32, types::I64);
+        let read = sig(&module, &[i32t, i32t, i64t], Some(i32t));
+        let store = sig(&module, &[i32t, i32t, i32t, i64t, i64t], None);
+        let fault = sig(&module, &[i32t, i64t], None);
+        let unwind = sig(&module, &[i64t, i64t], None);
+
+        let mut import = |name, sig: &Signature| module.declare_function(name, Linkage::Import, sig).unwrap();
+        let read = import("jit_read", &read);
+        let store = import("jit_store", &store);
+        let fault = import("jit_fault", &fault);
+        let unwind = import("jit_unwind", &unwind);
+
+        Jit {
+            module: ManuallyDrop::new(module),
+            funcs: HashMap::new(),
+            ids: HashMap::new(),
+            calls: HashMap::new(),
+            cold: HashSet::new(),
+            code: 0..0,
+            image: Vec::new(),
+            sources: HashMap::new(),
+            watched: false,
+            read,
+            store,
+            fault,
+            unwind,
+        }
+    }
+
+    // throw away everything compiled
+    fn reset(&mut self, watched: bool) {
+        *self = Jit::new();
+        self.watched = watched;
+    }
+
+    // native code for the function at `addr`, if it's been called enough to be worth compiling
+    fn hot(&mut self, addr: usize, mem: &[u8], regs: usize) -> Option<Native> {
+        if let Some(&f) = self.funcs.get(&addr) {
+            if self.unchanged(addr, mem) {
+                return Some(f);
+            }
+            // the interpreter wrote over compiled code, or this is another run's memory
+            self.reset(self.watched);
+        }
+        if self.cold.contains(&addr) {
+            return None;
+        }
+        let calls = self.calls.entry(addr).or_default();
+        *calls += 1;
+        if *calls < HOT {
+            return None;
+        }
+        if self.compile(addr, mem, regs).is_none() {
+            self.cold.insert(addr);
+        }
+        self.funcs.get(&addr).copied()
+    }
+
+    // whether the function at `addr` and everything it calls are still what was compiled
+    fn unchanged(&self, addr: usize, mem: &[u8]) -> bool {
+        let mut todo = vec![addr];
+        let mut seen = HashSet::new();
+        while let Some(addr) = todo.pop() {
+            if !seen.insert(addr) {
+                continue;
+            }
+            let source = &self.sources[&addr];
+            if mem.get(source.range.clone()) != Some(&source.bytes[..]) {
+                return false;
+            }
+            todo.extend(&source.callees);
+        }
+        true
+    }
+
+    // compile `entry` and everything it can call that isn't compiled yet
+    fn compile(&mut self, entry: usize, mem: &[u8], regs: usize) -> Option<()> {
+        let mut bodies: Vec<(usize, Body)> = Vec::new();
+        let mut todo = vec![entry];
+        let mut seen = HashSet::new();
+        while let Some(addr) = todo.pop() {
+            if self.funcs.contains_key(&addr) || !seen.insert(addr) {
+                continue;
+            }
+            let body = decode::function(addr, mem, regs)?;
+            for (_, inst, _) in &body {
+                if inst.op == Operation::Jmp {
+                    todo.push(inst.dest as usize);
+                }
+            }
+            bodies.push((addr, body));
+        }
+
+        let ptr = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(ptr));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+
+        for (addr, _) in &bodies {
+            let name = format!("vm_{:x}", addr);
+            let id = self.module.declare_function(&name, Linkage::Local, &sig).ok()?;
+            self.ids.insert(*addr, id);
+        }
+
+        let mut ctx = self.module.make_context();
+        let mut fctx = FunctionBuilderContext::new();
+        for (addr, body) in &bodies {
+            let id = self.ids[addr];
+            ctx.func.signature = sig.clone();
+            ctx.func.name = UserFuncName::user(0, id.as_u32());
+            self.translate(&mut ctx, &mut fctx, *addr, body);
+            self.module.define_function(id, &mut ctx).ok()?;
+            self.module.clear_context(&mut ctx);
+        }
+        self.module.finalize_definitions().ok()?;
+
+        for (addr, body) in &bodies {
+            let code = self.module.get_finalized_function(self.ids[addr]);
+            // safety: it was just built with the Native signature
+            let f = unsafe { std::mem::transmute::<*const u8, Native>(code) };
+            self.funcs.insert(*addr, f);
+
+            let end = body.last().map_or(*addr, |&(_, _, next)| next);
+            let callees = body.iter().filter(|(_, inst, _)| inst.op == Operation::Jmp).map(|(_, inst, _)| inst.dest as usize);
+            let source = Source {
+                range: *addr..end,
+                bytes: mem[*addr..end].to_vec(),
+                callees: callees.collect(),
+            };
+            self.sources.insert(*addr, source);
+            self.code = if self.code.is_empty() {
+                *addr..end
+            } else {
+                self.code.start.min(*addr)..self.code.end.max(end)
+            };
+        }
+        // the bytes between functions as they are now, and each function as it was compiled
+        self.image = mem[self.code.clone()].to_vec();
+        for source in self.sources.values() {
+            let at = source.range.start - self.code.start;
+            self.image[at..at + source.bytes.len()].copy_from_slice(&source.bytes);
+        }
+        Some(())
+    }
+
+    fn translate(
+        &mut self,
+        ctx: &mut Context,
+        fctx: &mut FunctionBuilderContext,
+        addr: usize,
+        body: &Body,
+    ) {
+        let config = self.module.target_config();
+        let mut b = FunctionBuilder::new(&mut ctx.func, fctx);
+        let start = b.create_block();
+        b.append_block_params_for_function_params(start);
+        b.switch_to_block(start);
+        let depth = b.declare_var(types::I64);
+        b.def_var(depth, b.block_params(start)[1]);
+        let frames = b.declare_var(types::I64);
+        let none = b.ins().iconst(types::I64, 0);
+        b.def_var(frames, none);
+
+        // the call to itself that's a loop, and where it returns to
+        let tail = body
+            .windows(2)
+            .find(|w| w[0].1.op == Operation::Jmp && w[0].1.dest as usize == addr && w[1].1.op == Operation::Ret)
+            .map(|w| (w[0].0, w[0].2));
+
+        let mut t = Translator {
+            ctx: b.block_params(start)[0],
+            depth,
+            frames,
+            tail: tail.map(|(_, next)| next),
+            head: start,
+            regs: None,
+            vars: HashMap::new(),
+            written: Vec::new(),
+            pending: 0,
+            inline: !self.watched,
+            read: self.module.declare_func_in_func(self.read, b.func),
+            store: self.module.declare_func_in_func(self.store, b.func),
+            fault: self.module.declare_func_in_func(self.fault, b.func),
+            unwind: self.module.declare_func_in_func(self.unwind, b.func),
+            b,
+        };
+        t.regs = Some(t.load(types::I64, offset_of!(Ctx, regs)));
+
+        // every register the body looks at, and the ones it changes
+        for (_, inst, _) in body {
+            let mut used = vec![];
+            match inst.op {
+                Operation::Ret => {}
+                Operation::Jmp => used.push(inst.src),
+                _ => {
+                    if matches!(inst.src_mode, SrcMode::H | SrcMode::L) {
+                        used.push(inst.src);
+                    }
+                    if matches!(inst.dest_mode, DestMode::NoPlusMinus | DestMode::Plus) {
+                        used.push(inst.dest);
+                    }
+                    if inst.dest_mode == DestMode::NoPlusMinus && !t.written.contains(&inst.dest) {
+                        t.written.push(inst.dest);
+                    }
+                }
+            }
+            for n in used {
+                if !t.vars.contains_key(&n) {
+                    let var = t.b.declare_var(types::I32);
+                    t.vars.insert(n, var);
+                }
+            }
+        }
+        t.reload();
+        t.head = t.b.create_block();
+        t.b.ins().jump(t.head, &[]);
+        t.b.switch_to_block(t.head);
+
+        // the functions this one calls, they're all compiled or being compiled
+        let mut funcs = HashMap::new();
+        for (_, inst, _) in body {
+            let target = inst.dest as usize;
+            if inst.op == Operation::Jmp && !funcs.contains_key(&target) {
+                let f = self.module.declare_func_in_func(self.ids[&target], t.b.func);
+                funcs.insert(target, f);
+            }
+        }
+
+        for &(pc, inst, next) in body {
+            t.pending += 1;
+            match inst.op {
+                Operation::Ret => {
+                    t.flush_steps();
+                    t.tail_rets();
+                    t.spill();
+                    let ok = t.b.ins().iconst(types::I64, RUNNING as i64);
+                    t.b.ins().return_(&[ok]);
+                }
+                Operation::Jmp if tail.map(|(at, _)| at) == Some(pc) => t.loop_call(&inst, pc),
+                Operation::Jmp => t.call(&inst, pc, next, funcs[&(inst.dest as usize)]),
+                op => t.arith(op, &inst, pc, next),
+            }
+        }
+
+        t.b.seal_all_blocks();
+        t.b.finalize(config);
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // safety: every Native from this module is gone with the Jit
+        unsafe { ManuallyDrop::take(&mut self.module).free_memory() };
+    }
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Translator<'a> {
+    b: FunctionBuilder<'a>,
+    ctx: Value,
+    depth: Variable,
+    // calls to itself taken as a loop, each one a frame the vm would have pushed `tail` for
+    frames: Variable,
+    tail: Option<usize>,
+    // where the loop goes back to, after the registers are loaded
+    head: Block,
+    regs: Option<Value>,
+    // registers held natively, and the ones that need storing back
+    vars: HashMap<u32, Variable>,
+    written: Vec<u32>,
+    // steps not added to Ctx::steps yet, they're flushed before a call or return and added on the
+    // way out of anything that unwinds
+    pending: i64,
+    // memory loaded and stored inline, the helpers only for what the bounds check turns away
+    inline: bool,
+    read: cranelift_codegen::ir::FuncRef,
+    store: cranelift_codegen::ir::FuncRef,
+    fault: cranelift_codegen::ir::FuncRef,
+    unwind: cranelift_codegen::ir::FuncRef,
+}
+
+impl Translator<'_> {
+    fn load(&mut self, ty: types::Type, offset: usize) -> Value {
+        self.b.ins().load(ty, MemFlagsData::trusted(), self.ctx, offset as i32)
+    }
+
+    fn save(&mut self, value: Value, offset: usize) {
+        self.b.ins().store(MemFlagsData::trusted(), value, self.ctx, offset as i32);
+    }
+
+    fn reg(&mut self, n: u32) -> Value {
+        self.b.use_var(self.vars[&n])
+    }
+
+    fn set_reg(&mut self, n: u32, value: Value) {
+        self.b.def_var(self.vars[&n], value);
+    }
+
+    // registers from State::regs, after a callee could have changed them
+    fn reload(&mut self) {
+        let regs = self.regs.unwrap();
+        for (&n, &var) in &self.vars {
+            let value = self.b.ins().load(types::I32, MemFlagsData::trusted(), regs, n as i32 * 4);
+            self.b.def_var(var, value);
+        }
+    }
+
+    // registers back to State::regs, before anything else can look at them
+    fn spill(&mut self) {
+        let regs = self.regs.unwrap();
+        for i in 0..self.written.len() {
+            let n = self.written[i];
+            let value = self.b.use_var(self.vars[&n]);
+            self.b.ins().store(MemFlagsData::trusted(), value, regs, n as i32 * 4);
+        }
+    }
+
+    fn imm(&mut self, n: u32) -> Value {
+        self.b.ins().iconst(types::I32, n as i32 as i64)
+    }
+
+    fn wide(&mut self, n: usize) -> Value {
+        self.b.ins().iconst(types::I64, n as i64)
+    }
+
+    // add the pending steps on a way out, code carrying on past it still has them pending
+    fn exit_steps(&mut self) {
+        if self.pending > 0 {
+            let steps = self.load(types::I64, offset_of!(Ctx, steps));
+            let steps = self.b.ins().iadd_imm_s(steps, self.pending);
+            self.save(steps, offset_of!(Ctx, steps));
+        }
+    }
+
+    fn flush_steps(&mut self) {
+        self.exit_steps();
+        self.pending = 0;
+    }
+
+    // return the status if a helper set one
+    fn check(&mut self) {
+        let status = self.load(types::I64, offset_of!(Ctx, status));
+        let unwinding = self.b.create_block();
+        let ok = self.b.create_block();
+        self.b.set_cold_block(unwinding);
+        self.b.ins().brif(status, unwinding, &[], ok, &[]);
+
+        self.b.switch_to_block(unwinding);
+        self.exit_steps();
+        self.spill();
+        self.unwind_frames();
+        self.b.ins().return_(&[status]);
+
+        self.b.switch_to_block(ok);
+    }
+
+    fn fail(&mut self, kind: i64, pc: usize) {
+        self.exit_steps();
+        self.spill();
+        let kind = self.b.ins().iconst(types::I32, kind);
+        let pc = self.wide(pc);
+        let ctx = self.ctx;
+        self.b.ins().call(self.fault, &[ctx, kind, pc]);
+        self.unwind_frames();
+        let status = self.b.ins().iconst(types::I64, FAULT as i64);
+        self.b.ins().return_(&[status]);
+    }
+
+    // the return addresses of the frames the loop stands for
+    fn unwind_frames(&mut self) {
+        if let Some(tail) = self.tail {
+            let ret = self.wide(tail);
+            let frames = self.b.use_var(self.frames);
+            let ctx = self.ctx;
+            self.b.ins().call(self.unwind, &[ctx, ret, frames]);
+        }
+    }
+
+    // each frame the loop stands for would have run its ret
+    fn tail_rets(&mut self) {
+        if self.tail.is_some() {
+            let steps = self.load(types::I64, offset_of!(Ctx, steps));
+            let frames = self.b.use_var(self.frames);
+            let steps = self.b.ins().iadd(steps, frames);
+            self.save(steps, offset_of!(Ctx, steps));
+        }
+    }
+
+    // branch on a call's condition and the stack limit, leaving the builder where the call is made.
+    // returns the block for after the call
+    fn taken(&mut self, inst: &Instruction, pc: usize) -> Block {
+        self.flush_steps();
+        let cond = self.reg(inst.src);
+        let cc = match inst.dest_mode {
+            DestMode::Minus => Some(IntCC::SignedLessThan),
+            DestMode::Plus => Some(IntCC::SignedGreaterThan),
+            DestMode::ZeroPad => Some(IntCC::Equal),
+            DestMode::NoPlusMinus => None,
+        };
+
+        let taken = self.b.create_block();
+        let after = self.b.create_block();
+        match cc {
+            Some(cc) => {
+                let taken_ = self.b.ins().icmp_imm_s(cc, cond, 0);
+                self.b.ins().brif(taken_, taken, &[], after, &[]);
+            }
+            None => {
+                self.b.ins().jump(taken, &[]);
+            }
+        }
+
+        self.b.switch_to_block(taken);
+        let depth = self.b.use_var(self.depth);
+        let full = self.b.ins().icmp_imm_s(IntCC::UnsignedGreaterThanOrEqual, depth, MAX_DEPTH as i64);
+        let overflow = self.b.create_block();
+        let call = self.b.create_block();
+        self.b.set_cold_block(overflow);
+        self.b.ins().brif(full, overflow, &[], call, &[]);
+
+        self.b.switch_to_block(overflow);
+        self.fail(STACK_OVERFLOW, pc);
+
+        self.b.switch_to_block(call);
+        after
+    }
+
+    // %C: a call when the condition on the src register holds
+    fn call(&mut self, inst: &Instruction, pc: usize, next: usize, target: cranelift_codegen::ir::FuncRef) {
+        let after = self.taken(inst, pc);
+        self.spill();
+        let depth = self.b.use_var(self.depth);
+        let deeper = self.b.ins().iadd_imm_s(depth, 1);
+        let ctx = self.ctx;
+        let call = self.b.ins().call(target, &[ctx, deeper]);
+        let status = self.b.inst_results(call)[0];
+
+        // the callee's registers are already in State::regs, this frame only adds `next` to the
+        // unwound stack
+        let unwinding = self.b.create_block();
+        let returned = self.b.create_block();
+        self.b.set_cold_block(unwinding);
+        self.b.ins().brif(status, unwinding, &[], returned, &[]);
+
+        self.b.switch_to_block(unwinding);
+        let ret = self.wide(next);
+        let one = self.wide(1);
+        self.b.ins().call(self.unwind, &[ctx, ret, one]);
+        self.unwind_frames();
+        self.b.ins().return_(&[status]);
+
+        self.b.switch_to_block(returned);
+        self.reload();
+        self.b.ins().jump(after, &[]);
+
+        self.b.switch_to_block(after);
+    }
+
+    // the call to itself before a ret: one frame deeper and back to the top, registers and all
+    fn loop_call(&mut self, inst: &Instruction, pc: usize) {
+        let after = self.taken(inst, pc);
+        let depth = self.b.use_var(self.depth);
+        let deeper = self.b.ins().iadd_imm_s(depth, 1);
+        self.b.def_var(self.depth, deeper);
+        let frames = self.b.use_var(self.frames);
+        let frames = self.b.ins().iadd_imm_s(frames, 1);
+        self.b.def_var(self.frames, frames);
+        self.b.ins().jump(self.head, &[]);
+
+        self.b.switch_to_block(after);
+    }
+
+    // the guest address as an offset into State::mem, and the end of the access
+    fn span(&mut self, addr: Value, width: Width) -> (Value, Value) {
+        let index = self.b.ins().uextend(types::I64, addr);
+        let end = self.b.ins().iadd_imm_s(index, width.bytes() as i64);
+        (index, end)
+    }
+
+    fn host(&mut self, index: Value) -> Value {
+        let mem = self.load(types::I64, offset_of!(Ctx, mem));
+        self.b.ins().iadd(mem, index)
+    }
+
+    // narrow reads are zero extended, like State::read_width
+    fn mem_read(&mut self, addr: Value, width: Width, pc: usize) -> Value {
+        if !self.inline {
+            return self.read_helper(addr, width, pc);
+        }
+        let (index, end) = self.span(addr, width);
+        let len = self.load(types::I64, offset_of!(Ctx, len));
+        let fits = self.b.ins().icmp(IntCC::UnsignedLessThanOrEqual, end, len);
+
+        let fast = self.b.create_block();
+        let slow = self.b.create_block();
+        let done = self.b.create_block();
+        self.b.set_cold_block(slow);
+        let value = self.b.append_block_param(done, types::I32);
+        self.b.ins().brif(fits, fast, &[], slow, &[]);
+
+        self.b.switch_to_block(fast);
+        let at = self.host(index);
+        let flags = MemFlagsData::new().with_notrap();
+        let loaded = match width {
+            Width::W8 => self.b.ins().uload8(types::I32, flags, at, 0),
+            Width::W16 => self.b.ins().uload16(types::I32, flags, at, 0),
+            Width::W32 | Width::W64 => self.b.ins().load(types::I32, flags, at, 0),
+        };
+        self.b.ins().jump(done, &[loaded.into()]);
+
+        // growing memory or faulting
+        self.b.switch_to_block(slow);
+        let read = self.read_helper(addr, width, pc);
+        self.b.ins().jump(done, &[read.into()]);
+
+        self.b.switch_to_block(done);
+        value
+    }
+
+    fn read_helper(&mut self, addr: Value, width: Width, pc: usize) -> Value {
+        let bytes = self.b.ins().iconst(types::I32, width.bytes() as i64);
+        let pc = self.wide(pc);
+        let ctx = self.ctx;
+        let call = self.b.ins().call(self.read, &[ctx, addr, bytes, pc]);
+        let value = self.b.inst_results(call)[0];
+        self.check();
+        value
+    }
+
+    fn mem_store(&mut self, addr: Value, value: Value, width: Width, pc: usize, next: usize) {
+        if !self.inline {
+            self.store_helper(addr, value, width, pc, next);
+            return;
+        }
+        let (index, end) = self.span(addr, width);
+        let len = self.load(types::I64, offset_of!(Ctx, len));
+        let fits = self.b.ins().icmp(IntCC::UnsignedLessThanOrEqual, end, len);
+        let code_start = self.load(types::I64, offset_of!(Ctx, code_start));
+        let code_end = self.load(types::I64, offset_of!(Ctx, code_end));
+        let below = self.b.ins().icmp(IntCC::UnsignedLessThan, index, code_end);
+        let above = self.b.ins().icmp(IntCC::UnsignedGreaterThan, end, code_start);
+        let code = self.b.ins().band(below, above);
+        let plain = self.b.ins().band_not(fits, code);
+
+        let fast = self.b.create_block();
+        let slow = self.b.create_block();
+        let done = self.b.create_block();
+        self.b.set_cold_block(slow);
+        self.b.ins().brif(plain, fast, &[], slow, &[]);
+
+        self.b.switch_to_block(fast);
+        let at = self.host(index);
+        let flags = MemFlagsData::new().with_notrap();
+        match width {
+            Width::W8 => self.b.ins().istore8(flags, value, at, 0),
+            Width::W16 => self.b.ins().istore16(flags, value, at, 0),
+            Width::W32 | Width::W64 => self.b.ins().store(flags, value, at, 0),
+        };
+        let start = self.load(types::I64, offset_of!(Ctx, written_start));
+        let start = self.b.ins().umin(start, index);
+        self.save(start, offset_of!(Ctx, written_start));
+        let stop = self.load(types::I64, offset_of!(Ctx, written_end));
+        let stop = self.b.ins().umax(stop, end);
+        self.save(stop, offset_of!(Ctx, written_end));
+        self.b.ins().jump(done, &[]);
+
+        // growing memory, faulting or writing over compiled code
+        self.b.switch_to_block(slow);
+        self.store_helper(addr, value, width, pc, next);
+        self.b.ins().jump(done, &[]);
+
+        self.b.switch_to_block(done);
+    }
+
+    fn store_helper(&mut self, addr: Value, value: Value, width: Width, pc: usize, next: usize) {
+        let bytes = self.b.ins().iconst(types::I32, width.bytes() as i64);
+        let pc = self.wide(pc);
+        let next = self.wide(next);
+        let ctx = self.ctx;
+        self.b.ins().call(self.store, &[ctx, addr, value, bytes, pc, next]);
+        self.check();
+    }
+
+    // everything that isn't a call or ret, same order of reads as the interpreter
+    fn arith(&mut self, op: Operation, inst: &Instruction, pc: usize, next: usize) {
+        let src = match inst.src_mode {
+            SrcMode::HH => {
+                let addr = self.imm(inst.src);
+                self.mem_read(addr, inst.width, pc)
+            }
+            SrcMode::H => {
+                let addr = self.reg(inst.src);
+                self.mem_read(addr, inst.width, pc)
+            }
+            SrcMode::L => self.reg(inst.src),
+            SrcMode::LL => self.imm(inst.src),
+            SrcMode::None => unreachable!(),
+        };
+
+        match inst.dest_mode {
+            DestMode::NoPlusMinus => {
+                let dest = self.reg(inst.dest);
+                let value = self.apply(op, dest, src, pc);
+                self.set_reg(inst.dest, value);
+            }
+            DestMode::Minus | DestMode::Plus => {
+                let addr = match inst.dest_mode {
+                    DestMode::Minus => self.imm(inst.dest),
+                    _ => self.reg(inst.dest),
+                };
+                let value = match op {
+                    Operation::Mov => src,
+                    _ => {
+                        let dest = self.mem_read(addr, inst.width, pc);
+                        self.apply(op, dest, src, pc)
+                    }
+                };
+                self.mem_store(addr, value, inst.width, pc, next);
+            }
+            DestMode::ZeroPad => unreachable!(),
+        }
+    }
+
+    // wrapping int math, idiv and sar, like vm::apply
+    fn apply(&mut self, op: Operation, dest: Value, src: Value, pc: usize) -> Value {
+        let ins = self.b.ins();
+        match op {
+            Operation::Mov => src,
+            Operation::Add => ins.iadd(dest, src),
+            Operation::Sub => ins.isub(dest, src),
+            Operation::Mul => ins.imul(dest, src),
+            Operation::ShLeft => ins.ishl(dest, src),
+            Operation::ShRight => ins.sshr(dest, src),
+            Operation::Xor => ins.bxor(dest, src),
+            Operation::And => ins.band(dest, src),
+            Operation::Or => ins.bor(dest, src),
+            Operation::Div | Operation::Mod => {
+                let zero = self.b.create_block();
+                let divide = self.b.create_block();
+                self.b.ins().brif(src, divide, &[], zero, &[]);
+
+                self.b.switch_to_block(zero);
+                self.fail(DIVIDE_BY_ZERO, pc);
+
+                // i32::MIN / -1 traps natively, wrapping_div gives MIN and wrapping_rem 0, which
+                // is what dividing by 1 instead does
+                self.b.switch_to_block(divide);
+                let min = self.b.ins().icmp_imm_s(IntCC::Equal, dest, i32::MIN as i64);
+                let minus_one = self.b.ins().icmp_imm_s(IntCC::Equal, src, -1);
+                let overflow = self.b.ins().band(min, minus_one);
+                let one = self.b.ins().iconst(types::I32, 1);
+                let src = self.b.ins().select(overflow, one, src);
+                match op {
+                    Operation::Div => self.b.ins().sdiv(dest, src),
+                    _ => self.b.ins().srem(dest, src),
+                }
+            }
+            Operation::Jmp | Operation::Ret => unreachable!(),
+        }
+    }
+}
+
+// kinds for jit_fault
+const DIVIDE_BY_ZERO: i64 = 0;
+const STACK_OVERFLOW: i64 = 1;
+
+fn width(bytes: i32) -> Width {
+    match bytes {
+        1 => Width::W8,
+        2 => Width::W16,
+        _ => Width::W32,
+    }
+}
+
+// a fault on the State (out of bounds) turns into the vm error. memory may have grown, so native
+// code gets pointed at it again
+unsafe fn check_state(ctx: &mut Ctx, pc: u64) {
+    let s = &mut *ctx.state;
+    ctx.mem = s.mem.as_mut_ptr();
+    ctx.len = s.mem.len() as u64;
+    if let Some(e) = s.fault {
+        ctx.status = FAULT;
+        ctx.error = Some(e);
+        ctx.pc = pc as usize;
+    }
+}
+
+unsafe extern "C" fn jit_read(ctx: *mut Ctx, addr: i32, bytes: i32, pc: u64) -> i32 {
+    let ctx = &mut *ctx;
+    let value = (*ctx.state).read_width(addr, width(bytes));
+    check_state(ctx, pc);
+    value
+}
+
+unsafe extern "C" fn jit_store(ctx: *mut Ctx, addr: i32, value: i32, bytes: i32, pc: u64, next: u64) {
+    let ctx = &mut *ctx;
+    (*ctx.state).store_width(addr, value, width(bytes));
+    check_state(ctx, pc);
+    let index = addr as u32 as u64;
+    let end = index + bytes as u64;
+    ctx.written_start = ctx.written_start.min(index);
+    ctx.written_end = ctx.written_end.max(end);
+    if ctx.status == RUNNING && index < ctx.code_end && end > ctx.code_start {
+        let (start, stop) = (index.max(ctx.code_start), end.min(ctx.code_end));
+        let image = std::slice::from_raw_parts(ctx.image, (ctx.code_end - ctx.code_start) as usize);
+        let s = &*ctx.state;
+        let stored = &s.mem[start as usize..stop as usize];
+        if stored != &image[(start - ctx.code_start) as usize..(stop - ctx.code_start) as usize] {
+            ctx.status = CODE_WRITE;
+            ctx.pc = next as usize;
+        }
+    }
+}
+
+unsafe extern "C" fn jit_fault(ctx: *mut Ctx, kind: i32, pc: u64) {
+    let ctx = &mut *ctx;
+    let pc = pc as usize;
+    ctx.status = FAULT;
+    ctx.pc = pc;
+    ctx.error = Some(match kind as i64 {
+        DIVIDE_BY_ZERO => VmError::DivideByZero(pc),
+        _ => VmError::StackOverflow(pc),
+    });
+}
+
+unsafe extern "C" fn jit_unwind(ctx: *mut Ctx, ret: u64, count: u64) {
+    let ctx = &mut *ctx;
+    ctx.unwound.extend(std::iter::repeat_n(ret as usize, count as usize));
+}
+
+// run to the end like Vm::run, with hot functions compiled. the things native code doesn't keep
+// track of (W^X, memory protection, assertions and the decode cache) need the plain interpreter
+pub fn run(vm: &mut Vm) -> Result<(), VmError> {
+    run_with(vm, &mut Jit::new())
+}
+
+// run with a jit that can have code compiled already, like one kept from an earlier run of the
+// same program
+pub fn run_with(vm: &mut Vm, jit: &mut Jit) -> Result<(), VmError> {
+    let tracked = vm.wx.mode != WxMode::Off || vm.protection.is_some() || !vm.asserts.is_empty() || !vm.hooks.is_empty();
+    if tracked || vm.code.is_some() {
+        println!("jit: W^X, protection, assertions, call hooks and the decode cache need the interpreter");
+        return vm.run();
+    }
+
+    let watched = !vm.s.quiet || vm.s.trace.is_some() || vm.s.endian != Endian::Little;
+    if jit.watched != watched {
+        jit.reset(watched);
+    }
+    while !vm.halted {
+        let depth = vm.stack.len();
+        vm.step()?;
+        // just called into a function
+        if vm.stack.len() != depth + 1 {
+            continue;
+        }
+        let f = match jit.hot(vm.pc, &vm.s.mem, vm.s.regs.len()) {
+            Some(f) => f,
+            None => continue,
+        };
+
+        let mut ctx = Ctx {
+            regs: vm.s.regs.as_mut_ptr(),
+            mem: vm.s.mem.as_mut_ptr(),
+            len: vm.s.mem.len() as u64,
+            steps: 0,
+            status: RUNNING,
+            code_start: jit.code.start as u64,
+            code_end: jit.code.end as u64,
+            written_start: u64::MAX,
+            written_end: 0,
+            image: jit.image.as_ptr(),
+            state: &mut vm.s,
+            pc: 0,
+            error: None,
+            unwound: Vec::new(),
+        };
+        // safety: regs, state and the jit's image outlive the call, nothing resizes regs or the
+        // image while it runs, and only the helpers move memory, pointing mem and len at it again
+        let status = unsafe { f(&mut ctx, vm.stack.len() as u64) };
+        vm.steps += ctx.steps;
+        let written = ctx.written_end.saturating_sub(ctx.written_start);
+        vm.cache.invalidate(ctx.written_start as usize, written as usize);
+
+        match status {
+            RUNNING => match vm.stack.pop() {
+                Some(ret) => vm.pc = ret,
+                None => vm.halted = true,
+            },
+            _ => {
+                vm.pc = ctx.pc;
+                vm.stack.extend(ctx.unwound.iter().rev());
+                // after a write over compiled code the interpreter carries on, and whatever it
+                // calls next is checked against memory before it runs natively
+                if status == FAULT {
+                    return Err(ctx.error.unwrap());
+                }
+            }
+        }
+    }
+    Ok(())
+}
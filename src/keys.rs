@@ -0,0 +1,89 @@
+// recovering the xor key of an encrypted stage without knowing what its first byte should be.
+// every one byte key is tried and the plaintext it gives is scored by how much of it reads as
+// code: specifiers that decode, nuls between functions, and opcode letters. the right key turns
+// the whole stage into code, a wrong one leaves a '%' here and there and nothing that parses
+use crate::inst::try_parse;
+use std::ops::Range;
+
+// below this the best key is only reported, not used
+pub const CONFIDENT: f64 = 0.9;
+
+// the letters that end a specifier, see Instruction::parse
+const LETTERS: &[u8] = b"CMSOXVNLREIU";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate {
+    pub key: u8,
+    // fraction of the stage covered by instructions that decode
+    pub score: f64,
+    // how many of those aren't rets
+    pub specifiers: usize,
+    // opcode letters anywhere in the plaintext, decoded or not
+    pub letters: usize,
+}
+
+// how much of `plain` is code, sweeping it like the stage2 listing but stepping over junk
+pub fn score(key: u8, plain: &[u8]) -> Candidate {
+    let mut covered = 0;
+    let mut specifiers = 0;
+    let mut curr = 0;
+    while curr < plain.len() {
+        // only a '%' or a nul can start an instruction, skipping the rest saves a panic each
+        let decoded = match plain[curr] {
+            b'%' | 0 => try_parse(&plain[curr..]),
+            _ => None,
+        };
+        match decoded {
+            Some((_, len)) => {
+                covered += len;
+                if plain[curr] != 0 {
+                    specifiers += 1;
+                }
+                curr += len;
+            }
+            None => curr += 1,
+        }
+    }
+    Candidate {
+        key,
+        score: covered as f64 / plain.len().max(1) as f64,
+        specifiers,
+        letters: plain.iter().filter(|b| LETTERS.contains(b)).count(),
+    }
+}
+
+// every key for `range` of `image`, best first
+pub fn rank(image: &[u8], range: Range<usize>) -> Vec<Candidate> {
+    let cipher = &image[range];
+    let mut candidates: Vec<Candidate> = (0..=255u8)
+        .map(|key| {
+            let plain: Vec<u8> = cipher.iter().map(|b| b ^ key).collect();
+            score(key, &plain)
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap()
+            .then(b.specifiers.cmp(&a.specifiers))
+            .then(b.letters.cmp(&a.letters))
+    });
+    candidates
+}
+
+// the best key, if it scored at least CONFIDENT
+pub fn recover(image: &[u8], range: Range<usize>) -> Option<u8> {
+    rank(image, range)
+        .first()
+        .filter(|best| best.score >= CONFIDENT)
+        .map(|best| best.key)
+}
+
+// `image` with `range` xored by `key`
+pub fn unxor(image: &[u8], range: Range<usize>, key: u8) -> Vec<u8> {
+    let mut mem = image.to_vec();
+    for b in &mut mem[range] {
+        *b ^= key;
+    }
+    mem
+}
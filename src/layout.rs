@@ -0,0 +1,114 @@
+// what lives where in State::mem, and what the program should be allowed to do with it
+use crate::primes;
+use crate::vm::VmError;
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+// permission bits
+pub const R: u8 = 1;
+pub const W: u8 = 2;
+pub const X: u8 = 4;
+// W, but only once per byte
+pub const ONCE: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub range: Range<usize>,
+    pub perms: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLayout {
+    // first match wins, anything not covered is read-write scratch
+    pub regions: Vec<Region>,
+    // code in here runs before the protections mean anything. stage1 decrypts stage2 in place and
+    // puts "none" in the flag, like a loader would
+    pub loader: Range<usize>,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        let region = |name, range, perms| Region { name, range, perms };
+        MemoryLayout {
+            regions: vec![
+                region("stage1", 0x0..0xc8, R | X),
+                region("stage2", 0xc8..0x6fc, R | X),
+                region("image", 0x6fc..0x700, R),
+                region("user input", 0x1000..0x1100, R),
+                region("first pass", 0x1190..0x1290, R | W),
+                region("RNG numbers", primes::REGION, R | W),
+                region("flag output", 0x1800..0x1900, R | ONCE),
+            ],
+            loader: 0x0..0xc8,
+        }
+    }
+}
+
+impl MemoryLayout {
+    pub fn region(&self, index: usize) -> Option<&Region> {
+        self.regions.iter().find(|r| r.range.contains(&index))
+    }
+
+    pub fn perms(&self, index: usize) -> u8 {
+        self.region(index).map_or(R | W, |r| r.perms)
+    }
+}
+
+// a layout being enforced on a running vm
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    pub layout: MemoryLayout,
+    // bytes of ONCE regions that have had their write
+    written: BTreeSet<usize>,
+}
+
+impl Protection {
+    pub fn new(layout: MemoryLayout) -> Self {
+        Protection {
+            layout,
+            written: BTreeSet::new(),
+        }
+    }
+
+    // `len` bytes at `index`, accessed by the instruction at `pc`
+    pub fn check(&mut self, pc: usize, index: usize, len: usize, access: Access) -> Result<(), VmError> {
+        if self.layout.loader.contains(&pc) {
+            return Ok(());
+        }
+
+        for i in index..index.saturating_add(len) {
+            let perms = self.layout.perms(i);
+            let allowed = match access {
+                Access::Read => perms & R != 0,
+                Access::Execute => perms & X != 0,
+                Access::Write if perms & ONCE != 0 => self.written.insert(i),
+                Access::Write => perms & W != 0,
+            };
+            if !allowed {
+                return Err(VmError::ProtectionFault { pc, index: i, access });
+            }
+        }
+        Ok(())
+    }
+}
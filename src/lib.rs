@@ -0,0 +1,151 @@
+// without the std feature only the engine builds: decoding, the state and the interpreter, plus the
+// c interface to drive them. that's no_std + alloc, for wasm or embedded harnesses that do their
+// own i/o (see state::Console). everything else is tooling around it and needs std
+//
+//     cargo rustc --lib --no-default-features --crate-type rlib
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// format_vm! expands to paths through ::disasm, this makes those work in here too
+extern crate self as disasm;
+
+pub use format_vm::format_vm;
+
+// the engine
+pub mod decode;
+pub mod inst;
+pub mod layout;
+pub mod memory;
+pub mod primes;
+pub mod programs;
+pub mod state;
+pub mod trace;
+pub mod vm;
+pub mod word;
+// instructions and machines out of fuzzer bytes
+#[cfg(feature = "arbitrary")]
+pub mod structured;
+// assertions the interpreter checks, and the expressions they're made of
+pub mod expr;
+// the interpreter for c callers
+pub mod ffi;
+
+// emulation code in ex.rs, and the same generated from the image at build time
+#[cfg(feature = "std")]
+pub mod ex;
+#[cfg(feature = "std")]
+pub mod generated;
+// listings of the whole program, and what can be worked out from them
+#[cfg(feature = "std")]
+pub mod analyze;
+#[cfg(feature = "std")]
+pub mod defuse;
+#[cfg(feature = "std")]
+pub mod keys;
+#[cfg(feature = "std")]
+pub mod memdiff;
+#[cfg(feature = "std")]
+pub mod pointers;
+#[cfg(feature = "std")]
+pub mod ranges;
+#[cfg(feature = "std")]
+pub mod transform;
+#[cfg(feature = "std")]
+pub mod unpack;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod variant;
+#[cfg(feature = "std")]
+pub mod listing;
+#[cfg(feature = "std")]
+pub mod names;
+#[cfg(feature = "std")]
+pub mod project;
+// interactive prompt
+#[cfg(feature = "std")]
+pub mod repl;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod pager;
+#[cfg(feature = "std")]
+pub mod crash;
+#[cfg(feature = "std")]
+pub mod dashboard;
+#[cfg(feature = "std")]
+pub mod snapshot;
+// the challenge server, and a practice one
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod remote;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod metrics;
+// shell tab completion for the cli, and defaults for its flags
+#[cfg(feature = "std")]
+pub mod completions;
+#[cfg(feature = "std")]
+pub mod config;
+// the solution written up, and a standalone program that prints the flag
+#[cfg(feature = "std")]
+pub mod proof;
+#[cfg(feature = "std")]
+pub mod report;
+// the original binary against the emulator, and ex.rs against the program
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod native;
+#[cfg(feature = "std")]
+pub mod verify;
+// other ways of running the program, and a harness that checks them against ex.rs
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod jit;
+#[cfg(feature = "llvm")]
+pub mod lift;
+#[cfg(feature = "std")]
+pub mod threaded;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+// per phase timings of the interpreter, see benches/ for comparing the ways of running it, and
+// the summary at the end of a run
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod timing;
+// how long `run` gets, and what it leaves behind when that's up
+#[cfg(feature = "std")]
+pub mod budget;
+// every path through a program, forking at the branches, and inputs for each side of them
+#[cfg(feature = "std")]
+pub mod cover;
+#[cfg(feature = "std")]
+pub mod explore;
+// event recording, and the golden trace regression check
+#[cfg(feature = "std")]
+pub mod flame;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod golden;
+#[cfg(feature = "std")]
+pub mod recording;
+#[cfg(feature = "std")]
+pub mod tracediff;
+#[cfg(feature = "std")]
+pub mod heatmap;
+#[cfg(feature = "std")]
+pub mod hot;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "std")]
+pub mod dataflow;
+// random inputs for the fuzzers
+#[cfg(feature = "std")]
+pub mod rng;
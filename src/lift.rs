@@ -0,0 +1,290 @@
+// the decoded program as llvm ir, for llvm's optimizer or for klee to run symbolically. every vm
+// function becomes an ir function taking a pointer to
+//
+//     %state = type { [5 x i32], i8* }
+//
+// the registers, and a flat little endian memory the caller sets up. the image the functions were
+// lifted from is in @image for copying into it. memory isn't bounds checked or grown, that's up to
+// whoever owns the buffer (klee checks it anyway). a divide by zero calls @vm_fault with the kind
+// and pc, which the harness provides. there's no call depth limit, recursion is native recursion.
+//
+// the lift is of the code as it is in `mem`, so a program that writes over its own code (stage1
+// decrypting stage2) needs lifting from the image after that's happened
+use crate::ex::REGS;
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width};
+use crate::decode::{self, Body};
+use crate::project::Project;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::builder::{Builder, BuilderError};
+use inkwell::context::Context;
+use inkwell::module::Linkage;
+use inkwell::types::IntType;
+use inkwell::values::{BasicValue, FunctionValue, IntValue, PointerValue};
+use inkwell::{AddressSpace, IntPredicate};
+use std::collections::{BTreeMap, HashSet};
+
+// kinds passed to @vm_fault
+const DIVIDE_BY_ZERO: u64 = 0;
+
+// `entry` and everything it calls, as the text of an llvm module
+pub fn lift(mem: &[u8], entry: usize, project: &Project) -> Result<String, String> {
+    let bodies = discover(mem, entry)?;
+
+    let context = Context::create();
+    let module = context.create_module("weather");
+    let i8t = context.i8_type();
+    let i32t = context.i32_type();
+    let i64t = context.i64_type();
+    let state = context.opaque_struct_type("state");
+    state.set_body(
+        &[
+            i32t.array_type(REGS as u32).into(),
+            i8t.ptr_type(AddressSpace::default()).into(),
+        ],
+        false,
+    );
+
+    let image = module.add_global(i8t.array_type(mem.len() as u32), None, "image");
+    image.set_initializer(&context.const_string(mem, false));
+    image.set_constant(true);
+
+    let void = context.void_type();
+    let fault_type = void.fn_type(&[i32t.into(), i64t.into()], false);
+    let fault = module.add_function("vm_fault", fault_type, Some(Linkage::External));
+    let noreturn = context.create_enum_attribute(Attribute::get_named_enum_kind_id("noreturn"), 0);
+    fault.add_attribute(AttributeLoc::Function, noreturn);
+
+    let fn_type = void.fn_type(&[state.ptr_type(AddressSpace::default()).into()], false);
+    let funcs: BTreeMap<usize, FunctionValue> = bodies
+        .keys()
+        .map(|&addr| (addr, module.add_function(&project.function(addr), fn_type, None)))
+        .collect();
+
+    for (addr, body) in &bodies {
+        let mut t = Lifter {
+            context: &context,
+            builder: context.create_builder(),
+            func: funcs[addr],
+            i32t,
+            fault,
+        };
+        t.lift(body, &funcs).map_err(|e| format!("lifting {:#x}: {}", addr, e))?;
+    }
+
+    module.verify().map_err(|e| e.to_string())?;
+    Ok(module.print_to_string().to_string())
+}
+
+// the function at `entry` and everything reachable through its calls
+fn discover(mem: &[u8], entry: usize) -> Result<BTreeMap<usize, Body>, String> {
+    let mut bodies = BTreeMap::new();
+    let mut todo = vec![entry];
+    let mut seen = HashSet::new();
+    while let Some(addr) = todo.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        let body = decode::function(addr, mem, REGS).ok_or(format!("can't lift the function at {:#x}", addr))?;
+        for (_, inst, _) in &body {
+            if inst.op == Operation::Jmp {
+                todo.push(inst.dest as usize);
+            }
+        }
+        bodies.insert(addr, body);
+    }
+    Ok(bodies)
+}
+
+// builder calls only fail when the builder is misused, lift turns that into a string
+type Lifted<T> = Result<T, BuilderError>;
+
+struct Lifter<'ctx> {
+    context: &'ctx Context,
+    builder: Builder<'ctx>,
+    func: FunctionValue<'ctx>,
+    i32t: IntType<'ctx>,
+    fault: FunctionValue<'ctx>,
+}
+
+impl<'ctx> Lifter<'ctx> {
+    fn lift(&mut self, body: &Body, funcs: &BTreeMap<usize, FunctionValue<'ctx>>) -> Result<(), String> {
+        let start = self.context.append_basic_block(self.func, "start");
+        self.builder.position_at_end(start);
+        for &(pc, inst, _) in body {
+            match inst.op {
+                Operation::Ret => self.builder.build_return(None).map(|_| ()),
+                Operation::Jmp => self.call(&inst, funcs[&(inst.dest as usize)]),
+                op => self.arith(op, &inst, pc),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> PointerValue<'ctx> {
+        self.func.get_first_param().unwrap().into_pointer_value()
+    }
+
+    fn reg_ptr(&self, n: u32) -> Lifted<PointerValue<'ctx>> {
+        let regs = self.builder.build_struct_gep(self.state(), 0, "regs")?;
+        let zero = self.i32t.const_zero();
+        let n = self.i32t.const_int(n as u64, false);
+        // safety: the register number was checked against REGS when decoding
+        unsafe { self.builder.build_in_bounds_gep(regs, &[zero, n], "reg") }
+    }
+
+    fn reg(&self, n: u32) -> Lifted<IntValue<'ctx>> {
+        let ptr = self.reg_ptr(n)?;
+        Ok(self.builder.build_load(ptr, &format!("r{}", n))?.into_int_value())
+    }
+
+    fn set_reg(&self, n: u32, value: IntValue<'ctx>) -> Lifted<()> {
+        let ptr = self.reg_ptr(n)?;
+        self.builder.build_store(ptr, value)?;
+        Ok(())
+    }
+
+    fn imm(&self, n: u32) -> IntValue<'ctx> {
+        self.i32t.const_int(n as u64, false)
+    }
+
+    // pointer to `width` bytes of memory at `addr`, which is an unsigned index
+    fn mem_ptr(&self, addr: IntValue<'ctx>, width: Width) -> Lifted<(PointerValue<'ctx>, IntType<'ctx>)> {
+        let mem = self.builder.build_struct_gep(self.state(), 1, "mem")?;
+        let mem = self.builder.build_load(mem, "mem")?.into_pointer_value();
+        let index = self.builder.build_int_z_extend(addr, self.context.i64_type(), "index")?;
+        // safety: memory is the caller's buffer, it's up to them to make it big enough
+        let byte = unsafe { self.builder.build_gep(mem, &[index], "byte")? };
+        let ty = self.context.custom_width_int_type(width.bytes() as u32 * 8);
+        let ptr = self.builder.build_pointer_cast(byte, ty.ptr_type(AddressSpace::default()), "ptr")?;
+        Ok((ptr, ty))
+    }
+
+    // narrow reads are zero extended, like State::read_width
+    fn mem_read(&self, addr: IntValue<'ctx>, width: Width) -> Lifted<IntValue<'ctx>> {
+        let (ptr, ty) = self.mem_ptr(addr, width)?;
+        let load = self.builder.build_load(ptr, "load")?;
+        load.as_instruction_value().unwrap().set_alignment(1).unwrap();
+        let value = load.into_int_value();
+        if ty == self.i32t {
+            return Ok(value);
+        }
+        self.builder.build_int_z_extend(value, self.i32t, "value")
+    }
+
+    fn mem_store(&self, addr: IntValue<'ctx>, value: IntValue<'ctx>, width: Width) -> Lifted<()> {
+        let (ptr, ty) = self.mem_ptr(addr, width)?;
+        let value = if ty == self.i32t {
+            value
+        } else {
+            self.builder.build_int_truncate(value, ty, "narrow")?
+        };
+        let store = self.builder.build_store(ptr, value)?;
+        store.set_alignment(1).unwrap();
+        Ok(())
+    }
+
+    // %C: a call when the condition on the src register holds
+    fn call(&self, inst: &Instruction, target: FunctionValue<'ctx>) -> Lifted<()> {
+        let cond = self.reg(inst.src)?;
+        let zero = self.i32t.const_zero();
+        let taken = match inst.dest_mode {
+            DestMode::Minus => Some(self.builder.build_int_compare(IntPredicate::SLT, cond, zero, "taken")?),
+            DestMode::Plus => Some(self.builder.build_int_compare(IntPredicate::SGT, cond, zero, "taken")?),
+            DestMode::ZeroPad => Some(self.builder.build_int_compare(IntPredicate::EQ, cond, zero, "taken")?),
+            DestMode::NoPlusMinus => None,
+        };
+        let state = self.state().into();
+        let taken = match taken {
+            Some(taken) => taken,
+            None => {
+                self.builder.build_call(target, &[state], "")?;
+                return Ok(());
+            }
+        };
+
+        let call = self.context.append_basic_block(self.func, "call");
+        let after = self.context.append_basic_block(self.func, "after");
+        self.builder.build_conditional_branch(taken, call, after)?;
+        self.builder.position_at_end(call);
+        self.builder.build_call(target, &[state], "")?;
+        self.builder.build_unconditional_branch(after)?;
+        self.builder.position_at_end(after);
+        Ok(())
+    }
+
+    // everything that isn't a call or ret, same order of reads as the interpreter
+    fn arith(&self, op: Operation, inst: &Instruction, pc: usize) -> Lifted<()> {
+        let src = match inst.src_mode {
+            SrcMode::HH => self.mem_read(self.imm(inst.src), inst.width)?,
+            SrcMode::H => self.mem_read(self.reg(inst.src)?, inst.width)?,
+            SrcMode::L => self.reg(inst.src)?,
+            SrcMode::LL => self.imm(inst.src),
+            SrcMode::None => unreachable!(),
+        };
+
+        match inst.dest_mode {
+            DestMode::NoPlusMinus => {
+                let dest = self.reg(inst.dest)?;
+                let value = self.apply(op, dest, src, pc)?;
+                self.set_reg(inst.dest, value)
+            }
+            DestMode::Minus | DestMode::Plus => {
+                let addr = match inst.dest_mode {
+                    DestMode::Minus => self.imm(inst.dest),
+                    _ => self.reg(inst.dest)?,
+                };
+                let value = match op {
+                    Operation::Mov => src,
+                    _ => {
+                        let dest = self.mem_read(addr, inst.width)?;
+                        self.apply(op, dest, src, pc)?
+                    }
+                };
+                self.mem_store(addr, value, inst.width)
+            }
+            DestMode::ZeroPad => unreachable!(),
+        }
+    }
+
+    // wrapping int math like vm::apply. shift amounts are masked since llvm calls an oversized
+    // shift poison, and i32::MIN / -1 divides by 1 instead, which gives the same answer as wrapping
+    fn apply(&self, op: Operation, dest: IntValue<'ctx>, src: IntValue<'ctx>, pc: usize) -> Lifted<IntValue<'ctx>> {
+        let b = &self.builder;
+        let shift = || b.build_and(src, self.imm(31), "amount");
+        Ok(match op {
+            Operation::Mov => src,
+            Operation::Add => b.build_int_add(dest, src, "add")?,
+            Operation::Sub => b.build_int_sub(dest, src, "sub")?,
+            Operation::Mul => b.build_int_mul(dest, src, "mul")?,
+            Operation::ShLeft => b.build_left_shift(dest, shift()?, "shl")?,
+            Operation::ShRight => b.build_right_shift(dest, shift()?, true, "sar")?,
+            Operation::Xor => b.build_xor(dest, src, "xor")?,
+            Operation::And => b.build_and(dest, src, "and")?,
+            Operation::Or => b.build_or(dest, src, "or")?,
+            Operation::Div | Operation::Mod => {
+                let zero = b.build_int_compare(IntPredicate::EQ, src, self.i32t.const_zero(), "zero")?;
+                let fail = self.context.append_basic_block(self.func, "divide_by_zero");
+                let divide = self.context.append_basic_block(self.func, "divide");
+                b.build_conditional_branch(zero, fail, divide)?;
+
+                b.position_at_end(fail);
+                let kind = self.i32t.const_int(DIVIDE_BY_ZERO, false);
+                let pc = self.context.i64_type().const_int(pc as u64, false);
+                b.build_call(self.fault, &[kind.into(), pc.into()], "")?;
+                b.build_unreachable()?;
+
+                b.position_at_end(divide);
+                let min = b.build_int_compare(IntPredicate::EQ, dest, self.imm(i32::MIN as u32), "min")?;
+                let minus_one = b.build_int_compare(IntPredicate::EQ, src, self.imm(u32::MAX), "minus_one")?;
+                let overflow = b.build_and(min, minus_one, "overflow")?;
+                let src = b.build_select(overflow, self.imm(1), src, "divisor")?.into_int_value();
+                match op {
+                    Operation::Div => b.build_int_signed_div(dest, src, "div")?,
+                    _ => b.build_int_signed_rem(dest, src, "rem")?,
+                }
+            }
+            Operation::Jmp | Operation::Ret => unreachable!(),
+        })
+    }
+}
@@ -0,0 +1,253 @@
+// the disassembly listings, as text. ListingWriter lays out the lines, its stage1, stage2 and
+// sweep walk the parts of an image and feed it instructions and data
+use crate::color;
+use crate::inst::{try_parse, Instruction, Operation};
+use crate::project::Project;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+// which columns a listing has and how wide they are
+#[derive(Debug, Clone)]
+pub struct Columns {
+    // width of the raw bytes column, None leaves it out
+    pub raw: Option<usize>,
+    // where the comment column starts
+    pub comment_at: usize,
+    // "// ..." notes from the project: comments, and regions touched
+    pub comments: bool,
+    // "<- 0x0dd 0x15f" on the first line of everything that's called
+    pub xrefs: bool,
+    // a web page, every line an anchor and every call target a link to one
+    pub html: bool,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            raw: Some(24),
+            comment_at: 60,
+            comments: true,
+            xrefs: false,
+            html: false,
+        }
+    }
+}
+
+pub struct ListingWriter<'a> {
+    project: &'a Project,
+    columns: Columns,
+    // hex digits in an address, enough for the last byte of the image
+    digits: usize,
+    // callers by call target, from every part walked so far
+    callers: BTreeMap<usize, Vec<usize>>,
+    out: String,
+}
+
+impl<'a> ListingWriter<'a> {
+    pub fn new(project: &'a Project, columns: Columns, image_len: usize) -> Self {
+        ListingWriter {
+            project,
+            columns,
+            digits: format!("{:x}", image_len.saturating_sub(1)).len().max(2),
+            callers: BTreeMap::new(),
+            out: String::new(),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        match self.columns.html {
+            true => format!("<!DOCTYPE html>\n<html><body><pre>\n{}</pre></body></html>\n", self.out),
+            false => self.out,
+        }
+    }
+
+    // the entry point and the xor decryptor, straight from the image
+    pub fn stage1(&mut self, mem: &[u8]) {
+        self.find_calls(mem, 0..0xc8);
+
+        // first instruction is weird, it prints flag
+        // note: the reason it's weird is because it has one "real" instruction (a call) then it has
+        // a %s which prints the flag and I don't parse that. it's the end of the program anyway
+        let (inst, rest) = Instruction::parse(mem);
+        self.instruction(mem, 0, &inst, mem.len() - rest.len());
+
+        // this part disassembles the first stub. it un-xors the rest of the instructions
+        let mut curr: usize = 6;
+        while curr < 0xc8 {
+            let (inst, next) = Instruction::parse(&mem[curr..]);
+            let len = mem.len() - next.len() - curr;
+            self.label(curr);
+            self.instruction(mem, curr, &inst, len);
+            curr += len;
+        }
+    }
+
+    // everything after 0xc8. `mem` needs to be un-xored already
+    pub fn stage2(&mut self, mem: &[u8]) {
+        self.sweep(mem, 0xc8..mem.len());
+    }
+
+    // every instruction from `range.start` on, one after the other, for images with no known
+    // layout. bytes that don't decode are listed as data and the sweep carries on after them
+    pub fn sweep(&mut self, mem: &[u8], range: Range<usize>) {
+        let end = range.end.min(mem.len());
+        self.find_calls(mem, range.start..end);
+        let mut curr = range.start;
+        while curr < end {
+            self.label(curr);
+            match try_parse(&mem[curr..]) {
+                Some((inst, len)) => {
+                    self.instruction(mem, curr, &inst, len);
+                    curr += len;
+                }
+                None => curr = self.data(mem, curr, end),
+            }
+        }
+    }
+
+    // bytes from `start` that don't decode, up to the next '%' that does (or `end`). runs of 4 or
+    // more printable bytes become .ascii, aligned words .word and whatever is left .byte. gives
+    // where decoding should pick up again
+    fn data(&mut self, mem: &[u8], start: usize, end: usize) -> usize {
+        let stop = (start + 1..end)
+            .find(|&at| mem[at] == b'%' && try_parse(&mem[at..]).is_some())
+            .unwrap_or(end);
+        let printable = |b: &u8| (0x20..0x7f).contains(b);
+
+        let mut curr = start;
+        while curr < stop {
+            if curr != start {
+                self.label(curr);
+            }
+            let text = mem[curr..stop].iter().take(32).take_while(|b| printable(b)).count();
+            let (line, len) = if text >= 4 {
+                let s: String = mem[curr..curr + text].iter().map(|&b| b as char).collect();
+                (format!(".ascii {:?}", s), text)
+            } else if curr.is_multiple_of(4) && curr + 4 <= stop {
+                let word = u32::from_le_bytes([mem[curr], mem[curr + 1], mem[curr + 2], mem[curr + 3]]);
+                (format!(".word {:#010x}", word), 4)
+            } else {
+                (format!(".byte {:#04x}", mem[curr]), 1)
+            };
+            let line = match self.columns.html {
+                true => escape(&line),
+                false => line,
+            };
+            let note = self.data_note(curr);
+            self.line(curr, &mem[curr..curr + len], &line, note);
+            curr += len;
+        }
+        stop
+    }
+
+    fn instruction(&mut self, mem: &[u8], addr: usize, inst: &Instruction, len: usize) {
+        let mut named = self.project.named(inst, addr);
+        if self.columns.html {
+            named.palette = None;
+            named.link = None;
+        }
+        let mut text = named.to_string();
+        if self.columns.html {
+            text = escape(&text);
+            if inst.op == Operation::Jmp {
+                let target = escape(&self.project.function(inst.dest as usize));
+                let link = format!("<a href=\"#{:#x}\">{}</a>", inst.dest, target);
+                text = text.replacen(&target, &link, 1);
+            }
+        }
+        let note = match self.columns.comments {
+            true => self.project.annotation(inst, addr),
+            false => None,
+        };
+        self.line(addr, &mem[addr..addr + len], &text, note);
+    }
+
+    // the comment or the region for a data line, like Project::annotation does for code
+    fn data_note(&self, addr: usize) -> Option<String> {
+        if !self.columns.comments {
+            return None;
+        }
+        let note = match self.project.comments.get(&addr) {
+            Some(comment) => comment.clone(),
+            None => format!("[{}]", self.project.region(addr)?),
+        };
+        Some(format!("// {}", note))
+    }
+
+    // address, raw bytes, text, then the comment column with the note and the callers
+    fn line(&mut self, addr: usize, bytes: &[u8], text: &str, note: Option<String>) {
+        let addr_text = format!("{:#0w$x}:", addr, w = self.digits + 2);
+        let mut line = match self.columns.html {
+            true => format!("<a id=\"{:#x}\"></a>{}  ", addr, addr_text),
+            false => color::paint(|t| t.addr, &addr_text) + "  ",
+        };
+        if let Some(width) = self.columns.raw {
+            let raw = format!("{:w$}  ", raw(bytes), w = width);
+            line += &match self.columns.html {
+                true => escape(&raw),
+                false => raw,
+            };
+        }
+        line += text;
+
+        let mut notes: Vec<String> = note.into_iter().collect();
+        if self.columns.xrefs {
+            if let Some(callers) = self.callers.get(&addr) {
+                let callers: Vec<String> = callers.iter().map(|c| format!("{:#0w$x}", c, w = self.digits + 2)).collect();
+                notes.push(format!("<- {}", callers.join(" ")));
+            }
+        }
+        match notes.is_empty() {
+            true => writeln!(self.out, "{}", line).unwrap(),
+            false => {
+                let notes = notes.join("  ");
+                let notes = match self.columns.html {
+                    true => escape(&notes),
+                    false => color::paint(|t| t.note, &notes),
+                };
+                writeln!(self.out, "{} {}", color::pad(&line, self.columns.comment_at), notes).unwrap()
+            }
+        }
+    }
+
+    // blank line and "name:" above labeled addresses
+    fn label(&mut self, addr: usize) {
+        if let Some(label) = self.project.labels.get(&addr) {
+            let label = match self.columns.html {
+                true => escape(label),
+                false => label.clone(),
+            };
+            writeln!(self.out, "\n{}:", label).unwrap();
+        }
+    }
+
+    // every %C in `range`, for the xref column
+    fn find_calls(&mut self, mem: &[u8], range: Range<usize>) {
+        if !self.columns.xrefs {
+            return;
+        }
+        let mut curr = range.start;
+        while curr < range.end {
+            match try_parse(&mem[curr..]) {
+                Some((inst, len)) => {
+                    if inst.op == Operation::Jmp {
+                        self.callers.entry(inst.dest as usize).or_default().push(curr);
+                    }
+                    curr += len;
+                }
+                None => curr += 1,
+            }
+        }
+    }
+}
+
+// text for inside the html listing's <pre>
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// an instruction's own bytes as text, with the ret nul and anything else unprintable escaped
+pub fn raw(bytes: &[u8]) -> String {
+    bytes.iter().flat_map(|&b| std::ascii::escape_default(b)).map(char::from).collect()
+}
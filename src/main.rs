@@ -1,247 +1,1534 @@
-// emulation code in ex.rs
-mod ex;
-
-#[derive(Debug, Clone, Copy)]
-struct Instruction {
-    // width
-    dest: u32,
-    // precision
-    src: u32,
-    // operand1 mode
-    dest_mode: DestMode,
-    // operand2 mode
-    src_mode: SrcMode,
-    // arithmetic to do
-    op: Operation,
-}
-
-#[derive(Debug, Clone, Copy)]
-enum DestMode {
-    NoPlusMinus,
-    Plus,
-    Minus,
-    ZeroPad,
-}
-
-#[derive(Debug, Clone, Copy)]
-enum SrcMode {
-    HH,
-    H,
-    LL,
-    L,
-    None,
-}
-
-#[derive(Debug, Clone, Copy)]
-enum Operation {
-    Jmp,
-    Mov,
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Mod,
-    ShLeft,
-    ShRight,
-    Xor,
-    And,
-    Or,
-    Ret,
-}
-
-// this prints the instruction. started out as syntax like "mov r1, [r0]" but then changed to
-// output pseudo rust code that only required small fixups in ex.rs to actually execute
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let op = match self.op {
-            Operation::Jmp => {
-                let op = match self.dest_mode {
-                    DestMode::Minus => "< 0",
-                    DestMode::Plus => "> 0",
-                    DestMode::ZeroPad => "== 0",
-                    DestMode::NoPlusMinus => return write!(f, "stage2_{:x}(&mut s);", self.dest),
-                };
-
-                return write!(f, "if s.r{} {} {{ stage2_{:x}(&mut s); }}", self.src, op, self.dest);
-            }
-            /*   // old syntax
-            Operation::Mov => "mov",
-            Operation::Add => "add",
-            Operation::Sub => "sub",
-            Operation::Mul => "mul",
-            Operation::Div => "div",
-            Operation::Mod => "mod",
-            Operation::ShLeft => "shl",
-            Operation::ShRight => "shr",
-            Operation::Xor => "xor",
-            Operation::And => "and",
-            Operation::Or => " or",
-            */
-            // new syntax
-            Operation::Mov => "=",
-            Operation::Add => "+=",
-            Operation::Sub => "-=",
-            Operation::Mul => "*=",
-            Operation::Div => "/=",
-            Operation::Mod => "%=",
-            Operation::ShLeft => "<<=",
-            Operation::ShRight => ">>=",
-            Operation::Xor => "^=",
-            Operation::And => "&=",
-            Operation::Or => "|=",
-            Operation::Ret => return write!(f, "ret"),
+use disasm::ex::State;
+use disasm::expr::Assertion;
+use disasm::inst::{Instruction, Widths};
+use disasm::layout::Protection;
+use disasm::listing::{Columns, ListingWriter};
+use disasm::memory::Endian;
+use disasm::names::RegNames;
+use disasm::programs::{self, Program, WEATHER};
+use disasm::project::Project;
+use disasm::budget::{self, Budget};
+use disasm::explore::Explorer;
+use disasm::stats::{Clock, Stats};
+use disasm::timing::{self, Timing};
+use disasm::trace::Event;
+use disasm::variant::{self, Variant};
+use disasm::vm::{OnCall, Vm, VmError, WxMode};
+use disasm::word::Word;
+use disasm::{analyze, bench, color, completions, cover, config, crash, dashboard, dataflow, defuse, diff, ex, flame, fuzz, gdb, generated, golden, primes, heatmap, hot, inst, jit, keys, memdiff, metrics, native, pager, pointers, proof, ranges, recording, remote, repl, report, script, snapshot, threaded, timeline, tracediff, unpack, validate, verify};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cmd = args.first().filter(|a| !a.starts_with("--"));
+    crash::install();
+    config::init(flags(&args, "--config").pop()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    // snapshots and files written with --out stay plain unless asked, whatever the config says
+    let plain = cmd.is_some_and(|c| c == "snapshot") || flag(&args, "--out").is_some();
+    let when = match plain {
+        true => flags(&args, "--color").pop().unwrap_or("never"),
+        false => flag(&args, "--color").unwrap_or("auto"),
+    };
+    color::init(when, flag(&args, "--theme").unwrap_or("dark")).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    // narrow accesses spelled with zeros on the precision, which the challenge binary doesn't have
+    if switch(&args, "--narrow-widths") {
+        inst::set_widths(Widths::Zeros);
+    }
+    // for a campaign left running, see metrics.rs
+    if let Some(period) = seconds(&args, "--metrics-every") {
+        metrics::every(period);
+    }
+
+    match cmd.map(String::as_str) {
+        Some("disasm") => disassemble(&args),
+        Some("diff") => diff::run(),
+        Some("golden") => golden::run(),
+        Some("snapshot") => snapshot::run(args.iter().any(|a| a == "--update")),
+        Some("fuzz") => {
+            let iterations = args.get(1).filter(|a| !a.starts_with("--")).map(|n| parse_num(n) as usize);
+            if args.iter().any(|a| a == "--persistent") {
+                fuzz::persistent(iterations.unwrap_or(100_000));
+            } else if args.iter().any(|a| a == "--mutate") {
+                fuzz::mutate(iterations.unwrap_or(1000));
+            } else {
+                fuzz::run(iterations.unwrap_or(1000));
+            }
+        }
+        Some("call") => call(&args),
+        Some("bench") => {
+            let runs = args.get(1).filter(|a| !a.starts_with("--")).map(|n| parse_num(n));
+            bench::run(runs.unwrap_or(10) as usize).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+        }
+        Some("hot") => {
+            let count = args.get(1).filter(|a| !a.starts_with("--")).map(|n| parse_num(n));
+            hot::run(count.unwrap_or(10) as usize, &project(&args));
+        }
+        Some("repl") => repl::run(project(&args)),
+        Some("script") => match args.get(1) {
+            Some(path) => script::run(path).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }),
+            None => {
+                eprintln!("usage: script <file.rhai>");
+                std::process::exit(2);
+            }
+        },
+        Some("post-mortem") => {
+            let path = args.get(1).map_or(crash::PATH, String::as_str);
+            repl::post_mortem(path, project(&args)).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            });
+        }
+        Some("heatmap") => heat_map(&args),
+        Some("lift") => lift(&args),
+        Some("timeline") => timeline::run(&project(&args)),
+        Some("flamegraph") => {
+            let path = args.get(1).filter(|a| !a.starts_with("--"));
+            flame::run(path.map(String::as_str), &project(&args));
+        }
+        Some("completions") => match completions::script(args.get(1).map_or("", String::as_str)) {
+            Ok(script) => print!("{}", script),
+            Err(e) => {
+                eprintln!("usage: completions bash|zsh|fish\n{}", e);
+                std::process::exit(2);
+            }
+        },
+        Some("config") => match args.get(1).map(String::as_str) {
+            Some("dump") => {
+                let mut config = config::get().clone();
+                config.cli(&args);
+                print!("{}", config.dump());
+            }
+            _ => {
+                eprintln!("usage: config dump [flags..]");
+                std::process::exit(2);
+            }
+        },
+        Some("programs") => {
+            for p in programs::PROGRAMS {
+                println!("{:16} {}", p.name, p.about);
+            }
+        }
+        Some("analyze") => analyze(&args),
+        Some("keys") => keys(&args),
+        Some("unpack") => unpack(&args),
+        Some("validate") => validate(&args),
+        Some("diff-mem-files") => diff_mem_files(&args),
+        Some("trace-diff") => trace_diff(&args),
+        Some("explore") => explore(&args),
+        Some("cover") => cover(&args),
+        Some("verify-check") => match verify::run() {
+            Ok((report, agree)) => {
+                println!("{}", report);
+                if !agree {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("solve") => solve(&args),
+        Some("proof") => proof(&args),
+        Some("serve") => {
+            let addr = args.get(1).filter(|a| !a.starts_with("--"));
+            remote::serve(addr.map_or("127.0.0.1:1337", String::as_str), &remote_options(&args)).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+        }
+        Some("native") => {
+            let inputs: Vec<String> = args[1..].iter().take_while(|a| !a.starts_with("--")).cloned().collect();
+            let random = flag(&args, "--random").map_or(20, |n| parse_num(n) as usize);
+            native::run(flag(&args, "--binary").unwrap_or("weather"), &inputs, random).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+        }
+        Some("check") => check(&args),
+        Some("ranges") => {
+            let program = program(&args);
+            let analysis = ranges::analyze(&program.unpacked(), program.main);
+            pager::page(&ranges::report(&analysis, &project(&args)), !switch(&args, "--no-pager"));
+        }
+        Some("def-use") => def_use(&args),
+        Some("dataflow") => {
+            let path = args.get(1).filter(|a| !a.starts_with("--"));
+            dataflow::run(path.map(String::as_str), &project(&args));
+        }
+        Some("gdb") => {
+            let path = args.get(1).filter(|a| !a.starts_with("--"));
+            gdb::run(flag(&args, "--binary").unwrap_or("weather"), path.map(String::as_str), &project(&args));
+        }
+        Some("label") | Some("comment") | Some("region") | Some("project") => annotate(&args),
+        None | Some("run") => run(&args),
+        Some(other) => {
+            eprintln!("unknown command {}", other);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn run(args: &[String]) {
+    if args.iter().any(|a| a == "--verify-primes") {
+        if !primes::verify() {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // a bad one is better found out before the run than after
+    regions(args);
+    let expect = flag(args, "--expect-digest").map(|expect| {
+        u64::from_str_radix(expect.trim_start_matches("0x"), 16).unwrap_or_else(|_| {
+            eprintln!("bad --expect-digest {}, expected 16 hex digits", expect);
+            std::process::exit(2);
+        })
+    });
+
+    // another program has no transpiled version, so it starts from its entry point
+    // so does a format string from --inline
+    let entry = match flag(args, "--entry") {
+        Some(entry) => Some(parse_num(entry) as usize),
+        None if flag(args, "--inline").is_some() => Some(0),
+        // the transpiled functions don't make calls the interpreter can see
+        None if flag(args, "--trace-fn").is_some() => Some(disasm::vm::ENTRY),
+        None => flag(args, "--program").map(|_| program(args).entry),
+    };
+    let digest = match entry {
+        Some(entry) => match flag(args, "--word") {
+            None | Some("32") => run_entry::<i32>(entry, args),
+            Some("64") => run_entry::<i64>(entry, args),
+            Some(other) => {
+                eprintln!("bad --word {}, expected 32 or 64", other);
+                std::process::exit(2);
+            }
+        },
+        None if args.iter().any(|a| a == "--generated") => run_generated(args),
+        None => {
+            let mut s = State::new();
+            s.quiet = porcelain(args) || switch(args, "--quiet");
+            if flag(args, "--record").is_some() {
+                s.trace = Some(Vec::new());
+            }
+            let input = input_file(args);
+            let finished = match porcelain(args) {
+                true => ex::execute(s, input.as_deref()),
+                false => ex::run(s, input.as_deref()),
+            };
+            if porcelain(args) {
+                print_porcelain(Some(&finished.input), &finished.flag, Some(finished.digest));
+            }
+            record(args, &finished.trace);
+            dump_regions(args, &finished.mem);
+            finished.digest
+        }
+    };
+
+    // lets a refactor be checked against a digest from before it. porcelain output stays only
+    // the keys, so this goes to stderr there
+    if let Some(expect) = expect {
+        let say = |line: String| match porcelain(args) {
+            true => eprintln!("{}", line),
+            false => println!("{}", line),
         };
+        if digest != expect {
+            say(format!("MISMATCH: expected digest {:016x}", expect));
+            std::process::exit(1);
+        }
+        say("ok: digest matches".to_string());
+    }
+}
 
-        // write the destination part
-        match self.dest_mode {
-            DestMode::Minus => write!(f, "[{:#0x}]", self.dest)?,
-            DestMode::Plus => write!(f, "[r{}]", self.dest)?,
-            DestMode::NoPlusMinus => write!(f, "s.r{}", self.dest)?,
-            _ => panic!(),
+// `--input-file <file>`: the city name from a file instead of the winning one, any bytes at all.
+// scanf would stop short of a newline, so one at the end is dropped. the NUL after it is there so
+// nothing of a longer input is left behind
+fn input_file(args: &[String]) -> Option<Vec<u8>> {
+    let path = flag(args, "--input-file")?;
+    let mut input = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(2);
+    });
+    if input.last() == Some(&b'\n') {
+        input.pop();
+    }
+    // the region and its NUL
+    let room = ex::REGIONS[0].0.len() - 1;
+    if input.len() > room {
+        eprintln!("{} is {} bytes, the input region only has room for {}", path, input.len(), room);
+        std::process::exit(2);
+    }
+    input.push(0);
+    Some(input)
+}
+
+// `--record <file>`: the run's events with their ids, see recording.rs
+fn record(args: &[String], events: &[Event]) {
+    if let Some(path) = flag(args, "--record") {
+        recording::save(path, events, switch(args, "--compress")).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if !porcelain(args) {
+            println!("recorded {} events to {}", events.len(), path);
         }
+    }
+}
 
-        // write the opcode
-        write!(f, " {} ", op)?;
+// every `--dump-region 0x1194:0x1c=firstpass.bin`, as the bytes and the file they go to
+fn regions(args: &[String]) -> Vec<(Range<usize>, &str)> {
+    let regions = flags(args, "--dump-region").into_iter().map(|spec| {
+        let parsed = spec.split_once('=').and_then(|(at, path)| {
+            let (start, len) = at.split_once(':')?;
+            let start = inst::parse_num(start)? as usize;
+            Some((start..start + inst::parse_num(len)? as usize, path))
+        });
+        parsed.filter(|(_, path)| !path.is_empty()).unwrap_or_else(|| {
+            eprintln!("bad --dump-region {}, expected addr:len=file", spec);
+            std::process::exit(2);
+        })
+    });
+    regions.collect()
+}
 
-        // write the source part
-        match self.src_mode {
-            SrcMode::HH => write!(f, "[{:#0x}];", self.src),
-            SrcMode::H => write!(f, "s.mem[s.r{} as u32 as usize];", self.src),
-            SrcMode::L => write!(f, "s.r{};", self.src),
-            SrcMode::LL => write!(f, "{:#0x};", self.src),
-            _ => panic!(),
+// `--dump-region`: buffers out of memory after the run, for looking at with other tools
+fn dump_regions(args: &[String], mem: &[u8]) {
+    for (range, path) in regions(args) {
+        let bytes = mem.get(range.clone()).unwrap_or_else(|| {
+            eprintln!("--dump-region {:#x}:{:#x} is past the end of memory at {:#x}", range.start, range.len(), mem.len());
+            std::process::exit(2);
+        });
+        std::fs::write(path, bytes).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        });
+        if !porcelain(args) {
+            println!("dumped {:#x}..{:#x} to {}", range.start, range.end, path);
         }
     }
 }
 
-impl Instruction {
+fn porcelain(args: &[String]) -> bool {
+    args.iter().any(|a| a == "--porcelain")
+}
 
-    // this parses a string like "%+4.7hhX" and then returns an Instruction as well as where to
-    // keep parsing from next
-    fn parse(mem: &[u8]) -> (Self, &[u8]) {
-        if mem[0] == 0 {
-            return (Self {
-                dest: 0,
-                src: 0,
-                dest_mode: DestMode::Minus,
-                src_mode: SrcMode::LL,
-                op: Operation::Ret,
-            }, &mem[1..]);
+// `--porcelain` output for scripts, one `key<tab>value` line each:
+//
+//     flag     what the program printed as the flag, "none" when the check fails
+//     input    the city name it was given
+//     verdict  "accepted", or "rejected" for a flag of "none" or nothing
+//     digest   16 hex digits, as --expect-digest takes them
+//
+// values are escaped like a rust byte string, so no tab or newline gets into one. these keys keep
+// their names and meaning, new ones only ever go after them, and one with nothing to say (no
+// digest from a remote solve) is left out
+fn print_porcelain(input: Option<&[u8]>, flag: &[u8], digest: Option<u64>) {
+    let flag = &flag[..flag.iter().position(|&b| b == 0).unwrap_or(flag.len())];
+    println!("flag\t{}", flag.escape_ascii());
+    if let Some(input) = input {
+        println!("input\t{}", input.escape_ascii());
+    }
+    let rejected = flag.is_empty() || flag == b"none";
+    println!("verdict\t{}", if rejected { "rejected" } else { "accepted" });
+    if let Some(digest) = digest {
+        println!("digest\t{:016x}", digest);
+    }
+}
+
+// stage2 as build.rs generated it from the image, on the winning input
+fn run_generated(args: &[String]) -> u64 {
+    let mut s = ex::winning_state();
+    generated::stage2_c8(&mut s);
+    s.check_canaries();
+    let digest = s.digest();
+    dump_regions(args, &s.mem);
+    if porcelain(args) {
+        print_porcelain(Some(&s.mem[0x1000..0x1000 + variant::LEN]), &s.mem[WEATHER.flag.clone()], Some(digest));
+        return digest;
+    }
+    let flag = String::from_utf8_lossy(&s.mem[WEATHER.flag.clone()]).into_owned();
+    println!("Flag: {}", flag);
+    println!("Digest: {:016x}", digest);
+    digest
+}
+
+// run one vm function to completion on the booted machine, like `call 0x105 --set r0=0x3391`
+fn call(args: &[String]) {
+    let addr = match args.get(1) {
+        Some(addr) => parse_num(addr) as usize,
+        None => {
+            eprintln!("usage: call <addr> [--set rN=value]... [--wx warn|fault] [--protect] [--assert expr]...");
+            std::process::exit(2);
+        }
+    };
+
+    let mut vm = Vm::boot(ex::winning_state()).unwrap();
+    set_registers(&mut vm.s, args);
+    vm.wx.mode = wx_mode(args);
+    protect(&mut vm, args);
+    assertions(&mut vm, args);
+    let before = vm.s.clone();
+    let steps = vm.steps;
+
+    println!("before: {}", vm.s.print_regs());
+    if let Err(e) = crash::guard(&mut vm, |vm| vm.call(addr)) {
+        println!("fault: {}", e);
+    }
+    println!("after:  {}", vm.s.print_regs());
+    println!("{} steps", vm.steps - steps);
+    vm.s.check_canaries();
+    diff::compare(&before, &vm.s, ["before", "after"]);
+}
+
+// `def-use <rN|addr> [--function <addr>] [--at <pc>]`: over the winning run, or one function
+fn def_use(args: &[String]) {
+    let project = project(args);
+    let cell = match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(cell) => defuse::Cell::parse(cell, &project).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }),
+        None => {
+            eprintln!("usage: def-use <rN|addr> [--function <addr>] [--at <pc>]");
+            std::process::exit(2);
+        }
+    };
+    let program = program(args);
+    let mem = program.unpacked();
+    let chains = match flag(args, "--function") {
+        Some(entry) => defuse::function(&mem, program.main, parse_num(entry) as usize, cell).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        None if program.name != WEATHER.name => {
+            eprintln!("def-use over a run is only for weather, use --function");
+            std::process::exit(2);
         }
+        None => defuse::dynamic(cell),
+    };
+    let chains = match flag(args, "--at") {
+        Some(pc) => defuse::at(chains, parse_num(pc) as usize),
+        None => chains,
+    };
+    pager::page(&defuse::report(cell, &chains, &mem, &project), !switch(args, "--no-pager"));
+}
 
-        assert!(b'%' == mem[0]);
-        let mem = &mem[1..];
+// memory accesses over the winning solve as a png, like `heatmap run.png --rows 1000`
+fn heat_map(args: &[String]) {
+    let path = match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: heatmap <file.png> [--rows N]");
+            std::process::exit(2);
+        }
+    };
+    let rows = flag(args, "--rows").map_or(800, |n| parse_num(n) as usize);
+    let png = heatmap::render(&golden::known_good_trace(), rows);
+    std::fs::write(path, png).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+    println!("wrote {}", path);
+}
 
-        // parse mode from flags
-        let (op1_mode, mem) = match mem {
-            &[b'-', ..] => (DestMode::Minus, &mem[1..]),
-            &[b'+', ..] => (DestMode::Plus, &mem[1..]),
-            &[b'0', b'.', ..] => (DestMode::NoPlusMinus, mem),
-            &[b'0', ..] => (DestMode::ZeroPad, &mem[1..]),
-            _ => (DestMode::NoPlusMinus, mem),
+// the unpacked program from main (or `--entry addr`) as llvm ir, into a file or on stdout
+#[cfg(feature = "llvm")]
+fn lift(args: &[String]) {
+    let program = program(args);
+    let entry = flag(args, "--entry").map_or(program.main, |n| parse_num(n) as usize);
+    let ir = disasm::lift::lift(&program.unpacked(), entry, &project(args)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(path) => {
+            std::fs::write(path, ir).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => print!("{}", ir),
+    }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn lift(_: &[String]) {
+    eprintln!("lift needs llvm, build with --features llvm");
+    std::process::exit(2);
+}
+
+// the machine `run --entry` starts from, everything the command line says about it set up. the
+// hooks are left to the caller
+fn machine<R: Word>(entry: usize, args: &[String]) -> Vm<R> {
+    let program = program(args);
+    let inline = inline_image(args);
+    let mut vm: Vm<R> = if let Some(image) = &inline {
+        Vm::new(State::from_image(image))
+    } else if let Some(path) = flag(args, "--image") {
+        // somebody else's image, there's no stage1 to get through first
+        Vm::new(State::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }))
+    } else if program.name != WEATHER.name {
+        // no winning input or boot for the others
+        Vm::new(State::from_image(program.image))
+    } else {
+        let s = match input_file(args) {
+            Some(input) => {
+                let mut s = State::with_input(&input);
+                s.quiet = true;
+                s
+            }
+            None => ex::winning_state(),
         };
+        match entry >= 0xc8 {
+            true => Vm::boot(s).unwrap_or_else(|e| {
+                eprintln!("stage1: {}", e);
+                std::process::exit(1);
+            }),
+            false => Vm::new(s),
+        }
+    };
+    if let Some(count) = flag(args, "--reg-count") {
+        vm.s.set_reg_count(parse_num(count) as usize);
+    }
+    if switch(args, "--big-endian") {
+        vm.s.endian = Endian::Big;
+    }
+    vm.pc = entry;
+    set_registers(&mut vm.s, args);
+    vm.wx.mode = wx_mode(args);
+    protect(&mut vm, args);
+    assertions(&mut vm, args);
+    vm
+}
 
-        // parse width (operand1)
-        let (operand1, mem) = parse_int(mem);
+// interpret from any instruction until the function it's in returns. stage2 entries get a
+// machine that has already been through stage1, unless `--image <file>` loads a different one.
+// `--word 64` runs it with 64 bit registers and memory words, `--max-steps n` and `--deadline secs`
+// stop it early, see budget.rs
+fn run_entry<R: Word>(entry: usize, args: &[String]) -> u64 {
+    let mut vm: Vm<R> = machine(entry, args);
+    let program = program(args);
+    let inline = inline_image(args);
+    // stubbed functions never get entered, so they don't get logged either
+    stubs(&mut vm, args);
+    if switch(args, "--log-calls") {
+        log_calls(&mut vm, project(args));
+    }
 
-        let (operand2, op2_mode, mem) = if mem[0] == b'.' {
-            let mem = &mem[1..];
+    let budget = Budget {
+        steps: flag(args, "--max-steps").map(|n| parse_num(n) as u64),
+        deadline: seconds(args, "--deadline"),
+    };
+    let mut expired = None;
+    // stubs, --log-calls and --trace-fn are call hooks, and the jit doesn't stop at calls
+    let jit = !switch(args, "--trace") && !budget.is_set() && args.iter().any(|a| a == "--jit");
+    let hooked = !flags(args, "--stub").is_empty() || switch(args, "--log-calls") || flag(args, "--trace-fn").is_some();
+    if jit && hooked {
+        println!("--jit: --stub, --log-calls and --trace-fn need the interpreter, running that instead");
+    }
+    let jitted = jit && !hooked;
 
-            let (operand2, mem) = parse_int(mem);
+    // the summary's stages and instruction mix. runners that skip the trace just leave the mix
+    // out, and the stages hook would send the jit back to the interpreter
+    let clock = Rc::new(RefCell::new(Clock::default()));
+    if !jitted {
+        let stages = clock.clone();
+        vm.on_call(move |_, addr| {
+            stages.borrow_mut().enter(addr);
+            OnCall::Enter
+        });
+        clock.borrow_mut().enter(vm.pc);
+    }
+    let recording = vm.s.trace.is_none();
+    if recording {
+        vm.s.trace = Some(Vec::new());
+    }
+    let outside = trace_fns(&mut vm, args);
 
-            let (op2_mode, mem) = match mem {
-                &[b'h', b'h', .. ] => (SrcMode::HH, &mem[2..]),
-                &[b'h', .. ] => (SrcMode::H, &mem[1..]),
-                &[b'l', b'l', .. ] => (SrcMode::LL, &mem[2..]),
-                &[b'l', .. ] => (SrcMode::L, &mem[1..]),
-                _ => (SrcMode::None, mem),
-            };
-            (operand2, op2_mode, mem)
-        } else {
-            (0, SrcMode::None, mem)
+    let steps = vm.steps;
+    let result = if switch(args, "--trace") {
+        // the trace goes through the pager at the end, or straight out with --no-pager. --timing
+        // puts where the time went after it, see timing.rs
+        let project = project(args);
+        let mut lines = Vec::new();
+        let paged = !switch(args, "--no-pager");
+        let mut timing = switch(args, "--timing").then(Timing::default);
+        if timing.is_some() {
+            vm.s.console = timing::console();
+        }
+        let result = match paged {
+            true => crash::guard(&mut vm, |vm| trace(vm, &project, &mut lines, timing.as_mut())),
+            false => crash::guard(&mut vm, |vm| trace(vm, &project, &mut std::io::stdout().lock(), timing.as_mut())),
         };
-        
-        let operation = match mem[0] {
-            b'C' => Operation::Jmp,
-            b'M' => Operation::Mov,
-            b'S' => Operation::Add,
-            b'O' => Operation::Sub,
-            b'X' => Operation::Mul,
-            b'V' => Operation::Div,
-            b'N' => Operation::Mod,
-            b'L' => Operation::ShLeft,
-            b'R' => Operation::ShRight,
-            b'E' => Operation::Xor,
-            b'I' => Operation::And,
-            b'U' => Operation::Or,
-            _ => panic!(),
+        pager::page(&String::from_utf8_lossy(&lines), paged);
+        if let Some(timing) = timing {
+            let top = flag(args, "--top").map_or(10, |n| parse_num(n) as usize);
+            println!("{}", timing.report(top, &vm.s.mem, &project));
+        }
+        result
+    } else if budget.is_set() {
+        crash::guard(&mut vm, |vm| budget.run(vm)).map(|out| expired = out)
+    } else if jitted {
+        crash::guard(&mut vm, run_jit)
+    } else if args.iter().any(|a| a == "--threaded") {
+        crash::guard(&mut vm, threaded::run)
+    } else if args.iter().any(|a| a == "--dashboard") {
+        let project = project(args);
+        let hex = flag(args, "--hex").map(|at| dashboard::Hex::new(parse_num(at) as usize));
+        crash::guard(&mut vm, |vm| dashboard::run(vm, &project, hex))
+    } else {
+        crash::guard(&mut vm, Vm::run)
+    };
+    let mut stats = Stats::default();
+    let human = !porcelain(args);
+    if let Err(e) = result {
+        if human {
+            println!("fault: {}", e);
+        }
+        stats.warnings.push(format!("fault: {}", e));
+    }
+    if let Some(expired) = &expired {
+        // written either way, only the porcelain leaves out saying so
+        let report = budget::report(&vm, expired, &project(args));
+        if human {
+            println!("{}", report);
+        }
+        stats.warnings.push(format!("budget: {}", expired.why));
+    }
+    if human {
+        println!("registers: {}", vm.s.print_regs());
+    }
+    stats.steps = Some(vm.steps - steps);
+    stats.stages = clock.borrow().stages();
+    // the events so far are put aside whenever the run leaves the traced functions
+    if let Some(events) = outside.take() {
+        vm.s.trace = Some(events);
+    }
+    if recording {
+        let events = vm.s.trace.take().unwrap_or_default();
+        stats.count(&events, &vm.s.mem);
+        record(args, &events);
+    }
+    dump_regions(args, &vm.s.mem);
+
+    vm.s.check_canaries();
+    for at in vm.s.clobbered_canaries() {
+        stats.warnings.push(format!("canary at {:#x} clobbered", at));
+    }
+    let digest = vm.s.digest();
+    if !human {
+        // the keys are there even for an inline program, which likely never got as far as the
+        // flag buffer
+        print_porcelain(None, vm.s.mem.get(program.flag.clone()).unwrap_or(&[]), Some(digest));
+        return digest;
+    }
+
+    // an inline program has nowhere in particular to put a flag, and a short image may not reach it
+    if let (None, Some(flag)) = (&inline, vm.s.mem.get(program.flag.clone())) {
+        println!("Flag: {}", String::from_utf8_lossy(flag));
+    }
+    println!("Digest: {:016x}", digest);
+    println!("{}", stats);
+    digest
+}
+
+// `--log-calls`: every call with the registers it gets, and every return with the ones it leaves
+fn log_calls<R: Word>(vm: &mut Vm<R>, project: Project) {
+    let project = Rc::new(project);
+    let depth = Rc::new(Cell::new(0));
+    let (names, calls) = (project.clone(), depth.clone());
+    vm.on_call(move |s, addr| {
+        println!("{:w$}{}({})", "", names.function(addr), s.print_regs(), w = 2 * calls.get());
+        calls.set(calls.get() + 1);
+        OnCall::Enter
+    });
+    vm.on_return(move |s, addr| {
+        depth.set(depth.get().saturating_sub(1));
+        println!("{:w$}{} -> {}", "", project.function(addr), s.print_regs(), w = 2 * depth.get());
+    });
+}
+
+// a vm function by name: a project label, stage2_<addr> like the listings call the rest, one of
+// the names in gdb::FUNCTIONS, or just its address
+fn function_addr(project: &Project, name: &str) -> usize {
+    let known = || {
+        let found = gdb::FUNCTIONS.iter().find(|(_, about)| about.split([',', ' ']).next() == Some(name));
+        found.map(|&(addr, _)| addr)
+    };
+    let addr = project
+        .lookup(name)
+        .or_else(|| name.strip_prefix("stage2_").and_then(|hex| usize::from_str_radix(hex, 16).ok()))
+        .or_else(known)
+        .or_else(|| inst::parse_num(name).map(|n| n as usize));
+    addr.unwrap_or_else(|| {
+        eprintln!("no function {}, try a label, stage2_<addr> or an address", name);
+        std::process::exit(2);
+    })
+}
+
+// `--trace-fn buffer_check`: only the events from a call into one of these until its ret, and
+// whatever they call, get into the trace. the rest of the run's are never kept, the trace is put
+// aside while the run is outside them and what's returned holds it then
+fn trace_fns<R: Word>(vm: &mut Vm<R>, args: &[String]) -> Rc<RefCell<Option<Vec<Event>>>> {
+    let outside = Rc::new(RefCell::new(None));
+    let names = flags(args, "--trace-fn");
+    if names.is_empty() {
+        return outside;
+    }
+    let project = project(args);
+    let traced: Vec<usize> = names.iter().map(|name| function_addr(&project, name)).collect();
+
+    // a run that starts in one of them is inside from the start
+    let depth = Rc::new(Cell::new(traced.contains(&vm.pc) as usize));
+    if depth.get() == 0 {
+        *outside.borrow_mut() = vm.s.trace.take();
+    }
+    let (entered, left) = (depth.clone(), depth);
+    let (enter, leave) = (traced.clone(), traced);
+    let (put_back, put_aside) = (outside.clone(), outside.clone());
+    vm.on_call(move |s, addr| {
+        if enter.contains(&addr) {
+            if entered.get() == 0 {
+                s.trace = put_back.borrow_mut().take();
+            }
+            entered.set(entered.get() + 1);
+        }
+        OnCall::Enter
+    });
+    vm.on_return(move |s, addr| {
+        if leave.contains(&addr) && left.get() > 0 {
+            left.set(left.get() - 1);
+            if left.get() == 0 {
+                *put_aside.borrow_mut() = s.trace.take();
+            }
+        }
+    });
+    outside
+}
+
+// every `--stub <name>` from ex::STUBS, which are for the 32 bit machine
+fn stubs<R: Word>(vm: &mut Vm<R>, args: &[String]) {
+    for name in flags(args, "--stub") {
+        let (_, addr, f) = ex::STUBS.iter().find(|(n, _, _)| *n == name).unwrap_or_else(|| {
+            let names: Vec<&str> = ex::STUBS.iter().map(|(n, _, _)| *n).collect();
+            eprintln!("no stub {}, there's {}", name, names.join(", "));
+            std::process::exit(2);
+        });
+        match (vm as &mut dyn std::any::Any).downcast_mut::<Vm>() {
+            Some(vm) => vm.stub(*addr, *f),
+            None => {
+                eprintln!("--stub is only for --word 32");
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+// the jit only knows the 32 bit machine
+fn run_jit<R: Word>(vm: &mut Vm<R>) -> Result<(), VmError> {
+    match (vm as &mut dyn std::any::Any).downcast_mut::<Vm>() {
+        Some(vm) => jit::run(vm),
+        None => {
+            println!("jit: only for --word 32, interpreting instead");
+            vm.run()
+        }
+    }
+}
+
+// run to the end printing every instruction and the registers after it
+fn trace<R: Word>(vm: &mut Vm<R>, project: &Project, out: &mut dyn Write, mut timing: Option<&mut Timing>) -> Result<(), VmError> {
+    while !vm.halted {
+        let pc = vm.pc;
+        // bad code is the step's fault to report, not a reason to panic here
+        let inst = vm.s.mem.get(pc..).and_then(Instruction::checked).map(|(inst, _)| inst);
+        let before = vm.s.regs.clone();
+        let (start, logged) = (std::time::Instant::now(), timing::logging());
+        vm.step()?;
+        let (stepped, logged) = (start.elapsed(), timing::logging() - logged);
+        let inst = inst.map_or("??".to_string(), |inst| project.named(&inst, pc).to_string());
+        let addr = color::paint(|t| t.addr, &format!("{:#05x}:", pc));
+        writeln!(out, "{}  {} {}", addr, color::pad(&inst, 50), color::regs(&before, &vm.s.regs)).unwrap();
+        // the log lines happen inside the step, but they're watching it as much as the trace is
+        if let Some(timing) = timing.as_deref_mut() {
+            timing.add(pc, stepped - logged, start.elapsed() - stepped + logged);
+        }
+    }
+    Ok(())
+}
+
+// apply every `--set rN=value`
+fn set_registers<R: Word>(s: &mut State<R>, args: &[String]) {
+    for set in flags(args, "--set") {
+        s.assign(set).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        });
+    }
+}
+
+// `--wx warn` or `--wx fault` to watch for writes into code
+fn wx_mode(args: &[String]) -> WxMode {
+    match flag(args, "--wx") {
+        Some(mode) => mode.parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }),
+        None => WxMode::Off,
+    }
+}
+
+// `--protect` enforces the default memory layout's permissions
+fn protect<R: Word>(vm: &mut Vm<R>, args: &[String]) {
+    if switch(args, "--protect") {
+        vm.protection = Some(Protection::default());
+    }
+}
+
+// every `--assert "mem32(0x1388) == 0x3391"` or `--assert "at 0x1f4 r0 <= 0x1c"`
+fn assertions<R: Word>(vm: &mut Vm<R>, args: &[String]) {
+    let project = project(args);
+    for text in flags(args, "--assert") {
+        let assert = Assertion::parse(text, &project).unwrap_or_else(|e| {
+            eprintln!("bad assertion {}: {}", text, e);
+            std::process::exit(2);
+        });
+        vm.asserts.push(assert);
+    }
+}
+
+// value of a `--name value` style argument, or its default from the environment
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    flags(args, name).pop().or_else(|| config::value(name))
+}
+
+// a `--name` argument given, or on by default
+fn switch(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name) || config::switch(name)
+}
+
+// every value given for a repeatable `--name value` argument
+fn flags<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    for (i, a) in args.iter().enumerate() {
+        if a == name {
+            match args.get(i + 1) {
+                Some(value) => values.push(value.as_str()),
+                None => {
+                    eprintln!("{} needs a value", name);
+                    std::process::exit(2);
+                }
+            }
+        }
+    }
+    values
+}
+
+// a `--name secs` duration, fractions allowed
+fn seconds(args: &[String], name: &str) -> Option<Duration> {
+    flag(args, name).map(|secs| match secs.parse::<f64>() {
+        Ok(secs) if secs > 0.0 => Duration::from_secs_f64(secs),
+        _ => {
+            eprintln!("bad {} {}, expected seconds", name, secs);
+            std::process::exit(2);
+        }
+    })
+}
+
+// hex with 0x, otherwise decimal
+fn parse_num(s: &str) -> u32 {
+    inst::parse_num(s).unwrap_or_else(|| {
+        eprintln!("bad number {}", s);
+        std::process::exit(2);
+    })
+}
+
+// the listing, with columns from `--no-raw`, `--raw-width n`, `--no-comments`, `--comment-at n`
+// and `--xrefs`, on stdout or into `--out <file>`. `--html <file>` writes it as a web page too, and
+// `--links` makes the call targets on the terminal hyperlinks to their line in that page
+// (listing.html if there's no --html)
+fn disassemble(args: &[String]) {
+    let has = |name: &str| switch(args, name);
+    let columns = Columns {
+        raw: match has("--no-raw") {
+            true => None,
+            false => Some(flag(args, "--raw-width").map_or(24, |n| parse_num(n) as usize)),
+        },
+        comment_at: flag(args, "--comment-at").map_or(60, |n| parse_num(n) as usize),
+        comments: !has("--no-comments"),
+        xrefs: has("--xrefs"),
+        html: false,
+    };
+    let mut project = match inline_image(args) {
+        Some(_) => Project::default(),
+        None => {
+            // where indirect loads and stores can land for the comments, and which registers are
+            // addresses
+            let program = program(args);
+            let analysis = ranges::analyze(&program.unpacked(), program.main);
+            Project {
+                targets: ranges::targets(&analysis),
+                pointers: pointers::infer(&analysis.bodies).after,
+                ..project(args)
+            }
+        }
+    };
+    let render = |project: &Project, columns: Columns| match (inline_image(args), flag(args, "--range")) {
+        (Some(image), _) => {
+            let mut writer = ListingWriter::new(project, columns, image.len());
+            writer.sweep(&image, 0..inline_code(args).unwrap().len());
+            writer.finish()
+        }
+        (None, Some(range)) => {
+            let (image, range) = range_image(args, range);
+            let mut writer = ListingWriter::new(project, columns, image.len());
+            writer.sweep(&image, range);
+            writer.finish()
+        }
+        (None, None) => program(args).listing(project, columns),
+    };
+
+    let html = flag(args, "--html").or(if has("--links") { Some("listing.html") } else { None });
+    if let Some(path) = html {
+        let page = render(&project, Columns { html: true, ..columns.clone() });
+        std::fs::write(path, page).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        });
+        if !has("--links") {
+            println!("wrote {}", path);
+            return;
+        }
+        let path = std::fs::canonicalize(path).unwrap();
+        project.links = Some(format!("file://{}", path.display()));
+    }
+
+    let text = render(&project, columns);
+    match flag(args, "--out") {
+        Some(path) => {
+            std::fs::write(path, text).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => pager::page(&text, !has("--no-pager")),
+    }
+}
+
+// the program and the range for `disasm --range 0xc8..0x6fc`. the range is listed from the
+// unpacked program, unless `--xor-key <key>` says to decrypt it from the original image with that
+// key, or with whatever key scores best for `--xor-key auto`
+fn range_image(args: &[String], range: &str) -> (Vec<u8>, std::ops::Range<usize>) {
+    let program = program(args);
+    let range = match range.split_once("..") {
+        Some((start, end)) => parse_num(start) as usize..parse_num(end) as usize,
+        None => {
+            eprintln!("bad --range {}, expected start..end", range);
+            std::process::exit(2);
+        }
+    };
+    if range.start >= range.end || range.end > program.image.len() {
+        eprintln!("bad --range {:#x}..{:#x} for a {:#x} byte image", range.start, range.end, program.image.len());
+        std::process::exit(2);
+    }
+    let key = match flag(args, "--xor-key") {
+        None => return (program.unpacked(), range),
+        Some("auto") => keys::recover(program.image, range.clone()).unwrap_or_else(|| {
+            eprintln!("no key scored {:.2} or better, try `keys` for the candidates", keys::CONFIDENT);
+            std::process::exit(1);
+        }),
+        Some(key) => parse_num(key) as u8,
+    };
+    (keys::unxor(program.image, range.clone(), key), range)
+}
+
+// the format string from `--inline '%1.10llM%16C\0...'` with a nul after it. `\0` in the string is
+// a nul too, to split it into functions, since a shell can't pass a real one
+fn inline_code(args: &[String]) -> Option<Vec<u8>> {
+    let mut code = flag(args, "--inline")?.replace("\\0", "\0").into_bytes();
+    code.push(0);
+    Some(code)
+}
+
+// memory for --inline: the code at 0, and `--data <file>` copied in at `--data-at` (0x1000 if not
+// given)
+fn inline_image(args: &[String]) -> Option<Vec<u8>> {
+    let mut image = inline_code(args)?;
+    if let Some(path) = flag(args, "--data") {
+        let data = std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        });
+        let at = flag(args, "--data-at").map_or(0x1000, |a| parse_num(a) as usize);
+        image.resize(image.len().max(at + data.len()), 0);
+        image[at..at + data.len()].copy_from_slice(&data);
+    }
+    Some(image)
+}
+
+// entropy and printable bytes across the image in `--window n` byte steps (32 by default).
+// `--after` scans memory at the end of the winning solve instead, with the primes and the flag
+// filled in, and `--image <file>` scans some other image
+fn analyze(args: &[String]) {
+    let window = flag(args, "--window").map_or(32, |n| parse_num(n) as usize).max(2);
+    let mem = if args.iter().any(|a| a == "--after") {
+        let mut vm: Vm = Vm::new(ex::winning_state());
+        vm.pc = WEATHER.entry;
+        if let Err(e) = crash::guard(&mut vm, Vm::run) {
+            println!("fault: {}", e);
+        }
+        vm.s.mem.to_vec()
+    } else if let Some(path) = flag(args, "--image") {
+        std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        })
+    } else {
+        program(args).image.to_vec()
+    };
+    print!("{}", analyze::report(&mem, window));
+}
+
+// the winning input for weather or a `--variant <file>` of it, checked by running `--image` with it
+fn solve(args: &[String]) {
+    let variant = variant(args);
+    let input = variant.solve().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let human = !porcelain(args);
+    if human {
+        println!("Transform: {}", variant.transform());
+        println!("Winning input: {}", String::from_utf8_lossy(&input));
+    }
+    // the steps run forwards again, before the machine gets to check it
+    if !variant.check(&input) {
+        eprintln!("the input doesn't survive the steps forwards");
+        std::process::exit(1);
+    }
+
+    // the real thing checks it, not the emulator
+    let (answer, digest) = if let Some(addr) = flag(args, "--remote") {
+        let mut transcript = remote::Transcript::default();
+        let answer = remote::solve(addr, &input, &remote_options(args), &mut transcript);
+        // failed sessions are the ones worth reading afterwards
+        if let Some(path) = flag(args, "--transcript") {
+            std::fs::write(path, transcript.to_string()).unwrap_or_else(|e| eprintln!("{}: {}", path, e));
+        }
+        let answer = answer.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        (answer, None)
+    } else {
+        let vm = run_input(variant_image(args).as_deref(), &input).unwrap_or_else(|e| {
+            eprintln!("fault: {}", e);
+            std::process::exit(1);
+        });
+        // the program writes "none" there when the check fails
+        let flag = &vm.s.mem[WEATHER.flag.clone()];
+        let end = flag.iter().position(|&b| b == 0).unwrap_or(flag.len());
+        (String::from_utf8_lossy(&flag[..end]).into_owned(), Some(vm.s.digest()))
+    };
+    match human {
+        true => println!("Flag: {}", answer),
+        false => print_porcelain(Some(&input), answer.as_bytes(), digest),
+    }
+
+    if let Some(path) = flag(args, "--report") {
+        let report = report::report(&variant, &input, &answer, &unpacked_image(args), &project(args));
+        std::fs::write(path, report).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        });
+        if human {
+            println!("wrote {}", path);
+        }
+    }
+}
+
+// a standalone rust file that prints the flag, see proof.rs
+fn proof(args: &[String]) {
+    let input = variant(args).solve().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let vm = run_input(variant_image(args).as_deref(), &input).unwrap_or_else(|e| {
+        eprintln!("fault: {}", e);
+        std::process::exit(1);
+    });
+    let source = proof::source(&input, &vm.s.mem).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(path) => {
+            std::fs::write(path, source).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("wrote {}", path);
+        }
+        None => print!("{}", source),
+    }
+}
+
+// --timeout <seconds> and --connections <n>, for solve --remote and serve. --retries <n> and
+// --backoff <seconds> for the client, --metrics <host:port> for the server
+fn remote_options(args: &[String]) -> remote::Options {
+    let mut options = remote::Options::default();
+    if let Some(timeout) = seconds(args, "--timeout") {
+        options.timeout = timeout;
+    }
+    if let Some(backoff) = seconds(args, "--backoff") {
+        options.backoff = backoff;
+    }
+    if let Some(n) = flag(args, "--retries") {
+        options.retries = parse_num(n);
+    }
+    if let Some(n) = flag(args, "--connections") {
+        options.connections = (parse_num(n) as usize).max(1);
+    }
+    if let Some(at) = flag(args, "--metrics") {
+        options.metrics = Some(at.parse().unwrap_or_else(|_| {
+            eprintln!("bad --metrics {}, expected host:port like 127.0.0.1:9100", at);
+            std::process::exit(2);
+        }));
+    }
+    options
+}
+
+// `check <input>`: run a guess and show how much of the first pass buffer it gets right, for
+// working byte by byte against a variant. `--next` tries every value for the first wrong byte
+fn check(args: &[String]) {
+    let input = match args.get(1) {
+        Some(input) if !input.starts_with("--") => input.as_bytes().to_vec(),
+        _ => {
+            eprintln!("usage: check <input> [--next] [--variant file] [--image file]");
+            std::process::exit(2);
+        }
+    };
+    let variant = variant(args);
+    let image = variant_image(args);
+    let progress = |input: &[u8]| -> (usize, Vec<u8>) {
+        match run_input(image.as_deref(), input) {
+            Ok(vm) => {
+                let first_pass = vm.s.mem[0x1194..0x1194 + input.len().min(variant.input_len())].to_vec();
+                (variant.progress(&first_pass), first_pass)
+            }
+            // a wrong first byte decrypts stage2 into garbage
+            Err(_) => (0, Vec::new()),
+        }
+    };
+
+    let (matched, first_pass) = progress(&input);
+    let hex = |bytes: &[u8]| -> String {
+        let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        bytes.join(" ")
+    };
+    let marks: Vec<String> = (0..first_pass.len())
+        .map(|i| match i < matched {
+            true => color::paint(|t| t.ok, "^^"),
+            false => color::paint(|t| t.bad, "xx"),
+        })
+        .collect();
+    println!("first pass  {}", hex(&first_pass));
+    println!("goodboy     {}", hex(&variant.goodboy));
+    println!("            {}", marks.join(" "));
+    println!("{}/{} leading bytes match", matched, variant.input_len());
+    if matched == variant.input_len() {
+        return;
+    }
+
+    if args.iter().any(|a| a == "--next") {
+        let mut guess = input.clone();
+        guess.resize(guess.len().max(matched + 1), b'?');
+        let found = (1..=255u8).find(|&b| {
+            guess[matched] = b;
+            progress(&guess).0 > matched
+        });
+        match found {
+            Some(b) => println!("byte {} is {:?} ({:#04x}): {}", matched, b as char, b, String::from_utf8_lossy(&guess)),
+            None => println!("no value for byte {} gets it any further", matched),
+        }
+    }
+    std::process::exit(1);
+}
+
+// weather as shipped, with the goodboy buffer from `--image` if there's one and then whatever
+// `--variant <file>` changes on top
+fn variant(args: &[String]) -> Variant {
+    let base = match variant_image(args) {
+        Some(_) => Variant::from_image(&unpacked_image(args)),
+        None => Ok(Variant::default()),
+    };
+    let variant = match flag(args, "--variant") {
+        Some(path) => base.and_then(|base| Variant::load(path, base)),
+        None => base,
+    };
+    variant.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    })
+}
+
+// the patched image from `--image <file>`, if there is one
+fn variant_image(args: &[String]) -> Option<Vec<u8>> {
+    flag(args, "--image").map(|path| {
+        std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        })
+    })
+}
+
+// the shipped image with `--image` over it, stage2 decrypted. a patched image longer than the
+// shipped one is kept whole
+fn unpacked_image(args: &[String]) -> Vec<u8> {
+    let mut image = WEATHER.image.to_vec();
+    if let Some(patched) = variant_image(args) {
+        if patched.len() > image.len() {
+            image.resize(patched.len(), 0);
+        }
+        image[..patched.len()].copy_from_slice(&patched);
+    }
+    inst::unxor_stage2(&image)
+}
+
+// the whole program on `input`, in `image` or the shipped one. the patched image goes over the
+// shipped one, so the rest of the layout is still there
+fn run_input(image: Option<&[u8]>, input: &[u8]) -> Result<Vm, VmError> {
+    let mut s: State = State::new();
+    if let Some(image) = image {
+        s.write_bytes(0, image);
+    }
+    s.quiet = true;
+    s.write_bytes(0x1000, input);
+    metrics::prepare(&mut s);
+    let mut vm = Vm::boot(s)?;
+    let result = vm.run();
+    metrics::record(&vm);
+    result.map(|_| vm)
+}
+
+// two images side by side, see memdiff.rs
+fn diff_mem_files(args: &[String]) {
+    let (a, b) = match (args.get(1), args.get(2)) {
+        (Some(a), Some(b)) if !a.starts_with("--") && !b.starts_with("--") => (a, b),
+        _ => {
+            eprintln!("usage: diff-mem-files <a> <b> [--program name] [--no-pager]");
+            std::process::exit(2);
+        }
+    };
+    let read = |path: &str| {
+        std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        })
+    };
+    let report = memdiff::report(&read(a), &read(b), [a, b], program(args).encrypted.clone());
+    pager::page(&report, !switch(args, "--no-pager"));
+}
+
+// `explore`: every path from the entry, forking at each conditional call, see explore.rs. the
+// machine is set up like `run --entry`'s. `--max-paths n` and `--max-steps n` bound it
+fn explore(args: &[String]) {
+    let entry = match flag(args, "--entry") {
+        Some(entry) => parse_num(entry) as usize,
+        None if flag(args, "--inline").is_some() => 0,
+        None => program(args).entry,
+    };
+    let mut vm: Vm = machine(entry, args);
+    vm.s.quiet = true;
+    let mut explorer = Explorer::new(vm);
+    if let Some(n) = flag(args, "--max-paths") {
+        explorer.max_paths = (parse_num(n) as usize).max(1);
+    }
+    if let Some(n) = flag(args, "--max-steps") {
+        explorer.max_steps = parse_num(n) as u64;
+    }
+
+    let mut ends = BTreeMap::new();
+    let mut paths = 0;
+    for path in &mut explorer {
+        let forced: Vec<String> = path
+            .branches
+            .iter()
+            .filter(|b| b.forced)
+            .map(|b| format!("{:#05x} {}", b.pc, if b.taken { "taken" } else { "not taken" }))
+            .collect();
+        let forced = match forced.is_empty() {
+            true => "as the registers say".to_string(),
+            false => format!("forced {}", forced.join(", ")),
         };
+        println!("path {}: {} after {} steps, {} branches, {}", paths, path.end, path.vm.steps, path.branches.len(), forced);
+        *ends.entry(path.end.to_string()).or_insert(0) += 1;
+        paths += 1;
+    }
+    let ends: Vec<String> = ends.iter().map(|(end, n)| format!("{} {}", n, end)).collect();
+    println!("{} paths: {}", paths, ends.join(", "));
+    if explorer.started >= explorer.max_paths {
+        println!("stopped forking at --max-paths {}, there are likely more", explorer.max_paths);
+    }
+}
 
-        (Self {
-            dest: operand1,
-            src: operand2,
-            dest_mode: op1_mode,
-            src_mode: op2_mode,
-            op: operation,
-        }, &mem[1..])
+// `cover [input]`: inputs taking each side of the conditional calls, see cover.rs. the search
+// starts from `input`, or `--input-file`'s, or the winning one. `--max-runs n`, `--max-paths n`
+// and `--max-steps n` bound it
+fn cover(args: &[String]) {
+    if program(args).name != WEATHER.name {
+        eprintln!("cover needs an input to vary, and only weather reads one");
+        std::process::exit(2);
+    }
+    let seed = match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(input) => input.as_bytes().to_vec(),
+        None => input_file(args).unwrap_or_else(|| {
+            let mut scratch = State::new();
+            scratch.quiet = true;
+            ex::winning_input(&mut scratch)
+        }),
+    };
+    let mut limits = cover::Limits::default();
+    if let Some(n) = flag(args, "--max-runs") {
+        limits.runs = parse_num(n) as usize;
     }
+    if let Some(n) = flag(args, "--max-paths") {
+        limits.paths = (parse_num(n) as usize).max(1);
+    }
+    if let Some(n) = flag(args, "--max-steps") {
+        limits.steps = parse_num(n) as u64;
+    }
+    let make = |input: &[u8]| {
+        let mut s = State::with_input(input);
+        s.quiet = true;
+        let mut vm = Vm::new(s);
+        vm.pc = disasm::vm::ENTRY;
+        vm
+    };
+    println!("{}", cover::report(&cover::cover(make, &seed, limits)));
 }
 
-fn parse_int(mut mem: &[u8]) -> (u32, &[u8]) {
-    let mut val = 0;
-    loop {
-        let curr = mem[0];
-        if curr.is_ascii_digit() {
-            val *= 10;
-            val += curr as u32 - b'0' as u32;
-            mem = &mem[1..];
-        } else {
-            break;
+// `trace-diff a.trace b.trace [--only store,read]`, for two `run --record` files
+fn trace_diff(args: &[String]) {
+    let (a, b) = match (args.get(1), args.get(2)) {
+        (Some(a), Some(b)) if !a.starts_with("--") && !b.starts_with("--") => (a, b),
+        _ => {
+            eprintln!("usage: trace-diff <a.trace> <b.trace> [--only kinds] [--program name] [--no-pager]");
+            std::process::exit(2);
+        }
+    };
+    let only = flag(args, "--only").map_or(Ok(Vec::new()), tracediff::kinds).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let load = |path: &str| {
+        recording::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        })
+    };
+    let (ta, tb) = (load(a), load(b));
+    let report = tracediff::report((a, b), &ta, &tb, &only, &program(args).unpacked(), &project(args));
+    pager::page(&report, !switch(args, "--no-pager"));
+}
+
+// rank every xor key for the program's encrypted stage, or for `keys <start> <end>` of `--image
+// <file>`. `--top n` shows more, and `--out <file>` writes the image decrypted with the best key
+// when it's a confident one
+fn keys(args: &[String]) {
+    let program = program(args);
+    let image = match flag(args, "--image") {
+        Some(path) => std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        }),
+        None => program.image.to_vec(),
+    };
+    let range = match (args.get(1), args.get(2)) {
+        (Some(start), Some(end)) if !start.starts_with("--") => {
+            parse_num(start) as usize..parse_num(end) as usize
+        }
+        _ => program.encrypted.clone(),
+    };
+    if range.end > image.len() || range.start >= range.end {
+        eprintln!("bad range {:#x}..{:#x} for a {:#x} byte image", range.start, range.end, image.len());
+        std::process::exit(2);
+    }
+
+    let top = flag(args, "--top").map_or(5, |n| parse_num(n) as usize);
+    println!("key   score  specifiers  letters");
+    for c in keys::rank(&image, range.clone()).iter().take(top) {
+        println!("{:#04x}  {:.3}  {:10}  {:7}", c.key, c.score, c.specifiers, c.letters);
+    }
+    match keys::recover(&image, range.clone()) {
+        Some(key) => {
+            println!("using key {:#04x}", key);
+            if let Some(path) = flag(args, "--out") {
+                std::fs::write(path, keys::unxor(&image, range, key)).unwrap_or_else(|e| {
+                    eprintln!("{}: {}", path, e);
+                    std::process::exit(1);
+                });
+            }
+        }
+        None => {
+            println!("no key scored {:.2} or better", keys::CONFIDENT);
+            std::process::exit(1);
         }
     }
-    (val, mem)
 }
 
-fn main() {
-    //disassemble();
-    ex::run();
-}
-
-#[allow(dead_code)]
-fn disassemble() {
-    // I dumped bytes with ghidra copy + paste to a python interpreter, then wrote to raw bytes
-    let mem = include_bytes!("../mem");
-
-    // first instruction is weird, it prints flag
-    // note: the reason it's weird is because it has one "real" instruction (a call) then it has a
-    // %s which prints the flag and I don't parse that. it's the end of the program anyway
-    let (inst, _) = Instruction::parse(mem);
-    println!("   0: {}", inst);
-
-    // this part disassembles the first stub. it un-xors the rest of the instructions
-    let mut curr: usize = 6;
-    while curr < 0xc8 {
-        let s = String::from_utf8(mem[curr..curr+20].to_vec()).unwrap();
-        let (inst, next) = Instruction::parse(&mem[curr..]);
-        println!("{:#04x}: {:30}   {}", curr, s, inst);
-        curr = mem.len() - next.len();
-    }
-
-    // manually un-xor the second stage
-    let key = b'%' ^ mem[0xc8];
-    let mut mem = mem.to_vec();
-    for ii in 0xc8..0x6fc {
-        mem[ii] ^= key;
-    }
-
-    // seek to second stage and disassemble
-    let mut curr: usize = 0xc8;
-    while mem.len() > curr {
-        let next = curr+1+mem[curr + 1..].iter().position(|c| *c == b'%' || *c == 0).unwrap_or_else(|| 30);
-        let upper = next.min(mem.len());
-        let s = String::from_utf8(mem[curr..upper].to_vec()).unwrap();
-        let (inst, next) = Instruction::parse(&mem[curr..]);
-        println!("{:#05x}:  {}", curr, inst);
-        curr = mem.len() - next.len();
+// decode a dump without running it, `validate [file]`. exits 1 when there's anything that doesn't
+// decode or looks odd, so a script can tell a bad dump
+fn validate(args: &[String]) {
+    let program = program(args);
+    let (path, mem) = match args.get(1).filter(|a| !a.starts_with("--")) {
+        Some(path) => (path.as_str(), std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        })),
+        None => (program.name, program.image.to_vec()),
+    };
+    let (report, clean) = validate::report(path, &mem, program.encrypted.clone());
+    println!("{}", report);
+    if !clean {
+        std::process::exit(1);
+    }
+}
+
+// run the program's own decryptor on the winning input, or on `--input-file <file>`, and show each
+// layer it hands over to. `--image <file>` unpacks a variant of the program instead, and `--out
+// <file>` writes the image with every layer decrypted
+fn unpack(args: &[String]) {
+    let program = program(args);
+    let original = match flag(args, "--image") {
+        Some(path) => std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        }),
+        None => program.image.to_vec(),
+    };
+    let input = input_file(args).unwrap_or_else(|| {
+        let mut scratch = State::new();
+        scratch.quiet = true;
+        ex::winning_input(&mut scratch)
+    });
+    let layers = unpack::layers(&original, program.entry, &input).unwrap_or_else(|e| {
+        eprintln!("fault: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut image = original.clone();
+    for layer in &layers {
+        let changed = image.iter().zip(&layer.image).filter(|(a, b)| a != b).count();
+        println!(
+            "step {:7}: {:#05x} written by {:#05x}, {} bytes decrypted",
+            layer.steps, layer.entry, layer.writer, changed
+        );
+        image = layer.image.clone();
+    }
+    if layers.is_empty() {
+        println!("nothing ran from written memory, the image isn't packed or the input is wrong");
+        std::process::exit(1);
+    }
+
+    // the built in unpacker should agree with the program's own, at least on the real image
+    let differ = image.iter().zip(program.unpacked()).filter(|(a, b)| **a != *b).count();
+    match differ {
+        0 => println!("matches the {} unpacker", program.name),
+        n => println!("{} bytes differ from the {} unpacker", n, program.name),
+    }
+    if let Some(path) = flag(args, "--out") {
+        std::fs::write(path, &image).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        });
     }
 }
+
+// the built in program from --program <name>, weather2021 if there isn't one
+fn program(args: &[String]) -> &'static Program {
+    match flag(args, "--program") {
+        Some(name) => programs::by_name(name).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }),
+        None => &WEATHER,
+    }
+}
+
+// the project file for the image, plus register names from --regs <file>
+fn project(args: &[String]) -> Project {
+    let program = program(args);
+    let mut project = Project::open(program.image).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    if let Some(path) = flag(args, "--regs") {
+        project.regs = RegNames::load(path, &program.unpacked()).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        });
+    }
+    project.verbose = switch(args, "--verbose");
+    project
+}
+
+// label, comment and region commands all edit the project file
+fn annotate(args: &[String]) {
+    let mut project = project(args);
+    let addr = args.get(1).map(|a| parse_num(a) as usize);
+    let text = args.get(2..).map(|rest| rest.join(" ")).unwrap_or_default();
+
+    match (args[0].as_str(), addr) {
+        ("label", Some(addr)) if !text.is_empty() => {
+            project.labels.insert(addr, text);
+        }
+        ("comment", Some(addr)) if !text.is_empty() => {
+            project.comments.insert(addr, text);
+        }
+        ("region", Some(start)) if args.len() > 3 => {
+            let end = parse_num(&args[2]) as usize;
+            project.regions.push((start, end, args[3..].join(" ")));
+        }
+        ("project", _) => {
+            println!("{}", project.path());
+            println!(
+                "{} labels, {} comments, {} regions",
+                project.labels.len(),
+                project.comments.len(),
+                project.regions.len()
+            );
+            return;
+        }
+        _ => {
+            eprintln!("usage: label <addr> <name> | comment <addr> <text> | region <start> <end> <name>");
+            std::process::exit(2);
+        }
+    }
+
+    project.save().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    println!("saved {}", project.path());
+}
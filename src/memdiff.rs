@@ -0,0 +1,179 @@
+// `diff-mem-files a b`: two images of the same kind of program, like the 2021 challenge and a
+// remix of it. the byte ranges that differ come first, then both sides decoded and lined up
+// instruction by instruction. a constant in the printf code is decimal text, so changing one
+// can move everything after it, which is why the instructions are matched by shape (operation,
+// operand modes and width) instead of by address. a pair that matches but has different
+// immediates is a changed constant, which is what a remix of the flag check mostly is
+use crate::color;
+use crate::inst::{try_parse, DestMode, Instruction, Operation, SrcMode};
+use crate::keys;
+use std::fmt::Write;
+use std::ops::Range;
+
+// an instruction, or a byte that doesn't decode
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    addr: usize,
+    inst: Option<Instruction>,
+    byte: u8,
+}
+
+impl Item {
+    fn same_shape(&self, other: &Item) -> bool {
+        match (self.inst, other.inst) {
+            (Some(a), Some(b)) => {
+                let regs = |i: &Instruction| {
+                    let dest = (i.dest_mode == DestMode::NoPlusMinus || i.dest_mode == DestMode::Plus).then_some(i.dest);
+                    let src = (i.op == Operation::Jmp || matches!(i.src_mode, SrcMode::H | SrcMode::L)).then_some(i.src);
+                    (dest, src)
+                };
+                a.op == b.op && a.dest_mode == b.dest_mode && a.src_mode == b.src_mode && a.width == b.width && regs(&a) == regs(&b)
+            }
+            (None, None) => self.byte == other.byte,
+            _ => false,
+        }
+    }
+
+    fn text(&self) -> String {
+        match self.inst {
+            Some(inst) => inst.to_string(),
+            None => format!(".byte {:#04x}", self.byte),
+        }
+    }
+}
+
+// the image with `encrypted` decrypted, if a key for it can be found, and the key
+pub fn decrypt(image: &[u8], encrypted: Range<usize>) -> (Vec<u8>, Option<u8>) {
+    if encrypted.is_empty() || image.len() < encrypted.end {
+        return (image.to_vec(), None);
+    }
+    match keys::recover(image, encrypted.clone()) {
+        Some(key) => (keys::unxor(image, encrypted, key), Some(key)),
+        None => (image.to_vec(), None),
+    }
+}
+
+// the whole image as instructions, stepping a byte at a time over anything that doesn't decode
+fn decode(mem: &[u8]) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut curr = 0;
+    while curr < mem.len() {
+        let (inst, len) = match try_parse(&mem[curr..]) {
+            Some((inst, len)) => (Some(inst), len),
+            None => (None, 1),
+        };
+        items.push(Item {
+            addr: curr,
+            inst,
+            byte: mem[curr],
+        });
+        curr += len;
+    }
+    items
+}
+
+// byte ranges where the two differ, past the end of the shorter one counts as different. once
+// the code has shifted, bytes keep matching by chance, so ranges closer than GAP are joined
+const GAP: usize = 8;
+
+pub fn ranges(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in 0..a.len().max(b.len()) {
+        if a.get(i) == b.get(i) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if i - last.end < GAP => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+// both images are decrypted first, if they have a stage at `encrypted` like Program::encrypted
+pub fn report(a: &[u8], b: &[u8], names: [&str; 2], encrypted: Range<usize>) -> String {
+    let mut out = String::new();
+    let (a, key_a) = decrypt(a, encrypted.clone());
+    let (b, key_b) = decrypt(b, encrypted);
+    for (name, mem, key) in [(names[0], &a, key_a), (names[1], &b, key_b)] {
+        let key = key.map_or("not decrypted".to_string(), |k| format!("stage2 key {:#04x}", k));
+        writeln!(out, "{}: {:#x} bytes, {}", name, mem.len(), key).unwrap();
+    }
+
+    let ranges = ranges(&a, &b);
+    let total = (0..a.len().max(b.len())).filter(|&i| a.get(i) != b.get(i)).count();
+    writeln!(out, "\n{} bytes differ in {} ranges", total, ranges.len()).unwrap();
+    for r in &ranges {
+        writeln!(out, "  {:#06x}..{:#06x}", r.start, r.end).unwrap();
+    }
+    if ranges.is_empty() {
+        return out;
+    }
+
+    writeln!(out, "\ninstructions:").unwrap();
+    let (items_a, items_b) = (decode(&a), decode(&b));
+    let width = 44;
+    for step in align(&items_a, &items_b) {
+        match step {
+            (Some(x), Some(y)) if x.text() == y.text() => {}
+            (Some(x), Some(y)) => {
+                let line = format!("  {:#06x} {:#06x}  {}", x.addr, y.addr, color::pad(&x.text(), width));
+                writeln!(out, "{}| {}  {}", line, y.text(), color::paint(|t| t.bad, &constants(x, y))).unwrap();
+            }
+            (Some(x), None) => writeln!(out, "- {:#06x}         {}", x.addr, x.text()).unwrap(),
+            (None, Some(y)) => writeln!(out, "+        {:#06x}  {}", y.addr, y.text()).unwrap(),
+            (None, None) => {}
+        }
+    }
+    out
+}
+
+// "constant 0x3278f102 -> 0x3278f103" for each immediate that differs between matched items
+fn constants(x: &Item, y: &Item) -> String {
+    let (a, b) = match (x.inst, y.inst) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return String::new(),
+    };
+    let mut changed = Vec::new();
+    if a.dest != b.dest {
+        changed.push(format!("{:#x} -> {:#x}", a.dest, b.dest));
+    }
+    if a.src != b.src {
+        changed.push(format!("{:#x} -> {:#x}", a.src, b.src));
+    }
+    match changed.is_empty() {
+        true => String::new(),
+        false => format!("constant {}", changed.join(", ")),
+    }
+}
+
+// longest common subsequence of shapes, as pairs in order. unmatched items get a None partner
+fn align<'a>(a: &'a [Item], b: &'a [Item]) -> Vec<(Option<&'a Item>, Option<&'a Item>)> {
+    // lengths of the common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i].same_shape(&b[j]) {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].same_shape(&b[j]) {
+            steps.push((Some(&a[i]), Some(&b[j])));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            steps.push((Some(&a[i]), None));
+            i += 1;
+        } else {
+            steps.push((None, Some(&b[j])));
+            j += 1;
+        }
+    }
+    steps
+}
@@ -0,0 +1,144 @@
+// backing for State::mem. the challenge image is tiny and just lives in a Vec, but a full process
+// dump is better mapped straight from the file, copy-on-write so stores never reach the disk.
+// forked machines share one copy until either of them stores something
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use memmap2::{MmapMut, MmapOptions};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+// byte order of multi byte memory accesses. the challenge is little endian, big endian is for
+// other members of the vm family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    // put `bytes`, which are little endian, into this order. it's its own inverse
+    pub fn order(self, bytes: &mut [u8]) {
+        if self == Endian::Big {
+            bytes.reverse();
+        }
+    }
+}
+
+// there's no mmap in the browser, a map there is just the file read in. without std there are no
+// files to map at all
+#[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+type MmapMut = Vec<u8>;
+
+pub enum Memory {
+    Owned(Vec<u8>),
+    Mapped(MmapMut),
+    // between forks, the first store copies it out if anyone else still has it
+    Shared(Arc<Vec<u8>>),
+}
+
+impl Memory {
+    // private copy-on-write mapping of the whole file. pages are only read in, and only copied,
+    // when they're touched
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn map(path: &str) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("can't open {}: {}", path, e))?;
+        // safety: the mapping is private, so our writes stay in our pages. someone else
+        // truncating the file under us would still fault, same as any other mmap user
+        let map = unsafe { MmapOptions::new().map_copy(&file) }
+            .map_err(|e| format!("can't map {}: {}", path, e))?;
+        Ok(Memory::Mapped(map))
+    }
+
+    #[cfg(all(feature = "std", target_arch = "wasm32"))]
+    pub fn map(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("can't open {}: {}", path, e))?;
+        Ok(Memory::Mapped(bytes))
+    }
+
+    // growing a map means copying it out, which is fine since dumps rarely need to grow
+    pub fn resize(&mut self, len: usize, value: u8) {
+        match self {
+            Memory::Owned(v) => v.resize(len, value),
+            Memory::Mapped(map) => {
+                let mut v = map.to_vec();
+                v.resize(len, value);
+                *self = Memory::Owned(v);
+            }
+            Memory::Shared(v) => Arc::make_mut(v).resize(len, value),
+        }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Memory::Mapped(_))
+    }
+
+    // another handle on the same bytes, for a fork. this one is shared from now on too
+    pub fn share(&mut self) -> Memory {
+        if !matches!(self, Memory::Shared(_)) {
+            *self = Memory::Shared(Arc::new(self.to_vec()));
+        }
+        match self {
+            Memory::Shared(v) => Memory::Shared(v.clone()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Memory::Owned(v) => v,
+            Memory::Mapped(map) => map,
+            Memory::Shared(v) => v,
+        }
+    }
+}
+
+impl DerefMut for Memory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Memory::Owned(v) => v,
+            Memory::Mapped(map) => map,
+            Memory::Shared(v) => Arc::make_mut(v).as_mut_slice(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Memory {
+    fn from(v: Vec<u8>) -> Self {
+        Memory::Owned(v)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::Owned(Vec::new())
+    }
+}
+
+// a clone of a map is an owned copy, the State being cloned keeps the map
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        Memory::Owned(self.to_vec())
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Memory::Owned(v) => write!(f, "Owned({} bytes)", v.len()),
+            Memory::Mapped(map) => write!(f, "Mapped({} bytes)", map.len()),
+            Memory::Shared(v) => write!(f, "Shared({} bytes, {} handles)", v.len(), Arc::strong_count(v)),
+        }
+    }
+}
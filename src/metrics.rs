@@ -0,0 +1,119 @@
+// how fast a long campaign is going, for `serve`, `check --next` and `native --random` left
+// running. every machine that's run to the end adds its steps, its memory events and one to the
+// runs (each input is checked from a fresh snapshot of the machine) to the totals here, and:
+//
+//   - `--metrics-every secs` prints a line to stderr that often, with the rates since the last one
+//   - `serve --metrics host:port` answers http on that port with the totals in prometheus' text
+//     format, for scraping
+//
+// counting is off until one of those asks for it. the events are counted from the run's trace, so
+// a counted run records one
+use crate::state::State;
+use crate::trace::Event;
+use crate::vm::Vm;
+use crate::word::Word;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STEPS: AtomicU64 = AtomicU64::new(0);
+static EVENTS: AtomicU64 = AtomicU64::new(0);
+static RUNS: AtomicU64 = AtomicU64::new(0);
+
+// the totals at one moment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub steps: u64,
+    pub events: u64,
+    pub runs: u64,
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn totals() -> Totals {
+    Totals {
+        steps: STEPS.load(Ordering::Relaxed),
+        events: EVENTS.load(Ordering::Relaxed),
+        runs: RUNS.load(Ordering::Relaxed),
+    }
+}
+
+// a machine about to be run, so its memory events can be counted after
+pub fn prepare<R>(s: &mut State<R>) {
+    if enabled() && s.trace.is_none() {
+        s.trace = Some(Vec::new());
+    }
+}
+
+// a machine that's been run, however it ended
+pub fn record<R: Word>(vm: &Vm<R>) {
+    if !enabled() {
+        return;
+    }
+    let events = vm.s.trace.as_deref().unwrap_or_default();
+    let memory = events.iter().filter(|e| matches!(e, Event::Read { .. } | Event::Store { .. })).count();
+    STEPS.fetch_add(vm.steps, Ordering::Relaxed);
+    EVENTS.fetch_add(memory as u64, Ordering::Relaxed);
+    RUNS.fetch_add(1, Ordering::Relaxed);
+}
+
+// millions a second, or thousands when it's slower than that
+fn rate(n: u64, over: Duration) -> String {
+    let per_sec = n as f64 / over.as_secs_f64().max(1e-9);
+    match per_sec >= 1e6 {
+        true => format!("{:.2}M/s", per_sec / 1e6),
+        false => format!("{:.1}k/s", per_sec / 1e3),
+    }
+}
+
+// the log line for what happened between `before` and `now`, `over` apart
+pub fn line(before: Totals, now: Totals, over: Duration) -> String {
+    format!(
+        "metrics: steps {}, memory events {}, runs {} ({} so far, {} steps)",
+        rate(now.steps - before.steps, over),
+        rate(now.events - before.events, over),
+        now.runs - before.runs,
+        now.runs,
+        now.steps
+    )
+}
+
+// a line on stderr every `period` for the rest of the process
+pub fn every(period: Duration) {
+    enable();
+    std::thread::spawn(move || {
+        let mut last = (Instant::now(), totals());
+        loop {
+            std::thread::sleep(period);
+            let now = (Instant::now(), totals());
+            eprintln!("{}", line(last.1, now.1, now.0 - last.0));
+            last = now;
+        }
+    });
+}
+
+// the totals as prometheus scrapes them, `up` is how long the process has been counting
+pub fn prometheus(totals: Totals, up: Duration) -> String {
+    let mut out = String::new();
+    let counters = [
+        ("weather_steps_total", "instructions run", totals.steps),
+        ("weather_memory_events_total", "memory reads and stores", totals.events),
+        ("weather_runs_total", "machines run to the end", totals.runs),
+    ];
+    for (name, help, value) in counters {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} counter", name).unwrap();
+        writeln!(out, "{} {}", name, value).unwrap();
+    }
+    writeln!(out, "# HELP weather_steps_per_second instructions run a second, on average since the start").unwrap();
+    writeln!(out, "# TYPE weather_steps_per_second gauge").unwrap();
+    writeln!(out, "weather_steps_per_second {:.1}", totals.steps as f64 / up.as_secs_f64().max(1e-9)).unwrap();
+    out
+}
@@ -0,0 +1,89 @@
+// register names per function, so listings and traces can say `index` instead of `s.r0`. the
+// config is one function per line:
+//
+//     # read_input_byte
+//     0x1f4 r0=index r4=byte
+//
+// functions start at call targets and run until the next one, so names only need to be given
+// once for the whole body
+use crate::inst::{parse_num, Instruction, Operation};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Default)]
+pub struct RegNames {
+    // function start -> names indexed by register number
+    funcs: BTreeMap<usize, Vec<Option<String>>>,
+    // every call target in the program
+    starts: BTreeSet<usize>,
+}
+
+impl RegNames {
+    // `mem` must have stage2 decrypted already, it's where the function boundaries come from
+    pub fn parse(text: &str, mem: &[u8]) -> Result<Self, String> {
+        let mut funcs = BTreeMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let addr: usize = match words.next() {
+                Some(addr) => parse_num(addr).ok_or(format!("line {}: bad address", i + 1))? as usize,
+                None => continue,
+            };
+
+            let names: &mut Vec<Option<String>> = funcs.entry(addr).or_default();
+            for word in words {
+                let (reg, name) = word
+                    .split_once('=')
+                    .ok_or(format!("line {}: expected rN=name, got {}", i + 1, word))?;
+                let n: usize = reg
+                    .strip_prefix('r')
+                    .and_then(|n| n.parse().ok())
+                    .ok_or(format!("line {}: bad register {}", i + 1, reg))?;
+                if names.len() <= n {
+                    names.resize(n + 1, None);
+                }
+                names[n] = Some(name.to_string());
+            }
+        }
+
+        Ok(Self {
+            funcs,
+            starts: call_targets(mem),
+        })
+    }
+
+    pub fn load(path: &str, mem: &[u8]) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text, mem).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // names for the function that `pc` is in
+    pub fn at(&self, pc: usize) -> &[Option<String>] {
+        let start = self.starts.range(..=pc).next_back();
+        match start.and_then(|start| self.funcs.get(start)) {
+            Some(names) => names,
+            None => &[],
+        }
+    }
+}
+
+// every address some %C can jump to, walking the same ranges the disassembler does
+pub fn call_targets(mem: &[u8]) -> BTreeSet<usize> {
+    let mut targets = BTreeSet::new();
+
+    // the first instruction is the call into stage1, the rest of it isn't code
+    let (first, _) = Instruction::parse(mem);
+    targets.insert(first.dest as usize);
+
+    for (start, end) in [(6, 0xc8), (0xc8, mem.len())] {
+        let mut curr = start;
+        while curr < end {
+            let (inst, next) = Instruction::parse(&mem[curr..]);
+            if let Operation::Jmp = inst.op {
+                targets.insert(inst.dest as usize);
+            }
+            curr = mem.len() - next.len();
+        }
+    }
+    targets
+}
@@ -0,0 +1,177 @@
+// the emulator against the real thing. every candidate input goes to the original binary on stdin
+// and to the vm, and the flag line each prints is compared:
+//
+//     disasm native                      the winning input and some near misses
+//     disasm native London --random 100  an input of your own, and more near misses
+//
+// the binary reads the city with scanf("%100s"), so the vm gets what that would have kept. a
+// crash, a hang, and the flag or "none" each only agree with the same thing from the other side
+use crate::ex::State;
+use crate::metrics;
+use crate::programs::WEATHER;
+use crate::rng::Rng;
+use crate::variant::Variant;
+use crate::vm::{Vm, ENTRY};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// the winning run is under half a million, garbage from a wrong first byte can recurse forever
+const MAX_STEPS: u64 = 10_000_000;
+
+// the width in the binary's "%100s"
+const SCANF_WIDTH: usize = 100;
+
+// the binary answers in a few milliseconds
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    // what follows "Flag: ", "none" when the check fails
+    Flag(String),
+    // a vm fault, or the binary dying without printing a flag
+    Crashed(String),
+    Hung,
+}
+
+impl Outcome {
+    fn agrees(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Crashed(_), Outcome::Crashed(_)) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Flag(flag) => write!(f, "{}", flag),
+            Outcome::Crashed(why) => write!(f, "crashed ({})", why),
+            Outcome::Hung => write!(f, "hung"),
+        }
+    }
+}
+
+// what scanf("%100s") stores: leading whitespace skipped, then up to the next whitespace
+pub fn scanf(input: &[u8]) -> &[u8] {
+    let start = input.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(input.len());
+    let rest = &input[start..];
+    let end = rest.iter().position(|b| b.is_ascii_whitespace()).unwrap_or(rest.len());
+    &rest[..end.min(SCANF_WIDTH)]
+}
+
+// how the vm's run ended when it didn't finish
+fn finish(vm: &mut Vm) -> Option<Outcome> {
+    while !vm.halted {
+        if vm.steps >= MAX_STEPS {
+            return Some(Outcome::Hung);
+        }
+        if let Err(e) = vm.step() {
+            return Some(Outcome::Crashed(e.to_string()));
+        }
+    }
+    None
+}
+
+// the vm on `input`, as the binary's %F handler would see it. from the real entry point rather
+// than `Vm::boot`, which goes into stage2 whether or not stage1's check lets it
+pub fn emulated(input: &[u8]) -> Outcome {
+    let mut s: State = State::with_input(input);
+    s.quiet = true;
+    metrics::prepare(&mut s);
+    let mut vm = Vm::new(s);
+    vm.pc = ENTRY;
+    let outcome = finish(&mut vm);
+    metrics::record(&vm);
+    if let Some(outcome) = outcome {
+        return outcome;
+    }
+    let flag = &vm.s.mem[WEATHER.flag.clone()];
+    let end = flag.iter().position(|&b| b == 0).unwrap_or(flag.len());
+    Outcome::Flag(String::from_utf8_lossy(&flag[..end]).into_owned())
+}
+
+// the binary at `binary` on `input`, killed if it runs past `limit`
+pub fn native(binary: &str, input: &[u8], limit: Duration) -> Result<Outcome, String> {
+    // a path, not something to look for on PATH. joining an absolute one just gives it back
+    let mut child = Command::new(Path::new(".").join(binary))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", binary, e))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut line = input.to_vec();
+    line.push(b'\n');
+    // it might be gone before reading anything, that shows up in the output
+    let _ = stdin.write_all(&line);
+    drop(stdin);
+
+    // the output is a few lines, so it fits in the pipe while this waits
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if start.elapsed() > limit {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(Outcome::Hung);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    let mut out = Vec::new();
+    child.stdout.take().unwrap().read_to_end(&mut out).map_err(|e| e.to_string())?;
+
+    let out = String::from_utf8_lossy(&out);
+    match out.lines().find_map(|line| line.strip_prefix("Flag: ")) {
+        Some(flag) => Ok(Outcome::Flag(flag.to_string())),
+        None => match status.code() {
+            Some(code) => Ok(Outcome::Crashed(format!("exit status {}", code))),
+            None => Ok(Outcome::Crashed("killed by a signal".to_string())),
+        },
+    }
+}
+
+// the winning input with a few bytes changed, scanf-safe so both sides read the same thing
+fn near_miss(rng: &mut Rng, winning: &[u8]) -> Vec<u8> {
+    let mut input = winning.to_vec();
+    for _ in 0..=rng.below(3) {
+        let at = rng.below(input.len() as u64) as usize;
+        input[at] = b'!' + rng.below(94) as u8;
+    }
+    input
+}
+
+pub fn run(binary: &str, inputs: &[String], random: usize) -> Result<(), String> {
+    let winning = Variant::default().solve()?;
+    let mut candidates = vec![winning.clone()];
+    candidates.extend(inputs.iter().map(|i| i.as_bytes().to_vec()));
+    let mut rng = Rng::new(0x1c);
+    candidates.extend((0..random).map(|_| near_miss(&mut rng, &winning)));
+
+    let mut diverged = 0;
+    let mut accepted = 0;
+    for input in &candidates {
+        let native = native(binary, input, TIMEOUT)?;
+        let emulated = emulated(scanf(input));
+        if matches!(&native, Outcome::Flag(f) if f != "none") {
+            accepted += 1;
+        }
+        if !native.agrees(&emulated) {
+            diverged += 1;
+            println!("{}", input.escape_ascii());
+            println!("    native   {}", native);
+            println!("    emulated {}", emulated);
+        }
+    }
+    println!("{} inputs, {} accepted by the binary, {} diverged", candidates.len(), accepted, diverged);
+    match diverged {
+        0 => Ok(()),
+        _ => Err(format!("the emulator disagrees with {} on {} inputs", binary, diverged)),
+    }
+}
@@ -0,0 +1,92 @@
+// long output one screen at a time. $PAGER gets the text if it's set, otherwise the built in pager
+// here reads a command per line from the terminal, so it works without raw mode:
+//
+//     enter      next screen
+//     /text      next line containing text, n repeats it
+//     g, G       top, bottom
+//     q          quit
+//
+// nothing is paged when stdout isn't a terminal or the text fits on one screen, and `--no-pager`
+// turns it off for scripts
+use crate::color;
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+pub fn page(text: &str, enabled: bool) {
+    let height = screen_height();
+    if !enabled || !std::io::stdout().is_terminal() || text.lines().count() < height {
+        print!("{}", text);
+        return;
+    }
+    match std::env::var("PAGER") {
+        Ok(pager) if !pager.is_empty() => external(&pager, text),
+        _ => builtin(text, height),
+    }
+}
+
+// rows on the terminal, from $LINES, less one for the prompt
+fn screen_height() -> usize {
+    let lines = std::env::var("LINES").ok().and_then(|l| l.parse().ok());
+    lines.unwrap_or(24usize).saturating_sub(1).max(1)
+}
+
+fn external(pager: &str, text: &str) {
+    let child = Command::new("sh").arg("-c").arg(pager).stdin(Stdio::piped()).spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("{}: {}", pager, e);
+            print!("{}", text);
+            return;
+        }
+    };
+    // the pager quitting early closes the pipe, that's not an error
+    let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+    let _ = child.wait();
+}
+
+fn builtin(text: &str, height: usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut top = 0;
+    let mut search = String::new();
+    loop {
+        let end = (top + height).min(lines.len());
+        for line in &lines[top..end] {
+            println!("{}", line);
+        }
+        if end == lines.len() {
+            return;
+        }
+        print!("-- {}/{} -- enter, /text, n, g, G, q: ", end, lines.len());
+        std::io::stdout().flush().unwrap();
+
+        let mut command = String::new();
+        if input.read_line(&mut command).unwrap_or(0) == 0 {
+            return;
+        }
+        let command = command.trim_end_matches(['\r', '\n']);
+        top = match command {
+            "q" => return,
+            "g" => 0,
+            "G" => lines.len().saturating_sub(height),
+            "" => end,
+            _ if command == "n" || command.starts_with('/') => {
+                if let Some(text) = command.strip_prefix('/') {
+                    search = text.to_string();
+                }
+                // matching skips the color escapes, the search is for what's on screen
+                let found = (top + 1..lines.len()).find(|&i| color::strip(lines[i]).contains(&search));
+                match found {
+                    Some(i) => i,
+                    None => {
+                        println!("not found: {}", search);
+                        top
+                    }
+                }
+            }
+            _ => top,
+        };
+    }
+}
@@ -0,0 +1,113 @@
+// which registers hold addresses and which hold plain integers, so the listing can show loads and
+// stores through a register as `*(u32*)(s.r4)` and a constant going into one as `(u8*)0x1388`.
+//
+// a value is an address if it gets used as one: read or written through, or moved or stepped
+// along into a register that is. a step is a register or a small constant, anything bigger is
+// the base of a table, like the `+= 0x1000` in `r2 = r0; r2 += 0x1000`, and what it's added to
+// is an index. that's a backwards walk of each
+// function from its ret, the same way liveness works. a call passes through whatever the callee
+// doesn't write, and the callee's own uses on entry count at the call. what a function returns
+// counts as an address if any caller uses it as one after the call
+use crate::ex::REGS;
+use crate::inst::{DestMode, Operation, SrcMode};
+use crate::decode::Body;
+use crate::ranges::writes;
+use std::collections::BTreeMap;
+
+type Regs = [bool; REGS];
+
+// constants below this are steps along a table, not where one starts
+const STEP: u32 = 0x100;
+
+#[derive(Debug, Default)]
+pub struct Pointers {
+    // registers holding an address right after each instruction
+    pub after: BTreeMap<usize, Regs>,
+    // registers each function takes an address in
+    pub entry: BTreeMap<usize, Regs>,
+}
+
+pub fn infer(bodies: &BTreeMap<usize, Body>) -> Pointers {
+    let writes = writes(bodies);
+    let mut entry: BTreeMap<usize, Regs> = BTreeMap::new();
+    let mut exit: BTreeMap<usize, Regs> = BTreeMap::new();
+    loop {
+        let mut after = BTreeMap::new();
+        let mut returns: BTreeMap<usize, Regs> = BTreeMap::new();
+        let mut changed = false;
+        for (&addr, body) in bodies {
+            let live = walk(body, exit.get(&addr).copied().unwrap_or_default(), &entry, &writes, &mut after, &mut returns);
+            changed |= merge(&mut entry, addr, live);
+        }
+        for (addr, regs) in returns {
+            changed |= merge(&mut exit, addr, regs);
+        }
+        if !changed {
+            return Pointers { after, entry };
+        }
+    }
+}
+
+fn merge(into: &mut BTreeMap<usize, Regs>, addr: usize, regs: Regs) -> bool {
+    let old = into.entry(addr).or_default();
+    let new: Regs = std::array::from_fn(|n| old[n] || regs[n]);
+    let changed = new != *old;
+    *old = new;
+    changed
+}
+
+// one function backwards from `live` at its ret, giving the addresses it takes on entry
+fn walk(
+    body: &Body,
+    live: Regs,
+    entry: &BTreeMap<usize, Regs>,
+    writes: &BTreeMap<usize, Regs>,
+    after: &mut BTreeMap<usize, Regs>,
+    returns: &mut BTreeMap<usize, Regs>,
+) -> Regs {
+    let mut live = live;
+    for &(pc, inst, _) in body.iter().rev() {
+        after.insert(pc, live);
+        match inst.op {
+            Operation::Ret => {}
+            Operation::Jmp => {
+                let target = inst.dest as usize;
+                let written = writes.get(&target).copied().unwrap_or([true; REGS]);
+                let uses = entry.get(&target).copied().unwrap_or_default();
+                let mut returned = [false; REGS];
+                let mut taken = [false; REGS];
+                for n in 0..REGS {
+                    returned[n] = written[n] && live[n];
+                    taken[n] = uses[n] || (!written[n] && live[n]);
+                }
+                merge(returns, target, returned);
+                live = match inst.dest_mode {
+                    DestMode::NoPlusMinus => taken,
+                    // it might not be called at all
+                    _ => std::array::from_fn(|n| taken[n] || live[n]),
+                };
+            }
+            op => {
+                if inst.dest_mode == DestMode::NoPlusMinus {
+                    let d = inst.dest as usize;
+                    let was = live[d];
+                    live[d] = match (op, inst.src_mode) {
+                        (Operation::Add | Operation::Sub, SrcMode::LL) => was && inst.src < STEP,
+                        (Operation::Add | Operation::Sub, SrcMode::L) => was,
+                        _ => false,
+                    };
+                    if let (Operation::Mov, SrcMode::L) = (op, inst.src_mode) {
+                        live[inst.src as usize] |= was;
+                    }
+                }
+                if inst.dest_mode == DestMode::Plus {
+                    live[inst.dest as usize] = true;
+                }
+                if inst.src_mode == SrcMode::H {
+                    live[inst.src as usize] = true;
+                }
+            }
+        }
+    }
+    live
+}
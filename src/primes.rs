@@ -0,0 +1,108 @@
+// the buffer generate_buffer (0x151) fills at 0x1388, worked out at compile time. I called these
+// RNG numbers while solving, they're every prime in 0x3390..0x3520, 16 bits each. each one goes in
+// with a full 32 bit store two bytes after the last, so the zero top half of the last prime lands
+// just past the table
+#[cfg(feature = "std")]
+use crate::ex;
+use crate::state::State;
+#[cfg(feature = "std")]
+use crate::vm::Vm;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub const START: usize = 0x1388;
+// the counter generate_buffer runs over
+pub const NUMBERS: Range<u32> = 0x3390..0x3520;
+pub const COUNT: usize = count();
+pub const PRIMES: [u16; COUNT] = table();
+// every byte generate_buffer writes
+pub const REGION: Range<usize> = START..START + 2 * COUNT + 2;
+
+// trial division, same as the program but without the recursion
+pub const fn is_prime(n: u32) -> bool {
+    let mut d = 2;
+    while d * d <= n {
+        if n.is_multiple_of(d) {
+            return false;
+        }
+        d += 1;
+    }
+    n >= 2
+}
+
+const fn count() -> usize {
+    let mut count = 0;
+    let mut n = NUMBERS.start;
+    while n < NUMBERS.end {
+        if is_prime(n) {
+            count += 1;
+        }
+        n += 1;
+    }
+    count
+}
+
+const fn table() -> [u16; COUNT] {
+    let mut table = [0; COUNT];
+    let mut i = 0;
+    let mut n = NUMBERS.start;
+    while n < NUMBERS.end {
+        if is_prime(n) {
+            table[i] = n as u16;
+            i += 1;
+        }
+        n += 1;
+    }
+    table
+}
+
+// REGION as it should be once generate_buffer is done
+pub fn expected() -> Vec<u8> {
+    let mut bytes: Vec<u8> = PRIMES.iter().flat_map(|p| p.to_le_bytes()).collect();
+    bytes.extend_from_slice(&[0, 0]);
+    bytes
+}
+
+// the first entry of REGION in `s` that's off from the table, None if it all matches
+pub fn check(s: &State) -> Option<String> {
+    let expected = expected();
+    let got = &s.mem[REGION];
+    let i = (0..expected.len()).find(|&i| got[i] != expected[i])? & !1;
+    let word = |b: &[u8]| u16::from_le_bytes([b[i], b[i + 1]]);
+    Some(format!(
+        "entry {} at {:#x} is {:#x}, the table has {:#x}",
+        i / 2,
+        START + i,
+        word(got),
+        word(&expected)
+    ))
+}
+
+// `run --verify-primes`: every way of running generate_buffer against the table
+#[cfg(feature = "std")]
+pub fn verify() -> bool {
+    println!("{} primes at {:#x}..{:#x}", COUNT, REGION.start, REGION.end);
+
+    // the interpreter runs main up to right after generate_buffer returns
+    let mut vm: Vm = Vm::boot(ex::winning_state()).unwrap();
+    vm.run_until(0xe2).unwrap();
+
+    let mut transpiled = ex::winning_state();
+    ex::generate_buffer(&mut transpiled);
+    let mut sieve = ex::winning_state();
+    ex::generate_buffer_fast(&mut sieve);
+
+    let mut ok = true;
+    for (name, s) in [("interpreter", &vm.s), ("transpiled", &transpiled), ("sieve", &sieve)] {
+        match check(s) {
+            None => println!("ok: {}", name),
+            Some(why) => {
+                println!("MISMATCH: {}: {}", name, why);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
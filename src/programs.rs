@@ -0,0 +1,76 @@
+// the challenge images built into the tool, picked with `--program <name>`. every one is a
+// printf vm program sharing the one engine, what differs is the bytes, where they start, how the
+// code unpacks and where the flag comes out. adding one is an image file, an entry here and
+// whatever unpacking it needs
+use crate::inst::unxor_stage2;
+#[cfg(feature = "std")]
+use crate::listing::{Columns, ListingWriter};
+#[cfg(feature = "std")]
+use crate::project::Project;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub struct Program {
+    pub name: &'static str,
+    // where the challenge came from, for `programs`
+    pub about: &'static str,
+    // memory at startup, before any stage has run
+    pub image: &'static [u8],
+    // where the flag formatter starts
+    pub entry: usize,
+    // the first function of the unpacked code, where lifting starts
+    pub main: usize,
+    // the image with every stage already unpacked, for listings and lifting
+    pub unpack: fn(&[u8]) -> Vec<u8>,
+    // disassembly of the unpacked program
+    #[cfg(feature = "std")]
+    pub list: fn(&Program, &mut ListingWriter),
+    // bytes printed as the flag
+    pub flag: Range<usize>,
+    // the xored stage, where `keys` looks for the key
+    pub encrypted: Range<usize>,
+}
+
+impl Program {
+    pub fn unpacked(&self) -> Vec<u8> {
+        (self.unpack)(self.image)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn listing(&self, project: &Project, columns: Columns) -> String {
+        let mut writer = ListingWriter::new(project, columns, self.image.len());
+        (self.list)(self, &mut writer);
+        writer.finish()
+    }
+}
+
+pub const WEATHER: Program = Program {
+    name: "weather2021",
+    about: "google ctf 2021, weather",
+    // I dumped bytes with ghidra copy + paste to a python interpreter, then wrote to raw bytes
+    image: include_bytes!("../mem"),
+    entry: 0x34,
+    main: 0xc8,
+    unpack: unxor_stage2,
+    #[cfg(feature = "std")]
+    list: weather_list,
+    flag: 0x1800..0x1820,
+    encrypted: 0xc8..0x6fc,
+};
+
+pub const PROGRAMS: &[Program] = &[WEATHER];
+
+pub fn by_name(name: &str) -> Result<&'static Program, String> {
+    PROGRAMS.iter().find(|p| p.name == name).ok_or_else(|| {
+        let names: Vec<&str> = PROGRAMS.iter().map(|p| p.name).collect();
+        format!("no program {}, there's {}", name, names.join(", "))
+    })
+}
+
+#[cfg(feature = "std")]
+fn weather_list(program: &Program, writer: &mut ListingWriter) {
+    writer.stage1(program.image);
+    writer.stage2(&program.unpacked());
+}
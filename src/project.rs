@@ -0,0 +1,212 @@
+// sidecar project file with everything learned about an image: labels, comments per instruction
+// and names for memory regions. it lives in projects/<image hash>.project, so it's picked up
+// again whenever the same mem image is opened
+//
+//     label 0x105 is_prime
+//     comment 0x111 divisible, so not prime
+//     region 0x1000 0x1100 user input
+use crate::inst::{parse_num, DestMode, Instruction, Named, Operation, SrcMode};
+use crate::color;
+use crate::ex::{self, REGS};
+use crate::names::RegNames;
+use crate::trace::Fnv;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::ops::Range;
+
+const DIR: &str = "projects";
+
+#[derive(Debug, Clone, Default)]
+pub struct Project {
+    // hash of the (still encrypted) image this belongs to
+    pub image: u64,
+    pub labels: BTreeMap<usize, String>,
+    pub comments: BTreeMap<usize, String>,
+    // start, end (exclusive), name
+    pub regions: Vec<(usize, usize, String)>,
+    // register names come from --regs, they aren't saved with the project
+    pub regs: RegNames,
+    // other forms of immediates in listings, from --verbose. not saved either
+    pub verbose: bool,
+    // page that call targets link into, from disasm --links
+    pub links: Option<String>,
+    // bytes each indirect access can touch, by pc and register, from ranges::targets. not saved
+    pub targets: BTreeMap<(usize, u32), Range<usize>>,
+    // registers holding an address after each instruction, from pointers::infer. not saved
+    pub pointers: BTreeMap<usize, [bool; REGS]>,
+}
+
+impl Project {
+    // the project for `image`, or an empty one if there isn't one yet
+    pub fn open(image: &[u8]) -> Result<Self, String> {
+        let mut hash = Fnv::new();
+        hash.write(image);
+        let mut project = Project {
+            image: hash.finish(),
+            ..Default::default()
+        };
+
+        let text = match fs::read_to_string(project.path()) {
+            Ok(text) => text,
+            Err(_) => return Ok(project),
+        };
+        for (i, line) in text.lines().enumerate() {
+            project
+                .parse_line(line)
+                .map_err(|e| format!("{} line {}: {}", project.path(), i + 1, e))?;
+        }
+        Ok(project)
+    }
+
+    pub fn path(&self) -> String {
+        format!("{}/{:016x}.project", DIR, self.image)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let (addr, rest) = split_addr(rest)?;
+        match kind {
+            "label" => {
+                self.labels.insert(addr, rest.to_string());
+            }
+            "comment" => {
+                self.comments.insert(addr, rest.to_string());
+            }
+            "region" => {
+                let (end, name) = split_addr(rest)?;
+                self.regions.push((addr, end, name.to_string()));
+            }
+            _ => return Err(format!("unknown entry {}", kind)),
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut out = String::new();
+        for (addr, label) in &self.labels {
+            writeln!(out, "label {:#x} {}", addr, label).unwrap();
+        }
+        for (addr, comment) in &self.comments {
+            writeln!(out, "comment {:#x} {}", addr, comment).unwrap();
+        }
+        for (start, end, name) in &self.regions {
+            writeln!(out, "region {:#x} {:#x} {}", start, end, name).unwrap();
+        }
+
+        fs::create_dir_all(DIR).map_err(|e| e.to_string())?;
+        fs::write(self.path(), out).map_err(|e| format!("{}: {}", self.path(), e))
+    }
+
+    // name of the user defined region `addr` falls in
+    pub fn region(&self, addr: usize) -> Option<&str> {
+        self.regions
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&addr))
+            .map(|(_, _, name)| name.as_str())
+    }
+
+    // "[r4] 0x1388..0x16aa RNG numbers" for an indirect access through `reg` at `pc`: every named
+    // region it can reach, user ones first, then the ones ex.rs knows
+    fn target(&self, pc: usize, reg: u32) -> Option<String> {
+        let range = self.targets.get(&(pc, reg))?;
+        let overlaps = |start: usize, end: usize| start < range.end && range.start < end;
+        let mut names: Vec<&str> = Vec::new();
+        let known = ex::REGIONS.iter().map(|(r, name)| (r.start, r.end, *name));
+        for (start, end, name) in self.regions.iter().map(|(s, e, n)| (*s, *e, n.as_str())).chain(known) {
+            if overlaps(start, end) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let at = format!("[r{}] {:#x}..{:#x}", reg, range.start, range.end);
+        match names.is_empty() {
+            true => Some(at),
+            false => Some(format!("{} {}", at, names.join(", "))),
+        }
+    }
+
+    // the user defined region `addr` falls in, or else the one ex.rs knows
+    pub fn region_or_known(&self, addr: usize) -> Option<&str> {
+        let known = || ex::REGIONS.iter().find(|(r, _)| r.contains(&addr)).map(|(_, name)| *name);
+        self.region(addr).or_else(known)
+    }
+
+    // address for a name in an expression: a label, or the start of a region, user ones before
+    // the ones ex.rs knows. region names have spaces, so those can be written with underscores
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        let label = self.labels.iter().find(|(_, label)| label.as_str() == name);
+        if let Some((&addr, _)) = label {
+            return Some(addr);
+        }
+        let user = self.regions.iter().map(|(start, _, region)| (*start, region.as_str()));
+        let known = ex::REGIONS.iter().map(|(r, region)| (r.start, *region));
+        user.chain(known).find(|(_, region)| region.replace(' ', "_") == name).map(|(start, _)| start)
+    }
+
+    // what to call the function starting at `addr`, same as the listings do
+    pub fn function(&self, addr: usize) -> String {
+        match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("stage2_{:x}", addr),
+        }
+    }
+
+    // `inst` at `pc`, with register names and the label of its call target
+    pub fn named<'a>(&'a self, inst: &'a Instruction, pc: usize) -> Named<'a> {
+        let label = match inst.op {
+            Operation::Jmp => self.labels.get(&(inst.dest as usize)).map(String::as_str),
+            _ => None,
+        };
+        Named {
+            inst,
+            names: self.regs.at(pc),
+            label,
+            regions: &self.regions,
+            verbose: self.verbose,
+            palette: color::theme().map(|t| &t.palette),
+            link: self.links.as_deref(),
+            pointers: self.pointers.get(&pc).map_or(&[], |p| &p[..]),
+        }
+    }
+
+    // the trailing "// ..." for a listing line: the user's comment, the regions of any fixed
+    // addresses the instruction touches, and where an indirect one can land
+    pub fn annotation(&self, inst: &Instruction, pc: usize) -> Option<String> {
+        let mut notes = Vec::new();
+        if let Some(comment) = self.comments.get(&pc) {
+            notes.push(comment.clone());
+        }
+        if inst.op != Operation::Jmp && inst.op != Operation::Ret {
+            if let DestMode::Minus = inst.dest_mode {
+                notes.extend(self.region(inst.dest as usize).map(|r| format!("[{}]", r)));
+            }
+            if let SrcMode::HH = inst.src_mode {
+                notes.extend(self.region(inst.src as usize).map(|r| format!("[{}]", r)));
+            }
+            if let DestMode::Plus = inst.dest_mode {
+                notes.extend(self.target(pc, inst.dest));
+            }
+            if let SrcMode::H = inst.src_mode {
+                notes.extend(self.target(pc, inst.src));
+            }
+        }
+
+        if notes.is_empty() {
+            None
+        } else {
+            Some(format!("// {}", notes.join(" ")))
+        }
+    }
+}
+
+// "0x105 rest of the line"
+fn split_addr(s: &str) -> Result<(usize, &str), String> {
+    let (addr, rest) = s.split_once(' ').unwrap_or((s, ""));
+    let addr = parse_num(addr).ok_or(format!("bad address {}", addr))?;
+    Ok((addr as usize, rest.trim()))
+}
@@ -0,0 +1,69 @@
+// `disasm proof weather_proof.rs`: a proof of solve that needs nothing but rustc. the file has the
+// winning input and stage2_28d's xor, which is all the flag is once the check has passed: each
+// dword of the input goes into a running key, and each dword of the flag is that key xored with a
+// constant.
+//
+// the seed comes from the instruction at 0x28d, and the constants are worked back out of the flag
+// the run wrote, so a variant gets its own
+use crate::inst::{DestMode, Instruction, Operation, SrcMode};
+use crate::programs::WEATHER;
+use std::fmt::Write;
+
+// stage2_28d, where the xor stage starts with `mov r0, seed`
+const XOR_STAGE: usize = 0x28d;
+
+// `input` is the winning input, `mem` the vm's memory after running it
+pub fn source(input: &[u8], mem: &[u8]) -> Result<String, String> {
+    let seed = match Instruction::checked(&mem[XOR_STAGE..]) {
+        Some((inst, _))
+            if inst.op == Operation::Mov
+                && inst.dest == 0
+                && inst.dest_mode == DestMode::NoPlusMinus
+                && inst.src_mode == SrcMode::LL =>
+        {
+            inst.src
+        }
+        _ => return Err(format!("{:#x} doesn't start with the seed going into r0", XOR_STAGE)),
+    };
+    if !input.len().is_multiple_of(4) || input.len() > WEATHER.flag.len() {
+        return Err(format!("a {} byte input isn't whole dwords of the flag", input.len()));
+    }
+
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let flag = &mem[WEATHER.flag.clone()];
+    let mut key = seed;
+    let mut constants = Vec::new();
+    for (i, chunk) in input.chunks(4).enumerate() {
+        key ^= word(chunk);
+        constants.push(word(&flag[i * 4..]) ^ key);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// generated by `disasm proof`. the weather challenge's flag, from its winning input:").unwrap();
+    writeln!(out, "//").unwrap();
+    writeln!(out, "//     rustc -O weather_proof.rs && ./weather_proof").unwrap();
+    writeln!(out, "//").unwrap();
+    writeln!(out, "// the input is the goodboy buffer at 0x1194 run backwards through the per byte steps. once it").unwrap();
+    writeln!(out, "// passes, stage2_28d xors each dword of it into a key starting at SEED, and writes each dword").unwrap();
+    writeln!(out, "// of the flag as that key xored with the next constant").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "const INPUT: &[u8; {}] = b\"{}\";", input.len(), input.escape_ascii()).unwrap();
+    writeln!(out, "const SEED: u32 = {:#010x};", seed).unwrap();
+    writeln!(out, "const CONSTANTS: [u32; {}] = [", constants.len()).unwrap();
+    for c in &constants {
+        writeln!(out, "    {:#010x},", c).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "fn main() {{").unwrap();
+    writeln!(out, "    let mut key = SEED;").unwrap();
+    writeln!(out, "    let mut flag = Vec::new();").unwrap();
+    writeln!(out, "    for (word, constant) in INPUT.chunks(4).zip(CONSTANTS.iter()) {{").unwrap();
+    writeln!(out, "        key ^= u32::from_le_bytes([word[0], word[1], word[2], word[3]]);").unwrap();
+    writeln!(out, "        flag.extend_from_slice(&(constant ^ key).to_le_bytes());").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    println!(\"Winning input: {{}}\", String::from_utf8_lossy(INPUT));").unwrap();
+    writeln!(out, "    println!(\"Flag: {{}}\", String::from_utf8_lossy(&flag));").unwrap();
+    writeln!(out, "}}").unwrap();
+    Ok(out)
+}
@@ -0,0 +1,657 @@
+// value ranges for every register at every instruction, by abstract interpretation over
+// intervals, like r0 in [0x3390, 0x351f] all through generate_buffer. it's for the 32 bit machine.
+//
+// every function is straight line code ending in a ret, and loops are conditional calls back to
+// the top, so a function's registers on entry are the join of every call to it and its registers
+// on exit are whatever reaches its ret. calls are worked through until nothing changes, widening
+// bounds that keep growing out to the next constant in the code (or all the way) so the recursion
+// settles, then a few passes without widening win the bounds back from the call conditions.
+//
+// a condition like `r1 = 0x3520; r1 -= r0; if r1 > 0 ..` only bounds r1, so each register also
+// remembers when it's another one plus or minus a constant, and the bound carries over to that
+//
+// the ranges are also bounds checks: every read and write through a register is listed with the
+// addresses it can reach and whether those stay in the machine's memory
+use crate::ex::{EXTENT, REGS};
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width};
+use crate::decode::{self, Body};
+use crate::project::Project;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+const MIN: i64 = i32::MIN as i64;
+const MAX: i64 = i32::MAX as i64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub const TOP: Interval = Interval { lo: MIN, hi: MAX };
+
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Interval { lo, hi }
+    }
+
+    pub fn constant(n: i64) -> Self {
+        Interval::new(n, n)
+    }
+
+    // the range, or TOP if any of it wraps around
+    fn fit(lo: i64, hi: i64) -> Self {
+        match lo < MIN || hi > MAX {
+            true => Interval::TOP,
+            false => Interval::new(lo, hi),
+        }
+    }
+
+    fn from_corners(corners: [i64; 4]) -> Self {
+        Interval::fit(*corners.iter().min().unwrap(), *corners.iter().max().unwrap())
+    }
+
+    pub fn is_top(&self) -> bool {
+        *self == Interval::TOP
+    }
+
+    fn join(self, other: Interval) -> Interval {
+        Interval::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    // None when they don't overlap
+    fn meet(self, other: Interval) -> Option<Interval> {
+        let (lo, hi) = (self.lo.max(other.lo), self.hi.min(other.hi));
+        (lo <= hi).then_some(Interval::new(lo, hi))
+    }
+
+    // bounds that moved since `old` go out to the next of `steps`, sorted, or all the way
+    fn widen(old: Interval, new: Interval, steps: &[i64]) -> Interval {
+        let lo = match new.lo < old.lo {
+            true => steps.iter().rev().find(|&&s| s <= new.lo).copied().unwrap_or(MIN),
+            false => old.lo,
+        };
+        let hi = match new.hi > old.hi {
+            true => steps.iter().find(|&&s| s >= new.hi).copied().unwrap_or(MAX),
+            false => old.hi,
+        };
+        Interval::new(lo, hi)
+    }
+
+    fn neg(self) -> Interval {
+        Interval::fit(-self.hi, -self.lo)
+    }
+
+    fn add(self, n: i64) -> Interval {
+        Interval::fit(self.lo + n, self.hi + n)
+    }
+
+    fn nonneg(self) -> bool {
+        self.lo >= 0
+    }
+
+    // what the vm leaves in a register after `op`, see vm::apply
+    fn apply(op: Operation, a: Interval, b: Interval) -> Interval {
+        match op {
+            Operation::Mov => b,
+            Operation::Add => Interval::fit(a.lo + b.lo, a.hi + b.hi),
+            Operation::Sub => Interval::fit(a.lo - b.hi, a.hi - b.lo),
+            Operation::Mul => Interval::from_corners([a.lo * b.lo, a.lo * b.hi, a.hi * b.lo, a.hi * b.hi]),
+            Operation::Div => {
+                // dividing by zero faults, so carrying on means it wasn't
+                let b = match (b.lo, b.hi) {
+                    (0, hi) if hi > 0 => Interval::new(1, hi),
+                    (lo, 0) if lo < 0 => Interval::new(lo, -1),
+                    _ => b,
+                };
+                if b.lo <= 0 && b.hi >= 0 {
+                    return Interval::TOP;
+                }
+                Interval::from_corners([a.lo / b.lo, a.lo / b.hi, a.hi / b.lo, a.hi / b.hi])
+            }
+            Operation::Mod => {
+                let m = b.lo.abs().max(b.hi.abs()) - 1;
+                match (a.lo >= 0, a.hi <= 0) {
+                    (true, _) => Interval::new(0, a.hi.min(m)),
+                    (_, true) => Interval::new(a.lo.max(-m), 0),
+                    _ => Interval::new(-m, m),
+                }
+            }
+            Operation::And => match (a.nonneg(), b.nonneg()) {
+                (true, true) => Interval::new(0, a.hi.min(b.hi)),
+                (true, false) => Interval::new(0, a.hi),
+                (false, true) => Interval::new(0, b.hi),
+                _ => Interval::TOP,
+            },
+            Operation::Or | Operation::Xor if a.nonneg() && b.nonneg() => {
+                let hi = ((a.hi.max(b.hi) + 1) as u64).next_power_of_two() as i64 - 1;
+                let lo = if op == Operation::Or { a.lo.max(b.lo) } else { 0 };
+                Interval::new(lo, hi)
+            }
+            Operation::ShLeft if b.lo == b.hi && (0..32).contains(&b.lo) => {
+                Interval::fit(a.lo << b.lo, a.hi << b.lo)
+            }
+            Operation::ShRight if b.lo == b.hi && (0..32).contains(&b.lo) => Interval::new(a.lo >> b.lo, a.hi >> b.lo),
+            Operation::ShRight if a.nonneg() => Interval::new(0, a.hi),
+            _ => Interval::TOP,
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = |n: i64| match n {
+            MIN => "min".to_string(),
+            MAX => "max".to_string(),
+            n if n < 0 => format!("-{:#x}", -n),
+            n => format!("{:#x}", n),
+        };
+        match self.lo == self.hi {
+            true => write!(f, "{}", bound(self.lo)),
+            false => write!(f, "[{}, {}]", bound(self.lo), bound(self.hi)),
+        }
+    }
+}
+
+pub type Regs = [Interval; REGS];
+
+// the registers a call condition allows through: `taken` or not
+fn condition(mode: DestMode, taken: bool) -> Option<Interval> {
+    match (mode, taken) {
+        (DestMode::Minus, true) => Some(Interval::new(MIN, -1)),
+        (DestMode::Minus, false) => Some(Interval::new(0, MAX)),
+        (DestMode::Plus, true) => Some(Interval::new(1, MAX)),
+        (DestMode::Plus, false) => Some(Interval::new(MIN, 0)),
+        (DestMode::ZeroPad, true) => Some(Interval::constant(0)),
+        // not zero only cuts off an end
+        (DestMode::ZeroPad, false) => None,
+        (DestMode::NoPlusMinus, _) => Some(Interval::TOP),
+    }
+}
+
+// register = (other register, negated, plus constant)
+type Relation = Option<(usize, bool, i64)>;
+
+// `regs` with register `n` narrowed to `to`, and whatever it's related to along with it. None
+// when that can't happen
+fn refine(regs: &Regs, relations: &[Relation; REGS], n: usize, to: Interval) -> Option<Regs> {
+    let mut regs = *regs;
+    regs[n] = regs[n].meet(to)?;
+    if let Some((other, negated, k)) = relations[n] {
+        let mut bound = regs[n].add(-k);
+        if negated {
+            bound = bound.neg();
+        }
+        regs[other] = regs[other].meet(bound)?;
+    }
+    Some(regs)
+}
+
+fn refine_nonzero(regs: &Regs, relations: &[Relation; REGS], n: usize) -> Option<Regs> {
+    let r = regs[n];
+    match (r.lo, r.hi) {
+        (0, 0) => None,
+        (0, hi) => refine(regs, relations, n, Interval::new(1, hi)),
+        (lo, 0) => refine(regs, relations, n, Interval::new(lo, -1)),
+        _ => Some(*regs),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub pc: usize,
+    pub write: bool,
+    pub reg: u32,
+    pub bytes: usize,
+    pub addr: Interval,
+}
+
+impl Access {
+    // in the memory the machine starts with
+    pub fn in_bounds(&self) -> bool {
+        self.addr.lo >= 0 && self.addr.hi + self.bytes as i64 <= EXTENT as i64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub bodies: BTreeMap<usize, Body>,
+    // registers before each instruction, only for instructions that can run
+    pub at: BTreeMap<usize, Regs>,
+    pub accesses: Vec<Access>,
+    // registers at the ret of each function
+    pub exits: BTreeMap<usize, Regs>,
+}
+
+// how far each register is from what it was on entry to the function, None once that's not a
+// constant amount
+type Deltas = [Option<Interval>; REGS];
+
+// what one pass over every function found
+#[derive(Default)]
+struct Pass {
+    entries: BTreeMap<usize, Regs>,
+    // the same, less the calls a function makes to itself
+    outside: BTreeMap<usize, Regs>,
+    exits: BTreeMap<usize, Regs>,
+    exit_deltas: BTreeMap<usize, Deltas>,
+    // deltas where a function calls itself, so what one trip round the loop does
+    loop_deltas: BTreeMap<usize, Deltas>,
+    at: BTreeMap<usize, Regs>,
+    accesses: Vec<Access>,
+}
+
+// what a pass needs to know about every function
+struct Code<'a> {
+    bodies: &'a BTreeMap<usize, Body>,
+    // registers each function writes, or anything it calls
+    writes: BTreeMap<usize, [bool; REGS]>,
+    exits: &'a BTreeMap<usize, Regs>,
+    exit_deltas: &'a BTreeMap<usize, Deltas>,
+}
+
+fn join(into: &mut BTreeMap<usize, Regs>, addr: usize, regs: Regs) {
+    let joined = match into.get(&addr) {
+        Some(old) => {
+            let mut joined = *old;
+            for (j, r) in joined.iter_mut().zip(&regs) {
+                *j = j.join(*r);
+            }
+            joined
+        }
+        None => regs,
+    };
+    into.insert(addr, joined);
+}
+
+fn join_deltas(into: &mut BTreeMap<usize, Deltas>, addr: usize, deltas: Deltas) {
+    let joined = match into.get(&addr) {
+        Some(old) => {
+            let mut joined = *old;
+            for (j, d) in joined.iter_mut().zip(&deltas) {
+                *j = j.zip(*d).map(|(a, b)| a.join(b));
+            }
+            joined
+        }
+        None => deltas,
+    };
+    into.insert(addr, joined);
+}
+
+// everything reachable from `entry` in `mem`, starting with nothing known about the registers
+pub fn analyze(mem: &[u8], entry: usize) -> Analysis {
+    let mut bodies = BTreeMap::new();
+    let mut todo = vec![entry];
+    let mut seen = HashSet::new();
+    while let Some(addr) = todo.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        if let Some(body) = decode::function(addr, mem, REGS) {
+            todo.extend(body.iter().filter(|(_, inst, _)| inst.op == Operation::Jmp).map(|(_, inst, _)| inst.dest as usize));
+            bodies.insert(addr, body);
+        }
+    }
+    let writes = writes(&bodies);
+
+    // every constant in the code, where loops usually stop
+    let mut steps: Vec<i64> = bodies
+        .values()
+        .flatten()
+        .filter(|(_, inst, _)| inst.op != Operation::Jmp && inst.src_mode == SrcMode::LL)
+        .map(|(_, inst, _)| inst.src as i32 as i64)
+        .collect();
+    steps.push(0);
+    steps.sort_unstable();
+    steps.dedup();
+
+    let start = BTreeMap::from([(entry, [Interval::TOP; REGS])]);
+    let mut entries = start.clone();
+    let mut exits = BTreeMap::new();
+    let mut exit_deltas: BTreeMap<usize, Deltas> = BTreeMap::new();
+
+    // up to a fixed point, widening as it goes. a delta that changes at all is given up on
+    loop {
+        let code = Code {
+            bodies: &bodies,
+            writes: writes.clone(),
+            exits: &exits,
+            exit_deltas: &exit_deltas,
+        };
+        let pass = pass(&code, &entries);
+        let mut changed = false;
+        for (old, new) in [(&mut entries, &pass.entries), (&mut exits, &pass.exits)] {
+            for (&addr, regs) in new {
+                let widened = match old.get(&addr) {
+                    Some(prev) => {
+                        let mut widened = *prev;
+                        for (w, r) in widened.iter_mut().zip(regs) {
+                            *w = Interval::widen(*w, w.join(*r), &steps);
+                        }
+                        widened
+                    }
+                    None => *regs,
+                };
+                if old.get(&addr) != Some(&widened) {
+                    old.insert(addr, widened);
+                    changed = true;
+                }
+            }
+        }
+        for (&addr, deltas) in &pass.exit_deltas {
+            let merged = match exit_deltas.get(&addr) {
+                Some(prev) => {
+                    let mut merged = *prev;
+                    for (m, d) in merged.iter_mut().zip(deltas) {
+                        if m != d {
+                            *m = None;
+                        }
+                    }
+                    merged
+                }
+                None => *deltas,
+            };
+            if exit_deltas.get(&addr) != Some(&merged) {
+                exit_deltas.insert(addr, merged);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // then narrowing, each pass is still safe and usually tighter. loops get bounded by how many
+    // times they can go round too
+    let mut last = Pass::default();
+    for _ in 0..4 {
+        let code = Code {
+            bodies: &bodies,
+            writes: writes.clone(),
+            exits: &exits,
+            exit_deltas: &exit_deltas,
+        };
+        last = pass(&code, &entries);
+        entries = start.clone();
+        for (&addr, regs) in &last.entries {
+            join(&mut entries, addr, *regs);
+        }
+        for (addr, regs) in entries.iter_mut() {
+            if let (Some(outside), Some(deltas)) = (last.outside.get(addr), last.loop_deltas.get(addr)) {
+                *regs = induction(regs, outside, deltas);
+            }
+        }
+        exits = last.exits.clone();
+    }
+    Analysis {
+        bodies,
+        at: last.at,
+        accesses: last.accesses,
+        exits,
+    }
+}
+
+// a loop's registers on entry, `regs`, cut down by how many times it can go round. that's how
+// far a counter with a fixed step can get across its range, and everything else can only move
+// that many of its own steps from where it was before the loop
+fn induction(regs: &Regs, outside: &Regs, deltas: &Deltas) -> Regs {
+    let trips = (0..REGS)
+        .filter_map(|n| {
+            let d = deltas[n]?;
+            let r = regs[n];
+            (d.lo == d.hi && d.lo != 0 && !r.is_top()).then(|| (r.hi - r.lo) / d.lo.abs())
+        })
+        .min();
+    let trips = match trips {
+        Some(trips) => trips,
+        None => return *regs,
+    };
+    let mut bounded = *regs;
+    for n in 0..REGS {
+        if let Some(d) = deltas[n] {
+            let bound = Interval::fit(outside[n].lo + d.lo.min(0) * trips, outside[n].hi + d.hi.max(0) * trips);
+            bounded[n] = bounded[n].meet(bound).unwrap_or(bounded[n]);
+        }
+    }
+    bounded
+}
+
+// registers written by each function and whatever it calls
+pub(crate) fn writes(bodies: &BTreeMap<usize, Body>) -> BTreeMap<usize, [bool; REGS]> {
+    let mut writes: BTreeMap<usize, [bool; REGS]> = bodies
+        .iter()
+        .map(|(&addr, body)| {
+            let mut w = [false; REGS];
+            for (_, inst, _) in body {
+                if inst.op != Operation::Jmp && inst.op != Operation::Ret && inst.dest_mode == DestMode::NoPlusMinus {
+                    w[inst.dest as usize] = true;
+                }
+            }
+            (addr, w)
+        })
+        .collect();
+    loop {
+        let mut changed = false;
+        for (addr, body) in bodies {
+            for (_, inst, _) in body.iter().filter(|(_, inst, _)| inst.op == Operation::Jmp) {
+                // something that can't be decoded could write anything
+                let callee = writes.get(&(inst.dest as usize)).copied().unwrap_or([true; REGS]);
+                let w = writes.get_mut(addr).unwrap();
+                for n in 0..REGS {
+                    if callee[n] && !w[n] {
+                        w[n] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            return writes;
+        }
+    }
+}
+
+// every function once, from the `entries` and exits so far
+fn pass(code: &Code, entries: &BTreeMap<usize, Regs>) -> Pass {
+    let mut out = Pass::default();
+    for (&addr, body) in code.bodies {
+        if let Some(regs) = entries.get(&addr) {
+            walk(body, *regs, code, &mut out);
+        }
+    }
+    out
+}
+
+// one function from `regs` on entry
+fn walk(body: &Body, regs: Regs, code: &Code, out: &mut Pass) {
+    let addr = body[0].0;
+    let mut regs = regs;
+    let mut deltas: Deltas = [Some(Interval::constant(0)); REGS];
+    let mut relations: [Relation; REGS] = [None; REGS];
+    for &(pc, inst, _) in body {
+        join(&mut out.at, pc, regs);
+        match inst.op {
+            Operation::Ret => {
+                join(&mut out.exits, addr, regs);
+                join_deltas(&mut out.exit_deltas, addr, deltas);
+                return;
+            }
+            Operation::Jmp => {
+                let (target, cond) = (inst.dest as usize, inst.src as usize);
+                let taken = match condition(inst.dest_mode, true) {
+                    Some(to) => refine(&regs, &relations, cond, to),
+                    None => None,
+                };
+                let skipped = match (inst.dest_mode, condition(inst.dest_mode, false)) {
+                    (DestMode::NoPlusMinus, _) => None,
+                    (_, Some(to)) => refine(&regs, &relations, cond, to),
+                    (_, None) => refine_nonzero(&regs, &relations, cond),
+                };
+                if let Some(taken) = taken {
+                    join(&mut out.entries, target, taken);
+                    match target == addr {
+                        true => join_deltas(&mut out.loop_deltas, addr, deltas),
+                        false => join(&mut out.outside, target, taken),
+                    }
+                }
+
+                // registers the call doesn't touch come through as they were, the rest are
+                // whatever the target returns with. a call to something that can't be decoded
+                // could do anything
+                let written = code.writes.get(&target).copied().unwrap_or([true; REGS]);
+                let returned = taken.and_then(|taken| match code.bodies.contains_key(&target) {
+                    true => {
+                        let exit = code.exits.get(&target)?;
+                        let exit_deltas = code.exit_deltas.get(&target);
+                        let mut after = (taken, deltas);
+                        for n in (0..REGS).filter(|&n| written[n]) {
+                            after.0[n] = exit[n];
+                            let step = exit_deltas.and_then(|d| d[n]);
+                            after.1[n] = deltas[n].zip(step).map(|(a, b)| Interval::fit(a.lo + b.lo, a.hi + b.hi));
+                        }
+                        Some(after)
+                    }
+                    false => Some(([Interval::TOP; REGS], [None; REGS])),
+                });
+                (regs, deltas) = match (returned, skipped) {
+                    (Some((a, da)), Some(b)) => {
+                        let mut joined = (a, da);
+                        for n in 0..REGS {
+                            joined.0[n] = a[n].join(b[n]);
+                            joined.1[n] = da[n].zip(deltas[n]).map(|(x, y)| x.join(y));
+                        }
+                        joined
+                    }
+                    (Some(a), None) => a,
+                    (None, Some(b)) => (b, deltas),
+                    // nothing gets past here
+                    (None, None) => return,
+                };
+                for (n, r) in relations.iter_mut().enumerate() {
+                    if written[n] || r.is_some_and(|(other, _, _)| written[other]) {
+                        *r = None;
+                    }
+                }
+            }
+            op => {
+                let width = match inst.width {
+                    Width::W32 | Width::W64 => None,
+                    width => Some(width),
+                };
+                let bytes = width.map_or(4, Width::bytes);
+                let loaded = match width {
+                    Some(w) => Interval::new(0, (1 << (8 * w.bytes())) - 1),
+                    None => Interval::TOP,
+                };
+                let src = match inst.src_mode {
+                    SrcMode::LL => Interval::constant(inst.src as i32 as i64),
+                    SrcMode::L => regs[inst.src as usize],
+                    SrcMode::HH => loaded,
+                    SrcMode::H => {
+                        out.accesses.push(Access {
+                            pc,
+                            write: false,
+                            reg: inst.src,
+                            bytes,
+                            addr: regs[inst.src as usize],
+                        });
+                        loaded
+                    }
+                    SrcMode::None => return,
+                };
+                match inst.dest_mode {
+                    DestMode::NoPlusMinus => {
+                        let n = inst.dest as usize;
+                        let before = regs[n];
+                        regs[n] = Interval::apply(op, before, src);
+                        deltas[n] = match (op, inst.src_mode) {
+                            (Operation::Add, SrcMode::LL) => deltas[n].map(|d| d.add(src.lo)),
+                            (Operation::Sub, SrcMode::LL) => deltas[n].map(|d| d.add(-src.lo)),
+                            _ => None,
+                        };
+                        relations[n] = relate(op, inst, before, relations[n]);
+                        for r in relations.iter_mut().filter(|r| r.is_some_and(|(other, _, _)| other == n)) {
+                            *r = None;
+                        }
+                    }
+                    DestMode::Plus => out.accesses.push(Access {
+                        pc,
+                        write: true,
+                        reg: inst.dest,
+                        bytes,
+                        addr: regs[inst.dest as usize],
+                    }),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// what register `inst.dest` is in terms of another one after `inst`, when it's still that simple
+fn relate(op: Operation, inst: Instruction, before: Interval, relation: Relation) -> Relation {
+    let n = inst.dest as usize;
+    let constant = (before.lo == before.hi).then_some(before.lo);
+    match (op, inst.src_mode, relation, constant) {
+        (Operation::Mov, SrcMode::L, _, _) if inst.src as usize != n => Some((inst.src as usize, false, 0)),
+        (Operation::Add, SrcMode::LL, Some((other, negated, k)), _) => Some((other, negated, k + inst.src as i32 as i64)),
+        (Operation::Sub, SrcMode::LL, Some((other, negated, k)), _) => Some((other, negated, k - inst.src as i32 as i64)),
+        (Operation::Add, SrcMode::L, None, Some(c)) if inst.src as usize != n => Some((inst.src as usize, false, c)),
+        (Operation::Sub, SrcMode::L, None, Some(c)) if inst.src as usize != n => Some((inst.src as usize, true, c)),
+        _ => None,
+    }
+}
+
+// the registers that aren't wide open, like "r0=[0x3390, 0x351f] r4=0x1388"
+pub fn known(regs: &Regs) -> String {
+    let known: Vec<String> = regs
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| !r.is_top())
+        .map(|(n, r)| format!("r{}={}", n, r))
+        .collect();
+    known.join(" ")
+}
+
+// the bytes each indirect access can touch, by pc and register, for the listing. the ones that
+// might leave memory are left out, there's nothing to name there
+pub fn targets(analysis: &Analysis) -> BTreeMap<(usize, u32), Range<usize>> {
+    analysis
+        .accesses
+        .iter()
+        .filter(|access| access.in_bounds())
+        .map(|access| {
+            let (lo, hi) = (access.addr.lo as usize, access.addr.hi as usize);
+            ((access.pc, access.reg), lo..hi + access.bytes)
+        })
+        .collect()
+}
+
+// every function with the ranges before each instruction, then the indirect accesses
+pub fn report(analysis: &Analysis, project: &Project) -> String {
+    let mut out = String::new();
+    for (&addr, body) in &analysis.bodies {
+        writeln!(out, "{}:", project.function(addr)).unwrap();
+        for (pc, inst, _) in body {
+            let ranges = match analysis.at.get(pc) {
+                Some(regs) => known(regs),
+                None => "unreachable".to_string(),
+            };
+            let text = project.named(inst, *pc).to_string();
+            writeln!(out, "  {:#05x}:  {}  {}", pc, crate::color::pad(&text, 40), ranges).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+
+    writeln!(out, "indirect accesses:").unwrap();
+    for a in &analysis.accesses {
+        let verdict = match a.in_bounds() {
+            true => "ok".to_string(),
+            false => format!("may leave the first {:#x} bytes", EXTENT),
+        };
+        let kind = if a.write { "write" } else { "read " };
+        writeln!(out, "  {:#05x}:  {} [r{}] {}  {:24}  {}", a.pc, kind, a.reg, a.bytes, a.addr.to_string(), verdict).unwrap();
+    }
+    out
+}
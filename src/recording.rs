@@ -0,0 +1,354 @@
+// `run --record run.trace`: every event of a run in a text file, each with its trace::Id, for
+// lining two runs up event by event later (interpreter against transpiled, or this build against
+// an older one):
+//
+//     # disasm trace, 574541 events, digest 571e2e8b17045ffd
+//     0 af63bd4c8601b7df af63bd4c8601b7df step 0x34
+//     1 ...
+//
+// seq, hash and chain, then the event as crash dumps have it
+//
+// `--compress` folds loops, the same events over and over with only the reads and stores
+// changing, into one go round and how many times. a field that changes each time is either a
+// progression in the iteration i or the list of what it was:
+//
+//     1204 loop 37 8c2fa66d80d1c6b4
+//       step 0x105
+//       read 0x1388+0x2i [0x33a1,0x33a3,...]
+//     end
+//
+// the seq of the first event, the count and the chain after the last. a loop with a branch in it
+// (stage2_105 only calls 0xfd for some divisors) goes round one of a few ways each time, those
+// are split by `or` and the line says which way each time round went, 0*3 for three of way 0:
+//
+//     4979 loop 40 3fefa4ba334aed11 0*3,1,0*2,1,...
+//       step 0x105
+//       ...
+//     or
+//       step 0x105
+//       ...
+//     end
+//
+// i is then the count of times round that way. loading expands it all again
+use crate::crash;
+use crate::inst::parse_num;
+use crate::trace::{self, Event, Id};
+use std::fmt::Write;
+
+// longest go round a loop that's looked for, in events
+const PERIOD: usize = 64;
+// fewer times round than this isn't worth folding
+const REPEATS: usize = 4;
+// most ways round one loop
+const WAYS: usize = 4;
+
+type Shape = (u8, usize, usize);
+
+// a loop: where each time round starts and which way it went, and how long each way is
+struct Fold {
+    rounds: Vec<(usize, usize)>,
+    ways: Vec<usize>,
+    // just past the last time round
+    end: usize,
+}
+
+pub fn write(events: &[Event]) -> String {
+    let mut out = String::new();
+    writeln!(out, "# disasm trace, {} events, digest {:016x}", events.len(), trace::digest(events)).unwrap();
+    for (id, e) in trace::ids(events).iter().zip(events) {
+        writeln!(out, "{} {:016x} {:016x} {}", id.seq, id.hash, id.chain, crash::event(e)).unwrap();
+    }
+    out
+}
+
+// what has to be the same each time round for events to be a loop: everything but the index and
+// value of reads and stores
+fn shape(e: &Event) -> Shape {
+    match *e {
+        Event::Read { .. } => (0, 0, 0),
+        Event::Store { .. } => (1, 0, 0),
+        Event::Step { pc } => (2, pc, 0),
+        Event::Call { from, to } => (3, from, to),
+        Event::Return { to } => (4, to.map_or(usize::MAX, |to| to), 0),
+    }
+}
+
+// how many times the `period` events from `at` come round in a row
+fn repeats(shapes: &[Shape], at: usize, period: usize) -> usize {
+    let first = &shapes[at..at + period];
+    let mut n = 1;
+    while shapes.get(at + n * period..at + (n + 1) * period) == Some(first) {
+        n += 1;
+    }
+    n
+}
+
+// the same events round and round from `at`, the period that covers the most
+fn periodic(shapes: &[Shape], at: usize) -> Option<Fold> {
+    let mut best: Option<(usize, usize)> = None;
+    for period in 1..=PERIOD.min(shapes.len() - at) {
+        let n = repeats(shapes, at, period);
+        if n >= REPEATS && best.is_none_or(|(p, c)| period * n > p * c) {
+            best = Some((period, n));
+        }
+    }
+    let (period, n) = best?;
+    Some(Fold {
+        rounds: (0..n).map(|i| (at + i * period, 0)).collect(),
+        ways: vec![period],
+        end: at + period * n,
+    })
+}
+
+// a loop with a branch in it: every time round starts with the event at `at`, and goes one of at
+// most WAYS ways before it's back there
+fn branching(shapes: &[Shape], at: usize) -> Option<Fold> {
+    let mut fold = Fold {
+        rounds: Vec::new(),
+        ways: Vec::new(),
+        end: at,
+    };
+    let mut ways: Vec<&[Shape]> = Vec::new();
+    loop {
+        let start = fold.end;
+        let mut ahead = shapes.get(start + 1..).unwrap_or(&[]).iter().take(PERIOD);
+        let len = match ahead.position(|s| *s == shapes[at]) {
+            Some(p) => p + 1,
+            None => break,
+        };
+        let round = &shapes[start..start + len];
+        let way = match ways.iter().position(|way| *way == round) {
+            Some(way) => way,
+            None if ways.len() < WAYS => {
+                ways.push(round);
+                fold.ways.push(len);
+                ways.len() - 1
+            }
+            None => break,
+        };
+        fold.rounds.push((start, way));
+        fold.end += len;
+    }
+    match fold.rounds.len() >= REPEATS && fold.ways.len() > 1 {
+        true => Some(fold),
+        false => None,
+    }
+}
+
+// whichever loop from `at` covers the most events
+fn best_fold(shapes: &[Shape], at: usize) -> Option<Fold> {
+    match (periodic(shapes, at), branching(shapes, at)) {
+        (Some(p), Some(b)) if b.end > p.end => Some(b),
+        (Some(p), _) => Some(p),
+        (None, b) => b,
+    }
+}
+
+// 0,0,0,1 as 0*3,1
+fn runs(order: &[usize]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < order.len() {
+        let n = order[i..].iter().take_while(|&&w| w == order[i]).count();
+        match n {
+            1 => out.push(order[i].to_string()),
+            _ => out.push(format!("{}*{}", order[i], n)),
+        }
+        i += n;
+    }
+    out.join(",")
+}
+
+fn parse_runs(text: &str) -> Option<Vec<usize>> {
+    let mut order = Vec::new();
+    for run in text.split(',') {
+        let (way, n) = run.split_once('*').unwrap_or((run, "1"));
+        let (way, n): (usize, usize) = (way.parse().ok()?, n.parse().ok()?);
+        order.extend(std::iter::repeat_n(way, n));
+    }
+    Some(order)
+}
+
+// one word of the go round from what it was each time: as it is if it never changes, a
+// progression if it steps by the same amount, or all of them
+fn field(words: &[&str]) -> String {
+    if words.iter().all(|w| *w == words[0]) {
+        return words[0].to_string();
+    }
+    let nums: Option<Vec<u32>> = words.iter().map(|w| parse_num(w)).collect();
+    if let Some(nums) = nums {
+        let step = nums[1].wrapping_sub(nums[0]);
+        if nums.windows(2).all(|w| w[1].wrapping_sub(w[0]) == step) {
+            return match (step as i32) < 0 {
+                true => format!("{:#x}-{:#x}i", nums[0], (step as i32).unsigned_abs()),
+                false => format!("{:#x}+{:#x}i", nums[0], step),
+            };
+        }
+    }
+    format!("[{}]", words.join(","))
+}
+
+// a field back to its word for time round `i`
+fn instance(field: &str, i: usize) -> Option<String> {
+    if let Some(list) = field.strip_prefix('[').and_then(|f| f.strip_suffix(']')) {
+        return list.split(',').nth(i).map(str::to_string);
+    }
+    if let Some(linear) = field.strip_suffix('i') {
+        let at = linear.rfind(['+', '-'])?;
+        let (base, step) = (parse_num(&linear[..at])?, parse_num(&linear[at + 1..])?);
+        let step = step.wrapping_mul(i as u32);
+        let value = match &linear[at..at + 1] {
+            "+" => base.wrapping_add(step),
+            _ => base.wrapping_sub(step),
+        };
+        return Some(format!("{:#x}", value));
+    }
+    Some(field.to_string())
+}
+
+// write with loops folded
+pub fn write_compressed(events: &[Event]) -> String {
+    let ids = trace::ids(events);
+    let texts: Vec<String> = events.iter().map(crash::event).collect();
+    let shapes: Vec<Shape> = events.iter().map(shape).collect();
+
+    let mut out = String::new();
+    writeln!(out, "# disasm trace, {} events, digest {:016x}, loops folded", events.len(), trace::digest(events)).unwrap();
+    let mut at = 0;
+    while at < events.len() {
+        let fold = match best_fold(&shapes, at) {
+            Some(fold) => fold,
+            None => {
+                let id = &ids[at];
+                writeln!(out, "{} {:016x} {:016x} {}", id.seq, id.hash, id.chain, texts[at]).unwrap();
+                at += 1;
+                continue;
+            }
+        };
+        write!(out, "{} loop {} {:016x}", ids[at].seq, fold.rounds.len(), ids[fold.end - 1].chain).unwrap();
+        if fold.ways.len() > 1 {
+            let order: Vec<usize> = fold.rounds.iter().map(|&(_, way)| way).collect();
+            write!(out, " {}", runs(&order)).unwrap();
+        }
+        writeln!(out).unwrap();
+        for (way, &len) in fold.ways.iter().enumerate() {
+            if way > 0 {
+                writeln!(out, "or").unwrap();
+            }
+            let starts: Vec<usize> = fold.rounds.iter().filter(|r| r.1 == way).map(|r| r.0).collect();
+            for p in 0..len {
+                let each: Vec<Vec<&str>> = starts.iter().map(|s| texts[s + p].split(' ').collect()).collect();
+                let fields: Vec<String> = (0..each[0].len())
+                    .map(|w| field(&each.iter().map(|words| words[w]).collect::<Vec<&str>>()))
+                    .collect();
+                writeln!(out, "  {}", fields.join(" ")).unwrap();
+            }
+        }
+        writeln!(out, "end").unwrap();
+        at = fold.end;
+    }
+    out
+}
+
+pub fn save(path: &str, events: &[Event], compress: bool) -> Result<(), String> {
+    let text = match compress {
+        true => write_compressed(events),
+        false => write(events),
+    };
+    std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+// what a line of the file says about the ids, checked once they're all worked out
+struct Written {
+    line: usize,
+    // the events the line became
+    first: usize,
+    last: usize,
+    seq: u64,
+    // a folded loop only has the chain
+    hash: Option<u64>,
+    chain: u64,
+}
+
+// the events and their ids. the ids are worked out again rather than taken from the file, which
+// only has to agree with them
+pub fn parse(text: &str) -> Result<Vec<(Id, Event)>, String> {
+    let mut events = Vec::new();
+    let mut written = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad = || format!("line {}: expected seq hash chain event, got {}", i + 1, line);
+        if words.len() < 4 {
+            return Err(bad());
+        }
+        let hex = |w: &str| u64::from_str_radix(w, 16).map_err(|_| bad());
+        let seq = words[0].parse().map_err(|_| bad())?;
+
+        if words[1] == "loop" {
+            let count: usize = words[2].parse().map_err(|_| bad())?;
+            let order = match words.get(4) {
+                Some(order) => parse_runs(order).filter(|o| o.len() == count).ok_or_else(bad)?,
+                None => vec![0; count],
+            };
+            let mut ways: Vec<Vec<Vec<&str>>> = vec![Vec::new()];
+            loop {
+                match lines.next().map(|(_, line)| line.trim()) {
+                    Some("end") => break,
+                    Some("or") => ways.push(Vec::new()),
+                    Some(line) => ways.last_mut().unwrap().push(line.split_whitespace().collect()),
+                    None => return Err(format!("line {}: the loop has no end", i + 1)),
+                }
+            }
+            if count == 0 || ways.iter().any(Vec::is_empty) || order.iter().any(|&w| w >= ways.len()) {
+                return Err(format!("line {}: a loop with an empty or missing way round", i + 1));
+            }
+            let first = events.len();
+            let mut times = vec![0; ways.len()];
+            for &way in &order {
+                let n = times[way];
+                times[way] += 1;
+                for fields in &ways[way] {
+                    let words: Option<Vec<String>> = fields.iter().map(|f| instance(f, n)).collect();
+                    let words = words.ok_or_else(|| format!("line {}: no time {} round for {}", i + 1, n, fields.join(" ")))?;
+                    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+                    events.push(crash::parse_event(&words).ok_or_else(|| format!("line {}: bad event {}", i + 1, words.join(" ")))?);
+                }
+            }
+            let last = events.len() - 1;
+            written.push(Written { line: i + 1, first, last, seq, hash: None, chain: hex(words[3])? });
+            continue;
+        }
+
+        events.push(crash::parse_event(&words[3..]).ok_or_else(bad)?);
+        let at = events.len() - 1;
+        written.push(Written { line: i + 1, first: at, last: at, seq, hash: Some(hex(words[1])?), chain: hex(words[2])? });
+    }
+
+    let ids = trace::ids(&events);
+    for w in &written {
+        let (first, last) = (&ids[w.first], &ids[w.last]);
+        if let Some(hash) = w.hash.filter(|&hash| (w.seq, hash, w.chain) != (last.seq, last.hash, last.chain)) {
+            return Err(format!(
+                "line {}: the event's id is {} {:016x} {:016x}, not {} {:016x} {:016x}",
+                w.line, last.seq, last.hash, last.chain, w.seq, hash, w.chain
+            ));
+        }
+        if first.seq != w.seq || last.chain != w.chain {
+            return Err(format!(
+                "line {}: the events' ids are #{}..#{} ending in chain {:016x}, not #{} and {:016x}",
+                w.line, first.seq, last.seq, last.chain, w.seq, w.chain
+            ));
+        }
+    }
+    Ok(ids.into_iter().zip(events).collect())
+}
+
+pub fn load(path: &str) -> Result<Vec<(Id, Event)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{} {}", path, e))
+}
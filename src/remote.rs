@@ -0,0 +1,269 @@
+// talking to the challenge over the network. `solve --remote host:port` answers a server's
+// question with the winning input and prints the flag it sends back, `serve` is a practice server
+// that asks the same question and has the emulator do the check. both are on tokio so the server
+// can take a whole team at once, and ctrl-c stops either one
+use crate::metrics;
+use crate::native::{self, Outcome};
+use std::future::Future;
+use std::io;
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// what the binary prints around the city name
+pub const WELCOME: &str = "Welcome to our global weather database!\nWhat city are you interested in?\n";
+const QUESTION: &str = "What city are you interested in?";
+
+// the input buffer at 0x1000, anything longer is cut off
+const MAX_INPUT: u64 = 0x100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // for connecting, and for each read or write after that
+    pub timeout: Duration,
+    // connections the server handles at once, the rest wait to be accepted
+    pub connections: usize,
+    // attempts the client makes after the first one fails
+    pub retries: u32,
+    // wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+    // where the server answers with its metrics, see metrics.rs
+    pub metrics: Option<SocketAddr>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            timeout: Duration::from_secs(10),
+            connections: 64,
+            retries: 0,
+            backoff: Duration::from_secs(1),
+            metrics: None,
+        }
+    }
+}
+
+// what the binary prints as the flag for `input`: the real one, or "none" when the check fails
+pub fn flag(input: &[u8]) -> String {
+    match native::emulated(input) {
+        Outcome::Flag(flag) => flag,
+        _ => "none".to_string(),
+    }
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Runtime::new().map_err(|e| format!("can't start tokio: {}", e))
+}
+
+// `what` is for the error, like "connecting to host:port"
+async fn within<T>(limit: Duration, what: &str, f: impl Future<Output = io::Result<T>>) -> Result<T, String> {
+    match tokio::time::timeout(limit, f).await {
+        Ok(Ok(t)) => Ok(t),
+        Ok(Err(e)) => Err(format!("{}: {}", what, e)),
+        Err(_) => Err(format!("{}: timed out after {:?}", what, limit)),
+    }
+}
+
+// every byte that went each way over every attempt, and when, one line per read or write:
+//
+//     # attempt 1, 127.0.0.1:1337
+//     0.001 < Welcome to our global weather database!\nWhat city are you interested in?\n
+//     0.001 > TheNewFlagHillsByTheCtfWoods\n
+//
+// escaped like a rust byte string, so nothing is lost and it can be fed back byte for byte
+#[derive(Debug, Default)]
+pub struct Transcript {
+    start: Option<Instant>,
+    text: String,
+}
+
+impl Transcript {
+    fn attempt(&mut self, n: u32, addr: &str) {
+        self.start = Some(Instant::now());
+        writeln!(self.text, "# attempt {}, {}", n, addr).unwrap();
+    }
+
+    // '<' for what the server sent, '>' for what went to it
+    fn record(&mut self, way: char, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let at = self.start.map_or(0.0, |start| start.elapsed().as_secs_f64());
+        writeln!(self.text, "{:.3} {} {}", at, way, bytes.escape_ascii()).unwrap();
+    }
+
+    fn note(&mut self, what: &str) {
+        writeln!(self.text, "# {}", what).unwrap();
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// the flag line of the server's answer to `input`, trying again as `options` says
+pub fn solve(addr: &str, input: &[u8], options: &Options, transcript: &mut Transcript) -> Result<String, String> {
+    runtime()?.block_on(async {
+        tokio::select! {
+            flag = attempts(addr, input, options, transcript) => flag,
+            _ = tokio::signal::ctrl_c() => Err("cancelled".to_string()),
+        }
+    })
+}
+
+async fn attempts(addr: &str, input: &[u8], options: &Options, transcript: &mut Transcript) -> Result<String, String> {
+    let mut wait = options.backoff;
+    for n in 1.. {
+        transcript.attempt(n, addr);
+        let e = match exchange(addr, input, options.timeout, transcript).await {
+            Ok(flag) => return Ok(flag),
+            Err(e) => e,
+        };
+        transcript.note(&e);
+        if n > options.retries {
+            return Err(e);
+        }
+        eprintln!("{}, trying again in {:?}", e, wait);
+        tokio::time::sleep(wait).await;
+        wait *= 2;
+    }
+    unreachable!()
+}
+
+async fn exchange(addr: &str, input: &[u8], limit: Duration, transcript: &mut Transcript) -> Result<String, String> {
+    let mut stream = within(limit, &format!("connecting to {}", addr), TcpStream::connect(addr)).await?;
+    let (read, mut write) = stream.split();
+    let mut read = BufReader::new(read);
+
+    // the input goes in once it's been asked for
+    let mut line = Vec::new();
+    while !String::from_utf8_lossy(&line).contains(QUESTION) {
+        line.clear();
+        let n = within(limit, "waiting for the prompt", read.read_until(b'\n', &mut line)).await;
+        transcript.record('<', &line);
+        if n? == 0 {
+            return Err("the server hung up before asking for a city".to_string());
+        }
+    }
+    let mut answer = input.to_vec();
+    answer.push(b'\n');
+    within(limit, "sending the input", write.write_all(&answer)).await?;
+    transcript.record('>', &answer);
+
+    // the server hangs up after the weather report
+    let mut report = Vec::new();
+    let read = within(limit, "reading the report", read.read_to_end(&mut report)).await;
+    transcript.record('<', &report);
+    read?;
+    let report = String::from_utf8_lossy(&report);
+    report
+        .lines()
+        .find_map(|line| line.strip_prefix("Flag: "))
+        .map(str::to_string)
+        .ok_or_else(|| format!("no flag in the report:\n{}", report))
+}
+
+// take connections on `addr` until ctrl-c. connections still going then are dropped
+pub fn serve(addr: &str, options: &Options) -> Result<(), String> {
+    runtime()?.block_on(async {
+        let listener = TcpListener::bind(addr).await.map_err(|e| format!("can't listen on {}: {}", addr, e))?;
+        println!("listening on {}", addr);
+        if let Some(at) = options.metrics {
+            let metrics = TcpListener::bind(at).await.map_err(|e| format!("can't listen on {}: {}", at, e))?;
+            println!("metrics on http://{}/metrics", at);
+            metrics::enable();
+            tokio::spawn(scrapes(metrics, options.timeout));
+        }
+
+        let slots = Arc::new(Semaphore::new(options.connections));
+        let mut tasks = JoinSet::new();
+        let stop = tokio::signal::ctrl_c();
+        tokio::pin!(stop);
+        loop {
+            let slot = tokio::select! {
+                slot = slots.clone().acquire_owned() => slot.unwrap(),
+                _ = &mut stop => break,
+            };
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        println!("accept: {}", e);
+                        continue;
+                    }
+                },
+                _ = &mut stop => break,
+            };
+            let limit = options.timeout;
+            tasks.spawn(async move {
+                match answer(stream, limit).await {
+                    Ok(flag) => println!("{}: {}", peer, flag),
+                    Err(e) => println!("{}: {}", peer, e),
+                }
+                drop(slot);
+            });
+            // finished connections, so the set doesn't grow with every one ever taken
+            while tasks.try_join_next().is_some() {}
+        }
+
+        while tasks.try_join_next().is_some() {}
+        println!("stopping, {} connections dropped", tasks.len());
+        tasks.shutdown().await;
+        Ok(())
+    })
+}
+
+// http on the metrics port, whatever's asked for gets the totals
+async fn scrapes(listener: TcpListener, limit: Duration) {
+    let start = Instant::now();
+    while let Ok((mut stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            let (read, mut write) = stream.split();
+            // the request line and headers, up to the blank line
+            let mut read = BufReader::new(read).take(8192);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match within(limit, "reading the request", read.read_until(b'\n', &mut line)).await {
+                    Ok(n) if n > 0 && line != b"\r\n" && line != b"\n" => continue,
+                    Ok(_) => break,
+                    Err(_) => return,
+                }
+            }
+            let body = metrics::prometheus(metrics::totals(), start.elapsed());
+            let response = format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = within(limit, "sending the metrics", write.write_all(response.as_bytes())).await;
+        });
+    }
+}
+
+// one connection: the question, the input, and the report with the flag in it. the weather itself
+// isn't emulated, only the flag line
+async fn answer(mut stream: TcpStream, limit: Duration) -> Result<String, String> {
+    let (read, mut write) = stream.split();
+    within(limit, "sending the prompt", write.write_all(WELCOME.as_bytes())).await?;
+
+    let mut input = Vec::new();
+    let mut read = BufReader::new(read).take(MAX_INPUT);
+    within(limit, "reading the input", read.read_until(b'\n', &mut input)).await?;
+    while input.last().is_some_and(|b| b"\r\n".contains(b)) {
+        input.pop();
+    }
+
+    // half a million instructions would hold up every other connection on this thread
+    let flag = tokio::task::spawn_blocking(move || flag(&input)).await.map_err(|e| e.to_string())?;
+    let report = format!("Weather for today:\nFlag: {}\n", flag);
+    within(limit, "sending the report", write.write_all(report.as_bytes())).await?;
+    Ok(flag)
+}
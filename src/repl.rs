@@ -0,0 +1,554 @@
+// interactive prompt for poking at the booted machine: run functions, look at code, and patch it
+// with an undo stack so experiments with a modified check are cheap
+use crate::decode::{Decoded, CODE};
+use crate::crash;
+use crate::ex;
+use crate::expr::{Assertion, Expr};
+use crate::inst::{parse_num, try_parse, Width};
+use crate::programs::WEATHER;
+use crate::project::Project;
+use crate::vm::{Vm, VmError, WxMode};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+regs                      print registers
+bt                        where execution is, and the return addresses on the stack
+events [count]            the last recorded trace events
+set rN=value              change a register
+list [addr] [count]       disassemble live memory
+call <addr>               run a vm function to completion
+continue                  carry on with a call stopped at a breakpoint
+print <expr>              evaluate, like print mem32(0x1194+4) ^ 0x8aa5c4a9
+watch [expr]              show expr every time a call stops, or list the watches
+unwatch <n>               drop watch n
+break [addr] [if expr]    stop a call before running addr, or list the breakpoints
+unbreak <addr>            drop the breakpoint at addr
+assert [[at addr] expr]   check expr before every instruction (or just addr's), or list them
+unassert <n>              drop assertion n
+patch <addr> <specifiers> assemble and write, like patch 0x214 %+3.2lS
+bytes <addr> <hex>        write raw bytes, like bytes 0x214 25 2b 33
+peek b|h|w <addr> [count] show memory in bytes, halves or words, like peek w RNG_numbers 10
+poke b|h|w <addr> <vals>  write values of that width, or a string, like poke b 0x1000 \"AAAA\"
+undo, redo                take back or redo the last patch
+write <file>              save the patched program image, encrypted like mem
+reset                     fresh machine with the patches applied again
+wx off|warn|fault         watch for the vm writing into code that has run
+quit";
+
+// bytes written over memory, with what was there before so it can be undone
+struct Patch {
+    addr: usize,
+    old: Vec<u8>,
+    new: Vec<u8>,
+}
+
+pub struct Repl {
+    vm: Vm,
+    project: Project,
+    undo: Vec<Patch>,
+    redo: Vec<Patch>,
+    watches: Vec<Expr>,
+    // address -> condition, None to always stop
+    breaks: BTreeMap<usize, Option<Expr>>,
+    // stack depth the call stopped at a breakpoint returns to
+    paused: Option<usize>,
+}
+
+pub fn run(project: Project) {
+    run_with(fresh(WxMode::Warn), project);
+}
+
+// open a crash dump instead of a fresh machine
+pub fn post_mortem(path: &str, project: Project) -> Result<(), String> {
+    let (vm, message) = crash::load(path)?;
+    println!("stopped at {:#x} after {} steps: {}", vm.pc, vm.steps, message);
+    println!("{}", vm.s.print_regs());
+    run_with(vm, project);
+    Ok(())
+}
+
+fn run_with(vm: Vm, project: Project) {
+    let mut repl = Repl {
+        vm,
+        project,
+        undo: Vec::new(),
+        redo: Vec::new(),
+        watches: Vec::new(),
+        breaks: BTreeMap::new(),
+        paused: None,
+    };
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first() {
+            Some(&"quit") | Some(&"q") => break,
+            Some(_) => {
+                if let Err(e) = repl.command(&words) {
+                    println!("{}", e);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+// the booted machine, with stage2 decrypted and ready to call into. the decode cache goes on
+// before stage1 runs so it follows the decryption, and so does the W^X tracking
+fn fresh(wx: WxMode) -> Vm {
+    let mut vm = Vm::new(ex::winning_state());
+    vm.code = Some(Decoded::sweep(&vm.s.mem, CODE));
+    vm.wx.mode = wx;
+    vm.run_stage1().unwrap();
+    vm
+}
+
+impl Repl {
+    fn command(&mut self, words: &[&str]) -> Result<(), String> {
+        let addr = |i: usize| -> Result<usize, String> {
+            let word = words.get(i).ok_or("missing address")?;
+            parse_num(word)
+                .map(|n| n as usize)
+                .ok_or(format!("bad address {}", word))
+        };
+
+        match words[0] {
+            "help" => println!("{}", HELP),
+            "regs" => println!("{}", self.vm.s.print_regs()),
+            "bt" => {
+                for (i, frame) in self.vm.backtrace().iter().enumerate() {
+                    println!("#{} {:#05x}", i, frame);
+                }
+            }
+            "events" => {
+                let count = match words.get(1) {
+                    Some(n) => parse_num(n).ok_or("bad count")? as usize,
+                    None => 20,
+                };
+                let events = self.vm.s.trace.as_deref().ok_or("no trace is being recorded")?;
+                for e in &events[events.len().saturating_sub(count)..] {
+                    println!("{:x?}", e);
+                }
+            }
+            "wx" => self.vm.wx.mode = words.get(1).ok_or("missing mode")?.parse()?,
+            "set" => self.vm.s.assign(words.get(1).ok_or("missing rN=value")?)?,
+            "list" => {
+                let start = if words.len() > 1 { addr(1)? } else { 0xc8 };
+                let count = match words.get(2) {
+                    Some(n) => parse_num(n).ok_or("bad count")? as usize,
+                    None => 10,
+                };
+                self.list(start, count);
+            }
+            "call" => {
+                let target = addr(1)?;
+                // a new call drops whatever was stopped at a breakpoint
+                self.vm.stack.clear();
+                self.vm.stack.push(self.vm.pc);
+                self.vm.pc = target;
+                self.resume(0)?;
+            }
+            "continue" | "c" => {
+                let depth = self.paused.ok_or("no call is stopped")?;
+                self.resume(depth)?;
+            }
+            "print" | "p" => {
+                let e: Expr = words[1..].join(" ").parse()?;
+                let value = e.eval(&self.vm.s, &self.project)?;
+                println!("{:#x} ({})", value, value);
+            }
+            "watch" if words.len() == 1 => {
+                for (i, e) in self.watches.iter().enumerate() {
+                    println!("{}: {}", i, e);
+                }
+            }
+            "watch" => {
+                let e: Expr = words[1..].join(" ").parse()?;
+                self.watches.push(e);
+                self.show_watches();
+            }
+            "unwatch" => {
+                let n = addr(1)?;
+                if n >= self.watches.len() {
+                    return Err(format!("no watch {}", n));
+                }
+                self.watches.remove(n);
+            }
+            "break" if words.len() == 1 => {
+                for (addr, cond) in &self.breaks {
+                    match cond {
+                        Some(cond) => println!("{:#05x} if {}", addr, cond),
+                        None => println!("{:#05x}", addr),
+                    }
+                }
+            }
+            "break" => {
+                let at = self.address(words[1])?;
+                let cond = match words.get(2) {
+                    Some(&"if") => Some(words[3..].join(" ").parse()?),
+                    Some(other) => return Err(format!("expected if, got {}", other)),
+                    None => None,
+                };
+                self.breaks.insert(at, cond);
+            }
+            "assert" if words.len() == 1 => {
+                for (i, assert) in self.vm.asserts.iter().enumerate() {
+                    println!("{}: {}", i, assert);
+                }
+            }
+            "assert" => {
+                let assert = Assertion::parse(&words[1..].join(" "), &self.project)?;
+                self.vm.asserts.push(assert);
+            }
+            "unassert" => {
+                let n = addr(1)?;
+                if n >= self.vm.asserts.len() {
+                    return Err(format!("no assertion {}", n));
+                }
+                self.vm.asserts.remove(n);
+            }
+            "unbreak" => {
+                let at = self.address(words.get(1).ok_or("missing address")?)?;
+                self.breaks.remove(&at).ok_or(format!("no breakpoint at {:#x}", at))?;
+            }
+            "patch" => {
+                let text = words.get(2..).ok_or("missing specifiers")?.concat();
+                let bytes = assemble(&text).ok_or(format!("can't assemble {}", text))?;
+                self.patch(addr(1)?, bytes)?;
+            }
+            "bytes" => {
+                let hex = words.get(2..).ok_or("missing bytes")?.concat();
+                let bytes = parse_hex(&hex).ok_or(format!("bad hex {}", hex))?;
+                self.patch(addr(1)?, bytes)?;
+            }
+            "peek" => {
+                let width = width(words.get(1).ok_or("missing width")?)?;
+                let at = self.address(words.get(2).ok_or("missing address")?)?;
+                let count = match words.get(3) {
+                    Some(n) => parse_num(n).ok_or("bad count")? as usize,
+                    None => 1,
+                };
+                self.peek(width, at, count)?;
+            }
+            "poke" => {
+                let width = width(words.get(1).ok_or("missing width")?)?;
+                let at = self.address(words.get(2).ok_or("missing address")?)?;
+                let values = words.get(3..).filter(|v| !v.is_empty()).ok_or("missing values")?;
+                let bytes = self.poke_bytes(width, &values.join(" "))?;
+                self.poke(at, bytes)?;
+            }
+            "undo" => {
+                let patch = self.undo.pop().ok_or("nothing to undo")?;
+                self.write_mem(patch.addr, &patch.old);
+                println!("undid {} bytes at {:#x}", patch.old.len(), patch.addr);
+                self.redo.push(patch);
+            }
+            "redo" => {
+                let patch = self.redo.pop().ok_or("nothing to redo")?;
+                self.write_mem(patch.addr, &patch.new);
+                println!("redid {} bytes at {:#x}", patch.new.len(), patch.addr);
+                self.undo.push(patch);
+            }
+            "write" => {
+                let path = words.get(1).ok_or("missing file name")?;
+                std::fs::write(path, self.image()).map_err(|e| e.to_string())?;
+                println!("wrote {}", path);
+            }
+            "reset" => {
+                let asserts = std::mem::take(&mut self.vm.asserts);
+                self.vm = fresh(self.vm.wx.mode);
+                self.vm.asserts = asserts;
+                self.paused = None;
+                let patches: Vec<(usize, Vec<u8>)> =
+                    self.undo.iter().map(|p| (p.addr, p.new.clone())).collect();
+                for (addr, new) in patches {
+                    self.write_mem(addr, &new);
+                }
+                println!("reset with {} patches", self.undo.len());
+            }
+            other => return Err(format!("unknown command {}, try help", other)),
+        }
+        Ok(())
+    }
+
+    // an address written as an expression, so labels work
+    fn address(&self, text: &str) -> Result<usize, String> {
+        let e: Expr = text.parse()?;
+        Ok(e.eval(&self.vm.s, &self.project)? as usize)
+    }
+
+    // step until the stack is back down to `depth`, or a breakpoint stops things first. the
+    // instruction the call is sitting on doesn't count, so continue can get past a breakpoint
+    fn resume(&mut self, depth: usize) -> Result<(), String> {
+        self.paused = None;
+        let steps = self.vm.steps;
+        let mut first = true;
+        while self.vm.stack.len() > depth && !self.vm.halted {
+            if !first && self.stop_here() {
+                println!("break at {:#05x}  ({} steps)", self.vm.pc, self.vm.steps - steps);
+                self.list(self.vm.pc, 1);
+                self.show_watches();
+                self.paused = Some(depth);
+                return Ok(());
+            }
+            first = false;
+
+            if let Err(e) = self.vm.step() {
+                // stay where the assertion failed so things can be looked at, like a breakpoint
+                if let VmError::AssertionFailed { .. } = e {
+                    self.show_watches();
+                    self.paused = Some(depth);
+                    return Err(e.to_string());
+                }
+                // a fault leaves the machine mid function, don't let that leak into the next call
+                self.vm.stack.clear();
+                self.vm.s.fault = None;
+                return Err(e.to_string());
+            }
+        }
+        println!("{}  ({} steps)", self.vm.s.print_regs(), self.vm.steps - steps);
+        self.show_watches();
+        Ok(())
+    }
+
+    // a condition that can't be evaluated stops too, it's probably worth a look
+    fn stop_here(&self) -> bool {
+        match self.breaks.get(&self.vm.pc) {
+            None => false,
+            Some(None) => true,
+            Some(Some(cond)) => match cond.eval(&self.vm.s, &self.project) {
+                Ok(value) => value != 0,
+                Err(e) => {
+                    println!("breakpoint at {:#x}: {}", self.vm.pc, e);
+                    true
+                }
+            },
+        }
+    }
+
+    fn show_watches(&self) {
+        for e in &self.watches {
+            match e.eval(&self.vm.s, &self.project) {
+                Ok(value) => println!("  {} = {:#x}", e, value),
+                Err(err) => println!("  {} = <{}>", e, err),
+            }
+        }
+    }
+
+    fn patch(&mut self, addr: usize, new: Vec<u8>) -> Result<(), String> {
+        let old = self
+            .vm
+            .s
+            .mem
+            .get(addr..addr + new.len())
+            .ok_or("patch runs past the end of memory")?
+            .to_vec();
+
+        if (addr..addr + new.len()).any(|i| self.vm.wx.executed(i)) {
+            println!("note: {:#x} has already been executed", addr);
+        }
+
+        // a different length than what's there means the following code shifts meaning
+        if let Some((_, len)) = try_parse(&old) {
+            if len != new.len() {
+                println!("note: replaced a {} byte instruction with {} bytes", len, new.len());
+            }
+        }
+
+        self.write_mem(addr, &new);
+        println!("patched {} bytes at {:#x}", new.len(), addr);
+        self.list(addr, 1);
+        self.undo.push(Patch { addr, old, new });
+        self.redo.clear();
+        Ok(())
+    }
+
+    // `count` values of `width` from `at`, a row for each 16 bytes with the region it starts in
+    fn peek(&self, width: Width, at: usize, count: usize) -> Result<(), String> {
+        let n = width.bytes();
+        let per_row = 16 / n;
+        for row in 0..count.div_ceil(per_row) {
+            let start = at + row * 16;
+            let values = (0..per_row.min(count - row * per_row)).map(|i| {
+                let value = self.vm.s.peek(start + i * n, width).ok_or(format!("{:#x} is past the end of memory", start + i * n))?;
+                Ok(format!("{:0w$x}", value, w = n * 2))
+            });
+            let values = values.collect::<Result<Vec<String>, String>>()?;
+            let region = match self.project.region_or_known(start) {
+                Some(name) => format!("  [{}]", name),
+                None => String::new(),
+            };
+            // bytes get the text beside them too, the input and the flag are mostly that
+            let text = match width {
+                Width::W8 => {
+                    let bytes: Vec<u8> = (0..values.len()).map(|i| self.vm.s.mem.get(start + i).copied().unwrap_or(0)).collect();
+                    format!("  {}", String::from_utf8_lossy(&bytes).replace(|c: char| c.is_control(), "."))
+                }
+                _ => String::new(),
+            };
+            // a short last row is padded so the text and region line up with the others
+            let row = format!("{:w$}", values.join(" "), w = per_row * (n * 2 + 1) - 1);
+            println!("{:#06x}:  {}{}{}", start, row, text, region);
+        }
+        Ok(())
+    }
+
+    // what `poke` writes: a quoted string as its bytes, otherwise each value at `width` in the
+    // machine's byte order
+    fn poke_bytes(&self, width: Width, values: &str) -> Result<Vec<u8>, String> {
+        if let Some(text) = values.strip_prefix('"') {
+            let text = text.strip_suffix('"').ok_or("unterminated string")?;
+            return unescape(text);
+        }
+        let n = width.bytes();
+        let mut bytes = Vec::new();
+        for word in values.split_whitespace() {
+            let value = match word.strip_prefix('-') {
+                Some(neg) => parse_num(neg).map(|v| v.wrapping_neg()),
+                None => parse_num(word),
+            };
+            let value = value.ok_or(format!("bad value {}", word))? as u64;
+            if n < 4 && value >> (n * 8) != 0 && !word.starts_with('-') {
+                return Err(format!("{} doesn't fit in {} bits", word, n * 8));
+            }
+            let mut le = value.to_le_bytes();
+            self.vm.s.endian.order(&mut le[..n]);
+            bytes.extend(&le[..n]);
+        }
+        Ok(bytes)
+    }
+
+    // like patch, but for data, so no listing of what it turned into
+    fn poke(&mut self, addr: usize, new: Vec<u8>) -> Result<(), String> {
+        let old = self.vm.s.mem.get(addr..addr + new.len()).ok_or("poke runs past the end of memory")?.to_vec();
+        self.write_mem(addr, &new);
+        let region = self.project.region_or_known(addr).map_or(String::new(), |name| format!(" [{}]", name));
+        println!("poked {} bytes at {:#x}{}", new.len(), addr, region);
+        self.undo.push(Patch { addr, old, new });
+        self.redo.clear();
+        Ok(())
+    }
+
+    fn write_mem(&mut self, addr: usize, bytes: &[u8]) {
+        self.vm.s.mem[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self.vm.cache.invalidate(addr, bytes.len());
+        if let Some(code) = &mut self.vm.code {
+            let redone = code.update(&self.vm.s.mem, addr, bytes.len());
+            if !redone.is_empty() {
+                println!("re-decoded {:#x}..{:#x}", redone.start, redone.end);
+            }
+        }
+    }
+
+    fn list(&self, addr: usize, count: usize) {
+        match &self.vm.code {
+            Some(code) if code.contains(addr) => {
+                for (addr, slot) in code.from(addr).take(count) {
+                    match slot.inst {
+                        Some(inst) => println!("{:#05x}:  {}", addr, self.project.named(&inst, addr)),
+                        None => println!("{:#05x}:  ??", addr),
+                    }
+                }
+            }
+            // outside the code region, decode on the spot
+            _ => {
+                let mut addr = addr;
+                for _ in 0..count {
+                    match self.vm.s.mem.get(addr..).and_then(try_parse) {
+                        Some((inst, len)) => {
+                            println!("{:#05x}:  {}", addr, self.project.named(&inst, addr));
+                            addr += len;
+                        }
+                        None => {
+                            println!("{:#05x}:  ??", addr);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // the program part of memory, with stage2 xored back so it looks like the original mem file
+    fn image(&self) -> Vec<u8> {
+        let original = WEATHER.image;
+        let key = b'%' ^ original[0xc8];
+
+        let mut image = self.vm.s.mem[..original.len()].to_vec();
+        for b in &mut image[0xc8..0x6fc] {
+            *b ^= key;
+        }
+        image
+    }
+}
+
+// specifiers back to bytes through the encoder, so the written form is the canonical one
+fn assemble(text: &str) -> Option<Vec<u8>> {
+    // a nul on the end stops the parser from running off the text
+    let mut mem = text.as_bytes().to_vec();
+    mem.push(0);
+
+    let mut out = Vec::new();
+    let mut curr = 0;
+    while curr < text.len() {
+        let (inst, len) = try_parse(&mem[curr..])?;
+        out.extend(inst.encode());
+        curr += len;
+    }
+    if curr != text.len() {
+        return None;
+    }
+    Some(out)
+}
+
+// peek and poke's b, h and w, as in the printf length modifiers
+fn width(word: &str) -> Result<Width, String> {
+    match word {
+        "b" => Ok(Width::W8),
+        "h" => Ok(Width::W16),
+        "w" => Ok(Width::W32),
+        other => Err(format!("bad width {}, expected b, h or w", other)),
+    }
+}
+
+// a poke string, with \n, \0, \\, \" and \xNN
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => out.push(b'\n'),
+            Some(b'0') => out.push(0),
+            Some(b'x') => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                let hex = std::str::from_utf8(&hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok());
+                out.push(hex.ok_or("bad \\x escape")?);
+            }
+            Some(c @ (b'\\' | b'"')) => out.push(c),
+            _ => return Err("bad escape in string".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
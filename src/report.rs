@@ -0,0 +1,89 @@
+// `solve --report report.md`: everything that went into the solution in one markdown file, for
+// the archive. the goodboy buffer, the tables each input byte is run through, the input and flag,
+// and the functions that do the checking as disassembled
+use crate::color;
+use crate::inst::{try_parse, Operation};
+use crate::listing::raw;
+use crate::primes;
+use crate::project::Project;
+use crate::transform::collatz;
+use crate::variant::Variant;
+use std::fmt::Write;
+
+// the functions worth reading to follow the check, in the order it runs them
+const EXCERPTS: &[(usize, &str)] = &[
+    (0x34, "stage1 checks the key and decrypts stage2"),
+    (0x151, "generate_buffer fills the prime table"),
+    (0x1d6, "collatz counts steps down to 1"),
+    (0x21c, "process_input_byte turns an input byte into a first pass byte"),
+    (0x4ee, "buffer_check compares the first pass against the goodboy buffer"),
+];
+
+// `mem` is the image with stage2 decrypted, `flag` what the check printed
+pub fn report(variant: &Variant, input: &[u8], flag: &str, mem: &[u8], project: &Project) -> String {
+    let transform = variant.transform();
+    let numbers: Vec<u32> = variant.primes.clone().filter(|&n| primes::is_prime(n)).collect();
+    let first_pass = transform.forward(input);
+
+    let mut out = String::new();
+    writeln!(out, "# weather solution\n").unwrap();
+    writeln!(out, "- winning input: `{}`", input.escape_ascii()).unwrap();
+    writeln!(out, "- flag: `{}`", flag).unwrap();
+    writeln!(out, "- stage2 key: `{:#04x}`, the first input byte", variant.key).unwrap();
+    writeln!(out, "- each byte: {}", transform).unwrap();
+
+    writeln!(out, "\n## goodboy dwords\n").unwrap();
+    writeln!(out, "what buffer_check wants the first pass buffer to be, at 0x1194\n").unwrap();
+    writeln!(out, "| offset | dword |").unwrap();
+    writeln!(out, "|---|---|").unwrap();
+    for (i, word) in variant.goodboy.chunks(4).enumerate() {
+        let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        writeln!(out, "| {:#04x} | `{:#010x}` |", i * 4, word).unwrap();
+    }
+
+    writeln!(out, "\n## prime bytes and collatz table\n").unwrap();
+    writeln!(
+        out,
+        "primes in {:#x}..{:#x}, the low byte of each, and collatz steps for index + 1, beside what \
+         they make of each input byte\n",
+        variant.primes.start, variant.primes.end
+    )
+    .unwrap();
+    writeln!(out, "| index | prime | prime byte | collatz | input | first pass | goodboy |").unwrap();
+    writeln!(out, "|---|---|---|---|---|---|---|").unwrap();
+    for i in 0..variant.input_len() {
+        let prime = numbers.get(i).map_or("-".to_string(), |p| format!("{:#06x}", p));
+        let byte = transform.primes.get(i).map_or("-".to_string(), |b| format!("{:#04x}", b));
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | `{}` {:#04x} | {:#04x} | {:#04x} |",
+            i,
+            prime,
+            byte,
+            collatz(i as u32 + 1),
+            [input[i]].escape_ascii(),
+            input[i],
+            first_pass[i],
+            variant.goodboy[i]
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\n## disassembly").unwrap();
+    for &(addr, about) in EXCERPTS {
+        writeln!(out, "\n### {} ({:#x})\n", project.function(addr), addr).unwrap();
+        writeln!(out, "{}\n", about).unwrap();
+        writeln!(out, "```").unwrap();
+        let mut pc = addr;
+        while let Some((inst, len)) = mem.get(pc..).and_then(try_parse) {
+            let named = color::strip(&project.named(&inst, pc).to_string());
+            writeln!(out, "{:#05x}:  {:24}  {}", pc, raw(&mem[pc..pc + len]), named).unwrap();
+            if inst.op == Operation::Ret {
+                break;
+            }
+            pc += len;
+        }
+        writeln!(out, "```").unwrap();
+    }
+    out
+}
@@ -0,0 +1,219 @@
+// rhai scripts against the booted machine, for experiments that would be tedious to type into the
+// repl again and again. `disasm script <file.rhai>` runs one with these on top of rhai's own:
+//
+//     boot() boot(input)          fresh machine through stage1, on the winning input or another
+//     reg(n) set_reg(n, v)        registers
+//     peek(addr) poke(addr, v)    32 bit little endian words of memory
+//     peek_byte poke_byte         single bytes
+//     pc() steps() halted()       where the machine is
+//     disasm(addr)                the instruction at addr
+//     break_at(addr) unbreak(addr)
+//     run(addr)                   call a vm function, "return" or "break" if it hit a breakpoint
+//                                 (rhai keeps `call` for itself)
+//     resume()                    carry on with a call stopped at a breakpoint
+//     step()                      one instruction
+//     flag()                      the flag buffer as text
+//     check(input) solve()        the forward check, and the input that passes it
+//
+// faults are thrown as errors, so a script can catch them. for example, the first prime, then the
+// flag:
+//
+//     boot();
+//     break_at(0x1f4);
+//     run(0xc8);
+//     print(peek(0x1388) & 0xffff);
+//     unbreak(0x1f4);
+//     resume();
+//     print(flag());
+use crate::ex::State;
+use crate::inst::try_parse;
+use crate::variant::Variant;
+use crate::vm::Vm;
+use rhai::{Engine, EvalAltResult};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+struct Machine {
+    vm: Vm,
+    breaks: BTreeSet<usize>,
+    // stack depth the call stopped at a breakpoint returns to
+    paused: Option<usize>,
+}
+
+impl Machine {
+    fn boot(input: &[u8]) -> Result<Machine> {
+        let mut s = State::with_input(input);
+        s.quiet = true;
+        Ok(Machine {
+            vm: Vm::boot(s).map_err(|e| e.to_string())?,
+            breaks: BTreeSet::new(),
+            paused: None,
+        })
+    }
+
+    fn mem(&self, addr: i64, len: usize) -> Result<std::ops::Range<usize>> {
+        let start = usize::try_from(addr).map_err(|_| format!("bad address {}", addr))?;
+        match start.checked_add(len).filter(|&end| end <= self.vm.s.mem.len()) {
+            Some(end) => Ok(start..end),
+            None => Err(format!("{:#x} is past the end of memory", start).into()),
+        }
+    }
+
+    fn reg(&self, n: i64) -> Result<usize> {
+        match usize::try_from(n).ok().filter(|&n| n < self.vm.s.regs.len()) {
+            Some(n) => Ok(n),
+            None => Err(format!("no register {}", n).into()),
+        }
+    }
+
+    // the same stepping as the repl's: stop before a breakpoint, but not the one it's sitting on
+    fn resume(&mut self, depth: usize) -> Result<String> {
+        self.paused = None;
+        let mut first = true;
+        while self.vm.stack.len() > depth && !self.vm.halted {
+            if !first && self.breaks.contains(&self.vm.pc) {
+                self.paused = Some(depth);
+                return Ok("break".to_string());
+            }
+            first = false;
+            if let Err(e) = self.vm.step() {
+                self.vm.stack.clear();
+                self.vm.s.fault = None;
+                return Err(e.to_string().into());
+            }
+        }
+        Ok("return".to_string())
+    }
+}
+
+pub fn run(path: &str) -> std::result::Result<(), String> {
+    let script = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let winning = Variant::default().solve()?;
+    let machine = Rc::new(RefCell::new(Machine::boot(&winning).map_err(|e| e.to_string())?));
+    let mut engine = Engine::new();
+
+    let m = machine.clone();
+    let input = winning.clone();
+    engine.register_fn("boot", move || -> Result<()> {
+        *m.borrow_mut() = Machine::boot(&input)?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("boot", move |input: &str| -> Result<()> {
+        *m.borrow_mut() = Machine::boot(input.as_bytes())?;
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("reg", move |n: i64| -> Result<i64> {
+        let m = m.borrow();
+        Ok(m.vm.s.regs[m.reg(n)?] as i64)
+    });
+    let m = machine.clone();
+    engine.register_fn("set_reg", move |n: i64, v: i64| -> Result<()> {
+        let mut m = m.borrow_mut();
+        let n = m.reg(n)?;
+        m.vm.s.regs[n] = v as i32;
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("peek", move |addr: i64| -> Result<i64> {
+        let m = m.borrow();
+        let bytes = &m.vm.s.mem[m.mem(addr, 4)?];
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64)
+    });
+    let m = machine.clone();
+    engine.register_fn("poke", move |addr: i64, v: i64| -> Result<()> {
+        let mut m = m.borrow_mut();
+        let range = m.mem(addr, 4)?;
+        m.vm.s.mem[range.clone()].copy_from_slice(&(v as u32).to_le_bytes());
+        m.vm.invalidate(range.start, 4);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("peek_byte", move |addr: i64| -> Result<i64> {
+        let m = m.borrow();
+        Ok(m.vm.s.mem[m.mem(addr, 1)?.start] as i64)
+    });
+    let m = machine.clone();
+    engine.register_fn("poke_byte", move |addr: i64, v: i64| -> Result<()> {
+        let mut m = m.borrow_mut();
+        let at = m.mem(addr, 1)?.start;
+        m.vm.s.mem[at] = v as u8;
+        m.vm.invalidate(at, 1);
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("pc", move || m.borrow().vm.pc as i64);
+    let m = machine.clone();
+    engine.register_fn("steps", move || m.borrow().vm.steps as i64);
+    let m = machine.clone();
+    engine.register_fn("halted", move || m.borrow().vm.halted);
+    let m = machine.clone();
+    engine.register_fn("disasm", move |addr: i64| -> Result<String> {
+        let m = m.borrow();
+        let at = m.mem(addr, 1)?.start;
+        match try_parse(&m.vm.s.mem[at..]) {
+            Some((inst, _)) => Ok(inst.to_string()),
+            None => Err(format!("no instruction decodes at {:#x}", at).into()),
+        }
+    });
+
+    let m = machine.clone();
+    engine.register_fn("break_at", move |addr: i64| {
+        m.borrow_mut().breaks.insert(addr as usize);
+    });
+    let m = machine.clone();
+    engine.register_fn("unbreak", move |addr: i64| {
+        m.borrow_mut().breaks.remove(&(addr as usize));
+    });
+    let m = machine.clone();
+    engine.register_fn("run", move |addr: i64| -> Result<String> {
+        let mut m = m.borrow_mut();
+        // a new call drops whatever was stopped at a breakpoint
+        let pc = m.vm.pc;
+        m.vm.stack.clear();
+        m.vm.stack.push(pc);
+        m.vm.halted = false;
+        m.vm.pc = addr as usize;
+        m.resume(0)
+    });
+    let m = machine.clone();
+    engine.register_fn("resume", move || -> Result<String> {
+        let mut m = m.borrow_mut();
+        let depth = m.paused.ok_or("no call is stopped")?;
+        m.resume(depth)
+    });
+    let m = machine.clone();
+    engine.register_fn("step", move || -> Result<()> {
+        m.borrow_mut().vm.step().map_err(|e| e.to_string().into())
+    });
+    let m = machine.clone();
+    engine.register_fn("flag", move || {
+        let m = m.borrow();
+        String::from_utf8_lossy(&m.vm.s.mem[crate::programs::WEATHER.flag.clone()]).into_owned()
+    });
+
+    engine.register_fn("check", |input: &str| Variant::default().check(input.as_bytes()));
+    engine.register_fn("solve", || -> Result<String> {
+        let input = Variant::default().solve()?;
+        Ok(String::from_utf8_lossy(&input).into_owned())
+    });
+
+    // nothing to do with the machine, but handy for working out by hand what the check wants
+    engine.register_fn("collatz", |n: i64| crate::transform::collatz(n as u32) as i64);
+    engine.register_fn("prime", |i: i64| -> Result<i64> {
+        match crate::primes::PRIMES.get(i as usize) {
+            Some(&p) => Ok(p as i64),
+            None => Err(format!("there are {} primes", crate::primes::COUNT).into()),
+        }
+    });
+
+    engine.run(&script).map_err(|e| format!("{}: {}", path, e))
+}
@@ -0,0 +1,73 @@
+// snapshot checks of the full listings. any change to Display or the parser shows up as a diff
+// against the files in snapshots/, which fails tests/snapshot.rs under cargo test. `snapshot`
+// shows the same diff, and rewrites the files with --update once the change is wanted
+use crate::inst::decrypted_image;
+use crate::listing::{Columns, ListingWriter};
+use crate::programs::WEATHER;
+use crate::project::Project;
+use std::fs;
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/snapshots");
+
+// each listing as it comes out now, by snapshot name
+pub fn listings() -> Vec<(&'static str, String)> {
+    let mem = WEATHER.image;
+    let project = Project::default();
+    let listing = |walk: &dyn Fn(&mut ListingWriter)| {
+        let mut writer = ListingWriter::new(&project, Columns::default(), mem.len());
+        walk(&mut writer);
+        writer.finish()
+    };
+    vec![
+        ("stage1", listing(&|w| w.stage1(mem))),
+        ("stage2", listing(&|w| w.stage2(&decrypted_image()))),
+    ]
+}
+
+pub fn path(name: &str) -> String {
+    format!("{}/{}.txt", DIR, name)
+}
+
+pub fn run(update: bool) {
+    let mut changed = 0;
+    for (name, current) in listings().iter() {
+        let path = path(name);
+        let old = fs::read_to_string(&path).unwrap_or_default();
+
+        if old == *current {
+            println!("ok: {}", name);
+        } else if update {
+            fs::create_dir_all(DIR).unwrap();
+            fs::write(&path, current).unwrap();
+            println!("updated: {}", path);
+        } else {
+            println!("MISMATCH: {}", path);
+            print!("{}", diff(&old, current));
+            changed += 1;
+        }
+    }
+
+    if changed > 0 {
+        println!("run `snapshot --update` to accept the new listings");
+        std::process::exit(1);
+    }
+}
+
+// line by line, which is all a listing needs since lines are keyed by address
+pub fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut out = String::new();
+    for i in 0..old.len().max(new.len()) {
+        let (a, b) = (old.get(i), new.get(i));
+        if a != b {
+            if let Some(a) = a {
+                out += &format!("  -{}\n", a);
+            }
+            if let Some(b) = b {
+                out += &format!("  +{}\n", b);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,377 @@
+// the machine's state, which is all the engine needs besides the interpreter in vm.rs. this and
+// everything it uses builds without std, so printing goes through `console`
+use crate::inst::{parse_num, Width};
+use crate::memory::{Endian, Memory};
+use crate::primes;
+use crate::programs::WEATHER;
+use crate::trace::{Event, Fnv};
+use crate::vm::VmError;
+use crate::word::Word;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+// guard bytes right after the program image and past the end of memory. nothing should ever touch
+// them, so a clobbered one means a store ran a little too far
+pub const CANARY: [u8; 8] = *b"canary!!";
+
+// the image plus the 8000 bytes the challenge uses, nothing goes past this. the end canary sits here
+pub const EXTENT: usize = 0x700 + 8000;
+
+// r0 to r4 is all the challenge uses
+pub const REGS: usize = 5;
+
+// memory grows to cover any access up to this, past it is out of bounds
+pub const MEM_CAP: usize = 0x100_0000;
+
+// all state that the vm keeps. registers are 32 bit like the challenge unless asked otherwise
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct State<R = i32> {
+    // registers, indexed by the number in the format specifier
+    pub regs: Vec<R>,
+    // memory, grown on access
+    pub mem: Memory,
+    // how far `mem` may grow
+    pub mem_cap: usize,
+    // has canaries, which only the challenge layout does
+    pub guarded: bool,
+    // skip the read/store logging, for when thousands of lines would just be noise
+    pub quiet: bool,
+    // progress bars for the slow parts, so a quiet run still shows it's alive
+    pub progress: bool,
+    // first bad memory access. the transpiled functions can't return errors, so out of bounds
+    // reads give 0 and stores are dropped, and whoever is driving checks this afterwards
+    pub fault: Option<VmError>,
+    // memory events, when recording is turned on
+    pub trace: Option<Vec<Event>>,
+    // byte order for memory accesses
+    pub endian: Endian,
+    // where the read/store log and other chatter goes
+    pub console: Console,
+}
+
+// a line of output from the engine. with std that's stdout, a no_std host that wants to see
+// anything gives it somewhere to go
+#[derive(Clone, Copy)]
+pub struct Console(pub fn(fmt::Arguments<'_>));
+
+impl Console {
+    pub fn line(self, args: fmt::Arguments<'_>) {
+        (self.0)(args)
+    }
+}
+
+impl Default for Console {
+    #[cfg(feature = "std")]
+    fn default() -> Self {
+        Console(|args| println!("{}", args))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> Self {
+        Console(|_| {})
+    }
+}
+
+impl fmt::Debug for Console {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Console")
+    }
+}
+
+// where output goes has nothing to do with what the machine computed
+impl PartialEq for Console {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl<R: Word> State<R> {
+    // program bytes at 0, memory past them is zeros until something touches it
+    pub fn new() -> Self {
+        // default inits everything to 0 which is fine, I manually checked for any register reads
+        // that could have been uninitialized
+        let image = WEATHER.image;
+        let mut s = State {
+            regs: vec![R::default(); REGS],
+            mem: image.to_vec().into(),
+            mem_cap: MEM_CAP,
+            guarded: true,
+            ..Default::default()
+        };
+        s.write_bytes(image.len(), &CANARY);
+        s.write_bytes(EXTENT, &CANARY);
+        s
+    }
+
+    // another program's image, none of the weather layout or its canaries
+    pub fn from_image(image: &[u8]) -> Self {
+        State {
+            regs: vec![R::default(); REGS],
+            mem: image.to_vec().into(),
+            mem_cap: MEM_CAP,
+            ..Default::default()
+        }
+    }
+
+    // a memory image from a file, like a process dump, mapped instead of read in. there's no
+    // challenge layout here, so no canaries either
+    #[cfg(feature = "std")]
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mem = Memory::map(path)?;
+        Ok(State {
+            regs: vec![R::default(); REGS],
+            mem_cap: MEM_CAP.max(mem.len()),
+            mem,
+            ..Default::default()
+        })
+    }
+
+    // fresh machine with `input` typed in as the city name
+    pub fn with_input(input: &[u8]) -> Self {
+        let mut s = State::new();
+        s.write_bytes(0x1000, input);
+        s
+    }
+
+    // make `mem` cover everything below `end`, false if that's past the cap
+    pub fn grow(&mut self, end: usize) -> bool {
+        if end <= self.mem.len() {
+            return true;
+        }
+        if end > self.mem_cap {
+            return false;
+        }
+        self.mem.resize(end, 0);
+        true
+    }
+
+    // host side writes, like typing in the input. these grow memory past the cap if they have to
+    pub fn write_bytes(&mut self, at: usize, bytes: &[u8]) {
+        let end = at + bytes.len();
+        if end > self.mem.len() {
+            self.mem.resize(end, 0);
+        }
+        self.mem[at..end].copy_from_slice(bytes);
+    }
+
+    // for variants that use more than r0 to r4. new registers start at 0
+    pub fn set_reg_count(&mut self, count: usize) {
+        self.regs.resize(count, R::default());
+    }
+
+    // the interpreter addresses registers by the number in the format specifier
+    pub fn reg(&self, n: u32) -> R {
+        self.regs[n as usize]
+    }
+
+    pub fn set_reg(&mut self, n: u32, value: R) {
+        self.regs[n as usize] = value;
+    }
+
+    // apply an assignment like "r0=0x3391"
+    pub fn assign(&mut self, set: &str) -> Result<(), String> {
+        let (reg, value) = set.split_once('=').unwrap_or((set, ""));
+        let n = reg.strip_prefix('r').and_then(|n| n.parse().ok());
+        match (n, parse_num(value)) {
+            (Some(n), Some(value)) if (n as usize) < self.regs.len() => {
+                self.set_reg(n, R::from_imm(value));
+                Ok(())
+            }
+            _ => Err(format!(
+                "bad register assignment {}, expected something like r0=0x3391",
+                set
+            )),
+        }
+    }
+
+    pub fn reg_mut(&mut self, n: u32) -> &mut R {
+        &mut self.regs[n as usize]
+    }
+
+    // memory accesses were always a whole register at a time, alignment didn't matter
+    pub fn store(&mut self, dest: R, src: R) {
+        self.store_width(dest, src, R::WIDTH);
+    }
+
+    pub fn store8(&mut self, dest: R, src: R) {
+        self.store_width(dest, src, Width::W8);
+    }
+
+    pub fn store16(&mut self, dest: R, src: R) {
+        self.store_width(dest, src, Width::W16);
+    }
+
+    pub fn store32(&mut self, dest: R, src: R) {
+        self.store_width(dest, src, Width::W32);
+    }
+
+    // store the low bytes of `src`
+    pub fn store_width(&mut self, dest: R, src: R, width: Width) {
+        let n = width.bytes();
+        let src = src.truncate(n);
+
+        // log the mem write
+        if !self.quiet {
+            self.console.line(format_args!("storing --> {:x} to index {:x} {}", src, dest, log_index(dest.low())));
+        }
+        // traces only know the 32 bit machine, a 64 bit run records the low halves
+        if let Some(trace) = &mut self.trace {
+            trace.push(Event::Store {
+                index: dest.low(),
+                value: src.low(),
+            });
+        }
+
+        // get index as usize
+        let i = dest.index();
+        // copy over the little endian bytes. a 64 bit register can hold an index with no end
+        if i.checked_add(n).is_some_and(|end| self.grow(end)) {
+            let mut bytes = src.to_le();
+            self.endian.order(&mut bytes[..n]);
+            self.mem[i..i + n].copy_from_slice(&bytes[..n]);
+        } else {
+            self.out_of_bounds(i);
+        }
+    }
+
+    // read a register's worth of memory
+    pub fn read(&mut self, src: R) -> R {
+        self.read_width(src, R::WIDTH)
+    }
+
+    pub fn read8(&mut self, src: R) -> R {
+        self.read_width(src, Width::W8)
+    }
+
+    pub fn read16(&mut self, src: R) -> R {
+        self.read_width(src, Width::W16)
+    }
+
+    pub fn read32(&mut self, src: R) -> R {
+        self.read_width(src, Width::W32)
+    }
+
+    // narrow reads are zero extended
+    pub fn read_width(&mut self, src: R, width: Width) -> R {
+        let n = width.bytes();
+
+        // log the mem read
+        if !self.quiet {
+            self.console.line(format_args!("reading <-- index {:x} {}", src, log_index(src.low())));
+        }
+
+        // index as usize
+        let i = src.index();
+        // copy memory bytes into temp buf
+        let mut buf = [0; 8];
+        if i.checked_add(n).is_some_and(|end| self.grow(end)) {
+            buf[..n].copy_from_slice(&self.mem[i..i + n]);
+        } else {
+            self.out_of_bounds(i);
+        }
+        // return value as little endian
+        self.endian.order(&mut buf[..n]);
+        let value = R::from_le(&buf[..n]);
+        if let Some(trace) = &mut self.trace {
+            trace.push(Event::Read {
+                index: src.low(),
+                value: value.low(),
+            });
+        }
+        value
+    }
+
+    // a read for looking at the machine from outside: no log, no trace, and memory isn't grown.
+    // past the end reads as zero like it would for the program, None if it's past the cap
+    pub fn peek(&self, index: usize, width: Width) -> Option<u64> {
+        let n = width.bytes();
+        if index.checked_add(n)? > self.mem_cap.max(self.mem.len()) {
+            return None;
+        }
+        let mut buf = [0; 8];
+        for (i, b) in buf[..n].iter_mut().enumerate() {
+            *b = self.mem.get(index + i).copied().unwrap_or(0);
+        }
+        self.endian.order(&mut buf[..n]);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    // only the first fault is kept, everything after it is probably fallout
+    fn out_of_bounds(&mut self, index: usize) {
+        if self.fault.is_none() {
+            self.fault = Some(VmError::OutOfBounds(index));
+        }
+    }
+
+    // one hash over the registers, the first pass buffer, and the flag. a cheap way to tell that
+    // a refactor didn't change the outcome of a run
+    pub fn digest(&self) -> u64 {
+        let mut hash = Fnv::new();
+        for n in 0..self.regs.len() as u32 {
+            hash.write(&self.reg(n).to_le()[..R::BYTES]);
+        }
+        // a small image (like one from --inline) reads zeros past its end
+        let bytes = |range: Range<usize>| -> Vec<u8> {
+            range.map(|i| self.mem.get(i).copied().unwrap_or(0)).collect()
+        };
+        hash.write(&bytes(0x1194..0x1194 + 0x1c));
+        hash.write(&bytes(0x1800..0x1820));
+        hash.finish()
+    }
+
+    // where the canaries are, after the image and after what the challenge uses
+    pub fn canaries(&self) -> Vec<usize> {
+        match self.guarded {
+            true => vec![WEATHER.image.len(), EXTENT],
+            false => Vec::new(),
+        }
+    }
+
+    // canaries that don't read "canary!!" anymore
+    pub fn clobbered_canaries(&self) -> Vec<usize> {
+        self.canaries()
+            .into_iter()
+            .filter(|&at| self.mem[at..at + CANARY.len()] != CANARY)
+            .collect()
+    }
+
+    // print a line for each clobbered canary, returns true if they're all intact
+    pub fn check_canaries(&self) -> bool {
+        let clobbered = self.clobbered_canaries();
+        for at in &clobbered {
+            self.console.line(format_args!(
+                "canary at {:#x} clobbered: {:x?}",
+                at,
+                &self.mem[*at..*at + CANARY.len()]
+            ));
+        }
+        clobbered.is_empty()
+    }
+
+    // debugging
+    #[allow(dead_code)]
+    pub fn print_regs(&self) -> String {
+        let regs: Vec<String> = self.regs.iter().map(|r| format!("{:04x}", r)).collect();
+        regs.join(" ")
+    }
+}
+
+// the known ranges of memory, for the logs and the listings
+pub const REGIONS: &[(Range<usize>, &str)] = &[
+    (0x1000..0x1101, "user input"),  // user input "city name"
+    (0x1190..0x1291, "first pass"),  // input lands here after XOR and add operations
+    (primes::REGION, "RNG numbers"), // really primes, see primes.rs
+    (0x1800..0x1901, "flag output"), // points to `flag` global addr in binary, see ghidra
+];
+
+// when memory is logged, I wanted to annotate certain known ranges
+pub fn log_index(index: i32) -> String {
+    REGIONS
+        .iter()
+        .find(|(range, _)| range.contains(&(index as usize)))
+        .map_or(String::new(), |(_, name)| format!("[{}]", name))
+}
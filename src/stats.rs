@@ -0,0 +1,128 @@
+// the summary `run` ends with: how many instructions ran and which, how much memory the program
+// touched, anything that went wrong along the way, and where the time went
+use crate::inst::{try_parse, Operation};
+use crate::trace::Event;
+use crate::vm::ENTRY;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// the stages of the program by the function each starts in. stage2_main calls all but the first
+// one after the other
+pub const STAGES: &[(&str, usize)] = &[
+    ("stage1", ENTRY),
+    ("prime generation", 0x151),
+    ("input transform", 0x1f4),
+    ("check", 0x4ee),
+    ("flag output", 0x28d),
+];
+
+// when each stage started, each one runs until the next
+#[derive(Debug, Default)]
+pub struct Clock {
+    marks: Vec<(&'static str, Instant)>,
+}
+
+impl Clock {
+    // the stage starting at `addr` is under way, if that's one and it hasn't been seen yet. the
+    // same function called again is still the same stage
+    pub fn enter(&mut self, addr: usize) {
+        if let Some(&(name, _)) = STAGES.iter().find(|&&(_, at)| at == addr) {
+            if !self.marks.iter().any(|&(seen, _)| seen == name) {
+                self.marks.push((name, Instant::now()));
+            }
+        }
+    }
+
+    // how long each stage took, the last one until now
+    pub fn stages(&self) -> Vec<(&'static str, Duration)> {
+        let now = Instant::now();
+        let ends = self.marks.iter().skip(1).map(|&(_, at)| at).chain([now]);
+        self.marks.iter().zip(ends).map(|(&(name, start), end)| (name, end - start)).collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    // none for ex.rs, the transpiled functions don't count what they run
+    pub steps: Option<u64>,
+    // instructions run by operation, empty when the runner doesn't record its steps
+    pub mix: BTreeMap<String, u64>,
+    // every byte read, and every one written
+    pub read: BTreeSet<usize>,
+    pub written: BTreeSet<usize>,
+    pub warnings: Vec<String>,
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+impl Stats {
+    // the mix and the memory footprint from a trace. `mem` is for decoding the steps, so it wants
+    // stage2 decrypted, as it is once the run is over
+    pub fn count(&mut self, events: &[Event], mem: &[u8]) {
+        for e in events {
+            match *e {
+                Event::Step { pc } => {
+                    let op = match mem.get(pc..).and_then(try_parse) {
+                        Some((inst, _)) if inst.op == Operation::Jmp => "call".to_string(),
+                        Some((inst, _)) => format!("{:?}", inst.op).to_lowercase(),
+                        None => "?".to_string(),
+                    };
+                    *self.mix.entry(op).or_default() += 1;
+                }
+                Event::Read { index, .. } => self.read.extend(index as u32 as usize..index as u32 as usize + 4),
+                Event::Store { index, .. } => self.written.extend(index as u32 as usize..index as u32 as usize + 4),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "summary:")?;
+        match self.steps {
+            Some(steps) => writeln!(f, "  steps     {}", steps)?,
+            None => writeln!(f, "  steps     not counted")?,
+        }
+
+        let mut mix: Vec<(&String, &u64)> = self.mix.iter().collect();
+        mix.sort_by(|a, b| b.1.cmp(a.1));
+        let total: u64 = self.mix.values().sum();
+        let mix: Vec<String> = mix
+            .iter()
+            .map(|(op, &n)| format!("{} {:.1}%", op, 100.0 * n as f64 / total as f64))
+            .collect();
+        // the jit only records the steps it leaves to the interpreter
+        match mix.is_empty() {
+            true => writeln!(f, "  mix       not recorded")?,
+            false if total < self.steps.unwrap_or(total) => writeln!(f, "  mix       {}, of the {} steps recorded", mix.join(", "), total)?,
+            false => writeln!(f, "  mix       {}", mix.join(", "))?,
+        }
+
+        let touched = self.read.union(&self.written).count();
+        writeln!(
+            f,
+            "  memory    {} bytes touched, {} read, {} written",
+            touched,
+            self.read.len(),
+            self.written.len()
+        )?;
+
+        match self.warnings.is_empty() {
+            true => writeln!(f, "  warnings  none")?,
+            false => {
+                for (i, warning) in self.warnings.iter().enumerate() {
+                    let label = if i == 0 { "warnings" } else { "" };
+                    writeln!(f, "  {:9} {}", label, warning)?;
+                }
+            }
+        }
+
+        let stages: Vec<String> = self.stages.iter().map(|(name, time)| format!("{} {:.1?}", name, time)).collect();
+        // the jit doesn't stop at calls to time them
+        match stages.is_empty() {
+            true => write!(f, "  stages    not recorded"),
+            false => write!(f, "  stages    {}", stages.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// arbitrary::Arbitrary for the engine's types, so fuzzers and property tests can ask for an
+// instruction or a whole machine instead of making one out of raw bytes:
+//
+//     fuzz_target!(|s: State| { ... });
+//
+// only with the arbitrary feature
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width, MAX_OPERAND};
+use crate::memory::Endian;
+use crate::state::{State, EXTENT, REGS};
+use crate::word::Word;
+use alloc::vec::Vec;
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+// structured instructions for fuzzers and property tests. every one of them encodes and parses
+// back to itself, see roundtrip.rs
+impl<'a> Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        // ret is just the nul terminator, so it always parses to the same fields
+        if u.ratio(1, 13)? {
+            return Ok(Self {
+                dest: 0,
+                src: 0,
+                dest_mode: DestMode::Minus,
+                src_mode: SrcMode::LL,
+                op: Operation::Ret,
+                width: Width::W32,
+            });
+        }
+        // mostly small register numbers, but 0 and the big immediates too, up to what printf reads
+        let operand = |u: &mut Unstructured<'a>| -> Result<u32> {
+            match u.int_in_range(0..=3)? {
+                0 => Ok(0),
+                1 => u.int_in_range(0..=4),
+                2 => u.int_in_range(0..=0x1fff),
+                _ => u.int_in_range(0..=MAX_OPERAND),
+            }
+        };
+        Ok(Self {
+            dest: operand(u)?,
+            src: operand(u)?,
+            dest_mode: *u.choose(&[DestMode::NoPlusMinus, DestMode::Plus, DestMode::Minus, DestMode::ZeroPad])?,
+            src_mode: *u.choose(&[SrcMode::HH, SrcMode::H, SrcMode::LL, SrcMode::L, SrcMode::None])?,
+            op: *u.choose(&[
+                Operation::Jmp,
+                Operation::Mov,
+                Operation::Add,
+                Operation::Sub,
+                Operation::Mul,
+                Operation::Div,
+                Operation::Mod,
+                Operation::ShLeft,
+                Operation::ShRight,
+                Operation::Xor,
+                Operation::And,
+                Operation::Or,
+            ])?,
+            // there's no spelling for W64
+            width: *u.choose(&[Width::W8, Width::W16, Width::W32])?,
+        })
+    }
+}
+
+// a machine for fuzzers and property tests: the usual registers, and memory that's a program of
+// arbitrary instructions at 0 and arbitrary data after it. memory stays within the challenge's
+// EXTENT, growing included, so a wild index faults instead of allocating. quiet, there's nobody
+// to read the log
+impl<'a, R: Word + Arbitrary<'a>> Arbitrary<'a> for State<R> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut regs = Vec::with_capacity(REGS);
+        for _ in 0..REGS {
+            regs.push(u.arbitrary()?);
+        }
+        let mut mem = Vec::new();
+        for _ in 0..u.int_in_range(0..=64)? {
+            mem.extend(u.arbitrary::<Instruction>()?.encode());
+        }
+        // the outermost function returns at the end of it
+        mem.push(0);
+        let data = u.int_in_range(0..=EXTENT - mem.len())?;
+        mem.extend(u.bytes(data.min(u.len()))?);
+        Ok(State {
+            regs,
+            mem: mem.into(),
+            mem_cap: EXTENT,
+            quiet: true,
+            endian: *u.choose(&[Endian::Little, Endian::Big])?,
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,344 @@
+// the interpreter again, but with the decoding and the matching done once per instruction instead
+// of once per step. every offset that runs is decoded into an Op holding a handler specialized for
+// its operation and operand modes, so a step is an index into a flat op array plus one indirect
+// call, like a computed goto. offsets that haven't run hold the decoding handler, so there's no
+// check for a miss either. Vm::step matches on the operation, both operand modes and the width
+// every time.
+//
+// results are the same as Vm::run, steps, trace events and faults included. a store drops the ops
+// it lands on, so stage1 decrypting stage2 and other self modifying code still work, and calls run
+// the on_call and on_return hooks. W^X, protection, assertions and the decode cache are left to the
+// plain interpreter
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width};
+use crate::trace::Event;
+use crate::vm::{apply, OnCall, Vm, VmError, WxMode, MAX_DEPTH};
+use crate::word::Word;
+
+type Handler<R> = fn(&mut Vm<R>, &mut Ops<R>, &Instruction, usize) -> Result<(), VmError>;
+
+// a decoded instruction and the code that runs it
+struct Op<R> {
+    exec: Handler<R>,
+    inst: Instruction,
+    // bytes it was decoded from, 0 for an offset that hasn't run
+    len: usize,
+}
+
+// derive would want R: Clone
+impl<R> Clone for Op<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for Op<R> {}
+
+// the arithmetic ops by index, for the const generic handlers
+const OPS: [Operation; 11] = [
+    Operation::Mov,
+    Operation::Add,
+    Operation::Sub,
+    Operation::Mul,
+    Operation::Div,
+    Operation::Mod,
+    Operation::ShLeft,
+    Operation::ShRight,
+    Operation::Xor,
+    Operation::And,
+    Operation::Or,
+];
+
+// what an offset holds before it runs, `decode` doesn't look at it
+const UNDECODED: Instruction = Instruction {
+    dest: 0,
+    src: 0,
+    dest_mode: DestMode::Minus,
+    src_mode: SrcMode::LL,
+    op: Operation::Ret,
+    width: Width::W32,
+};
+
+// an op for every byte of memory
+struct Ops<R> {
+    ops: Vec<Op<R>>,
+    // end of the furthest op decoded, stores past it can't land on one
+    end: usize,
+    // longest op, how far back a store can reach into one
+    longest: usize,
+}
+
+impl<R: Word> Ops<R> {
+    fn new(len: usize) -> Self {
+        let mut ops = Ops {
+            ops: Vec::new(),
+            end: 0,
+            longest: 0,
+        };
+        ops.grow(len);
+        ops
+    }
+
+    // memory grew, it's all undecoded
+    fn grow(&mut self, len: usize) {
+        let undecoded = Op {
+            exec: decode,
+            inst: UNDECODED,
+            len: 0,
+        };
+        self.ops.resize(len, undecoded);
+    }
+
+    fn insert(&mut self, at: usize, op: Op<R>) {
+        self.ops[at] = op;
+        self.end = self.end.max(at + op.len);
+        self.longest = self.longest.max(op.len);
+    }
+
+    // `len` bytes at `addr` changed, forget every op that overlaps them
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        if addr >= self.end {
+            return;
+        }
+        let start = addr.saturating_sub(self.longest.saturating_sub(1));
+        let end = addr.saturating_add(len).min(self.end);
+        for at in start..end {
+            let n = self.ops[at].len;
+            if n > 0 && at + n > addr {
+                self.ops[at] = Op {
+                    exec: decode,
+                    inst: UNDECODED,
+                    len: 0,
+                };
+            }
+        }
+    }
+}
+
+// operand kinds for the handlers
+const SRC_IMM_MEM: usize = 0;
+const SRC_REG_MEM: usize = 1;
+const SRC_REG: usize = 2;
+const SRC_IMM: usize = 3;
+const DEST_REG: usize = 0;
+const DEST_IMM_MEM: usize = 1;
+const DEST_REG_MEM: usize = 2;
+
+// run to the end like Vm::run
+pub fn run<R: Word>(vm: &mut Vm<R>) -> Result<(), VmError> {
+    if needs_interpreter(vm) {
+        return vm.run();
+    }
+    execute(vm, 0)
+}
+
+// run the function at `addr` to completion like Vm::call
+pub fn call<R: Word>(vm: &mut Vm<R>, addr: usize) -> Result<(), VmError> {
+    if needs_interpreter(vm) {
+        return vm.call(addr);
+    }
+    let depth = vm.stack.len();
+    vm.stack.push(vm.pc);
+    vm.pc = addr;
+    execute(vm, depth + 1)
+}
+
+fn needs_interpreter<R: Word>(vm: &Vm<R>) -> bool {
+    let tracked = vm.wx.mode != WxMode::Off || vm.protection.is_some() || !vm.asserts.is_empty();
+    if tracked || vm.code.is_some() {
+        println!("threaded: W^X, protection, assertions and the decode cache need the interpreter");
+    }
+    tracked || vm.code.is_some()
+}
+
+// step until halted or the stack is back below `depth`
+fn execute<R: Word>(vm: &mut Vm<R>, depth: usize) -> Result<(), VmError> {
+    let mut ops = Ops::new(vm.s.mem.len());
+    // stores here only keep the op array up to date, the interpreter decodes afresh after
+    vm.cache.clear();
+    while !vm.halted && vm.stack.len() >= depth {
+        let pc = vm.pc;
+        if pc >= ops.ops.len() {
+            if pc >= vm.s.mem.len() {
+                return Err(VmError::OutOfBounds(pc));
+            }
+            ops.grow(vm.s.mem.len());
+        }
+        vm.record(Event::Step { pc });
+        vm.steps += 1;
+        let op = ops.ops[pc];
+        (op.exec)(vm, &mut ops, &op.inst, pc + op.len)?;
+    }
+    Ok(())
+}
+
+// the handler for an offset that hasn't run: decode it, keep it, and run it
+fn decode<R: Word>(vm: &mut Vm<R>, ops: &mut Ops<R>, _: &Instruction, _: usize) -> Result<(), VmError> {
+    let pc = vm.pc;
+    let (inst, rest) = Instruction::checked(&vm.s.mem[pc..]).ok_or(VmError::BadInstruction(pc))?;
+    let len = vm.s.mem.len() - pc - rest.len();
+    let op = Op {
+        exec: handler(&inst),
+        inst,
+        len,
+    };
+    ops.insert(pc, op);
+    (op.exec)(vm, ops, &op.inst, pc + len)
+}
+
+fn handler<R: Word>(inst: &Instruction) -> Handler<R> {
+    match inst.op {
+        Operation::Ret => ret,
+        Operation::Jmp => match inst.dest_mode {
+            DestMode::Minus => jump::<R, 0>,
+            DestMode::Plus => jump::<R, 1>,
+            DestMode::ZeroPad => jump::<R, 2>,
+            DestMode::NoPlusMinus => jump::<R, 3>,
+        },
+        op => {
+            let src = match inst.src_mode {
+                SrcMode::HH => SRC_IMM_MEM,
+                SrcMode::H => SRC_REG_MEM,
+                SrcMode::L => SRC_REG,
+                SrcMode::LL => SRC_IMM,
+                SrcMode::None => return bad_operand,
+            };
+            let dest = match inst.dest_mode {
+                DestMode::NoPlusMinus => DEST_REG,
+                DestMode::Minus => DEST_IMM_MEM,
+                DestMode::Plus => DEST_REG_MEM,
+                DestMode::ZeroPad => return bad_operand,
+            };
+            by_op(OPS.iter().position(|&o| o == op).unwrap(), src, dest)
+        }
+    }
+}
+
+// the runtime indexes down to a monomorphized handler
+fn by_op<R: Word>(op: usize, src: usize, dest: usize) -> Handler<R> {
+    match op {
+        0 => by_src::<R, 0>(src, dest),
+        1 => by_src::<R, 1>(src, dest),
+        2 => by_src::<R, 2>(src, dest),
+        3 => by_src::<R, 3>(src, dest),
+        4 => by_src::<R, 4>(src, dest),
+        5 => by_src::<R, 5>(src, dest),
+        6 => by_src::<R, 6>(src, dest),
+        7 => by_src::<R, 7>(src, dest),
+        8 => by_src::<R, 8>(src, dest),
+        9 => by_src::<R, 9>(src, dest),
+        _ => by_src::<R, 10>(src, dest),
+    }
+}
+
+fn by_src<R: Word, const OP: usize>(src: usize, dest: usize) -> Handler<R> {
+    match src {
+        SRC_IMM_MEM => by_dest::<R, OP, SRC_IMM_MEM>(dest),
+        SRC_REG_MEM => by_dest::<R, OP, SRC_REG_MEM>(dest),
+        SRC_REG => by_dest::<R, OP, SRC_REG>(dest),
+        _ => by_dest::<R, OP, SRC_IMM>(dest),
+    }
+}
+
+fn by_dest<R: Word, const OP: usize, const SRC: usize>(dest: usize) -> Handler<R> {
+    match dest {
+        DEST_REG => arith::<R, OP, SRC, DEST_REG>,
+        DEST_IMM_MEM => arith::<R, OP, SRC, DEST_IMM_MEM>,
+        _ => arith::<R, OP, SRC, DEST_REG_MEM>,
+    }
+}
+
+fn ret<R: Word>(vm: &mut Vm<R>, _: &mut Ops<R>, _: &Instruction, _: usize) -> Result<(), VmError> {
+    let ret = vm.stack.pop();
+    if !vm.hooks.is_empty() {
+        vm.hooks.ret(&mut vm.s, vm.stack.len());
+    }
+    vm.record(Event::Return { to: ret });
+    match ret {
+        Some(ret) => vm.pc = ret,
+        None => vm.halted = true,
+    }
+    Ok(())
+}
+
+// %C, COND is the dest mode: less than, greater than or equal to zero, or always
+fn jump<R: Word, const COND: usize>(
+    vm: &mut Vm<R>,
+    _: &mut Ops<R>,
+    inst: &Instruction,
+    next: usize,
+) -> Result<(), VmError> {
+    let cond = vm.reg(inst.src)?;
+    let taken = match COND {
+        0 => cond < R::default(),
+        1 => cond > R::default(),
+        2 => cond == R::default(),
+        _ => true,
+    };
+    let skipped = taken && !vm.hooks.is_empty() && vm.hooks.call(&mut vm.s, inst.dest as usize) == OnCall::Skip;
+    if !taken || skipped {
+        vm.pc = next;
+        return Ok(());
+    }
+    if vm.stack.len() >= MAX_DEPTH {
+        return Err(VmError::StackOverflow(vm.pc));
+    }
+    vm.record(Event::Call {
+        from: vm.pc,
+        to: inst.dest as usize,
+    });
+    vm.stack.push(next);
+    vm.pc = inst.dest as usize;
+    Ok(())
+}
+
+fn bad_operand<R: Word>(vm: &mut Vm<R>, _: &mut Ops<R>, _: &Instruction, _: usize) -> Result<(), VmError> {
+    Err(VmError::BadOperand(vm.pc))
+}
+
+// everything that isn't a call or ret, in the same order as Vm::step
+fn arith<R: Word, const OP: usize, const SRC: usize, const DEST: usize>(
+    vm: &mut Vm<R>,
+    ops: &mut Ops<R>,
+    inst: &Instruction,
+    next: usize,
+) -> Result<(), VmError> {
+    let op = OPS[OP];
+    let width = match inst.width {
+        Width::W32 => R::WIDTH,
+        width => width,
+    };
+    let src = match SRC {
+        SRC_IMM_MEM => vm.s.read_width(R::from_imm(inst.src), width),
+        SRC_REG_MEM => {
+            let addr = vm.reg(inst.src)?;
+            vm.s.read_width(addr, width)
+        }
+        SRC_REG => vm.reg(inst.src)?,
+        _ => R::from_imm(inst.src),
+    };
+
+    if DEST == DEST_REG {
+        let dest = vm.reg(inst.dest)?;
+        *vm.s.reg_mut(inst.dest) = apply(op, dest, src, vm.pc)?;
+    } else {
+        let addr = match DEST {
+            DEST_IMM_MEM => R::from_imm(inst.dest),
+            _ => vm.reg(inst.dest)?,
+        };
+        let val = match op {
+            Operation::Mov => src,
+            _ => {
+                let dest = vm.s.read_width(addr, width);
+                apply(op, dest, src, vm.pc)?
+            }
+        };
+        vm.s.store_width(addr, val, width);
+        ops.invalidate(addr.index(), width.bytes());
+    }
+    vm.pc = next;
+
+    match vm.s.fault {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
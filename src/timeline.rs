@@ -0,0 +1,107 @@
+// which part of the program touches which memory, and when. each stage (a function called
+// straight from stage2's entry, or stage1 before that) gets its step range drawn as a bar, and
+// the regions it read and wrote, which traces the data from the user input through the first
+// pass to the flag
+use crate::golden;
+use crate::hot::describe;
+use crate::project::Project;
+use crate::trace::Event;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// characters in a timeline bar
+const BAR: usize = 50;
+
+#[derive(Debug, Default)]
+struct Stage {
+    first: u64,
+    last: u64,
+    steps: u64,
+    // which slices of the run it was active in
+    bar: Vec<bool>,
+    // region -> (reads, writes), in order of first access
+    regions: Vec<(String, u64, u64)>,
+}
+
+pub fn report(events: &[Event], project: &Project) -> String {
+    let total = events.iter().filter(|e| matches!(e, Event::Step { .. })).count().max(1) as u64;
+    let mut stack: Vec<usize> = Vec::new();
+    // keyed by the stage's start, stage1 has none
+    let mut stages: BTreeMap<Option<usize>, Stage> = BTreeMap::new();
+    let mut step = 0;
+    for e in events {
+        // stack[0] is stage2 itself, stack[1] the function it called
+        let stage = stack.get(1).or(stack.first()).copied();
+        let access = match *e {
+            Event::Step { .. } => {
+                let s = stages.entry(stage).or_insert_with(|| Stage {
+                    first: step,
+                    bar: vec![false; BAR],
+                    ..Default::default()
+                });
+                s.last = step;
+                s.steps += 1;
+                s.bar[(step * BAR as u64 / total) as usize] = true;
+                step += 1;
+                None
+            }
+            Event::Call { to, .. } => {
+                stack.push(to);
+                None
+            }
+            Event::Return { .. } => {
+                stack.pop();
+                None
+            }
+            Event::Read { index, .. } => Some((index, true)),
+            Event::Store { index, .. } => Some((index, false)),
+        };
+
+        if let (Some((index, read)), Some(s)) = (access, stages.get_mut(&stage)) {
+            let region = describe(index, project);
+            let i = match s.regions.iter().position(|r| r.0 == region) {
+                Some(i) => i,
+                None => {
+                    s.regions.push((region, 0, 0));
+                    s.regions.len() - 1
+                }
+            };
+            if read {
+                s.regions[i].1 += 1;
+            } else {
+                s.regions[i].2 += 1;
+            }
+        }
+    }
+
+    let mut ordered: Vec<(Option<usize>, Stage)> = stages.into_iter().collect();
+    ordered.sort_by_key(|(_, s)| s.first);
+
+    let mut out = String::new();
+    for (start, s) in ordered {
+        let name = match start {
+            Some(addr) => project.function(addr),
+            None => "stage1".to_string(),
+        };
+        let bar: String = s.bar.iter().map(|&on| if on { '#' } else { '.' }).collect();
+        writeln!(
+            out,
+            "{:<14} {:>7}..{:<7} {:>7} steps  {}",
+            name,
+            s.first,
+            s.last + 1,
+            s.steps,
+            bar
+        )
+        .unwrap();
+        for (region, reads, writes) in &s.regions {
+            writeln!(out, "    {:<14} {:>5} reads {:>5} writes", region, reads, writes).unwrap();
+        }
+    }
+    out
+}
+
+// the winning solve, since that goes through every stage
+pub fn run(project: &Project) {
+    print!("{}", report(&golden::known_good_trace(), project));
+}
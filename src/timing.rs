@@ -0,0 +1,106 @@
+// `run --trace --timing`: where the time goes, instruction by instruction. each step is split in
+// two: dispatch, the interpreter decoding and running it, and observer, what watching it costs on
+// top. that's the trace line, and the engine's read/store log when it isn't --quiet, which goes
+// through console() to be timed. the offsets with the most time between the two come at the end,
+// `--top n` of them
+use crate::inst::try_parse;
+use crate::project::Project;
+use crate::state::Console;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use std::time::Instant;
+
+// nanoseconds the engine's log lines have taken
+static LOGGING: AtomicU64 = AtomicU64::new(0);
+
+// stdout like the default console, keeping count of the time
+pub fn console() -> Console {
+    Console(|args| {
+        let start = Instant::now();
+        println!("{}", args);
+        LOGGING.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    })
+}
+
+// all the time console() has spent so far
+pub fn logging() -> Duration {
+    Duration::from_nanos(LOGGING.load(Ordering::Relaxed))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cost {
+    pub count: u64,
+    pub dispatch: Duration,
+    pub observer: Duration,
+}
+
+impl Cost {
+    pub fn total(&self) -> Duration {
+        self.dispatch + self.observer
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Timing {
+    pub by_pc: HashMap<usize, Cost>,
+}
+
+impl Timing {
+    pub fn add(&mut self, pc: usize, dispatch: Duration, observer: Duration) {
+        let cost = self.by_pc.entry(pc).or_default();
+        cost.count += 1;
+        cost.dispatch += dispatch;
+        cost.observer += observer;
+    }
+
+    // the `top` most expensive offsets, `mem` for showing what's there
+    pub fn report(&self, top: usize, mem: &[u8], project: &Project) -> String {
+        let mut costs: Vec<(usize, Cost)> = self.by_pc.iter().map(|(&pc, &cost)| (pc, cost)).collect();
+        costs.sort_by_key(|&(pc, cost)| (std::cmp::Reverse(cost.total()), pc));
+        let all = costs.iter().fold(Cost::default(), |all, (_, c)| Cost {
+            count: all.count + c.count,
+            dispatch: all.dispatch + c.dispatch,
+            observer: all.observer + c.observer,
+        });
+        let share = |d: Duration| match all.total().is_zero() {
+            true => 0.0,
+            false => 100.0 * d.as_secs_f64() / all.total().as_secs_f64(),
+        };
+
+        let mut out = String::new();
+        writeln!(out, "timing, the top {} of {} instructions by time", top.min(costs.len()), costs.len()).unwrap();
+        writeln!(out, "  {:7} {:>8} {:>11} {:>11} {:>9} {:>6}  instruction", "pc", "count", "dispatch", "observer", "per step", "share").unwrap();
+        for &(pc, cost) in costs.iter().take(top) {
+            let inst = match mem.get(pc..).and_then(try_parse) {
+                Some((inst, _)) => project.named(&inst, pc).to_string(),
+                None => "?".to_string(),
+            };
+            writeln!(
+                out,
+                "  {:<#7x} {:>8} {:>11} {:>11} {:>9} {:>5.1}%  {}",
+                pc,
+                cost.count,
+                format!("{:.1?}", cost.dispatch),
+                format!("{:.1?}", cost.observer),
+                format!("{:.0?}", cost.total() / cost.count.max(1) as u32),
+                share(cost.total()),
+                inst
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "  all {} steps: dispatch {:.1?} ({:.1}%), observer {:.1?} ({:.1}%)",
+            all.count,
+            all.dispatch,
+            share(all.dispatch),
+            all.observer,
+            share(all.observer)
+        )
+        .unwrap();
+        out.pop();
+        out
+    }
+}
@@ -0,0 +1,100 @@
+// recording of what the machine does, as an alternative to printing every memory access. a
+// State with `trace` set to Some keeps every event in order
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    // 4 byte read from memory
+    Read { index: i32, value: i32 },
+    // 4 byte write to memory
+    Store { index: i32, value: i32 },
+    // the interpreter is about to execute the instruction at pc
+    Step { pc: usize },
+    // a %C was taken
+    Call { from: usize, to: usize },
+    // nul terminator, back to the caller. the outermost return has nowhere to go
+    Return { to: Option<usize> },
+}
+
+impl Event {
+    // stable byte encoding, so hashes don't depend on the rust version like std's Hasher does
+    fn bytes(&self) -> Vec<u8> {
+        let (tag, a, b) = match *self {
+            Event::Read { index, value } => (0, index as u32 as u64, value as u32 as u64),
+            Event::Store { index, value } => (1, index as u32 as u64, value as u32 as u64),
+            Event::Step { pc } => (2, pc as u64, 0),
+            Event::Call { from, to } => (3, from as u64, to as u64),
+            Event::Return { to } => (4, to.map_or(u64::MAX, |to| to as u64), 0),
+        };
+        let mut out = vec![tag];
+        out.extend(&a.to_le_bytes());
+        out.extend(&b.to_le_bytes());
+        out
+    }
+
+    // FNV-1a over the event alone, the same for the same event in any run
+    pub fn hash(&self) -> u64 {
+        let mut hash = Fnv::new();
+        hash.write(&self.bytes());
+        hash.finish()
+    }
+}
+
+// where an event is in a run and what it was, stable from one run or build to the next so two
+// recordings can be lined up. `chain` is the digest of the run up to and including the event, so
+// two runs with the same chain at some seq haven't differed yet, and the last one is digest()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Id {
+    pub seq: u64,
+    pub hash: u64,
+    pub chain: u64,
+}
+
+pub fn ids(events: &[Event]) -> Vec<Id> {
+    let mut chain = Fnv::new();
+    let mut ids = Vec::with_capacity(events.len());
+    for (seq, e) in events.iter().enumerate() {
+        chain.write(&e.bytes());
+        ids.push(Id {
+            seq: seq as u64,
+            hash: e.hash(),
+            chain: chain.finish(),
+        });
+    }
+    ids
+}
+
+// 64 bit FNV-1a over the whole event stream
+pub fn digest(events: &[Event]) -> u64 {
+    let mut hash = Fnv::new();
+    for e in events {
+        hash.write(&e.bytes());
+    }
+    hash.finish()
+}
+
+pub struct Fnv(u64);
+
+impl Fnv {
+    pub fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
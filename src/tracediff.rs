@@ -0,0 +1,233 @@
+// `trace-diff a.trace b.trace`: two runs recorded with `run --record`, lined up event by event.
+// the first place they part ways comes first, with the instruction the first run was on and the
+// events either side, then every stretch that differs and the memory each run left behind.
+// traces don't have the registers, so the memory side is all there is to compare.
+//
+// the runs are lined up by each event's hash. after a difference, the next spot where SYNC events
+// in a row match again is where they're back in step, looking at most WINDOW events ahead on each
+// side. `--only store,read` leaves the rest out first, which is how the transpiled run (it has no
+// steps or calls) compares against the interpreter
+use crate::crash;
+use crate::inst::try_parse;
+use crate::project::Project;
+use crate::trace::{Event, Id};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::ops::Range;
+
+const SYNC: usize = 8;
+const WINDOW: usize = 10_000;
+
+// events shown from each side of a stretch
+const SHOWN: usize = 5;
+// stretches and memory cells listed in the summary
+const LISTED: usize = 20;
+
+pub const KINDS: &[&str] = &["read", "store", "step", "call", "return"];
+
+fn kind(e: &Event) -> &'static str {
+    match e {
+        Event::Read { .. } => "read",
+        Event::Store { .. } => "store",
+        Event::Step { .. } => "step",
+        Event::Call { .. } => "call",
+        Event::Return { .. } => "return",
+    }
+}
+
+// "store,read" into the kinds to keep
+pub fn kinds(list: &str) -> Result<Vec<&'static str>, String> {
+    list.split(',')
+        .map(|k| KINDS.iter().find(|&&known| known == k).copied())
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("bad --only {}, the kinds are {}", list, KINDS.join(", ")))
+}
+
+// a stretch where the runs differ, by index into the filtered events
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stretch {
+    a: Range<usize>,
+    b: Range<usize>,
+}
+
+// where a[i..] and b[j..] are back in step, as how far along each
+fn resync(a: &[(Id, Event)], b: &[(Id, Event)]) -> Option<(usize, usize)> {
+    let matches = |i: usize, j: usize| {
+        let (a, b) = (&a[i..], &b[j..]);
+        let n = SYNC.min(a.len()).min(b.len());
+        // both at the end counts, one running out doesn't
+        (n == SYNC || a.len() == b.len()) && (0..n).all(|k| a[k].0.hash == b[k].0.hash)
+    };
+    let mut at: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (j, (id, _)) in b.iter().enumerate().take(WINDOW) {
+        at.entry(id.hash).or_default().push(j);
+    }
+
+    let mut best: Option<(usize, usize)> = None;
+    for (i, (id, _)) in a.iter().enumerate().take(WINDOW) {
+        if best.is_some_and(|(bi, bj)| i >= bi + bj) {
+            break;
+        }
+        for &j in at.get(&id.hash).map_or(&[][..], Vec::as_slice) {
+            if best.is_some_and(|(bi, bj)| i + j >= bi + bj) {
+                break;
+            }
+            if matches(i, j) {
+                best = Some((i, j));
+                break;
+            }
+        }
+    }
+    best
+}
+
+fn stretches(a: &[(Id, Event)], b: &[(Id, Event)]) -> Vec<Stretch> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < a.len() && j < b.len() && a[i].0.hash == b[j].0.hash {
+            i += 1;
+            j += 1;
+        }
+        if i == a.len() && j == b.len() {
+            return out;
+        }
+        let (di, dj) = resync(&a[i..], &b[j..]).unwrap_or((a.len() - i, b.len() - j));
+        out.push(Stretch {
+            a: i..i + di,
+            b: j..j + dj,
+        });
+        i += di;
+        j += dj;
+    }
+}
+
+// what every store left in memory, by index
+fn memory(events: &[(Id, Event)]) -> BTreeMap<i32, i32> {
+    let mut mem = BTreeMap::new();
+    for (_, e) in events {
+        if let Event::Store { index, value } = *e {
+            mem.insert(index, value);
+        }
+    }
+    mem
+}
+
+// the steps before event `seq`, and the last of them
+fn steps_before(events: &[(Id, Event)], seq: u64) -> (usize, Option<usize>) {
+    let mut steps = 0;
+    let mut last = None;
+    for (_, e) in events.iter().take_while(|(id, _)| id.seq < seq) {
+        if let Event::Step { pc } = *e {
+            steps += 1;
+            last = Some(pc);
+        }
+    }
+    (steps, last)
+}
+
+fn range(events: &[(Id, Event)], r: &Range<usize>) -> String {
+    match r.is_empty() {
+        true => "nothing".to_string(),
+        false => format!("#{}..#{}", events[r.start].0.seq, events[r.end - 1].0.seq),
+    }
+}
+
+// `a` and `b` as loaded, `only` the kinds to compare or empty for all of them. `mem` is the
+// program the runs are of, for showing instructions
+pub fn report(
+    names: (&str, &str),
+    a: &[(Id, Event)],
+    b: &[(Id, Event)],
+    only: &[&str],
+    mem: &[u8],
+    project: &Project,
+) -> String {
+    let keep = |events: &[(Id, Event)]| -> Vec<(Id, Event)> {
+        events.iter().filter(|(_, e)| only.is_empty() || only.contains(&kind(e))).copied().collect()
+    };
+    let (fa, fb) = (keep(a), keep(b));
+    let stretches = stretches(&fa, &fb);
+
+    let mut out = String::new();
+    writeln!(out, "{}: {} events", names.0, a.len()).unwrap();
+    writeln!(out, "{}: {} events", names.1, b.len()).unwrap();
+    if !only.is_empty() {
+        writeln!(out, "comparing {} only: {} against {}", only.join(", "), fa.len(), fb.len()).unwrap();
+    }
+
+    let first = match stretches.first() {
+        Some(first) => first,
+        None => {
+            writeln!(out, "no differences").unwrap();
+            return out;
+        }
+    };
+
+    // the first divergence, from where the first run was
+    let seq = fa.get(first.a.start).or(fa.last()).map_or(0, |(id, _)| id.seq);
+    let (steps, pc) = steps_before(a, seq);
+    writeln!(out, "\nfirst divergence at {} {} / {} {}, after {} steps", names.0, range(&fa, &first.a), names.1, range(&fb, &first.b), steps).unwrap();
+    if let Some(pc) = pc {
+        let inst = match mem.get(pc..).and_then(try_parse) {
+            Some((inst, _)) => project.named(&inst, pc).to_string(),
+            None => "?".to_string(),
+        };
+        writeln!(out, "  on {:#05x}  {}", pc, inst).unwrap();
+    }
+    for (name, events, r) in [(names.0, &fa, &first.a), (names.1, &fb, &first.b)] {
+        for (id, e) in &events[r.start..r.end.min(r.start + SHOWN)] {
+            writeln!(out, "  {} #{}  {}", name, id.seq, crash::event(e)).unwrap();
+        }
+        if r.len() > SHOWN {
+            writeln!(out, "  {} ... {} more", name, r.len() - SHOWN).unwrap();
+        }
+    }
+    // the same kind of event at the same place with a different value is a memory delta
+    let pairs = fa[first.a.clone()].iter().zip(&fb[first.b.clone()]);
+    for ((_, x), (_, y)) in pairs.take(SHOWN) {
+        match (*x, *y) {
+            (Event::Read { index: i, value: v }, Event::Read { index: j, value: w })
+            | (Event::Store { index: i, value: v }, Event::Store { index: j, value: w })
+                if i == j =>
+            {
+                writeln!(out, "  {} {:#x}: {:#x} against {:#x}", kind(x), i, v, w).unwrap()
+            }
+            _ => {}
+        }
+    }
+
+    writeln!(out, "\nsummary: {} stretches differ", stretches.len()).unwrap();
+    for s in stretches.iter().take(LISTED) {
+        let changed = s.a.len().min(s.b.len());
+        let mut what = vec![format!("{} changed", changed)];
+        if s.a.len() > changed {
+            what.push(format!("{} only in {}", s.a.len() - changed, names.0));
+        }
+        if s.b.len() > changed {
+            what.push(format!("{} only in {}", s.b.len() - changed, names.1));
+        }
+        writeln!(out, "  {} / {}  {}", range(&fa, &s.a), range(&fb, &s.b), what.join(", ")).unwrap();
+    }
+    if stretches.len() > LISTED {
+        writeln!(out, "  ... {} more", stretches.len() - LISTED).unwrap();
+    }
+    let only_a: usize = stretches.iter().map(|s| s.a.len()).sum();
+    let only_b: usize = stretches.iter().map(|s| s.b.len()).sum();
+    writeln!(out, "  events  {} of {}'s and {} of {}'s aren't matched", only_a, names.0, only_b, names.1).unwrap();
+
+    let (ma, mb) = (memory(&fa), memory(&fb));
+    let mut cells: Vec<i32> = ma.keys().chain(mb.keys()).copied().filter(|i| ma.get(i) != mb.get(i)).collect();
+    cells.sort_unstable();
+    cells.dedup();
+    let value = |v: Option<&i32>| v.map_or("unwritten".to_string(), |v| format!("{:#x}", v));
+    writeln!(out, "  memory  {} cells end up different", cells.len()).unwrap();
+    for i in cells.iter().take(LISTED) {
+        writeln!(out, "    {:#x}: {} against {}", i, value(ma.get(i)), value(mb.get(i))).unwrap();
+    }
+    if cells.len() > LISTED {
+        writeln!(out, "    ... {} more", cells.len() - LISTED).unwrap();
+    }
+    out.pop();
+    out
+}
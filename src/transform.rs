@@ -0,0 +1,176 @@
+// what process_input_byte does to each input byte on the way to the first pass buffer, as data
+// instead of code, so the check can be run forwards and the solver can run it backwards from the
+// same description. weather's is
+//
+//     xor primes      low byte of the index'th prime from generate_buffer
+//     add collatz     collatz steps for index + 1
+//     mask 0xff
+//
+// a variant file can give its own steps, see variant.rs
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Table {
+    // the prime table, filled in from the variant's prime range
+    Primes,
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Amount {
+    Collatz,
+    Index,
+    Const(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Xor(Table),
+    Add(Amount),
+    Mask(u8),
+}
+
+impl Step {
+    // "xor primes", "xor 0x12 0x34 ..", "add collatz", "add index", "add 5", "mask 0xff"
+    pub fn parse(words: &[&str]) -> Result<Step, String> {
+        let num = |word: &str| -> Result<u8, String> {
+            let n = crate::inst::parse_num(word).ok_or(format!("bad number {}", word))?;
+            match n <= 0xff {
+                true => Ok(n as u8),
+                false => Err(format!("{:#x} isn't a byte", n)),
+            }
+        };
+        match words {
+            ["xor", "primes"] => Ok(Step::Xor(Table::Primes)),
+            ["xor", bytes @ ..] if !bytes.is_empty() => {
+                Ok(Step::Xor(Table::Bytes(bytes.iter().map(|b| num(b)).collect::<Result<_, _>>()?)))
+            }
+            ["add", "collatz"] => Ok(Step::Add(Amount::Collatz)),
+            ["add", "index"] => Ok(Step::Add(Amount::Index)),
+            ["add", n] => Ok(Step::Add(Amount::Const(num(n)?))),
+            ["mask", m] => Ok(Step::Mask(num(m)?)),
+            _ => Err(format!("bad step {}", words.join(" "))),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Xor(Table::Primes) => write!(f, "xor primes"),
+            Step::Xor(Table::Bytes(bytes)) => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+                write!(f, "xor {}", bytes.join(" "))
+            }
+            Step::Add(Amount::Collatz) => write!(f, "add collatz"),
+            Step::Add(Amount::Index) => write!(f, "add index"),
+            Step::Add(Amount::Const(n)) => write!(f, "add {:#x}", n),
+            Step::Mask(m) => write!(f, "mask {:#04x}", m),
+        }
+    }
+}
+
+// the steps weather takes
+pub fn weather() -> Vec<Step> {
+    vec![Step::Xor(Table::Primes), Step::Add(Amount::Collatz), Step::Mask(0xff)]
+}
+
+// collatz steps from `n` down to 1, what ex::collatz leaves in r0
+pub fn collatz(mut n: u32) -> u32 {
+    let mut steps = 0;
+    while n > 1 {
+        n = match n.is_multiple_of(2) {
+            true => n / 2,
+            false => n.wrapping_mul(3).wrapping_add(1),
+        };
+        steps += 1;
+    }
+    steps
+}
+
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub steps: Vec<Step>,
+    // low bytes of the primes, for Table::Primes
+    pub primes: Vec<u8>,
+}
+
+impl Transform {
+    // the longest input every table covers
+    pub fn len(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|step| match step {
+                Step::Xor(Table::Primes) => self.primes.len(),
+                Step::Xor(Table::Bytes(bytes)) => bytes.len(),
+                _ => usize::MAX,
+            })
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn table(&self, table: &Table, index: usize) -> u8 {
+        match table {
+            Table::Primes => self.primes[index],
+            Table::Bytes(bytes) => bytes[index],
+        }
+    }
+
+    fn amount(amount: Amount, index: usize) -> u8 {
+        match amount {
+            Amount::Collatz => collatz(index as u32 + 1) as u8,
+            Amount::Index => index as u8,
+            Amount::Const(n) => n,
+        }
+    }
+
+    // the first pass byte for input byte `b` at `index`
+    pub fn apply(&self, b: u8, index: usize) -> u8 {
+        self.steps.iter().fold(b, |b, step| match step {
+            Step::Xor(table) => b ^ self.table(table, index),
+            Step::Add(amount) => b.wrapping_add(Self::amount(*amount, index)),
+            Step::Mask(m) => b & m,
+        })
+    }
+
+    // the input byte at `index` that gives first pass byte `b`. a mask can't be undone, so this
+    // fails if `b` has bits outside one, and takes those bits as 0 going back
+    pub fn unapply(&self, b: u8, index: usize) -> Result<u8, String> {
+        let mut b = b;
+        for step in self.steps.iter().rev() {
+            b = match step {
+                Step::Xor(table) => b ^ self.table(table, index),
+                Step::Add(amount) => b.wrapping_sub(Self::amount(*amount, index)),
+                Step::Mask(m) if b & !m != 0 => {
+                    return Err(format!("byte {} is {:#04x}, which `{}` never gives", index, b, step))
+                }
+                Step::Mask(_) => b,
+            };
+        }
+        Ok(b)
+    }
+
+    // forwards, the first pass buffer `input` leaves for buffer_check
+    pub fn forward(&self, input: &[u8]) -> Vec<u8> {
+        input.iter().enumerate().map(|(i, &b)| self.apply(b, i)).collect()
+    }
+
+    // backwards, the input that leaves `first_pass`
+    pub fn inverse(&self, first_pass: &[u8]) -> Result<Vec<u8>, String> {
+        if first_pass.len() > self.len() {
+            return Err(format!("the tables cover {} bytes, {} needed", self.len(), first_pass.len()));
+        }
+        first_pass.iter().enumerate().map(|(i, &b)| self.unapply(b, i)).collect()
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<String> = self.steps.iter().map(Step::to_string).collect();
+        write!(f, "{}", steps.join(", "))
+    }
+}
@@ -0,0 +1,49 @@
+// unpacking by running the unpacker. instead of assuming how a stage was encrypted (keys.rs guesses
+// a one byte xor), the program runs in the interpreter with W^X bookkeeping on, and every time
+// execution lands on bytes stored by an instruction that hasn't produced code before, that's a new
+// layer. memory at that moment is the layer exactly as the program decrypted it, whether the
+// decryptor xors, adds or rolls its key along
+use crate::ex::State;
+use crate::vm::{Vm, VmError, WxMode};
+use std::collections::HashSet;
+
+// more than the weather solve needs, so a decryptor that never finishes doesn't hang
+const MAX_STEPS: u64 = 10_000_000;
+
+pub struct Layer {
+    // first instruction run from the decrypted bytes
+    pub entry: usize,
+    // the store that wrote them
+    pub writer: usize,
+    // steps run before getting here
+    pub steps: u64,
+    // the program's image at the handover, with this layer (and the ones before) decrypted
+    pub image: Vec<u8>,
+}
+
+// run `image` from `entry` on `input` and collect every layer it decrypts on the way
+pub fn layers(image: &[u8], entry: usize, input: &[u8]) -> Result<Vec<Layer>, VmError> {
+    let mut s = State::from_image(image);
+    s.write_bytes(0x1000, input);
+    s.quiet = true;
+    let mut vm: Vm = Vm::new(s);
+    vm.pc = entry;
+    vm.wx.mode = WxMode::Track;
+
+    let mut layers = Vec::new();
+    let mut writers = HashSet::new();
+    while !vm.halted && vm.steps < MAX_STEPS {
+        if let Some(writer) = vm.wx.writer(vm.pc) {
+            if writers.insert(writer) {
+                layers.push(Layer {
+                    entry: vm.pc,
+                    writer,
+                    steps: vm.steps,
+                    image: vm.s.mem[..image.len()].to_vec(),
+                });
+            }
+        }
+        vm.step()?;
+    }
+    Ok(layers)
+}
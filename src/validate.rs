@@ -0,0 +1,150 @@
+// `disasm validate <mem>`: a new dump looked over without running any of it. the encrypted stage
+// is decrypted with whatever key keys.rs finds for it, then everything up to the end of the code
+// (or the whole file if it's smaller) is swept like the listing and counted:
+//
+//   - instructions, by operation
+//   - bytes nothing decodes at, in runs, nul padding, which decodes as rets but isn't code, and
+//     the %s that prints the flag, which is printf's and not the vm's
+//   - specifiers that decode but that the interpreter would fault on or that no program of this
+//     family writes: registers past the five, a missing source, zero pad on anything but a call,
+//     narrow widths without memory, fixed addresses past the end of memory, calls to where no
+//     instruction starts
+use crate::decode::{Decoded, Slot, CODE};
+use crate::inst::{DestMode, Instruction, Operation, SrcMode, Width};
+use crate::keys;
+use crate::state::{MEM_CAP, REGS};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+// suspicious specifiers listed, the rest are only counted
+const LISTED: usize = 20;
+
+// what's odd about `inst`, if anything. `starts` is where every instruction begins
+fn suspicious(inst: &Instruction, starts: &BTreeSet<usize>) -> Option<String> {
+    if inst.op == Operation::Ret {
+        return None;
+    }
+    let reg = |n: u32| (n as usize) >= REGS;
+    if inst.op == Operation::Jmp {
+        // even a plain call reads its register, it just doesn't look at it
+        if reg(inst.src) {
+            return Some(format!("condition register r{} past the {} there are", inst.src, REGS));
+        }
+        if !starts.contains(&(inst.dest as usize)) {
+            return Some(format!("calls {:#x}, where no instruction starts", inst.dest));
+        }
+        return None;
+    }
+    if inst.dest_mode == DestMode::ZeroPad {
+        return Some("zero pad flag on something other than a call".to_string());
+    }
+    if inst.src_mode == SrcMode::None {
+        return Some("no source operand".to_string());
+    }
+    if matches!(inst.dest_mode, DestMode::NoPlusMinus | DestMode::Plus) && reg(inst.dest) {
+        return Some(format!("destination register r{} past the {} there are", inst.dest, REGS));
+    }
+    if matches!(inst.src_mode, SrcMode::H | SrcMode::L) && reg(inst.src) {
+        return Some(format!("source register r{} past the {} there are", inst.src, REGS));
+    }
+    let far = |imm: u32| (imm as usize) >= MEM_CAP;
+    if inst.src_mode == SrcMode::HH && far(inst.src) {
+        return Some(format!("reads {:#x}, past the end of memory", inst.src));
+    }
+    if inst.dest_mode == DestMode::Minus && far(inst.dest) {
+        return Some(format!("writes {:#x}, past the end of memory", inst.dest));
+    }
+    let memory = inst.dest_mode != DestMode::NoPlusMinus || matches!(inst.src_mode, SrcMode::HH | SrcMode::H);
+    if inst.width != Width::W32 && !memory {
+        return Some("narrow width with no memory operand".to_string());
+    }
+    None
+}
+
+// runs of consecutive addresses as ranges
+fn runs(addrs: impl Iterator<Item = usize>) -> Vec<Range<usize>> {
+    let mut out: Vec<Range<usize>> = Vec::new();
+    for a in addrs {
+        match out.last_mut() {
+            Some(r) if r.end == a => r.end = a + 1,
+            _ => out.push(a..a + 1),
+        }
+    }
+    out
+}
+
+// `mem` as read from `path`, `encrypted` where the program's encrypted stage is. the report, and
+// whether everything decoded and nothing looked odd
+pub fn report(path: &str, mem: &[u8], encrypted: Range<usize>) -> (String, bool) {
+    let mut out = String::new();
+    writeln!(out, "{}: {} bytes", path, mem.len()).unwrap();
+
+    let mut mem = mem.to_vec();
+    if encrypted.end <= mem.len() {
+        match keys::recover(&mem, encrypted.clone()) {
+            Some(0) => writeln!(out, "  {:#x}..{:#x} is already plaintext", encrypted.start, encrypted.end).unwrap(),
+            Some(key) => {
+                writeln!(out, "  decrypted {:#x}..{:#x} with key {:#04x}", encrypted.start, encrypted.end, key).unwrap();
+                mem = keys::unxor(&mem, encrypted, key);
+            }
+            None => writeln!(out, "  no key for {:#x}..{:#x}, sweeping it as it is", encrypted.start, encrypted.end).unwrap(),
+        }
+    }
+
+    let end = mem.len().min(CODE.end);
+    let decoded = Decoded::sweep(&mem, 0..end);
+    let slots: Vec<(usize, Slot)> = decoded.from(0).map(|(a, &slot)| (a, slot)).collect();
+    let starts: BTreeSet<usize> = slots.iter().filter(|(_, s)| s.inst.is_some()).map(|&(a, _)| a).collect();
+
+    // a nul after another is padding, the first one ends whatever came before it
+    let padding: BTreeSet<usize> = (1..end).filter(|&a| mem[a] == 0 && mem[a - 1] == 0).collect();
+    let mut ops: BTreeMap<String, usize> = BTreeMap::new();
+    let mut odd = Vec::new();
+    for &(at, slot) in &slots {
+        let inst = match slot.inst {
+            Some(inst) if !padding.contains(&at) => inst,
+            _ => continue,
+        };
+        *ops.entry(format!("{:?}", inst.op).to_lowercase()).or_default() += 1;
+        if let Some(why) = suspicious(&inst, &starts) {
+            odd.push((at, why));
+        }
+    }
+    // a %s is printf's, it's how the flag gets printed and isn't a vm instruction
+    let (prints, unknown): (Vec<Range<usize>>, Vec<Range<usize>>) =
+        runs(slots.iter().filter(|(_, s)| s.inst.is_none()).map(|&(a, _)| a)).into_iter().partition(|r| &mem[r.clone()] == b"%s");
+
+    let count: usize = ops.values().sum();
+    writeln!(out, "  swept      {:#x}..{:#x}", 0, end).unwrap();
+    let mut by_count: Vec<(&String, &usize)> = ops.iter().collect();
+    by_count.sort_by_key(|&(op, n)| (std::cmp::Reverse(*n), op.clone()));
+    let mix: Vec<String> = by_count.iter().map(|(op, n)| format!("{} {}", op, n)).collect();
+    writeln!(out, "  instructions {}: {}", count, mix.join(", ")).unwrap();
+    writeln!(out, "  padding    {} bytes", padding.len()).unwrap();
+    if !prints.is_empty() {
+        let at: Vec<String> = prints.iter().map(|r| format!("{:#x}", r.start)).collect();
+        writeln!(out, "  %s         {} [{}]", prints.len(), at.join(", ")).unwrap();
+    }
+    let unknown_bytes: usize = unknown.iter().map(|r| r.len()).sum();
+    writeln!(out, "  unknown    {} bytes in {} runs", unknown_bytes, unknown.len()).unwrap();
+    for r in unknown.iter().take(LISTED) {
+        let shown = &mem[r.start..r.end.min(r.start + 16)];
+        let why = Instruction::decode(&mem[r.start..end]).err().map_or(String::new(), |e| e.to_string());
+        writeln!(out, "    {:#05x}..{:#05x}  {:18}  {}", r.start, r.end, shown.escape_ascii().to_string(), why).unwrap();
+    }
+    if unknown.len() > LISTED {
+        writeln!(out, "    ... {} more", unknown.len() - LISTED).unwrap();
+    }
+    writeln!(out, "  suspicious {} specifiers", odd.len()).unwrap();
+    for (at, why) in odd.iter().take(LISTED) {
+        let inst = slots.iter().find(|(a, _)| a == at).and_then(|(_, s)| s.inst).unwrap();
+        writeln!(out, "    {:#05x}  {}  {}", at, String::from_utf8_lossy(&inst.encode()), why).unwrap();
+    }
+    if odd.len() > LISTED {
+        writeln!(out, "    ... {} more", odd.len() - LISTED).unwrap();
+    }
+    let clean = unknown.is_empty() && odd.is_empty();
+    out.pop();
+    (out, clean)
+}
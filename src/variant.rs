@@ -0,0 +1,208 @@
+// the constants a patched weather is likely to change, so the solver can be pointed at it without
+// editing the transpiled code. a variant file overrides any of them, the rest stay as shipped:
+//
+//     # goodboy <offset> <word>, the buffer buffer_check compares the first pass against
+//     goodboy 0x8 0x6f57a0a4
+//     # the counter range generate_buffer looks for primes in
+//     primes 0x3390 0x3520
+//     # what stage1 xors stage2 with, it has to come out as the first input byte
+//     key 0x54
+//     # what happens to each input byte, see transform.rs. any steps replace weather's
+//     xor primes
+//     add collatz
+//     mask 0xff
+//
+// how long the input is comes from the image, see check_constants, so a goodboy line can only
+// change one of the words its buffer_check compares
+use crate::decode;
+use crate::ex::{self, State};
+use crate::inst::{parse_num, DestMode, Operation, SrcMode};
+use crate::primes;
+use crate::state::REGS;
+use crate::transform::{self, Step, Transform};
+use std::ops::Range;
+
+// bytes of input, and of the goodboy buffer, in the challenge as shipped
+pub const LEN: usize = 0x1c;
+
+// buffer_check, one `xor` against a constant for each dword of the first pass
+const CHECK: usize = 0x4ee;
+
+// the constants buffer_check in `mem` (stage2 decrypted) compares the first pass against, which is
+// the goodboy buffer. each is built up in a register from immediates right before its `xor`, so
+// following the registers through the function is enough. None if it isn't like that
+pub fn check_constants(mem: &[u8]) -> Option<Vec<u8>> {
+    let body = decode::function(CHECK, mem, REGS)?;
+    let mut regs: [Option<u32>; REGS] = [None; REGS];
+    let mut constants = Vec::new();
+    for (_, inst, _) in body {
+        if inst.op == Operation::Xor && inst.src_mode == SrcMode::L {
+            constants.extend(regs[inst.src as usize]?.to_le_bytes());
+        }
+        // only register writes matter, the constants never go through memory
+        if inst.op == Operation::Ret || inst.dest_mode != DestMode::NoPlusMinus {
+            continue;
+        }
+        let src = match inst.src_mode {
+            SrcMode::LL => Some(inst.src),
+            SrcMode::L => regs[inst.src as usize],
+            _ => None,
+        };
+        let dest = &mut regs[inst.dest as usize];
+        *dest = match (inst.op, *dest, src) {
+            (Operation::Mov, _, src) => src,
+            (Operation::Add, Some(a), Some(b)) => Some(a.wrapping_add(b)),
+            (Operation::Sub, Some(a), Some(b)) => Some(a.wrapping_sub(b)),
+            (Operation::Xor, Some(a), Some(b)) => Some(a ^ b),
+            (Operation::Or, Some(a), Some(b)) => Some(a | b),
+            (Operation::And, Some(a), Some(b)) => Some(a & b),
+            _ => None,
+        };
+    }
+    match constants.is_empty() {
+        true => None,
+        false => Some(constants),
+    }
+}
+
+// bytes of input the program in `mem` (stage2 decrypted) wants. the input loop only stops at the
+// nul, so this comes from the check: four for each dword it compares
+pub fn input_len(mem: &[u8]) -> Option<usize> {
+    check_constants(mem).map(|c| c.len())
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub goodboy: Vec<u8>,
+    pub primes: Range<u32>,
+    pub key: u8,
+    pub steps: Vec<Step>,
+}
+
+impl Default for Variant {
+    // the challenge as shipped
+    fn default() -> Self {
+        let mut s = State::new();
+        s.quiet = true;
+        ex::winning_input(&mut s);
+        Variant {
+            goodboy: s.mem[0x1194..0x1194 + LEN].to_vec(),
+            primes: primes::NUMBERS,
+            key: 0x54,
+            steps: transform::weather(),
+        }
+    }
+}
+
+impl Variant {
+    // the shipped one, but with the goodboy buffer (and so the input length) of the patched
+    // program in `mem`, stage2 decrypted
+    pub fn from_image(mem: &[u8]) -> Result<Self, String> {
+        let goodboy = check_constants(mem).ok_or(format!("can't follow buffer_check at {:#x} to its constants", CHECK))?;
+        Ok(Variant {
+            goodboy,
+            ..Variant::default()
+        })
+    }
+
+    // `base` with what the variant file at `path` changes
+    pub fn load(path: &str, base: Variant) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut variant = base;
+        let mut steps = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            variant
+                .parse_line(line, &mut steps)
+                .map_err(|e| format!("{} line {}: {}", path, i + 1, e))?;
+        }
+        if !steps.is_empty() {
+            variant.steps = steps;
+        }
+        Ok(variant)
+    }
+
+    fn parse_line(&mut self, line: &str, steps: &mut Vec<Step>) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let num = |i: usize| -> Result<u32, String> {
+            let word = words.get(i).ok_or(format!("{} needs {} values", words[0], i))?;
+            parse_num(word).ok_or(format!("bad number {}", word))
+        };
+        match words[0] {
+            "goodboy" => {
+                let offset = num(1)? as usize;
+                if !offset.is_multiple_of(4) || offset + 4 > self.input_len() {
+                    return Err(format!("goodboy offset {:#x} isn't a word in {:#x} bytes", offset, self.input_len()));
+                }
+                self.goodboy[offset..offset + 4].copy_from_slice(&num(2)?.to_le_bytes());
+            }
+            "primes" => self.primes = num(1)?..num(2)?,
+            "key" => {
+                let key = num(1)?;
+                if key > 0xff {
+                    return Err(format!("key {:#x} isn't a byte", key));
+                }
+                self.key = key as u8;
+            }
+            "xor" | "add" | "mask" => steps.push(Step::parse(&words)?),
+            other => return Err(format!("unknown entry {}", other)),
+        }
+        Ok(())
+    }
+
+    // bytes of input, as many as buffer_check compares
+    pub fn input_len(&self) -> usize {
+        self.goodboy.len()
+    }
+
+    // the steps, with the prime table from the prime range
+    pub fn transform(&self) -> Transform {
+        let primes = self.primes.clone().filter(|&n| primes::is_prime(n)).map(|n| n as u8).collect();
+        Transform {
+            steps: self.steps.clone(),
+            primes,
+        }
+    }
+
+    // the forward direction of buffer_check: does `input` leave the goodboy buffer behind
+    pub fn check(&self, input: &[u8]) -> bool {
+        input.len() == self.input_len() && self.transform().forward(input) == self.goodboy
+    }
+
+    // how many leading bytes of a first pass buffer are already the goodboy ones. the steps work a
+    // byte at a time, so this only goes up as an input gets closer
+    pub fn progress(&self, first_pass: &[u8]) -> usize {
+        first_pass.iter().zip(&self.goodboy).take_while(|(a, b)| a == b).count()
+    }
+
+    // the input that passes buffer_check, the goodboy buffer run back through the steps
+    pub fn solve(&self) -> Result<Vec<u8>, String> {
+        let transform = self.transform();
+        if transform.len() < self.input_len() {
+            return Err(format!(
+                "the steps cover {} bytes with {} primes in {:#x}..{:#x}, the check needs {}",
+                transform.len(),
+                transform.primes.len(),
+                self.primes.start,
+                self.primes.end,
+                self.input_len()
+            ));
+        }
+        let input = transform.inverse(&self.goodboy)?;
+
+        // a nul would end the input early, and the first byte is also the stage2 key
+        if let Some(i) = input.iter().position(|&b| b == 0) {
+            return Err(format!("input byte {} would have to be a nul", i));
+        }
+        if input[0] != self.key {
+            return Err(format!(
+                "the input starts with {:#04x} but stage2 is xored with {:#04x}",
+                input[0], self.key
+            ));
+        }
+        Ok(input)
+    }
+}
@@ -0,0 +1,204 @@
+// `disasm verify-check`: ex.rs against the program on the one thing that has to come out right,
+// buffer_check on the winning input. stage2_main used to take its "cheating" branch every time,
+// and this is what tracks a disagreement like that down to the byte:
+//
+//   - the program is interpreted with the check instrumented, every `xor` in it gives the first
+//     pass dword it looked at and the constant it wanted
+//   - ex.rs runs its own transform and check, and buffer_create's constants are what was
+//     transcribed from the listing
+//   - each of the seven dwords is compared across all of those, and each byte that's off is
+//     put down to whatever went into it differently: the prime table byte (or where it was read
+//     from), or the transcribed constant
+//
+// the answer the first time was ex.rs reading the prime table at 0x1338, the program reads it at
+// 0x1388
+use crate::ex::{self, State};
+use crate::inst::{try_parse, Operation, SrcMode};
+use crate::trace::Event;
+use crate::variant::LEN;
+use crate::vm::{OnCall, Vm, VmError, ENTRY};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+const INPUT: i32 = 0x1000;
+// the first pass buffer, which buffer_create fills with the goodboy constants instead
+const FIRST_PASS: usize = 0x1194;
+const TRANSFORM: usize = 0x1f4;
+const CHECK: usize = 0x4ee;
+
+// one side's go at the check
+#[derive(Debug, Default)]
+struct Side {
+    first_pass: Vec<u8>,
+    // the prime table read for each input byte, as (index, low byte)
+    table: Vec<Option<(i32, u8)>>,
+    // what buffer_check returned
+    r0: u32,
+}
+
+// the read after each input byte's, which is the prime table lookup in process_input_byte
+fn table_reads(events: &[Event]) -> Vec<Option<(i32, u8)>> {
+    let mut table = vec![None; LEN];
+    let mut byte: Option<usize> = None;
+    for e in events {
+        if let Event::Read { index, value } = *e {
+            match byte.take() {
+                Some(i) => table[i] = table[i].or(Some((index, value as u8))),
+                None if (INPUT..INPUT + LEN as i32).contains(&index) => byte = Some((index - INPUT) as usize),
+                None => {}
+            }
+        }
+    }
+    table
+}
+
+// ex.rs, and the constants its buffer_create has
+fn transpiled(input: &[u8]) -> (Side, Vec<u8>) {
+    let mut s = State::new();
+    s.quiet = true;
+    ex::winning_input(&mut s);
+    let transcribed = s.mem[FIRST_PASS..FIRST_PASS + LEN].to_vec();
+
+    s.write_bytes(INPUT as usize, input);
+    s.trace = Some(Vec::new());
+    s.regs[0] = 0;
+    ex::read_input_byte(&mut s);
+    let first_pass = s.mem[FIRST_PASS..FIRST_PASS + LEN].to_vec();
+    ex::buffer_check(&mut s);
+    let side = Side {
+        first_pass,
+        table: table_reads(s.trace.as_deref().unwrap_or_default()),
+        r0: s.regs[0] as u32,
+    };
+    (side, transcribed)
+}
+
+// the program from the start, and the constants its check compares against
+fn interpreted(input: &[u8]) -> Result<(Side, Vec<u8>), VmError> {
+    let mut s: State = State::with_input(input);
+    s.quiet = true;
+    s.trace = Some(Vec::new());
+    let mut vm = Vm::new(s);
+    vm.pc = ENTRY;
+
+    let checking = Rc::new(RefCell::new((false, None)));
+    let (entered, left) = (checking.clone(), checking.clone());
+    vm.on_call(move |_, addr| {
+        if addr == CHECK {
+            entered.borrow_mut().0 = true;
+        }
+        OnCall::Enter
+    });
+    vm.on_return(move |s, addr| {
+        if addr == CHECK {
+            *left.borrow_mut() = (false, Some(s.regs[0] as u32));
+        }
+    });
+
+    let mut first_pass = Vec::new();
+    let mut constants = Vec::new();
+    let mut transform = None;
+    while !vm.halted {
+        if vm.pc == TRANSFORM && transform.is_none() {
+            transform = vm.s.trace.as_ref().map(Vec::len);
+        }
+        if checking.borrow().0 {
+            if let Some((inst, _)) = vm.s.mem.get(vm.pc..).and_then(try_parse) {
+                if inst.op == Operation::Xor && inst.src_mode == SrcMode::L {
+                    first_pass.extend((vm.s.regs[inst.dest as usize] as u32).to_le_bytes());
+                    constants.extend((vm.s.regs[inst.src as usize] as u32).to_le_bytes());
+                }
+            }
+        }
+        vm.step()?;
+    }
+
+    let events = vm.s.trace.as_deref().unwrap_or_default();
+    let side = Side {
+        first_pass,
+        table: table_reads(&events[transform.unwrap_or(events.len())..]),
+        r0: checking.borrow().1.unwrap_or(u32::MAX),
+    };
+    Ok((side, constants))
+}
+
+fn dword(bytes: &[u8], i: usize) -> String {
+    match bytes.get(i * 4..i * 4 + 4) {
+        Some(b) => format!("{:#010x}", u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => "-".to_string(),
+    }
+}
+
+// why byte `i` of ex.rs's first pass or constants isn't the program's
+fn cause(i: usize, ex: &Side, program: &Side, transcribed: &[u8], expected: &[u8]) -> Vec<String> {
+    let mut causes = Vec::new();
+    if ex.first_pass.get(i) != program.first_pass.get(i) {
+        match (ex.table[i], program.table[i]) {
+            (Some((at, _)), Some((want, _))) if at != want => causes.push(format!(
+                "ex.rs reads the prime table at {:#x} + 2i, the program at {:#x} + 2i",
+                at - 2 * i as i32,
+                want - 2 * i as i32
+            )),
+            (Some((_, got)), Some((_, want))) if got != want => {
+                causes.push(format!("the prime table byte is {:#04x} in ex.rs, {:#04x} in the program", got, want))
+            }
+            _ => causes.push("the same prime table byte, so the rest of the transform differs".to_string()),
+        }
+    }
+    if transcribed.get(i) != expected.get(i) {
+        causes.push("buffer_create's constant isn't the one buffer_check compares against".to_string());
+    }
+    causes
+}
+
+// the report, and whether ex.rs agrees with the program
+pub fn run() -> Result<(String, bool), String> {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    let input = ex::winning_input(&mut scratch);
+    let (ex, transcribed) = transpiled(&input);
+    let (program, expected) = interpreted(&input).map_err(|e| format!("the program faulted: {}", e))?;
+    if expected.len() != LEN {
+        return Err(format!("buffer_check compared {} dwords, expected {}", expected.len() / 4, LEN / 4));
+    }
+
+    let mut out = String::new();
+    writeln!(out, "buffer_check on {}", input.escape_ascii()).unwrap();
+    writeln!(out, "{:8} {:14} {:14} {:14} {:14}", "offset", "expected", "buffer_create", "ex.rs pass", "program pass").unwrap();
+    for i in 0..LEN / 4 {
+        let row = [dword(&expected, i), dword(&transcribed, i), dword(&ex.first_pass, i), dword(&program.first_pass, i)];
+        let ok = row.iter().all(|d| *d == row[0]);
+        writeln!(out, "{:<8} {:14} {:14} {:14} {:14} {}", format!("{:#04x}", i * 4), row[0], row[1], row[2], row[3], if ok { "ok" } else { "differs" }).unwrap();
+    }
+    writeln!(out, "returns  ex.rs {:#x}, program {:#x}", ex.r0, program.r0).unwrap();
+
+    // runs of bytes with the same causes
+    let mut causes: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    for i in 0..LEN {
+        let why = cause(i, &ex, &program, &transcribed, &expected);
+        match causes.last_mut() {
+            Some((_, end, last)) if *end == i && *last == why => *end = i + 1,
+            _ => causes.push((i, i + 1, why)),
+        }
+    }
+    causes.retain(|(_, _, why)| !why.is_empty());
+
+    let agree = causes.is_empty() && ex.r0 == 0 && program.r0 == 0;
+    if agree {
+        writeln!(out, "ok: ex.rs and the program agree, buffer_check passes").unwrap();
+    } else if causes.is_empty() {
+        writeln!(out, "ex.rs and the program agree, but buffer_check doesn't pass in either").unwrap();
+    }
+    for (start, end, why) in &causes {
+        let bytes = match end - start {
+            1 => format!("byte {:#04x}", start),
+            _ => format!("bytes {:#04x}..{:#04x}", start, end - 1),
+        };
+        for why in why {
+            writeln!(out, "root cause, {}: {}", bytes, why).unwrap();
+        }
+    }
+    out.pop();
+    Ok((out, agree))
+}
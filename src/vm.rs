@@ -0,0 +1,572 @@
+// generic interpreter for the printf vm. instead of transpiling each function by hand like ex.rs,
+// this decodes the format string at the program counter and executes it directly
+use crate::decode::{Cache, Decoded, Slot};
+use crate::expr::Assertion;
+use crate::inst::{DestMode, Inline, Instruction, Operation, SrcMode, Width};
+use crate::layout::{Access, Protection};
+use crate::state::{Console, State};
+use crate::trace::Event;
+use crate::word::Word;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+// the flag formatter starts everything with "%52C"
+pub const ENTRY: usize = 0x34;
+
+// the binary recurses on the host stack for every call, this is far deeper than anything the
+// challenge needs but stops runaway recursion from eating all memory
+pub(crate) const MAX_DEPTH: usize = 0x10000;
+
+// frames shown when an assertion fails, the prime sieve recurses a long way
+const BACKTRACE: usize = 16;
+
+// things that would crash (or worse) the real binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    // memory index past the end of State::mem
+    OutOfBounds(usize),
+    // the rest carry the offset of the faulting instruction
+    DivideByZero(usize),
+    StackOverflow(usize),
+    BadOperand(usize),
+    // bytes the decode cache couldn't make an instruction out of
+    BadInstruction(usize),
+    // a store into memory that has already been executed, with WxMode::Fault
+    CodeWrite(usize),
+    // a user assertion that didn't hold before the instruction at pc, by index
+    AssertionFailed {
+        pc: usize,
+        index: usize,
+    },
+    // an access the memory layout doesn't allow
+    ProtectionFault {
+        pc: usize,
+        index: usize,
+        access: Access,
+    },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::OutOfBounds(i) => write!(f, "memory access out of bounds at index {:#x}", i),
+            VmError::DivideByZero(pc) => write!(f, "divide by zero at {:#x}", pc),
+            VmError::StackOverflow(pc) => write!(f, "call stack overflow at {:#x}", pc),
+            VmError::BadOperand(pc) => write!(f, "bad operand at {:#x}", pc),
+            VmError::BadInstruction(pc) => write!(f, "no instruction decodes at {:#x}", pc),
+            VmError::CodeWrite(pc) => write!(f, "write into executed code at {:#x}", pc),
+            VmError::AssertionFailed { pc, index } => {
+                write!(f, "assertion {} failed at {:#x}", index, pc)
+            }
+            VmError::ProtectionFault { pc, index, access } => {
+                write!(f, "protection fault: {} of {:#x} at {:#x}", access, index, pc)
+            }
+        }
+    }
+}
+
+pub struct Vm<R = i32> {
+    pub s: State<R>,
+    // offset of the next specifier to decode
+    pub pc: usize,
+    // every %C is really a call (fprintf recursing), so keep the return addresses around
+    pub stack: Vec<usize>,
+    // set once the outermost fprintf hits its nul terminator
+    pub halted: bool,
+    // instructions executed so far
+    pub steps: u64,
+    // decode cache, kept up to date as the program writes over its own code
+    pub code: Option<Decoded>,
+    // instructions that have run, by offset. anything writing to s.mem behind the vm's back has
+    // to call invalidate
+    pub cache: Cache,
+    pub wx: Wx,
+    // memory permissions, when they're being enforced
+    pub protection: Option<Protection>,
+    // invariants checked before each instruction
+    pub asserts: Vec<Assertion>,
+    pub hooks: Hooks<R>,
+    // which way the next conditional call goes, whatever its register says. for the explorer
+    pub forced: Option<bool>,
+}
+
+// what an on_call hook wants done with the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnCall {
+    Enter,
+    // the hook did the function's work itself, carry on as if it had returned
+    Skip,
+}
+
+type CallHook<R> = Box<dyn FnMut(&mut State<R>, usize) -> OnCall>;
+type ReturnHook<R> = Box<dyn FnMut(&mut State<R>, usize)>;
+
+// the user's code, run as functions are entered and left. the jit doesn't stop at calls, so it
+// hands a hooked vm to the interpreter
+pub struct Hooks<R> {
+    on_call: Vec<CallHook<R>>,
+    on_return: Vec<ReturnHook<R>>,
+    // the function each hooked frame is in, innermost last
+    frames: Vec<usize>,
+}
+
+impl<R> Default for Hooks<R> {
+    fn default() -> Self {
+        Hooks {
+            on_call: Vec::new(),
+            on_return: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl<R> Hooks<R> {
+    pub fn is_empty(&self) -> bool {
+        self.on_call.is_empty() && self.on_return.is_empty()
+    }
+
+    // every on_call hook in the order they were added, up to the first that skips
+    pub(crate) fn call(&mut self, s: &mut State<R>, addr: usize) -> OnCall {
+        for hook in &mut self.on_call {
+            if hook(s, addr) == OnCall::Skip {
+                return OnCall::Skip;
+            }
+        }
+        self.frames.push(addr);
+        OnCall::Enter
+    }
+
+    // the stack is `depth` deep after a return, so any frame past that has been left
+    pub(crate) fn ret(&mut self, s: &mut State<R>, depth: usize) {
+        while self.frames.len() > depth {
+            let addr = self.frames.pop().unwrap();
+            for hook in &mut self.on_return {
+                hook(s, addr);
+            }
+        }
+    }
+}
+
+// what to do when code and data mix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WxMode {
+    #[default]
+    Off,
+    Warn,
+    // warn on running written memory, but stop on writes into code that has run
+    Fault,
+    // just the bookkeeping, no warnings, for unpack to find where each layer starts
+    Track,
+}
+
+impl core::str::FromStr for WxMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "off" => Ok(WxMode::Off),
+            "warn" => Ok(WxMode::Warn),
+            "fault" => Ok(WxMode::Fault),
+            _ => Err(format!("bad wx mode {}, expected off, warn or fault", s)),
+        }
+    }
+}
+
+// W^X style bookkeeping: which bytes have run, and which were written and from where. writing
+// into code that already ran is almost always a mistake, running written bytes is how stage1
+// hands over to the stage2 it just decrypted
+#[derive(Debug, Clone, Default)]
+pub struct Wx {
+    pub mode: WxMode,
+    executed: BTreeSet<usize>,
+    // index -> pc of the store
+    written: BTreeMap<usize, usize>,
+    // only warn once per stretch of execution in written memory
+    in_written: bool,
+}
+
+impl Wx {
+    fn execute(&mut self, pc: usize, next: usize, console: Console) {
+        match self.written.get(&pc) {
+            Some(writer) if !self.in_written => {
+                if self.mode != WxMode::Track {
+                    console.line(format_args!("wx: executing {:#x}, which was written by {:#x}", pc, writer));
+                }
+                self.in_written = true;
+            }
+            Some(_) => {}
+            None => self.in_written = false,
+        }
+        self.executed.extend(pc..next);
+    }
+
+    fn write(&mut self, pc: usize, index: usize, len: usize, console: Console) -> Result<(), VmError> {
+        if self.mode != WxMode::Track && (index..index.saturating_add(len)).any(|i| self.executed.contains(&i)) {
+            console.line(format_args!("wx: {:#x} writes to {:#x}, which has already been executed", pc, index));
+            if self.mode == WxMode::Fault {
+                return Err(VmError::CodeWrite(pc));
+            }
+        }
+        for i in index..index.saturating_add(len) {
+            self.written.insert(i, pc);
+        }
+        Ok(())
+    }
+
+    pub fn executed(&self, index: usize) -> bool {
+        self.executed.contains(&index)
+    }
+
+    // pc of the last store to `index`
+    pub fn writer(&self, index: usize) -> Option<usize> {
+        self.written.get(&index).copied()
+    }
+}
+
+impl<R: Word> Vm<R> {
+    pub fn new(s: State<R>) -> Self {
+        Self {
+            s,
+            pc: 0,
+            stack: Vec::new(),
+            halted: false,
+            steps: 0,
+            code: None,
+            cache: Cache::default(),
+            wx: Wx::default(),
+            protection: None,
+            asserts: Vec::new(),
+            hooks: Hooks::default(),
+            forced: None,
+        }
+    }
+
+    // a second machine where this one is, to go its own way from here. memory is shared until
+    // either of them stores to it, everything else is copied. hooks are closures and can't be, so
+    // the fork has none
+    pub fn fork(&mut self) -> Self {
+        let mem = self.s.mem.share();
+        let s = State {
+            regs: self.s.regs.clone(),
+            mem,
+            mem_cap: self.s.mem_cap,
+            guarded: self.s.guarded,
+            quiet: self.s.quiet,
+            progress: self.s.progress,
+            fault: self.s.fault,
+            trace: self.s.trace.clone(),
+            endian: self.s.endian,
+            console: self.s.console,
+        };
+        Self {
+            s,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            halted: self.halted,
+            steps: self.steps,
+            code: self.code.clone(),
+            cache: self.cache.clone(),
+            wx: self.wx.clone(),
+            protection: self.protection.clone(),
+            asserts: self.asserts.clone(),
+            hooks: Hooks::default(),
+            forced: self.forced,
+        }
+    }
+
+    // `hook` gets the state and the target before every call is taken, and can do the function's
+    // work itself and skip it. registers at entry are the arguments
+    pub fn on_call(&mut self, hook: impl FnMut(&mut State<R>, usize) -> OnCall + 'static) {
+        self.hooks.on_call.push(Box::new(hook));
+    }
+
+    // run `f` instead of the function at `addr`, every time it's called
+    pub fn stub(&mut self, addr: usize, mut f: impl FnMut(&mut State<R>) + 'static) {
+        self.on_call(move |s, at| match at == addr {
+            true => {
+                f(s);
+                OnCall::Skip
+            }
+            false => OnCall::Enter,
+        });
+    }
+
+    // `hook` gets the state and the function's address as each function returns
+    pub fn on_return(&mut self, hook: impl FnMut(&mut State<R>, usize) + 'static) {
+        self.hooks.on_return.push(Box::new(hook));
+    }
+
+    // a machine for a format_vm! program: its text as the only memory, and every instruction
+    // already in the decode cache. pc is on the first one
+    pub fn inline(program: &Inline) -> Self {
+        let mut vm = Vm::new(State::from_image(program.text));
+        for &(at, inst, len) in program.insts {
+            vm.cache.insert(at, inst, len);
+        }
+        vm
+    }
+
+    // run stage1 on `s`, stopping right before it jumps into the freshly decrypted stage2. pc is
+    // left on stage2's first instruction so `run_until` and `call` can pick up from there
+    pub fn boot(s: State<R>) -> Result<Self, VmError> {
+        let mut vm = Vm::new(s);
+        vm.run_stage1()?;
+        Ok(vm)
+    }
+
+    // the part of `boot` after the machine exists, for when it needs setting up first
+    pub fn run_stage1(&mut self) -> Result<(), VmError> {
+        self.pc = ENTRY;
+        self.run_until(0xb2)?;
+        self.pc = 0xc8;
+        Ok(())
+    }
+
+    // decode and execute a single instruction
+    pub fn step(&mut self) -> Result<(), VmError> {
+        let pc = self.pc;
+        if pc >= self.s.mem.len() {
+            return Err(VmError::OutOfBounds(pc));
+        }
+        if !self.asserts.is_empty() {
+            self.check_asserts()?;
+        }
+        self.record(Event::Step { pc });
+        self.steps += 1;
+        let (inst, next) = match self.code.as_ref().and_then(|code| code.at(pc)) {
+            Some(&Slot {
+                inst: Some(inst),
+                len,
+            }) => (inst, pc + len),
+            Some(Slot { inst: None, .. }) => return Err(VmError::BadInstruction(pc)),
+            _ => match self.cache.get(pc) {
+                Some((inst, len)) => (inst, pc + len),
+                None => {
+                    let (inst, rest) = Instruction::checked(&self.s.mem[pc..]).ok_or(VmError::BadInstruction(pc))?;
+                    let len = self.s.mem.len() - pc - rest.len();
+                    self.cache.insert(pc, inst, len);
+                    (inst, pc + len)
+                }
+            },
+        };
+        if self.wx.mode != WxMode::Off {
+            self.wx.execute(pc, next, self.s.console);
+        }
+        self.protect(pc, pc, next - pc, Access::Execute)?;
+
+        match inst.op {
+            Operation::Ret => {
+                let ret = self.stack.pop();
+                if !self.hooks.is_empty() {
+                    self.hooks.ret(&mut self.s, self.stack.len());
+                }
+                self.record(Event::Return { to: ret });
+                match ret {
+                    Some(ret) => self.pc = ret,
+                    None => self.halted = true,
+                }
+            }
+            Operation::Jmp => {
+                let cond = self.reg(inst.src)?;
+                let taken = match inst.dest_mode {
+                    DestMode::NoPlusMinus => true,
+                    _ if self.forced.is_some() => self.forced.take().unwrap(),
+                    DestMode::Minus => cond < R::default(),
+                    DestMode::Plus => cond > R::default(),
+                    DestMode::ZeroPad => cond == R::default(),
+                };
+                let skipped = taken && !self.hooks.is_empty() && self.hooks.call(&mut self.s, inst.dest as usize) == OnCall::Skip;
+                if taken && !skipped {
+                    if self.stack.len() >= MAX_DEPTH {
+                        return Err(VmError::StackOverflow(pc));
+                    }
+                    self.record(Event::Call {
+                        from: pc,
+                        to: inst.dest as usize,
+                    });
+                    self.stack.push(next);
+                    self.pc = inst.dest as usize;
+                } else {
+                    self.pc = next;
+                }
+            }
+            op => {
+                // a plain access is a whole register, whatever size those are
+                let width = match inst.width {
+                    Width::W32 => R::WIDTH,
+                    width => width,
+                };
+                // bytes in each memory access
+                let n = width.bytes();
+                let src = match inst.src_mode {
+                    SrcMode::HH => {
+                        let addr = R::from_imm(inst.src);
+                        self.protect(pc, addr.index(), n, Access::Read)?;
+                        self.s.read_width(addr, width)
+                    }
+                    SrcMode::H => {
+                        let addr = self.reg(inst.src)?;
+                        self.protect(pc, addr.index(), n, Access::Read)?;
+                        self.s.read_width(addr, width)
+                    }
+                    SrcMode::L => self.reg(inst.src)?,
+                    SrcMode::LL => R::from_imm(inst.src),
+                    SrcMode::None => return Err(VmError::BadOperand(pc)),
+                };
+
+                match inst.dest_mode {
+                    DestMode::NoPlusMinus => {
+                        let dest = self.reg(inst.dest)?;
+                        *self.s.reg_mut(inst.dest) = apply(op, dest, src, pc)?;
+                    }
+                    DestMode::Minus | DestMode::Plus => {
+                        let addr = match inst.dest_mode {
+                            DestMode::Minus => R::from_imm(inst.dest),
+                            _ => self.reg(inst.dest)?,
+                        };
+                        if self.wx.mode != WxMode::Off {
+                            self.wx.write(pc, addr.index(), n, self.s.console)?;
+                        }
+                        // mov doesn't need the old value, and reading it would add noise to the log
+                        let val = match op {
+                            Operation::Mov => src,
+                            _ => {
+                                self.protect(pc, addr.index(), n, Access::Read)?;
+                                let dest = self.s.read_width(addr, width);
+                                apply(op, dest, src, pc)?
+                            }
+                        };
+                        self.protect(pc, addr.index(), n, Access::Write)?;
+                        self.s.store_width(addr, val, width);
+                        self.invalidate(addr.index(), n);
+                    }
+                    DestMode::ZeroPad => return Err(VmError::BadOperand(pc)),
+                }
+                self.pc = next;
+            }
+        }
+
+        // memory faults are recorded on the state since the transpiled code shares it
+        match self.s.fault {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // `len` bytes at `index` were written, drop anything decoded from them
+    pub fn invalidate(&mut self, index: usize, len: usize) {
+        if let Some(code) = &mut self.code {
+            code.update(&self.s.mem, index, len);
+        }
+        self.cache.invalidate(index, len);
+    }
+
+    // the failing assertion is reported here, the error only carries its index
+    fn check_asserts(&self) -> Result<(), VmError> {
+        let pc = self.pc;
+        for (index, assert) in self.asserts.iter().enumerate() {
+            if assert.at.is_some_and(|at| at != pc) {
+                continue;
+            }
+            if let Some(why) = assert.check(&self.s) {
+                let console = self.s.console;
+                console.line(format_args!("assertion failed before {:#x}: {}", pc, why));
+                let frames = self.backtrace();
+                for (i, frame) in frames.iter().enumerate().take(BACKTRACE) {
+                    console.line(format_args!("  #{} {:#05x}", i, frame));
+                }
+                if frames.len() > BACKTRACE {
+                    console.line(format_args!("  ... {} more", frames.len() - BACKTRACE));
+                }
+                return Err(VmError::AssertionFailed { pc, index });
+            }
+        }
+        Ok(())
+    }
+
+    // where execution is, then where each function on the stack returns to, innermost first
+    pub fn backtrace(&self) -> Vec<usize> {
+        let mut frames = vec![self.pc];
+        frames.extend(self.stack.iter().rev());
+        frames
+    }
+
+    fn protect(&mut self, pc: usize, index: usize, len: usize, access: Access) -> Result<(), VmError> {
+        match &mut self.protection {
+            Some(protection) => protection.check(pc, index, len, access),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn record(&mut self, e: Event) {
+        if let Some(trace) = &mut self.s.trace {
+            trace.push(e);
+        }
+    }
+
+    // register numbers come straight from the width/precision, so they can be anything
+    pub(crate) fn reg(&self, n: u32) -> Result<R, VmError> {
+        match self.s.regs.get(n as usize) {
+            Some(&r) => Ok(r),
+            None => Err(VmError::BadOperand(self.pc)),
+        }
+    }
+
+    // run a single vm function to completion, like a %C that is always taken
+    pub fn call(&mut self, addr: usize) -> Result<(), VmError> {
+        let depth = self.stack.len();
+        if !self.hooks.is_empty() && self.hooks.call(&mut self.s, addr) == OnCall::Skip {
+            return Ok(());
+        }
+        self.stack.push(self.pc);
+        self.pc = addr;
+        while self.stack.len() > depth && !self.halted {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // run until the outermost function returns
+    pub fn run(&mut self) -> Result<(), VmError> {
+        while !self.halted {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // execute until the program counter reaches `stop` in the current function
+    pub fn run_until(&mut self, stop: usize) -> Result<(), VmError> {
+        let depth = self.stack.len();
+        while (self.pc != stop || self.stack.len() != depth) && !self.halted {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
+
+// arithmetic matches the C handlers in the binary: wrapping int math, idiv, and sar
+pub(crate) fn apply<R: Word>(op: Operation, dest: R, src: R, pc: usize) -> Result<R, VmError> {
+    if src == R::default() && matches!(op, Operation::Div | Operation::Mod) {
+        return Err(VmError::DivideByZero(pc));
+    }
+
+    Ok(match op {
+        Operation::Mov => src,
+        Operation::Add => dest.wrapping_add(src),
+        Operation::Sub => dest.wrapping_sub(src),
+        Operation::Mul => dest.wrapping_mul(src),
+        Operation::Div => dest.wrapping_div(src),
+        Operation::Mod => dest.wrapping_rem(src),
+        Operation::ShLeft => dest.wrapping_shl(src),
+        Operation::ShRight => dest.wrapping_shr(src),
+        Operation::Xor => dest.xor(src),
+        Operation::And => dest.and(src),
+        Operation::Or => dest.or(src),
+        Operation::Jmp | Operation::Ret => unreachable!(),
+    })
+}
@@ -0,0 +1,113 @@
+// the browser api, for web/index.html: paste a format string, read its listing, and step through
+// it watching the registers. built with
+//
+//     wasm-pack build --target web -- --features wasm
+//
+// which leaves pkg/ next to web/. the jit, rhai and mmap aren't in a wasm32 build, everything
+// else is the same interpreter the cli uses
+use crate::ex::{self, State};
+use crate::inst::try_parse;
+use crate::listing::{Columns, ListingWriter};
+use crate::project::Project;
+use crate::vm::Vm;
+use wasm_bindgen::prelude::*;
+
+// what --inline does with the text: `\0` for a nul, and one more at the end
+fn image(text: &str) -> Vec<u8> {
+    let mut code = text.replace("\\0", "\0").into_bytes();
+    code.push(0);
+    code
+}
+
+// the listing of `text`, like `disasm disasm --inline`
+#[wasm_bindgen]
+pub fn disassemble(text: &str) -> String {
+    let image = image(text);
+    let project = Project::default();
+    let mut writer = ListingWriter::new(&project, Columns::default(), image.len());
+    writer.sweep(&image, 0..image.len());
+    writer.finish()
+}
+
+#[wasm_bindgen]
+pub struct Machine {
+    vm: Vm,
+}
+
+#[wasm_bindgen]
+impl Machine {
+    // `text` as the only memory, pc on its first instruction
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &str) -> Machine {
+        let mut s = State::from_image(&image(text));
+        s.quiet = true;
+        Machine { vm: Vm::new(s) }
+    }
+
+    // the challenge itself on `input`, booted through stage1
+    pub fn weather(input: &str) -> Result<Machine, String> {
+        let mut s = State::with_input(input.as_bytes());
+        s.quiet = true;
+        let vm = Vm::boot(s).map_err(|e| e.to_string())?;
+        Ok(Machine { vm })
+    }
+
+    // the input that solves it
+    pub fn winning_input() -> String {
+        let mut s = State::new();
+        s.quiet = true;
+        String::from_utf8_lossy(&ex::winning_input(&mut s)).into_owned()
+    }
+
+    // one instruction, false once the program is done
+    pub fn step(&mut self) -> Result<bool, String> {
+        if !self.vm.halted {
+            self.vm.step().map_err(|e| e.to_string())?;
+        }
+        Ok(!self.vm.halted)
+    }
+
+    // up to `max_steps` instructions, so a page can run a long program in slices
+    pub fn run(&mut self, max_steps: u32) -> Result<bool, String> {
+        for _ in 0..max_steps {
+            if !self.step()? {
+                break;
+            }
+        }
+        Ok(!self.vm.halted)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.vm.pc
+    }
+
+    pub fn steps(&self) -> f64 {
+        self.vm.steps as f64
+    }
+
+    pub fn halted(&self) -> bool {
+        self.vm.halted
+    }
+
+    pub fn depth(&self) -> usize {
+        self.vm.stack.len()
+    }
+
+    pub fn regs(&self) -> Vec<i32> {
+        self.vm.s.regs.clone()
+    }
+
+    // `len` bytes at `addr`, short if that runs off the end
+    pub fn mem(&self, addr: usize, len: usize) -> Vec<u8> {
+        let mem = &self.vm.s.mem;
+        mem[addr.min(mem.len())..addr.saturating_add(len).min(mem.len())].to_vec()
+    }
+
+    // the instruction at pc
+    pub fn current(&self) -> String {
+        match self.vm.s.mem.get(self.vm.pc..).and_then(try_parse) {
+            Some((inst, _)) => inst.to_string(),
+            None => "??".to_string(),
+        }
+    }
+}
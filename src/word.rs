@@ -0,0 +1,118 @@
+// the register word. the challenge is a 32 bit machine, State and Vm are generic over this so the
+// same engine can run a 64 bit flavour of the vm, with 8 byte registers and memory words
+use crate::inst::Width;
+use core::fmt::{Debug, Display, LowerHex};
+
+pub trait Word: Copy + Default + Debug + Display + LowerHex + Ord + 'static {
+    const BYTES: usize;
+    // what a plain (no leading zeros) memory access moves
+    const WIDTH: Width;
+
+    // widths and precisions are C ints, so immediates sign extend
+    fn from_imm(imm: u32) -> Self;
+    // little endian, zero extended when there are fewer than BYTES
+    fn from_le(bytes: &[u8]) -> Self;
+    fn to_le(self) -> [u8; 8];
+    // as a memory index, reinterpreted as unsigned
+    fn index(self) -> usize;
+    // the low 32 bits, for things like traces that only know about the 32 bit machine
+    fn low(self) -> i32;
+    // sign extended, for the expression evaluator
+    fn wide(self) -> i64;
+
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn wrapping_div(self, rhs: Self) -> Self;
+    fn wrapping_rem(self, rhs: Self) -> Self;
+    fn wrapping_shl(self, rhs: Self) -> Self;
+    fn wrapping_shr(self, rhs: Self) -> Self;
+    fn xor(self, rhs: Self) -> Self;
+    fn and(self, rhs: Self) -> Self;
+    fn or(self, rhs: Self) -> Self;
+
+    // keep the low `bytes` bytes
+    fn truncate(self, bytes: usize) -> Self {
+        Self::from_le(&self.to_le()[..bytes])
+    }
+}
+
+macro_rules! word {
+    ($t:ty, $u:ty, $width:expr) => {
+        impl Word for $t {
+            const BYTES: usize = core::mem::size_of::<$t>();
+            const WIDTH: Width = $width;
+
+            fn from_imm(imm: u32) -> Self {
+                imm as i32 as $t
+            }
+
+            fn from_le(bytes: &[u8]) -> Self {
+                let mut buf = [0; core::mem::size_of::<$t>()];
+                buf[..bytes.len()].copy_from_slice(bytes);
+                <$t>::from_le_bytes(buf)
+            }
+
+            fn to_le(self) -> [u8; 8] {
+                let mut out = [0; 8];
+                out[..Self::BYTES].copy_from_slice(&self.to_le_bytes());
+                out
+            }
+
+            fn index(self) -> usize {
+                self as $u as usize
+            }
+
+            fn low(self) -> i32 {
+                self as i32
+            }
+
+            fn wide(self) -> i64 {
+                self as i64
+            }
+
+            fn wrapping_add(self, rhs: Self) -> Self {
+                <$t>::wrapping_add(self, rhs)
+            }
+
+            fn wrapping_sub(self, rhs: Self) -> Self {
+                <$t>::wrapping_sub(self, rhs)
+            }
+
+            fn wrapping_mul(self, rhs: Self) -> Self {
+                <$t>::wrapping_mul(self, rhs)
+            }
+
+            fn wrapping_div(self, rhs: Self) -> Self {
+                <$t>::wrapping_div(self, rhs)
+            }
+
+            fn wrapping_rem(self, rhs: Self) -> Self {
+                <$t>::wrapping_rem(self, rhs)
+            }
+
+            fn wrapping_shl(self, rhs: Self) -> Self {
+                <$t>::wrapping_shl(self, rhs as u32)
+            }
+
+            fn wrapping_shr(self, rhs: Self) -> Self {
+                <$t>::wrapping_shr(self, rhs as u32)
+            }
+
+            fn xor(self, rhs: Self) -> Self {
+                self ^ rhs
+            }
+
+            fn and(self, rhs: Self) -> Self {
+                self & rhs
+            }
+
+            fn or(self, rhs: Self) -> Self {
+                self | rhs
+            }
+        }
+    };
+}
+
+word!(i32, u32, Width::W32);
+word!(i64, u64, Width::W64);
@@ -0,0 +1,25 @@
+// ex.rs's stage2_main against the interpreter, stage by stage like `disasm diff`. a wrong input
+// is what catches a flag output that doesn't wait for the check, like the "cheating" branch
+// stage2_main used to take every time
+use disasm::diff;
+use disasm::ex::{self, State};
+
+fn winning() -> Vec<u8> {
+    let mut scratch = State::new();
+    scratch.quiet = true;
+    ex::winning_input(&mut scratch)
+}
+
+#[test]
+fn winning_input() {
+    assert!(diff::agree(&winning()));
+}
+
+#[test]
+fn wrong_input() {
+    // the first byte decrypts stage2, so it stays
+    let mut input = winning();
+    let last = input.len() - 1;
+    input[last] ^= 1;
+    assert!(diff::agree(&input));
+}
@@ -0,0 +1,240 @@
+// round trips between Instruction::decode and Instruction::encode. these pin down the encoding
+// rules, like "0." meaning register 0 instead of the zero pad flag, and the tables after them pin
+// down how printf's own spellings decode
+use disasm::inst::{decrypted_image, DestMode, Instruction, Operation, ParseError, SrcMode, Width, Widths, MAX_OPERAND};
+use proptest::prelude::*;
+
+const DEST_MODES: [DestMode; 4] = [DestMode::NoPlusMinus, DestMode::Plus, DestMode::Minus, DestMode::ZeroPad];
+const SRC_MODES: [SrcMode; 5] = [SrcMode::HH, SrcMode::H, SrcMode::LL, SrcMode::L, SrcMode::None];
+const WIDTHS: [Width; 3] = [Width::W8, Width::W16, Width::W32];
+const OPS: [Operation; 12] = [
+    Operation::Jmp,
+    Operation::Mov,
+    Operation::Add,
+    Operation::Sub,
+    Operation::Mul,
+    Operation::Div,
+    Operation::Mod,
+    Operation::ShLeft,
+    Operation::ShRight,
+    Operation::Xor,
+    Operation::And,
+    Operation::Or,
+];
+
+// ret is just the nul terminator, so it always parses to the same fields
+const RET: Instruction = Instruction {
+    dest: 0,
+    src: 0,
+    dest_mode: DestMode::Minus,
+    src_mode: SrcMode::LL,
+    op: Operation::Ret,
+    width: Width::W32,
+};
+
+// mostly small register numbers, but 0 and the big immediates too, up to what printf reads
+fn operand() -> impl Strategy<Value = u32> {
+    prop_oneof![Just(0), 0..5u32, 0..0x2000u32, 0..=MAX_OPERAND]
+}
+
+fn instruction() -> impl Strategy<Value = Instruction> {
+    let inst = (operand(), operand(), 0..4usize, 0..5usize, 0..12usize, 0..3usize).prop_map(|(dest, src, d, s, op, w)| Instruction {
+        dest,
+        src,
+        dest_mode: DEST_MODES[d],
+        src_mode: SRC_MODES[s],
+        op: OPS[op],
+        width: WIDTHS[w],
+    });
+    prop_oneof![1 => Just(RET), 12 => inst]
+}
+
+// bytes that look like specifiers more often than not
+fn specifier() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![
+        Just(b'%'),
+        Just(b'.'),
+        prop::sample::select(b"-+ #0".to_vec()),
+        prop::sample::select(b"0123456789".to_vec()),
+        prop::sample::select(b"hljztLq".to_vec()),
+        prop::sample::select(b"CMSOXVNLREIUs".to_vec()),
+        any::<u8>(),
+    ];
+    (prop::collection::vec(byte, 0..24)).prop_map(|mut bytes| {
+        bytes.insert(0, b'%');
+        bytes
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10_000))]
+
+    // narrow widths are spelled the Zeros way, so that's how they have to be read back
+    #[test]
+    fn decode_inverts_encode(inst in instruction()) {
+        let bytes = inst.encode();
+        let (decoded, rest) = Instruction::decode_with(&bytes, Widths::Zeros).unwrap();
+        prop_assert_eq!(decoded, inst, "encoded as {:?}", String::from_utf8_lossy(&bytes));
+        prop_assert!(rest.is_empty());
+        prop_assert_eq!(decoded.encode(), bytes);
+        // shown, even when the vm would fault on it
+        let _ = decoded.to_string();
+    }
+
+    // the way the binary reads them, every access is a whole register
+    #[test]
+    fn printf_ignores_zeros(inst in instruction()) {
+        let (decoded, _) = Instruction::decode_with(&inst.encode(), Widths::Printf).unwrap();
+        prop_assert_eq!(decoded, Instruction { width: Width::W32, ..inst });
+    }
+
+    // anything that decodes comes back the same from its own encoding, and nothing panics
+    #[test]
+    fn encode_is_canonical(bytes in specifier()) {
+        if let Ok((inst, _)) = Instruction::decode_with(&bytes, Widths::Printf) {
+            let again = inst.encode();
+            let (decoded, rest) = Instruction::decode_with(&again, Widths::Printf).unwrap();
+            prop_assert_eq!(decoded, inst, "{:?} encoded as {:?}", String::from_utf8_lossy(&bytes), String::from_utf8_lossy(&again));
+            prop_assert!(rest.is_empty());
+            let _ = inst.to_string();
+        }
+    }
+}
+
+// every instruction in the real program encodes back to the exact bytes it was decoded from
+#[test]
+fn corpus() {
+    let mem = decrypted_image();
+    let mut count = 0;
+
+    // skip the "%52C%s" entry, the %s isn't a vm instruction
+    for (start, end) in [(6, 0xc8), (0xc8, mem.len())] {
+        let mut curr = start;
+        while curr < end {
+            let (inst, next) = Instruction::decode_with(&mem[curr..], Widths::Printf).unwrap();
+            let next = mem.len() - next.len();
+            assert!(
+                inst.encode() == mem[curr..next],
+                "{:#x}: {:?} encoded as {:?}",
+                curr,
+                String::from_utf8_lossy(&mem[curr..next]),
+                String::from_utf8_lossy(&inst.encode())
+            );
+            curr = next;
+            count += 1;
+        }
+    }
+    assert_eq!(count, 248);
+}
+
+// flags in whatever order and combination, against the mode and width they come down to. the
+// image only ever has the one flag, other programs in the family needn't
+#[test]
+fn spellings() {
+    let cases: [(&[u8], DestMode, u32); 14] = [
+        (b"%0.1llM", DestMode::NoPlusMinus, 0),
+        (b"%00.1llC", DestMode::ZeroPad, 0),
+        (b"%05.1lC", DestMode::ZeroPad, 5),
+        (b"%-05.1llM", DestMode::Minus, 5),
+        (b"%0-5.1llM", DestMode::Minus, 5),
+        (b"%+-5.1llM", DestMode::Minus, 5),
+        (b"%-+5.1llM", DestMode::Minus, 5),
+        (b"%0+5.1llM", DestMode::Plus, 5),
+        (b"%+05.1llM", DestMode::Plus, 5),
+        (b"%+0.1llM", DestMode::Plus, 0),
+        (b"% 5.1llM", DestMode::NoPlusMinus, 5),
+        (b"%# 0+5.1llM", DestMode::Plus, 5),
+        (b"% #05.1lC", DestMode::ZeroPad, 5),
+        (b"%--++005.1llM", DestMode::Minus, 5),
+    ];
+    for (bytes, mode, dest) in cases {
+        let (inst, rest) = Instruction::decode_with(bytes, Widths::Printf).unwrap();
+        assert!(
+            inst.dest_mode == mode && inst.dest == dest && rest.is_empty(),
+            "{:?} decoded as {:?}, expected {:?} with width {}",
+            String::from_utf8_lossy(bytes),
+            inst,
+            mode,
+            dest
+        );
+    }
+}
+
+// the length modifiers past hh, h, l and ll, and the L that's a conversion and not one
+#[test]
+fn modifiers() {
+    let cases: [(&[u8], SrcMode, Operation); 9] = [
+        (b"%1.2jM", SrcMode::L, Operation::Mov),
+        (b"%1.2zM", SrcMode::L, Operation::Mov),
+        (b"%1.2tM", SrcMode::L, Operation::Mov),
+        (b"%1.2LM", SrcMode::LL, Operation::Mov),
+        (b"%1.2LL", SrcMode::LL, Operation::ShLeft),
+        (b"%1.2jL", SrcMode::L, Operation::ShLeft),
+        (b"%1.2L", SrcMode::None, Operation::ShLeft),
+        (b"%1.2llL", SrcMode::LL, Operation::ShLeft),
+        (b"%1.2M", SrcMode::None, Operation::Mov),
+    ];
+    for (bytes, mode, op) in cases {
+        let (inst, rest) = Instruction::decode_with(bytes, Widths::Printf).unwrap();
+        assert!(
+            inst.src_mode == mode && inst.op == op && inst.src == 2 && rest.is_empty(),
+            "{:?} decoded as {:?}, expected {:?} {:?}",
+            String::from_utf8_lossy(bytes),
+            inst,
+            mode,
+            op
+        );
+    }
+}
+
+// printf reads a precision's leading zeros as nothing, the binary can't see them. only Zeros
+// makes them a narrower access
+#[test]
+fn widths() {
+    // bytes, precision, width with Printf, width with Zeros
+    let cases: [(&[u8], u32, Width, Width); 4] = [
+        (b"%1.2hhM", 2, Width::W32, Width::W32),
+        (b"%1.02hhM", 2, Width::W32, Width::W16),
+        (b"%1.002hhM", 2, Width::W32, Width::W8),
+        (b"%1.0hhM", 0, Width::W32, Width::W32),
+    ];
+    for (bytes, src, printf, zeros) in cases {
+        for (widths, want) in [(Widths::Printf, printf), (Widths::Zeros, zeros)] {
+            let (inst, _) = Instruction::decode_with(bytes, widths).unwrap();
+            assert!(
+                inst.width == want && inst.src == src,
+                "{:?} decoded with {:?} as {:?}, expected {:?}",
+                String::from_utf8_lossy(bytes),
+                widths,
+                inst,
+                want
+            );
+        }
+    }
+}
+
+// bytes that aren't an instruction, and why. printf's own limits on a number are INT_MAX and the
+// digits that takes, however many zeros are in front
+#[test]
+fn rejected() {
+    let cases: [(&[u8], ParseError); 10] = [
+        (b"", ParseError::Truncated),
+        (b"%", ParseError::Truncated),
+        (b"%12", ParseError::Truncated),
+        (b"%1.2ll", ParseError::Truncated),
+        (b"x%1M", ParseError::NotASpecifier),
+        (b"%1.2llQ", ParseError::Conversion(b'Q')),
+        (b"%2147483648M", ParseError::Overflow),
+        (b"%1.99999999999llM", ParseError::Overflow),
+        (b"%1.010000000000llM", ParseError::TooManyDigits),
+        (b"%-123456789012345678901234567890M", ParseError::TooManyDigits),
+    ];
+    for (bytes, want) in cases {
+        let got = Instruction::decode_with(bytes, Widths::Printf).map(|(inst, _)| inst);
+        assert!(got == Err(want), "{:?} decoded as {:?}, expected {:?}", String::from_utf8_lossy(bytes), got, want);
+    }
+
+    // the biggest number printf takes, with as many zeros in front as anyone likes
+    let (inst, _) = Instruction::decode_with(b"%2147483647.0000000000002147483647llM", Widths::Printf).unwrap();
+    assert!(inst.dest == MAX_OPERAND && inst.src == MAX_OPERAND && inst.width == Width::W32);
+}
@@ -0,0 +1,17 @@
+// the stage1 and stage2 listings against snapshots/. a change to Display, the parser or the
+// listing fails here with the diff, `disasm snapshot --update` accepts it
+use disasm::snapshot;
+use std::fs;
+
+#[test]
+fn listings() {
+    let mut changed = Vec::new();
+    for (name, current) in snapshot::listings() {
+        let path = snapshot::path(name);
+        let old = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        if old != current {
+            changed.push(format!("{}:\n{}", path, snapshot::diff(&old, &current)));
+        }
+    }
+    assert!(changed.is_empty(), "listings changed, run `disasm snapshot --update` if that's wanted\n{}", changed.join("\n"));
+}